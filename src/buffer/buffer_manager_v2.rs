@@ -11,6 +11,8 @@ pub struct BufferV2 {
     pub tx_num: Option<i32>,
     lsn: Option<i32>,
     pin_count: i32,
+    // 最後にunpinされた時刻 (BufferManagerV2の中の通し番号)
+    last_unpinned: u64,
     log_manager: Arc<Mutex<LogManagerV2>>,
     file_manager: Arc<Mutex<FileManager>>,
 }
@@ -28,6 +30,7 @@ impl BufferV2 {
             block_id: None,
             tx_num: None,
             pin_count,
+            last_unpinned: 0,
             lsn: None,
             file_manager,
             log_manager,
@@ -59,10 +62,14 @@ impl BufferV2 {
 
     pub fn assign_to_block(&mut self, block_id: BlockId) {
         self.flush();
-        self.file_manager
-            .lock()
-            .unwrap()
-            .read(&block_id, &mut self.page);
+        let file_manager = self.file_manager.lock().unwrap();
+        // fileごとにblock sizeが異なるので、pageの大きさを合わせる
+        let block_size = file_manager.get_file_block_size(block_id.get_file_name());
+        if self.page.get_data().len() != block_size {
+            self.page = Page::new(block_size);
+        }
+        file_manager.read(&block_id, &mut self.page);
+        drop(file_manager);
         self.block_id = Some(block_id);
         self.pin_count = 0;
     }
@@ -91,10 +98,25 @@ impl BufferV2 {
     }
 }
 
+// commitのときに変更したbufferをdiskに書き出すかどうか
+// Force: commitのたびにflushする。recoveryはundoだけで済むが、commitが遅い
+// NoForce: commitではlogだけをflushする。commitは速いが、crashの後は
+//          logのredoでcommit済みの変更を戻す必要がある
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    Force,
+    NoForce,
+}
+
 pub struct BufferManagerV2 {
     buffer_pool: Vec<Arc<Mutex<BufferV2>>>,
     number_of_available: i32,
     file_manager: Arc<Mutex<FileManager>>,
+    flush_policy: FlushPolicy,
+    // unpinのたびに進める。LRUでどのbufferを追い出すかに使う
+    clock: u64,
+    hit_count: u32,
+    miss_count: u32,
 }
 
 impl BufferManagerV2 {
@@ -115,6 +137,40 @@ impl BufferManagerV2 {
             buffer_pool,
             number_of_available: number_of_buffer,
             file_manager: file_manager.clone(),
+            flush_policy: FlushPolicy::Force,
+            clock: 0,
+            hit_count: 0,
+            miss_count: 0,
+        }
+    }
+
+    pub fn set_flush_policy(&mut self, flush_policy: FlushPolicy) {
+        self.flush_policy = flush_policy;
+    }
+
+    pub fn get_flush_policy(&self) -> FlushPolicy {
+        self.flush_policy
+    }
+
+    // 消したfileのbufferが後から書き出されないように、変更を捨てる
+    pub fn discard_file(&mut self, file_name: &str) {
+        self.discard_blocks(file_name, 0);
+    }
+
+    // from_block_number以降のblockのbufferの変更を捨てる。切り詰めたfileに使う
+    pub fn discard_blocks(&mut self, file_name: &str, from_block_number: u64) {
+        for buffer in self.buffer_pool.iter() {
+            let mut buffer = buffer.lock().unwrap();
+            let Some(block_id) = buffer.block_id.as_ref() else {
+                continue;
+            };
+            if block_id.get_file_name() == file_name
+                && block_id.get_block_number() >= from_block_number
+                && !buffer.is_pinned()
+            {
+                buffer.tx_num = None;
+                buffer.block_id = None;
+            }
         }
     }
 
@@ -123,6 +179,8 @@ impl BufferManagerV2 {
         buf.unpin();
         if !buf.is_pinned() {
             self.number_of_available = self.number_of_available + 1;
+            self.clock += 1;
+            buf.last_unpinned = self.clock;
         }
     }
 
@@ -135,12 +193,23 @@ impl BufferManagerV2 {
         }
     }
 
+    // どのtransactionが変更したかに関係なく、変更のあるbufferを全部書き出す
+    pub fn flush_all_modified(&mut self) {
+        for buffer in self.buffer_pool.iter() {
+            buffer.lock().unwrap().flush();
+        }
+    }
+
     pub fn try_to_pin(&mut self, block_id: BlockId) -> Option<Arc<Mutex<BufferV2>>> {
         let buffer = self.find_existing_buffer(&block_id);
 
         let buffer = match buffer {
-            Some(buffer) => Some(buffer),
+            Some(buffer) => {
+                self.hit_count += 1;
+                Some(buffer)
+            }
             None => {
+                self.miss_count += 1;
                 let buffer = self.choose_unpinned_buffer();
                 match buffer {
                     Some(buffer) => {
@@ -167,8 +236,7 @@ impl BufferManagerV2 {
     fn find_existing_buffer(&mut self, block_id: &BlockId) -> Option<Arc<Mutex<BufferV2>>> {
         let buffer = self.buffer_pool.iter().find(|buffer| {
             let buffer_ref = buffer.lock().unwrap();
-            buffer_ref.block_id().is_some()
-                && buffer_ref.block_id().as_ref().unwrap().equals(&block_id)
+            buffer_ref.block_id().as_ref() == Some(block_id)
         });
 
         if let Some(buffer) = buffer {
@@ -178,11 +246,14 @@ impl BufferManagerV2 {
         }
     }
 
+    // unpinされているbufferのうち、unpinされてから一番時間が経ったものを選ぶ (LRU)
+    // 一度も使われていないbufferが先に選ばれる
     fn choose_unpinned_buffer(&mut self) -> Option<Arc<Mutex<BufferV2>>> {
-        let buffer = self.buffer_pool.iter().find(|buffer| {
-            let buffer = buffer.lock().unwrap();
-            !buffer.is_pinned()
-        });
+        let buffer = self
+            .buffer_pool
+            .iter()
+            .filter(|buffer| !buffer.lock().unwrap().is_pinned())
+            .min_by_key(|buffer| buffer.lock().unwrap().last_unpinned);
 
         if let Some(buffer) = buffer {
             return Some(buffer.clone());
@@ -198,6 +269,16 @@ impl BufferManagerV2 {
     pub fn get_available_buffer_size(&self) -> i32 {
         self.number_of_available
     }
+
+    // pinしたblockがすでにbufferにあった回数
+    pub fn hit_count(&self) -> u32 {
+        self.hit_count
+    }
+
+    // pinしたblockをdiskから読み込んだ回数
+    pub fn miss_count(&self) -> u32 {
+        self.miss_count
+    }
 }
 
 pub struct BufferListV2 {
@@ -349,4 +430,52 @@ mod tests {
 
         remove_file(test_dir.join("test_buffer_manager.txt")).unwrap();
     }
+
+    #[test]
+    fn test_lru_replacement() {
+        let test_dir = std::path::Path::new("test_data");
+
+        let block_size = 400;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
+        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
+        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
+            file_manager.clone(),
+            log_file_name.clone(),
+        )));
+        let mut buffer_manager = BufferManagerV2::new(3, file_manager.clone(), log_manager.clone());
+
+        let data_file_name = format!("test_lru_{}.txt", uuid::Uuid::new_v4());
+        let block_id = |block_number: u64| BlockId::new(data_file_name.clone(), block_number);
+
+        for block_number in 0..3 {
+            let buffer = buffer_manager.pin(block_id(block_number)).unwrap();
+            buffer_manager.unpin(&buffer);
+        }
+        assert_eq!(buffer_manager.miss_count(), 3);
+
+        // block 0を使い直したので、一番古いのはblock 1になる
+        let buffer = buffer_manager.pin(block_id(0)).unwrap();
+        buffer_manager.unpin(&buffer);
+        assert_eq!(buffer_manager.hit_count(), 1);
+
+        let buffer = buffer_manager.pin(block_id(3)).unwrap();
+        buffer_manager.unpin(&buffer);
+        assert_eq!(buffer_manager.miss_count(), 4);
+
+        // block 0とblock 2は残っていて、block 1は追い出されている
+        let buffer = buffer_manager.pin(block_id(0)).unwrap();
+        buffer_manager.unpin(&buffer);
+        let buffer = buffer_manager.pin(block_id(2)).unwrap();
+        buffer_manager.unpin(&buffer);
+        assert_eq!(buffer_manager.hit_count(), 3);
+        assert_eq!(buffer_manager.miss_count(), 4);
+
+        let buffer = buffer_manager.pin(block_id(1)).unwrap();
+        buffer_manager.unpin(&buffer);
+        assert_eq!(buffer_manager.hit_count(), 3);
+        assert_eq!(buffer_manager.miss_count(), 5);
+
+        let _ = remove_file(test_dir.join(data_file_name));
+        remove_file(test_dir.join(log_file_name)).unwrap();
+    }
 }
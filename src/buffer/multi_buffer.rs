@@ -124,9 +124,10 @@ impl ScanV2 for ChunkScan {
 
             record_page = self.get_current_record_page();
 
+            // 使っている行が無いblockは読み飛ばす
             self.current_slot_id = record_page
                 .find_next_after_slot_id(current_slot_id)
-                .unwrap();
+                .unwrap_or(-1);
         }
 
         return Ok(true);
@@ -153,9 +154,11 @@ impl ScanV2 for ChunkScan {
     }
 
     fn close(&mut self) {
-        for i in 0..self.record_page_list.len() {
-            let block = BlockId::new(self.file_name.clone(), (self.start_buffer_index + i) as u64);
-            self.transaction.borrow_mut().unpin(block);
+        // RecordPageは".tbl"のついたfileのblockをpinしているので、そのBlockIdでunpinする
+        for record_page in std::mem::take(&mut self.record_page_list) {
+            self.transaction
+                .borrow_mut()
+                .unpin(record_page.get_block_id());
         }
     }
 
@@ -163,14 +166,29 @@ impl ScanV2 for ChunkScan {
         &mut self,
         field_name: crate::query::predicate::TableNameAndFieldName,
     ) -> Option<ConstantValue> {
-        if self
+        let field_type = self
             .layout
             .schema
-            .get_field_type(field_name.field_name.clone())
-            == Some(TableFieldType::INTEGER)
-        {
+            .get_field_type(field_name.field_name.clone());
+        if field_type == Some(TableFieldType::INTEGER) {
             let int_value = self.get_integer(field_name)?;
             return Some(ConstantValue::Number(int_value));
+        } else if let Some(TableFieldType::DECIMAL(_, scale)) = field_type {
+            let value = self.get_integer(field_name)?;
+            return Some(ConstantValue::Decimal {
+                value,
+                scale: scale as u32,
+            });
+        } else if field_type == Some(TableFieldType::FLOAT) {
+            let current_slot_id = self.current_slot_id;
+            let record_page = self.get_current_record_page();
+            let float_value = record_page.get_float(field_name.field_name, current_slot_id)?;
+            return Some(ConstantValue::Float(float_value));
+        } else if field_type == Some(TableFieldType::DATE) {
+            let current_slot_id = self.current_slot_id;
+            let record_page = self.get_current_record_page();
+            let date_value = record_page.get_date(field_name.field_name, current_slot_id)?;
+            return Some(ConstantValue::Date(date_value));
         } else {
             let string_value = self.get_string(field_name)?;
             return Some(ConstantValue::String(string_value));
@@ -236,7 +254,7 @@ impl MultiBufferProductPlan {
         }
     }
 
-    fn copy_records(transaction: Rc<RefCell<TransactionV2>>, plan: &mut dyn PlanV2) -> TempTable {
+    fn copy_records(transaction: Rc<RefCell<TransactionV2>>, plan: &dyn PlanV2) -> TempTable {
         let mut source = plan.open().unwrap();
         let schema = plan.get_schema().clone();
 
@@ -256,23 +274,23 @@ impl MultiBufferProductPlan {
                 );
             }
         }
-        transaction.borrow_mut().commit();
+        // 途中でcommitするとtemp tableが消えるので、scanを閉じるだけにする
+        source.close();
+        destination_scan.close();
 
         return temp_table;
     }
 }
 
 impl PlanV2 for MultiBufferProductPlan {
-    fn open(&mut self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
         let left_scan = self.left_plan.open()?;
-        let right_plan = self.right_plan.as_mut();
-        let temp_table = Self::copy_records(self.transaction.clone(), right_plan);
+        let temp_table = Self::copy_records(self.transaction.clone(), self.right_plan.as_ref());
 
-        return Ok(Box::new(MultiBufferProductScan::new(
-            self.transaction.clone(),
-            left_scan,
-            temp_table,
-        )));
+        // 最初のchunkを読み込んでおかないとnextを呼べない
+        let mut scan = MultiBufferProductScan::new(self.transaction.clone(), left_scan, temp_table);
+        scan.move_to_before_first()?;
+        return Ok(Box::new(scan));
     }
 
     fn blocks_accessed(&self) -> u32 {
@@ -280,7 +298,7 @@ impl PlanV2 for MultiBufferProductPlan {
 
         let right_plan_block_accessed = {
             let layout = Layout::new(self.right_plan.get_schema().clone());
-            let rpb = self.transaction.borrow().get_block_size() as i32 / layout.get_slot_size();
+            let rpb = layout.records_per_block(self.transaction.borrow().get_block_size());
             self.right_plan.records_output() as u32 / rpb as u32
         };
 
@@ -310,6 +328,8 @@ impl PlanV2 for MultiBufferProductPlan {
     fn get_child_plans(&self) -> PlanTreeNodeForDebug {
         PlanTreeNodeForDebug {
             current_node_type: "MultiBufferProductPlan".to_string(),
+            records_output: self.records_output(),
+            blocks_accessed: self.blocks_accessed(),
             child_nodes: vec![
                 self.left_plan.get_child_plans(),
                 self.right_plan.get_child_plans(),
@@ -385,8 +405,9 @@ impl MultiBufferProductScan {
 
         let current_product_scan = self.product_scan.take();
 
+        // 前のchunkは閉じて、左のscanだけを次のchunkとの組み合わせに使う
         self.left_scan = if let Some(prod_scan) = current_product_scan {
-            Some(prod_scan.left_scan)
+            Some(prod_scan.into_left_scan())
         } else {
             self.left_scan.take()
         };
@@ -509,8 +530,9 @@ mod tests {
 
     #[test]
 
-    fn test_optimized_product_plan() -> Result<(), ValueNotFound> {
-        let directory_path_name = format!("test_data_{}", uuid::Uuid::new_v4());
+    fn test_optimized_product_plan() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -519,7 +541,7 @@ mod tests {
         let create_table_sql =
             "create table test_table_1 (A_1 integer, B_1 varchar(10))".to_string();
 
-        let parsed_sql_list = parse_sql(create_table_sql.clone());
+        let parsed_sql_list = parse_sql(create_table_sql.clone()).unwrap();
 
         let create_table_data = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::CreateTable(q) => q,
@@ -539,7 +561,7 @@ mod tests {
         let create_table_sql =
             "create table test_table_2 (A_2 integer, B_2 varchar(10))".to_string();
 
-        let parsed_sql_list = parse_sql(create_table_sql.clone());
+        let parsed_sql_list = parse_sql(create_table_sql.clone()).unwrap();
 
         let create_table_data = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::CreateTable(q) => q,
@@ -576,7 +598,7 @@ mod tests {
             .collect::<Vec<String>>();
 
         for insert_sql in insert_sql_list_for_table_1.iter() {
-            let parsed_sql_list = parse_sql(insert_sql.clone());
+            let parsed_sql_list = parse_sql(insert_sql.clone()).unwrap();
 
             let insert_data = match &parsed_sql_list[0] {
                 crate::query::parser::ParsedSQL::Insert(q) => q,
@@ -587,11 +609,11 @@ mod tests {
                 transaction.clone(),
                 &mut metadata_manager,
                 insert_data.clone(),
-            );
+            )?;
         }
 
         for insert_sql in insert_sql_list_for_table_2.iter() {
-            let parsed_sql_list = parse_sql(insert_sql.clone());
+            let parsed_sql_list = parse_sql(insert_sql.clone()).unwrap();
 
             let insert_data = match &parsed_sql_list[0] {
                 crate::query::parser::ParsedSQL::Insert(q) => q,
@@ -602,10 +624,10 @@ mod tests {
                 transaction.clone(),
                 &mut metadata_manager,
                 insert_data.clone(),
-            );
+            )?;
         }
 
-        transaction.borrow_mut().commit();
+        transaction.borrow_mut().commit()?;
 
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -626,12 +648,11 @@ mod tests {
         let left_plan = Box::new(table_plan_1) as Box<dyn PlanV2>;
         let right_plan = Box::new(table_plan_2) as Box<dyn PlanV2>;
 
-        let mut prod_plan = MultiBufferProductPlan::new(transaction.clone(), left_plan, right_plan);
+        let prod_plan = MultiBufferProductPlan::new(transaction.clone(), left_plan, right_plan);
+        let available_buffer_size = transaction.borrow().get_available_buffer_size();
 
         let mut scan = prod_plan.open()?;
 
-        scan.move_to_before_first()?;
-
         let mut result = vec![];
 
         while scan.next()? {
@@ -648,6 +669,12 @@ mod tests {
 
             result.push((a_1_value, b_1_value, a_2_value, b_2_value));
         }
+        scan.close();
+        // chunkごとにpinしたbufferはすべてunpinされている
+        assert_eq!(
+            transaction.borrow().get_available_buffer_size(),
+            available_buffer_size
+        );
 
         let test_assert_value = (0..21)
             .map(|i| {
@@ -706,6 +733,22 @@ mod tests {
             assert_eq!(result, test_assert_value);
         }
 
+        // 同じplanをもう一度openしても、最初から同じ行を読める
+        let mut scan = prod_plan.open()?;
+        let mut reopened_result = vec![];
+        while scan.next()? {
+            let a_1_value = scan.get_integer(TableNameAndFieldName::new(None, "A_1".to_string()));
+            let a_2_value = scan.get_integer(TableNameAndFieldName::new(None, "A_2".to_string()));
+            reopened_result.push((a_1_value, a_2_value));
+        }
+        scan.close();
+
+        let expected = result
+            .iter()
+            .map(|(a_1, _, a_2, _)| (*a_1, *a_2))
+            .collect::<Vec<(Option<i32>, Option<i32>)>>();
+        assert_eq!(reopened_result, expected);
+
         return Ok(());
     }
 }
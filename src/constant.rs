@@ -1,2 +1,4 @@
 pub const INTEGER_BYTE_SIZE: usize = std::mem::size_of::<i32>();
+pub const FLOAT_BYTE_SIZE: usize = std::mem::size_of::<f64>();
+pub const LONG_BYTE_SIZE: usize = std::mem::size_of::<i64>();
 pub const MAX_BYTE_SIZE_PER_CHAR: usize = 4; // UTF-8の最大バイト数
@@ -2,12 +2,153 @@ use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::{cell::RefCell, rc::Rc};
 
+use crate::error::{
+    ActiveTransactions, DatabaseError, FileAccessError, ParameterCountMismatch, ParseError,
+    UnsupportedStatement,
+};
+use crate::index::hash_index::HashIndex;
+use crate::metadata::metadata_manager::MetadataManager;
+use crate::query::index_update_planner::IndexUpdatePlanner;
+use crate::query::json::format_json_rows;
+use crate::query::parser::{
+    parameter_positions, parse_sql, parse_sql_with_parameters, ExplainFormat, ParsedSQL,
+};
+use crate::query::plan_v2::{
+    create_modify_plan, create_query_plan, execute_create_table, validate_delete, validate_insert,
+    validate_query, validate_update, PlanV2,
+};
+use crate::query::predicate::ConstantValue;
+use crate::query::query_handler::{export_csv, select_rows};
+use crate::record::record_page::TableSchema;
+use crate::storage::block::BlockId;
 use crate::storage::file_manager::FileManager;
 use crate::{
-    buffer::buffer_manager_v2::BufferManagerV2, tx::concurrency_manager::LockTable,
-    storage::log_manager_v2::LogManagerV2, tx::transaction_v2::TransactionV2,
+    buffer::buffer_manager_v2::{BufferManagerV2, FlushPolicy},
+    tx::concurrency_manager::LockTable,
+    storage::log_manager_v2::LogManagerV2,
+    tx::transaction_v2::TransactionV2,
 };
 
+// testごとに作るdirectory。dropのときに中のfileごと消す
+// Databaseより先に作っておけば、Databaseの後にdropされる
+#[cfg(test)]
+pub struct TestDirectory {
+    path_name: String,
+}
+
+#[cfg(test)]
+impl TestDirectory {
+    pub fn new() -> Self {
+        TestDirectory {
+            path_name: format!("test_data_{}", uuid::Uuid::new_v4()),
+        }
+    }
+
+    pub fn path_name(&self) -> String {
+        self.path_name.clone()
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDirectory {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path_name);
+    }
+}
+
+// Database::validateの結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidatedStatement {
+    // selectの出力schema
+    Query(TableSchema),
+    // DMLの対象table
+    Table(String),
+    Other,
+}
+
+// Database::executeの結果。複数の文を実行したときは最後の文の結果
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryResult {
+    // selectのcolumn名と行
+    Rows {
+        columns: Vec<String>,
+        rows: Vec<Vec<ConstantValue>>,
+    },
+    // insert / update / delete / copyで変わった行数。それ以外の文は0
+    RowsAffected(u32),
+    // vacuumで減ったblockの数
+    BlocksReclaimed(u32),
+}
+
+// Database::prepareで1回だけparseした文。? の値を変えて何度でも実行できる
+#[derive(Debug, Clone)]
+pub struct PreparedStatement {
+    // ? はsqlに出てくる順に0からの番号のConstantValue::Parameterになっている
+    statements: Vec<ParsedSQL>,
+    parameter_count: usize,
+}
+
+impl PreparedStatement {
+    pub fn parameter_count(&self) -> usize {
+        self.parameter_count
+    }
+
+    // ? を渡された値に置き換えて実行する。commitは呼び出し側で行う
+    pub fn execute(
+        &self,
+        parameters: &[ConstantValue],
+        transaction: Rc<RefCell<TransactionV2>>,
+    ) -> Result<QueryResult, DatabaseError> {
+        if parameters.len() != self.parameter_count {
+            return Err(ParameterCountMismatch::new(self.parameter_count, parameters.len()).into());
+        }
+
+        let mut statements = self.statements.clone();
+        for statement in statements.iter_mut() {
+            statement.for_each_constant_mut(&mut |constant| {
+                if let ConstantValue::Parameter(index) = constant.value {
+                    constant.value = match &parameters[index] {
+                        // 計算の結果と同じく、有限でないfloatはNULLとして扱う
+                        ConstantValue::Float(float) if !float.is_finite() => ConstantValue::Null,
+                        parameter => parameter.clone(),
+                    };
+                }
+            });
+        }
+
+        return TransactionV2::abort_on_lock_failure(transaction.clone(), || {
+            let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+            return Database::execute_parsed_statements(
+                &statements,
+                transaction.clone(),
+                &mut metadata_manager,
+            );
+        });
+    }
+}
+
+// recover / validate / resetが内部で使うtransactionの番号
+// 呼び出し側が付けるtransactionの番号と重ならないように負の数にする
+const SYSTEM_TRANSACTION_ID: i32 = -1;
+// databaseのdirectoryに作るlogのfile
+const LOG_FILE_NAME: &str = "log.txt";
+
+// Database::openの設定
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseConfig {
+    // openのときにlogを読んでrecoveryするか
+    // falseのときは、状態を確認してから呼び出し側がDatabase::recoverを呼ぶ
+    pub recover_on_open: bool,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            recover_on_open: true,
+        }
+    }
+}
+
 pub struct Database {
     lock_table: Arc<Mutex<LockTable>>,
     log_manager: Arc<Mutex<LogManagerV2>>,
@@ -20,7 +161,7 @@ impl Database {
         let file_manager = Arc::new(Mutex::new(FileManager::new(directory_path, 400)));
         let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
             file_manager.clone(),
-            "log.txt".to_string(),
+            LOG_FILE_NAME.to_string(),
         )));
 
         let buffer_manager = Arc::new(Mutex::new(BufferManagerV2::new(
@@ -39,6 +180,65 @@ impl Database {
         }
     }
 
+    // recoverできなかったときは、versionが違うときも含めてエラーを返す
+    pub fn open(directory_path: &Path, config: DatabaseConfig) -> Result<Self, DatabaseError> {
+        let database = Database::new(directory_path);
+        if config.recover_on_open {
+            database.recover()?;
+        }
+        return Ok(database);
+    }
+
+    // 終わっていないtransactionの変更をundoし、commit済みの変更をredoする
+    // 動いているtransactionの変更もundoしてしまうので、lockを持つtransactionがあれば何もしない
+    pub fn recover(&self) -> Result<(), DatabaseError> {
+        let active_transaction_ids: Vec<i32> = self
+            .held_locks()
+            .into_iter()
+            .map(|(transaction_id, _)| transaction_id)
+            .collect();
+        if !active_transaction_ids.is_empty() {
+            return Err(ActiveTransactions::new(active_transaction_ids).into());
+        }
+
+        let transaction = self.new_transaction(SYSTEM_TRANSACTION_ID);
+        // block sizeを変えたtableのfileに、databaseのblock sizeで書き戻さないようにする
+        // versionが違うdatabaseはMetadataManager::newで開けないので、logも書き戻さない
+        if let Err(error) = MetadataManager::register_file_block_sizes(transaction.clone()) {
+            transaction.borrow_mut().rollback();
+            return Err(error);
+        }
+        transaction.borrow_mut().recover();
+        return Ok(());
+    }
+
+    // testの前に、前の実行で残った.tblとlogのfileを消してcatalogを作り直す
+    // databaseが作っていないfileやdirectoryは消さない
+    // 同じdirectoryを使う別のtestが動いている間に呼んではいけない
+    #[cfg(test)]
+    pub fn reset(directory_path: &Path) -> Result<Self, DatabaseError> {
+        if let Ok(entries) = std::fs::read_dir(directory_path) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                // table、index、temp tableのfileはすべて.tblで終わる
+                let is_table_file = path.extension().is_some_and(|e| e == "tbl");
+                let is_log_file = path.file_name().is_some_and(|n| n == LOG_FILE_NAME);
+                if path.is_file() && (is_table_file || is_log_file) {
+                    std::fs::remove_file(&path).map_err(|e| {
+                        FileAccessError::new(path.display().to_string(), e.to_string())
+                    })?;
+                }
+            }
+        }
+
+        let database = Database::new(directory_path);
+        // catalogが無いので、MetadataManagerが別のtransactionで作ってcommitする
+        let transaction = database.new_transaction(SYSTEM_TRANSACTION_ID);
+        MetadataManager::new(transaction.clone())?;
+        transaction.borrow_mut().commit()?;
+        return Ok(database);
+    }
+
     pub fn new_transaction(&self, transaction_id: i32) -> Rc<RefCell<TransactionV2>> {
         Rc::new(RefCell::new(TransactionV2::new(
             transaction_id,
@@ -48,4 +248,1686 @@ impl Database {
             self.log_manager.clone(),
         )))
     }
+
+    pub fn set_flush_policy(&self, flush_policy: FlushPolicy) {
+        self.buffer_manager
+            .lock()
+            .unwrap()
+            .set_flush_policy(flush_policy);
+    }
+
+    pub fn set_lock_wait_timeout(&self, wait_timeout: std::time::Duration) {
+        self.lock_table
+            .lock()
+            .unwrap()
+            .set_wait_timeout(wait_timeout);
+    }
+
+    // 今lockを持っているtransactionと、そのblockとmodeの一覧
+    pub fn held_locks(&self) -> Vec<(i32, Vec<(BlockId, String)>)> {
+        self.lock_table.lock().unwrap().held_locks()
+    }
+
+    // transactionの中でsqlを実行する。commitは呼び出し側で行う
+    // lockを待ちきれなかったときはtransactionをrollbackしてTransactionAbortedを返すので、
+    // 呼び出し側は新しいtransactionで最初からやり直す
+    pub fn execute(
+        &self,
+        sql: &str,
+        transaction: Rc<RefCell<TransactionV2>>,
+    ) -> Result<QueryResult, DatabaseError> {
+        return TransactionV2::abort_on_lock_failure(transaction.clone(), || {
+            let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+            return Self::execute_statements(sql, transaction.clone(), &mut metadata_manager);
+        });
+    }
+
+    // executeと同じように実行し、最後の文の結果をjsonの文字列で返す
+    // selectは行ごとのobjectの配列、それ以外は変わった行数やblockの数のobject
+    pub fn execute_json(
+        &self,
+        sql: &str,
+        transaction: Rc<RefCell<TransactionV2>>,
+    ) -> Result<String, DatabaseError> {
+        match self.execute(sql, transaction)? {
+            QueryResult::Rows { columns, rows } => return Ok(format_json_rows(&columns, &rows)),
+            QueryResult::RowsAffected(count) => {
+                return Ok(format!("{{\"rows_affected\":{}}}", count));
+            }
+            QueryResult::BlocksReclaimed(blocks) => {
+                return Ok(format!("{{\"blocks_reclaimed\":{}}}", blocks));
+            }
+        }
+    }
+
+    // insert into t (a, b) values (?, ?) のように ? を値の代わりに書いた文をparseしておく
+    // ? を書けるのはselect / insert / update / deleteの定数の位置だけ
+    pub fn prepare(&self, sql: &str) -> Result<PreparedStatement, DatabaseError> {
+        let positions = parameter_positions(sql)?;
+        let mut statements = parse_sql_with_parameters(sql.to_string())?;
+
+        let mut bound_positions = vec![];
+        for statement in statements.iter_mut() {
+            statement.for_each_constant_mut(&mut |constant| {
+                if let ConstantValue::Parameter(position) = constant.value {
+                    bound_positions.push(position);
+                    let index = positions
+                        .iter()
+                        .position(|(p, _, _)| *p == position)
+                        .unwrap();
+                    constant.value = ConstantValue::Parameter(index);
+                }
+            });
+        }
+        if let Some((_, line, column)) = positions
+            .iter()
+            .find(|(position, _, _)| !bound_positions.contains(position))
+        {
+            return Err(ParseError::new(
+                *line,
+                *column,
+                "parameter is not allowed here".to_string(),
+            )
+            .into());
+        }
+
+        return Ok(PreparedStatement {
+            statements,
+            parameter_count: positions.len(),
+        });
+    }
+
+    // fileに書かれた文を、1つのtransactionの中で順に実行してcommitする
+    // 途中の文が失敗したときはrollbackするので、それより前の文も反映されない
+    pub fn run_script(
+        &self,
+        path: &Path,
+        transaction: Rc<RefCell<TransactionV2>>,
+    ) -> Result<QueryResult, DatabaseError> {
+        let sql = std::fs::read_to_string(path)
+            .map_err(|e| FileAccessError::new(path.display().to_string(), e.to_string()))?;
+
+        let result = self.execute(&sql, transaction.clone());
+        match &result {
+            Ok(_) => transaction.borrow_mut().commit()?,
+            // lockを待ちきれなかったときはexecuteがrollback済み
+            Err(DatabaseError::TransactionAborted(_)) => {}
+            Err(_) => transaction.borrow_mut().rollback(),
+        }
+        return result;
+    }
+
+    fn execute_statements(
+        sql: &str,
+        transaction: Rc<RefCell<TransactionV2>>,
+        metadata_manager: &mut MetadataManager,
+    ) -> Result<QueryResult, DatabaseError> {
+        let statements = parse_sql(sql.to_string())?;
+        return Self::execute_parsed_statements(&statements, transaction, metadata_manager);
+    }
+
+    fn execute_parsed_statements(
+        statements: &[ParsedSQL],
+        transaction: Rc<RefCell<TransactionV2>>,
+        metadata_manager: &mut MetadataManager,
+    ) -> Result<QueryResult, DatabaseError> {
+        let mut index_update_planner = IndexUpdatePlanner::new();
+        let mut result = QueryResult::RowsAffected(0);
+
+        for parsed_sql in statements.iter() {
+            result = QueryResult::RowsAffected(0);
+            match parsed_sql {
+                ParsedSQL::Query(query_data) => {
+                    let (columns, rows) =
+                        select_rows(query_data, metadata_manager, transaction.clone())?;
+                    result = QueryResult::Rows { columns, rows };
+                }
+                ParsedSQL::Insert(insert_data) => {
+                    let count = index_update_planner.execute_insert(
+                        insert_data.clone(),
+                        transaction.clone(),
+                        metadata_manager,
+                    )?;
+                    result = QueryResult::RowsAffected(count as u32);
+                }
+                ParsedSQL::Update(update_data) => {
+                    let count = index_update_planner.execute_modify(
+                        update_data.clone(),
+                        transaction.clone(),
+                        metadata_manager,
+                    )?;
+                    result = QueryResult::RowsAffected(count as u32);
+                }
+                ParsedSQL::Delete(delete_data) => {
+                    let count = index_update_planner.execute_delete(
+                        delete_data.clone(),
+                        transaction.clone(),
+                        metadata_manager,
+                    )?;
+                    result = QueryResult::RowsAffected(count as u32);
+                }
+                ParsedSQL::Copy(copy_data) => {
+                    let count = index_update_planner.execute_copy(
+                        copy_data.clone(),
+                        transaction.clone(),
+                        metadata_manager,
+                    )?;
+                    result = QueryResult::RowsAffected(count as u32);
+                }
+                ParsedSQL::CopyTo(copy_to_data) => {
+                    let count = export_csv(copy_to_data, metadata_manager, transaction.clone())?;
+                    result = QueryResult::RowsAffected(count as u32);
+                }
+                ParsedSQL::CreateTable(create_table_data) => {
+                    execute_create_table(
+                        transaction.clone(),
+                        metadata_manager,
+                        create_table_data.clone(),
+                    )?;
+                }
+                ParsedSQL::DropTable { table_name } => {
+                    metadata_manager.drop_table(table_name.clone(), transaction.clone())?;
+                }
+                // beginは渡されたtransactionをそのまま使う
+                // commitやrollbackの後は、呼び出し側で新しいtransactionを作る
+                ParsedSQL::Begin => {}
+                ParsedSQL::Commit => {
+                    transaction.borrow_mut().commit()?;
+                }
+                ParsedSQL::Rollback => {
+                    transaction.borrow_mut().rollback();
+                }
+                ParsedSQL::Vacuum { table_name } => {
+                    let blocks =
+                        metadata_manager.vacuum_table(table_name.clone(), transaction.clone())?;
+                    result = QueryResult::BlocksReclaimed(blocks as u32);
+                }
+                ParsedSQL::Reindex { table_name } => {
+                    index_update_planner.execute_reindex(
+                        table_name.clone(),
+                        transaction.clone(),
+                        metadata_manager,
+                    )?;
+                }
+                ParsedSQL::AlterTable(alter_table_data) => {
+                    metadata_manager.add_column(
+                        alter_table_data.table_name.clone(),
+                        alter_table_data.field_name.clone(),
+                        alter_table_data.field_info.clone(),
+                        transaction.clone(),
+                    )?;
+                }
+                ParsedSQL::CreateIndex(create_index_data) => {
+                    metadata_manager.create_index_with_buckets(
+                        create_index_data.index_name.clone(),
+                        create_index_data.table_name.clone(),
+                        create_index_data.field_name.clone(),
+                        create_index_data
+                            .number_of_buckets
+                            .unwrap_or(HashIndex::DEFAULT_NUMBER_OF_BUCKETS),
+                        transaction.clone(),
+                    )?;
+                }
+                ParsedSQL::ShowTables => {
+                    let rows = metadata_manager
+                        .list_tables(false, transaction.clone())
+                        .into_iter()
+                        .map(|table_name| vec![ConstantValue::String(table_name)])
+                        .collect();
+                    result = QueryResult::Rows {
+                        columns: vec!["table_name".to_string()],
+                        rows,
+                    };
+                }
+                ParsedSQL::ShowIndexes => {
+                    let rows = metadata_manager
+                        .list_indexes(transaction.clone())
+                        .into_iter()
+                        .map(|index| {
+                            vec![
+                                ConstantValue::String(index.index_name),
+                                ConstantValue::String(index.table_name),
+                                ConstantValue::String(index.field_name),
+                            ]
+                        })
+                        .collect();
+                    result = QueryResult::Rows {
+                        columns: vec![
+                            "index_name".to_string(),
+                            "table_name".to_string(),
+                            "field_name".to_string(),
+                        ],
+                        rows,
+                    };
+                }
+                ParsedSQL::DescribeTable { table_name } => {
+                    let layout =
+                        metadata_manager.get_layout(table_name.clone(), transaction.clone())?;
+                    let rows = layout
+                        .schema
+                        .fields
+                        .iter()
+                        .map(|field_name| {
+                            let field_info = &layout.schema.field_infos[field_name];
+                            vec![
+                                ConstantValue::String(field_name.clone()),
+                                ConstantValue::String(format!("{:?}", field_info.field_type)),
+                                ConstantValue::Number(field_info.field_length),
+                            ]
+                        })
+                        .collect();
+                    result = QueryResult::Rows {
+                        columns: vec![
+                            "field_name".to_string(),
+                            "field_type".to_string(),
+                            "field_length".to_string(),
+                        ],
+                        rows,
+                    };
+                }
+                ParsedSQL::Explain { query_data, format } => {
+                    validate_query(query_data, transaction.clone(), metadata_manager)?;
+                    let plan =
+                        create_query_plan(query_data, transaction.clone(), metadata_manager)?;
+                    result = Self::explain_result(plan.as_ref(), format);
+                }
+                ParsedSQL::ExplainDml { statement, format } => {
+                    let (table_name, predicate) = match statement.as_ref() {
+                        ParsedSQL::Delete(delete_data) => {
+                            (&delete_data.table_name, &delete_data.predicate)
+                        }
+                        ParsedSQL::Update(update_data) => {
+                            (&update_data.table_name, &update_data.predicate)
+                        }
+                        _ => {
+                            return Err(UnsupportedStatement::new("explain".to_string()).into());
+                        }
+                    };
+                    let plan = create_modify_plan(
+                        table_name,
+                        predicate,
+                        transaction.clone(),
+                        metadata_manager,
+                    )?;
+                    result = Self::explain_result(plan.as_ref(), format);
+                }
+                // viewの定義はsqlの文字列で保存するが、ここにはparseした結果しか無い
+                ParsedSQL::CreateView(_) => {
+                    return Err(UnsupportedStatement::new("create view".to_string()).into());
+                }
+            }
+        }
+
+        return Ok(result);
+    }
+
+    // explainの結果を1列の行で返す。textは1行ずつ、jsonは1行にまとめる
+    fn explain_result(plan: &dyn PlanV2, format: &ExplainFormat) -> QueryResult {
+        let rows = match format {
+            ExplainFormat::Text => plan
+                .explain()
+                .lines()
+                .map(|line| vec![ConstantValue::String(line.to_string())])
+                .collect(),
+            ExplainFormat::Json => {
+                vec![vec![ConstantValue::String(
+                    plan.get_child_plans().to_json(),
+                )]]
+            }
+        };
+        return QueryResult::Rows {
+            columns: vec!["plan".to_string()],
+            rows,
+        };
+    }
+
+    // sqlをparseしてplanまで作るが、scanのopenや書き込みはしない
+    pub fn validate(&self, sql: String) -> Result<Vec<ValidatedStatement>, DatabaseError> {
+        let transaction = self.new_transaction(SYSTEM_TRANSACTION_ID);
+        let result = MetadataManager::new(transaction.clone()).and_then(|mut metadata_manager| {
+            return Self::validate_statements(sql, transaction.clone(), &mut metadata_manager);
+        });
+
+        // 検証に失敗してもlockは解放する
+        // lockを待ちきれずに読んだ値は正しくないので、そのときは検証の結果よりTransactionAbortedを返す
+        transaction.borrow_mut().commit()?;
+
+        return result;
+    }
+
+    fn validate_statements(
+        sql: String,
+        transaction: Rc<RefCell<TransactionV2>>,
+        metadata_manager: &mut MetadataManager,
+    ) -> Result<Vec<ValidatedStatement>, DatabaseError> {
+        let mut result = Vec::new();
+
+        for parsed_sql in parse_sql(sql)?.iter() {
+            let validated = match parsed_sql {
+                ParsedSQL::Query(query_data) | ParsedSQL::Explain { query_data, .. } => {
+                    ValidatedStatement::Query(validate_query(
+                        query_data,
+                        transaction.clone(),
+                        metadata_manager,
+                    )?)
+                }
+                ParsedSQL::Insert(insert_data) => {
+                    validate_insert(transaction.clone(), metadata_manager, insert_data)?;
+                    ValidatedStatement::Table(insert_data.table_name.clone())
+                }
+                ParsedSQL::Update(update_data) => {
+                    validate_update(transaction.clone(), metadata_manager, update_data)?;
+                    ValidatedStatement::Table(update_data.table_name.clone())
+                }
+                ParsedSQL::Delete(delete_data) => {
+                    validate_delete(transaction.clone(), metadata_manager, delete_data)?;
+                    ValidatedStatement::Table(delete_data.table_name.clone())
+                }
+                ParsedSQL::ExplainDml { statement, .. } => match statement.as_ref() {
+                    ParsedSQL::Update(update_data) => {
+                        validate_update(transaction.clone(), metadata_manager, update_data)?;
+                        ValidatedStatement::Table(update_data.table_name.clone())
+                    }
+                    ParsedSQL::Delete(delete_data) => {
+                        validate_delete(transaction.clone(), metadata_manager, delete_data)?;
+                        ValidatedStatement::Table(delete_data.table_name.clone())
+                    }
+                    _ => ValidatedStatement::Other,
+                },
+                _ => ValidatedStatement::Other,
+            };
+            result.push(validated);
+        }
+
+        return Ok(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ValueNotFound;
+    use crate::query::plan_v2::{create_query_plan, execute_create_table, execute_insert};
+
+    fn prepare_test_data(database: &Database) -> Result<(), DatabaseError> {
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let parsed_sql_list = parse_sql(
+            "create table items (id integer, name varchar(10)); \
+             insert into items (id, name) values (1, 'pen')"
+                .to_string(),
+        )
+        .unwrap();
+
+        let ParsedSQL::CreateTable(create_table_data) = &parsed_sql_list[0] else {
+            panic!("Expected a CreateTable variant from parse_sql");
+        };
+        execute_create_table(
+            transaction.clone(),
+            &mut metadata_manager,
+            create_table_data.clone(),
+        )
+        .unwrap();
+
+        let ParsedSQL::Insert(insert_data) = &parsed_sql_list[1] else {
+            panic!("Expected a Insert variant from parse_sql");
+        };
+        execute_insert(
+            transaction.clone(),
+            &mut metadata_manager,
+            insert_data.clone(),
+        )
+        .unwrap();
+
+        transaction.borrow_mut().commit()?;
+        return Ok(());
+    }
+
+    #[test]
+    fn test_validate() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        prepare_test_data(&database)?;
+
+        let result = database
+            .validate("select name from items where id = 1".to_string())
+            .unwrap();
+        let mut expected_schema = TableSchema::new();
+        expected_schema.add_string_field("name".to_string(), 10);
+        assert_eq!(result, vec![ValidatedStatement::Query(expected_schema)]);
+
+        // where句と集約関数の引数は集約する前のfieldを参照できる
+        let result = database
+            .validate("select id, max(name) from items where id > 0 group by id".to_string())
+            .unwrap();
+        let mut expected_schema = TableSchema::new();
+        expected_schema.add_integer_field("id".to_string());
+        expected_schema.add_string_field("max_name".to_string(), 10);
+        assert_eq!(result, vec![ValidatedStatement::Query(expected_schema)]);
+
+        // group byに無いfieldは選べない
+        let result = database.validate("select name, max(id) from items group by id".to_string());
+        assert!(matches!(result, Err(DatabaseError::ValueNotFound(_))));
+        let result = database.validate("select name, count(*) from items".to_string());
+        assert!(matches!(result, Err(DatabaseError::ValueNotFound(_))));
+        let transaction = database.new_transaction(2);
+        let result = database.execute(
+            "select name, max(id) from items group by id",
+            transaction.clone(),
+        );
+        assert!(matches!(result, Err(DatabaseError::ValueNotFound(_))));
+        transaction.borrow_mut().rollback();
+
+        let result = database
+            .validate("update items set name = 'cup' where id = 1".to_string())
+            .unwrap();
+        assert_eq!(result, vec![ValidatedStatement::Table("items".to_string())]);
+
+        // 存在しないcolumn
+        let result = database.validate("select nmae from items".to_string());
+        assert!(matches!(result, Err(DatabaseError::ValueNotFound(_))));
+
+        let result = database.validate("delete from items where idd = 1".to_string());
+        assert!(matches!(result, Err(DatabaseError::ValueNotFound(_))));
+
+        // 存在しないtable
+        let result = database.validate("select id from missing_table".to_string());
+        assert!(matches!(result, Err(DatabaseError::ValueNotFound(_))));
+
+        // 型が合わない
+        let result =
+            database.validate("insert into items (id, name) values ('one', 'pen')".to_string());
+        assert!(matches!(result, Err(DatabaseError::TypeMismatch(_))));
+
+        let result = database
+            .validate("insert into items (id, name) select name, id from items".to_string());
+        assert!(matches!(result, Err(DatabaseError::TypeMismatch(_))));
+
+        let result =
+            database.validate("insert into items (id, name) select id from items".to_string());
+        assert!(matches!(result, Err(DatabaseError::ColumnCountMismatch(_))));
+
+        // 検証だけなので書き込まれていない
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let layout = metadata_manager.get_layout("items".to_string(), transaction.clone())?;
+        let stat_info =
+            metadata_manager.get_table_stats("items".to_string(), transaction.clone(), layout)?;
+        assert_eq!(stat_info.get_num_records(), 1);
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    fn count_rows(database: &Database, table_name: &str) -> Result<usize, DatabaseError> {
+        let transaction = database.new_transaction(100);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let parsed_sql_list = parse_sql(format!("select id from {}", table_name)).unwrap();
+        let ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+        let plan = create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+        let mut scan = plan.open()?;
+        let mut count = 0;
+        while scan.next()? {
+            count += 1;
+        }
+        scan.close();
+        transaction.borrow_mut().commit()?;
+        return Ok(count);
+    }
+
+    #[test]
+    fn test_deadlock_aborts_one_transaction() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table t (id integer); create table u (id integer); \
+             insert into t (id) values (0); insert into u (id) values (0)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        database.set_lock_wait_timeout(std::time::Duration::from_millis(300));
+
+        let transaction_1 = database.new_transaction(2);
+        let transaction_2 = database.new_transaction(3);
+
+        database.execute("insert into t (id) values (1)", transaction_1.clone())?;
+        database.execute("insert into u (id) values (2)", transaction_2.clone())?;
+
+        // transaction_1はtransaction_2が持つuのlockを待ち続けて諦める
+        let result = database.execute("insert into u (id) values (1)", transaction_1.clone());
+        assert!(matches!(result, Err(DatabaseError::TransactionAborted(_))));
+
+        // transaction_1のlockは解放されているのでtransaction_2は進める
+        database.execute("insert into t (id) values (2)", transaction_2.clone())?;
+        transaction_2.borrow_mut().commit()?;
+
+        assert_eq!(count_rows(&database, "t")?, 2);
+        assert_eq!(count_rows(&database, "u")?, 2);
+        // abortされたtransactionのbufferも全部unpinされている
+        assert_eq!(
+            database
+                .buffer_manager
+                .lock()
+                .unwrap()
+                .get_available_buffer_size(),
+            1000
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_validate_waiting_for_lock_is_aborted() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        prepare_test_data(&database)?;
+
+        database.set_lock_wait_timeout(std::time::Duration::from_millis(300));
+
+        // itemsに書き込んだままのtransactionがあると、検証はitemsを読めない
+        let transaction = database.new_transaction(2);
+        database.execute(
+            "insert into items (id, name) values (2, 'cup')",
+            transaction.clone(),
+        )?;
+
+        let result = database.validate("select name from items where id = 1".to_string());
+        assert!(matches!(result, Err(DatabaseError::TransactionAborted(_))));
+
+        transaction.borrow_mut().commit()?;
+
+        let result = database.validate("select name from items where id = 1".to_string());
+        assert!(result.is_ok());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_execute_show_and_explain() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table items (id integer, name varchar(10)); \
+             create index items_id on items (id)",
+            transaction.clone(),
+        )?;
+
+        let result = database.execute("show tables", transaction.clone())?;
+        assert_eq!(
+            result,
+            QueryResult::Rows {
+                columns: vec!["table_name".to_string()],
+                rows: vec![vec![ConstantValue::String("items".to_string())]],
+            }
+        );
+
+        let result = database.execute("show indexes", transaction.clone())?;
+        assert_eq!(
+            result,
+            QueryResult::Rows {
+                columns: vec![
+                    "index_name".to_string(),
+                    "table_name".to_string(),
+                    "field_name".to_string(),
+                ],
+                rows: vec![vec![
+                    ConstantValue::String("items_id".to_string()),
+                    ConstantValue::String("items".to_string()),
+                    ConstantValue::String("id".to_string()),
+                ]],
+            }
+        );
+
+        let QueryResult::Rows { rows, .. } =
+            database.execute("describe items", transaction.clone())?
+        else {
+            panic!("expected rows");
+        };
+        assert_eq!(rows.len(), 2);
+
+        let QueryResult::Rows { columns, rows } =
+            database.execute("explain select id from items", transaction.clone())?
+        else {
+            panic!("expected rows");
+        };
+        assert_eq!(columns, vec!["plan".to_string()]);
+        assert!(!rows.is_empty());
+
+        // 実行できない文は無視せずにerrorにする
+        let result = database.execute(
+            "create view item_ids as select id from items",
+            transaction.clone(),
+        );
+        assert!(matches!(
+            result,
+            Err(DatabaseError::UnsupportedStatement(_))
+        ));
+        transaction.borrow_mut().rollback();
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_deadlock_between_threads_aborts_one_transaction() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table t (id integer); create table u (id integer); \
+             insert into t (id) values (0); insert into u (id) values (0)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        database.set_lock_wait_timeout(std::time::Duration::from_millis(1000));
+        let barrier = std::sync::Barrier::new(2);
+
+        // 2つのthreadが互いのlockを待つ。transaction_2は遅れて待ち始めるので、
+        // 先に諦めるのはtransaction_1で、その後transaction_2が進める
+        let (result_1, result_2) = std::thread::scope(|scope| {
+            let handle_1 = scope.spawn(|| {
+                let transaction_1 = database.new_transaction(2);
+                database.execute("insert into t (id) values (1)", transaction_1.clone())?;
+                barrier.wait();
+                database.execute("insert into u (id) values (1)", transaction_1.clone())?;
+                transaction_1.borrow_mut().commit()?;
+                return Ok(());
+            });
+            let handle_2 = scope.spawn(|| {
+                let transaction_2 = database.new_transaction(3);
+                database.execute("insert into u (id) values (2)", transaction_2.clone())?;
+                barrier.wait();
+                std::thread::sleep(std::time::Duration::from_millis(500));
+                database.execute("insert into t (id) values (2)", transaction_2.clone())?;
+                transaction_2.borrow_mut().commit()?;
+                return Ok(());
+            });
+            let result_1: Result<(), DatabaseError> = handle_1.join().unwrap();
+            let result_2: Result<(), DatabaseError> = handle_2.join().unwrap();
+            (result_1, result_2)
+        });
+        assert!(matches!(
+            result_1,
+            Err(DatabaseError::TransactionAborted(_))
+        ));
+        result_2?;
+
+        // transaction_1の変更はrollbackされ、transaction_2の変更だけが残る
+        let transaction = database.new_transaction(4);
+        let result = database.execute("select id from t order by id", transaction.clone())?;
+        assert_eq!(
+            result,
+            QueryResult::Rows {
+                columns: vec!["id".to_string()],
+                rows: vec![
+                    vec![ConstantValue::Number(0)],
+                    vec![ConstantValue::Number(2)]
+                ],
+            }
+        );
+        let result = database.execute("select id from u order by id", transaction.clone())?;
+        assert_eq!(
+            result,
+            QueryResult::Rows {
+                columns: vec!["id".to_string()],
+                rows: vec![
+                    vec![ConstantValue::Number(0)],
+                    vec![ConstantValue::Number(2)]
+                ],
+            }
+        );
+        transaction.borrow_mut().commit()?;
+        assert_eq!(database.held_locks(), vec![]);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_reset_removes_previous_tables() -> Result<(), DatabaseError> {
+        // 同じdirectoryを使い回しても、resetすれば前の実行の影響を受けない
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::reset(directory_path)?;
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table items (id integer); insert into items (id) values (1)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+        assert_eq!(count_rows(&database, "items")?, 1);
+        drop(database);
+
+        // databaseが作ったfileだけを消し、それ以外のfileは残す
+        std::fs::write(directory_path.join("items.csv"), "id\n1\n").unwrap();
+        let database = Database::reset(directory_path)?;
+        assert!(directory_path.join("items.csv").exists());
+        let result = database.validate("select id from items".to_string());
+        assert!(matches!(result, Err(DatabaseError::ValueNotFound(_))));
+
+        // catalogは作り直されているので、同じ名前のtableをまた作れる
+        let transaction = database.new_transaction(1);
+        database.execute("create table items (id integer)", transaction.clone())?;
+        transaction.borrow_mut().commit()?;
+        assert_eq!(count_rows(&database, "items")?, 0);
+
+        return Ok(());
+    }
+
+    // commitしていない行をdiskに書き出したところでcrashしたことにする
+    fn crash_with_uncommitted_insert(directory_path: &Path) -> Result<(), DatabaseError> {
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table items (id integer); insert into items (id) values (1)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        database.execute("insert into items (id) values (2)", transaction.clone())?;
+        database.buffer_manager.lock().unwrap().flush_all(2);
+        return Ok(());
+    }
+
+    #[test]
+    fn test_recover_on_open() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        crash_with_uncommitted_insert(directory_path)?;
+
+        let database = Database::open(directory_path, DatabaseConfig::default())?;
+        assert_eq!(count_rows(&database, "items")?, 1);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_manual_recover() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        crash_with_uncommitted_insert(directory_path)?;
+
+        let database = Database::open(
+            directory_path,
+            DatabaseConfig {
+                recover_on_open: false,
+            },
+        )?;
+        // recoverするまではcommitしていない行も見える
+        assert_eq!(count_rows(&database, "items")?, 2);
+
+        // lockを持っているtransactionがあるうちはrecoverしない
+        let transaction = database.new_transaction(3);
+        database.execute("select id from items", transaction.clone())?;
+        let Err(DatabaseError::ActiveTransactions(error)) = database.recover() else {
+            panic!("expected recover to be refused");
+        };
+        assert_eq!(
+            error.to_string(),
+            "Cannot recover while transactions [3] are active"
+        );
+        transaction.borrow_mut().commit()?;
+
+        database.recover()?;
+        assert_eq!(count_rows(&database, "items")?, 1);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_open_returns_recover_error() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(1);
+        database.execute("create table items (id integer)", transaction.clone())?;
+        transaction.borrow_mut().commit()?;
+        drop(database);
+
+        // system_catalogが無いdatabaseはversionが違うので、recoverせずにエラーを返す
+        std::fs::remove_file(directory_path.join("system_catalog.tbl")).unwrap();
+        let result = Database::open(directory_path, DatabaseConfig::default());
+        assert!(matches!(result, Err(DatabaseError::IncompatibleVersion(_))));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_recover_table_with_block_size() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table big (id integer, name varchar(10)) with (block_size = 1024)",
+            transaction.clone(),
+        )?;
+        // 1つのblockに入りきらない行数にして、2つ目のblockにも書く
+        for id in 0..30 {
+            database.execute(
+                &format!("insert into big (id, name) values ({}, 'row{}')", id, id),
+                transaction.clone(),
+            )?;
+        }
+        transaction.borrow_mut().commit()?;
+
+        // commitしていない変更をdiskに書き出したところでcrashしたことにする
+        let transaction = database.new_transaction(2);
+        database.execute("update big set name = 'changed' where id >= 0", transaction.clone())?;
+        database.buffer_manager.lock().unwrap().flush_all(2);
+        drop(database);
+
+        // catalogのblock sizeで、どのblockも元の値に戻る
+        let database = Database::open(directory_path, DatabaseConfig::default())?;
+        let transaction = database.new_transaction(3);
+        let result =
+            database.execute("select id, name from big order by id", transaction.clone())?;
+        transaction.borrow_mut().commit()?;
+        let QueryResult::Rows { rows, .. } = result else {
+            panic!("Expected rows");
+        };
+        let expected = (0..30)
+            .map(|id| {
+                vec![
+                    ConstantValue::Number(id),
+                    ConstantValue::String(format!("row{}", id)),
+                ]
+            })
+            .collect::<Vec<Vec<ConstantValue>>>();
+        assert_eq!(rows, expected);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_execute_returns_query_result() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+
+        assert_eq!(
+            database.execute(
+                "create table t (id integer, name varchar(10))",
+                transaction.clone()
+            )?,
+            QueryResult::RowsAffected(0)
+        );
+        // 複数の文は最後の文の結果を返す
+        assert_eq!(
+            database.execute(
+                "insert into t (id, name) values (1, 'a'); \
+                 insert into t (id, name) values (2, 'b'); \
+                 insert into t (id, name) values (3, 'c')",
+                transaction.clone(),
+            )?,
+            QueryResult::RowsAffected(1)
+        );
+        assert_eq!(
+            database.execute("update t set name = 'z' where id > 1", transaction.clone())?,
+            QueryResult::RowsAffected(2)
+        );
+        assert_eq!(
+            database.execute("delete from t where id = 3", transaction.clone())?,
+            QueryResult::RowsAffected(1)
+        );
+        assert_eq!(
+            database.execute("select name, id from t where id < 10", transaction.clone())?,
+            QueryResult::Rows {
+                columns: vec!["name".to_string(), "id".to_string()],
+                rows: vec![
+                    vec![
+                        ConstantValue::String("a".to_string()),
+                        ConstantValue::Number(1)
+                    ],
+                    vec![
+                        ConstantValue::String("z".to_string()),
+                        ConstantValue::Number(2)
+                    ],
+                ],
+            }
+        );
+        assert_eq!(
+            database.execute("select count(id) from t where id > 5", transaction.clone())?,
+            QueryResult::Rows {
+                columns: vec!["count_id".to_string()],
+                rows: vec![vec![ConstantValue::Number(0)]],
+            }
+        );
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_run_script() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+
+        let script_path = directory_path.join("schema.sql");
+        std::fs::write(
+            &script_path,
+            "create table users (id integer, name varchar(10));\n\
+             create index users_id_index on users (id);\n\
+             insert into users (id, name) values (1, 'alice');\n\
+             insert into users (id, name) values (2, 'bob');\n",
+        )
+        .unwrap();
+        database.run_script(&script_path, database.new_transaction(1))?;
+
+        // 3つ目の文が失敗するので、前の2つの文もrollbackされる
+        let broken_script_path = directory_path.join("broken.sql");
+        std::fs::write(
+            &broken_script_path,
+            "create table orders (id integer);\n\
+             insert into users (id, name) values (3, 'carol');\n\
+             insert into missing_table (id) values (1);\n",
+        )
+        .unwrap();
+        let result = database.run_script(&broken_script_path, database.new_transaction(2));
+        assert!(matches!(result, Err(DatabaseError::ValueNotFound(_))));
+
+        let result = database.run_script(
+            &directory_path.join("none.sql"),
+            database.new_transaction(3),
+        );
+        assert!(matches!(result, Err(DatabaseError::FileAccess(_))));
+
+        let transaction = database.new_transaction(4);
+        assert_eq!(
+            database.execute("select name from users", transaction.clone())?,
+            QueryResult::Rows {
+                columns: vec!["name".to_string()],
+                rows: vec![
+                    vec![ConstantValue::String("alice".to_string())],
+                    vec![ConstantValue::String("bob".to_string())],
+                ],
+            }
+        );
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        assert!(!metadata_manager
+            .list_tables(false, transaction.clone())
+            .contains(&"orders".to_string()));
+        assert_eq!(metadata_manager.list_indexes(transaction.clone()).len(), 1);
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_begin_and_rollback() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table t (id integer); insert into t (id) values (1)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let parsed_sql_list = parse_sql("begin; commit; rollback".to_string()).unwrap();
+        assert!(matches!(
+            parsed_sql_list[..],
+            [ParsedSQL::Begin, ParsedSQL::Commit, ParsedSQL::Rollback]
+        ));
+
+        // rollbackするとbeginの後のinsertは残らない
+        database.execute(
+            "begin; insert into t (id) values (2); insert into t (id) values (3); rollback",
+            database.new_transaction(2),
+        )?;
+        database.execute(
+            "begin transaction; insert into t (id) values (4); commit",
+            database.new_transaction(3),
+        )?;
+
+        let transaction = database.new_transaction(4);
+        assert_eq!(
+            database.execute("select id from t", transaction.clone())?,
+            QueryResult::Rows {
+                columns: vec!["id".to_string()],
+                rows: vec![
+                    vec![ConstantValue::Number(1)],
+                    vec![ConstantValue::Number(4)]
+                ],
+            }
+        );
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_execute_json() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        assert_eq!(
+            database.execute_json(
+                "create table users (id integer, name varchar(20)); \
+                 insert into users (id, name) values (1, 'say \"hi\"'); \
+                 insert into users (id, name) values (2, null)",
+                transaction.clone(),
+            )?,
+            "{\"rows_affected\":1}"
+        );
+        assert_eq!(
+            database.execute_json("select id, name from users", transaction.clone())?,
+            "[{\"id\":1,\"name\":\"say \\\"hi\\\"\"},{\"id\":2,\"name\":null}]"
+        );
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_prepared_insert() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table users (id integer, name varchar(20))",
+            transaction.clone(),
+        )?;
+
+        let insert = database.prepare("insert into users (id, name) values (?, ?)")?;
+        assert_eq!(insert.parameter_count(), 2);
+        for (id, name) in [(1, Some("alice")), (2, None), (3, Some("it's ?"))] {
+            let name = match name {
+                Some(name) => ConstantValue::String(name.to_string()),
+                None => ConstantValue::Null,
+            };
+            assert_eq!(
+                insert.execute(&[ConstantValue::Number(id), name], transaction.clone())?,
+                QueryResult::RowsAffected(1)
+            );
+        }
+        assert!(matches!(
+            insert.execute(&[ConstantValue::Number(4)], transaction.clone()),
+            Err(DatabaseError::ParameterCountMismatch(_))
+        ));
+
+        // whereの中の ? も置き換わる
+        let select = database.prepare("select name from users where id > ? and id < ?")?;
+        assert_eq!(
+            select.execute(
+                &[ConstantValue::Number(1), ConstantValue::Number(4)],
+                transaction.clone()
+            )?,
+            QueryResult::Rows {
+                columns: vec!["name".to_string()],
+                rows: vec![
+                    vec![ConstantValue::Null],
+                    vec![ConstantValue::String("it's ?".to_string())]
+                ],
+            }
+        );
+        transaction.borrow_mut().commit()?;
+
+        // create viewの中には ? を書けない
+        let error = database
+            .prepare("create view v as select id from users where id = ?")
+            .unwrap_err();
+        let DatabaseError::Parse(error) = error else {
+            panic!("expected a parse error");
+        };
+        assert_eq!((error.get_line(), error.get_column()), (1, 50));
+
+        // prepareしていない文の ? は値が結びつかないのでparse errorにする
+        let transaction = database.new_transaction(2);
+        for sql in [
+            "insert into users (id, name) values (?, 'bob')",
+            "select name from users where id = ?",
+        ] {
+            let Err(DatabaseError::Parse(error)) = database.execute(sql, transaction.clone())
+            else {
+                panic!("expected a parse error");
+            };
+            assert_eq!(error.get_line(), 1);
+            assert!(matches!(
+                database.validate(sql.to_string()),
+                Err(DatabaseError::Parse(_))
+            ));
+        }
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_prepared_non_finite_float_is_null() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute("create table readings (reading float)", transaction.clone())?;
+
+        let insert = database.prepare("insert into readings (reading) values (?)")?;
+        for value in [f64::NAN, f64::INFINITY, 0.5] {
+            insert.execute(&[ConstantValue::Float(value)], transaction.clone())?;
+        }
+        assert_eq!(
+            database.execute(
+                "select reading from readings order by reading",
+                transaction.clone()
+            )?,
+            QueryResult::Rows {
+                columns: vec!["reading".to_string()],
+                rows: vec![
+                    vec![ConstantValue::Null],
+                    vec![ConstantValue::Null],
+                    vec![ConstantValue::Float(0.5)],
+                ],
+            }
+        );
+        transaction.borrow_mut().commit()?;
+        return Ok(());
+    }
+
+    #[test]
+    fn test_insert_values_count_and_type_are_checked() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table t (a integer, b varchar(5))",
+            transaction.clone(),
+        )?;
+
+        let result = database.execute("insert into t (a) values (1, 2)", transaction.clone());
+        assert!(matches!(result, Err(DatabaseError::ColumnCountMismatch(_))));
+        let result = database.execute("insert into t (a, b) values (1)", transaction.clone());
+        assert!(matches!(result, Err(DatabaseError::ColumnCountMismatch(_))));
+
+        let result = database.execute("insert into t (a) values ('one')", transaction.clone());
+        let Err(DatabaseError::TypeMismatch(error)) = result else {
+            panic!("expected TypeMismatch");
+        };
+        assert_eq!(
+            error.to_string(),
+            "Type mismatch for field 'a' of table 't'"
+        );
+        let result = database.execute("insert into t (b) values (1)", transaction.clone());
+        assert!(matches!(result, Err(DatabaseError::TypeMismatch(_))));
+
+        // どれも1行も書き込まれていない
+        assert_eq!(
+            database.execute("select a, b from t", transaction.clone())?,
+            QueryResult::Rows {
+                columns: vec!["a".to_string(), "b".to_string()],
+                rows: vec![],
+            }
+        );
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_too_long_string_is_rejected() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        // VARCHAR(2)には2 * 4byteまで入る
+        database.execute(
+            "create table t (a integer, b varchar(2)); \
+             create table source (b varchar(10)); \
+             insert into t (a, b) values (1, 'abcdefgh'); \
+             insert into source (b) values ('abcdefghi')",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        for sql in [
+            "insert into t (a, b) values (2, 'abcdefghi')",
+            "insert into t (a, b) values (2, '😀😀😀')",
+            "update t set b = 'abcdefghi' where a = 1",
+            "insert into t (b) select b from source",
+        ] {
+            let transaction = database.new_transaction(2);
+            let result = database.execute(sql, transaction.clone());
+            let Err(DatabaseError::ValueTooLong(error)) = result else {
+                panic!("expected ValueTooLong for {}", sql);
+            };
+            assert_eq!(
+                error.to_string(),
+                "Value for field 'b' of table 't' is longer than 8 bytes"
+            );
+            transaction.borrow_mut().rollback();
+        }
+
+        // 切り詰めた値も書き込まれていない
+        let transaction = database.new_transaction(3);
+        assert_eq!(
+            database.execute("select a, b from t", transaction.clone())?,
+            QueryResult::Rows {
+                columns: vec!["a".to_string(), "b".to_string()],
+                rows: vec![vec![
+                    ConstantValue::Number(1),
+                    ConstantValue::String("abcdefgh".to_string()),
+                ]],
+            }
+        );
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_insert_without_column_list() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table t (a integer, b varchar(5)); \
+             insert into t values (1, 'x'); \
+             insert into t select x, y from (values (2, 'y')) as v(x, y)",
+            transaction.clone(),
+        )?;
+
+        // 値はtableのfieldの数と合わなければならない
+        let result = database.execute("insert into t values (3)", transaction.clone());
+        let Err(DatabaseError::ColumnCountMismatch(error)) = result else {
+            panic!("expected ColumnCountMismatch");
+        };
+        assert_eq!(error.to_string(), "Table 't' expects 2 values but got 1");
+
+        assert_eq!(
+            database.execute("select a, b from t", transaction.clone())?,
+            QueryResult::Rows {
+                columns: vec!["a".to_string(), "b".to_string()],
+                rows: vec![
+                    vec![
+                        ConstantValue::Number(1),
+                        ConstantValue::String("x".to_string())
+                    ],
+                    vec![
+                        ConstantValue::Number(2),
+                        ConstantValue::String("y".to_string())
+                    ],
+                ],
+            }
+        );
+        transaction.borrow_mut().commit()?;
+
+        let result = database.validate("insert into t values ('z', 3)".to_string());
+        assert!(matches!(result, Err(DatabaseError::TypeMismatch(_))));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_not_null_and_default() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table t (a integer not null, b varchar(10) default 'x', \
+             c integer not null default 7, d decimal(5, 2) default 1.5); \
+             insert into t (a) values (1); \
+             insert into t (a, b, c, d) values (2, null, 3, 2.25)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        for sql in [
+            "insert into t (a, b) values (null, 'y')",
+            // defaultの無いnot nullのfieldは省略できない
+            "insert into t (b) values ('y')",
+            "insert into t values (3, 'y', null, 1.0)",
+            "update t set c = null where a = 1",
+        ] {
+            let transaction = database.new_transaction(2);
+            let result = database.execute(sql, transaction.clone());
+            let Err(DatabaseError::NotNullViolation(_)) = result else {
+                panic!("expected NotNullViolation for {}", sql);
+            };
+            transaction.borrow_mut().rollback();
+        }
+
+        let result = database.validate("insert into t (b) values ('y')".to_string());
+        let Err(DatabaseError::NotNullViolation(error)) = result else {
+            panic!("expected NotNullViolation");
+        };
+        assert_eq!(
+            error.to_string(),
+            "NULL value for not null field 'a' of table 't'"
+        );
+
+        // defaultはfieldの型に合わなければならない
+        let transaction = database.new_transaction(3);
+        let result = database.execute(
+            "create table bad (a integer default 'x')",
+            transaction.clone(),
+        );
+        assert!(matches!(result, Err(DatabaseError::TypeMismatch(_))));
+        transaction.borrow_mut().rollback();
+
+        // 開き直しても、catalogからnot nullとdefaultを読める
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(4);
+        database.execute("insert into t (a) values (3)", transaction.clone())?;
+        let result = database.execute("insert into t (b) values ('z')", transaction.clone());
+        assert!(matches!(result, Err(DatabaseError::NotNullViolation(_))));
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(5);
+        let default_row = |a: i32| {
+            vec![
+                ConstantValue::Number(a),
+                ConstantValue::String("x".to_string()),
+                ConstantValue::Number(7),
+                ConstantValue::Decimal {
+                    value: 150,
+                    scale: 2,
+                },
+            ]
+        };
+        assert_eq!(
+            database.execute("select a, b, c, d from t", transaction.clone())?,
+            QueryResult::Rows {
+                columns: vec![
+                    "a".to_string(),
+                    "b".to_string(),
+                    "c".to_string(),
+                    "d".to_string()
+                ],
+                rows: vec![
+                    default_row(1),
+                    vec![
+                        ConstantValue::Number(2),
+                        ConstantValue::Null,
+                        ConstantValue::Number(3),
+                        ConstantValue::Decimal {
+                            value: 225,
+                            scale: 2
+                        },
+                    ],
+                    default_row(3),
+                ],
+            }
+        );
+        transaction.borrow_mut().commit()?;
+
+        // 既存の行はNULLで読めてしまうので、defaultの無いnot nullのfieldは足せない
+        let transaction = database.new_transaction(6);
+        let result = database.execute(
+            "alter table t add column e integer not null",
+            transaction.clone(),
+        );
+        assert!(matches!(result, Err(DatabaseError::NotNullViolation(_))));
+        transaction.borrow_mut().rollback();
+
+        let transaction = database.new_transaction(7);
+        database.execute(
+            "alter table t add column e integer not null default 5; \
+             create table empty (a integer); \
+             alter table empty add column e integer not null",
+            transaction.clone(),
+        )?;
+        assert_eq!(
+            database.execute("select e from t where a = 2", transaction.clone())?,
+            QueryResult::Rows {
+                columns: vec!["e".to_string()],
+                rows: vec![vec![ConstantValue::Number(5)]],
+            }
+        );
+        transaction.borrow_mut().commit()?;
+
+        // drop tableでdefaultも消えるので、同じ名前で作り直したtableには残らない
+        let transaction = database.new_transaction(8);
+        database.execute("drop table t", transaction.clone())?;
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(9);
+        database.execute(
+            "create table t (a integer, b varchar(10)); insert into t (a) values (1)",
+            transaction.clone(),
+        )?;
+        assert_eq!(
+            database.execute("select b from t", transaction.clone())?,
+            QueryResult::Rows {
+                columns: vec!["b".to_string()],
+                rows: vec![vec![ConstantValue::Null]],
+            }
+        );
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_primary_key_and_unique() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table t (id integer primary key, email varchar(10) unique, name varchar(10)); \
+             insert into t (id, email, name) values (1, 'a@x', 'a'); \
+             insert into t (id, email, name) values (2, 'b@x', 'b'); \
+             insert into t (id, name) values (3, 'c'); \
+             insert into t (id, name) values (4, 'd')",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        // keyのfieldには自動でindexが作られる
+        let transaction = database.new_transaction(2);
+        let metadata_manager = MetadataManager::new(transaction.clone())?;
+        let indexes = metadata_manager.list_indexes(transaction.clone());
+        let indexed_fields = indexes
+            .iter()
+            .filter(|index| index.table_name == "t")
+            .map(|index| index.field_name.clone())
+            .collect::<Vec<String>>();
+        assert_eq!(indexed_fields, vec!["id".to_string(), "email".to_string()]);
+        transaction.borrow_mut().commit()?;
+
+        for sql in [
+            "insert into t (id, email, name) values (1, 'z@x', 'z')",
+            "insert into t (id, email, name) values (5, 'a@x', 'z')",
+            "update t set id = 2 where id = 1",
+            "update t set email = 'b@x' where id = 1",
+            // 2行を同じ値にする
+            "update t set id = 9 where id > 2",
+        ] {
+            let transaction = database.new_transaction(3);
+            let result = database.execute(sql, transaction.clone());
+            let Err(DatabaseError::UniqueConstraintViolation(_)) = result else {
+                panic!("expected UniqueConstraintViolation for {}", sql);
+            };
+            transaction.borrow_mut().rollback();
+        }
+
+        // primary keyはnot nullでもある
+        let transaction = database.new_transaction(4);
+        let result = database.execute("insert into t (name) values ('n')", transaction.clone());
+        assert!(matches!(result, Err(DatabaseError::NotNullViolation(_))));
+        transaction.borrow_mut().rollback();
+
+        // 2行以上あるtableにはunique fieldを足せない
+        let transaction = database.new_transaction(5);
+        let result = database.execute("alter table t add column code integer unique", transaction.clone());
+        let Err(DatabaseError::UniqueConstraintViolation(error)) = result else {
+            panic!("expected UniqueConstraintViolation");
+        };
+        assert_eq!(
+            error.to_string(),
+            "Duplicate value for unique index on table 't', field 'code'"
+        );
+        transaction.borrow_mut().rollback();
+
+        // 開き直しても重複は弾かれる。自分の行を同じ値に書き換えるのは重複ではない
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(6);
+        let result = database.execute(
+            "insert into t (id, email, name) values (2, 'y@x', 'y')",
+            transaction.clone(),
+        );
+        assert!(matches!(
+            result,
+            Err(DatabaseError::UniqueConstraintViolation(_))
+        ));
+        // 書き換える行が無ければ、既にある値にしても重複にならない
+        assert_eq!(
+            database.execute("update t set id = 2 where id = 99", transaction.clone())?,
+            QueryResult::RowsAffected(0)
+        );
+        database.execute(
+            "update t set id = 5 where id = 1; \
+             update t set email = 'b@x' where id = 2; \
+             insert into t (id, email, name) values (1, 'c@x', 'e')",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(7);
+        assert_eq!(
+            database.execute("select id from t where email = 'c@x'", transaction.clone())?,
+            QueryResult::Rows {
+                columns: vec!["id".to_string()],
+                rows: vec![vec![ConstantValue::Number(1)]],
+            }
+        );
+        assert_eq!(
+            database.execute("select id from t where email = 'a@x'", transaction.clone())?,
+            QueryResult::Rows {
+                columns: vec!["id".to_string()],
+                rows: vec![vec![ConstantValue::Number(5)]],
+            }
+        );
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_empty_string_is_not_null() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table t (id integer, name varchar(5)); \
+             insert into t (id, name) values (1, ''); \
+             insert into t (id, name) values (2, null); \
+             insert into t (id) values (3)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        // ''は長さ0の値として保存され、NULLとは区別される
+        let transaction = database.new_transaction(2);
+        assert_eq!(
+            database.execute(
+                "select id, name from t where name = ''",
+                transaction.clone()
+            )?,
+            QueryResult::Rows {
+                columns: vec!["id".to_string(), "name".to_string()],
+                rows: vec![vec![
+                    ConstantValue::Number(1),
+                    ConstantValue::String("".to_string())
+                ]],
+            }
+        );
+        // 書かなかったfieldもNULLとして読める
+        assert_eq!(
+            database.execute("select id from t where name is null", transaction.clone())?,
+            QueryResult::Rows {
+                columns: vec!["id".to_string()],
+                rows: vec![
+                    vec![ConstantValue::Number(2)],
+                    vec![ConstantValue::Number(3)]
+                ],
+            }
+        );
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_explain_does_not_run_subquery() -> Result<(), DatabaseError> {
+        let test_directory = TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table target (id integer); create table staging (id integer); \
+             insert into target (id) values (1); insert into target (id) values (2); \
+             insert into staging (id) values (2)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        // サブクエリのorder byは、scanをopenしたときにsortのrunをtemp tableに書き出す
+        let temp_files = || -> Vec<String> {
+            std::fs::read_dir(&directory_path_name)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .filter(|name| name.starts_with("temp_") && name.ends_with(".tbl"))
+                .collect()
+        };
+
+        database.validate(
+            "select id from target where id in (select id from staging order by id)".to_string(),
+        )?;
+        let transaction = database.new_transaction(2);
+        database.execute(
+            "explain select id from target where id in (select id from staging order by id)",
+            transaction.clone(),
+        )?;
+        database.execute(
+            "explain delete from target where id in (select id from staging order by id)",
+            transaction.clone(),
+        )?;
+        assert_eq!(temp_files(), Vec::<String>::new());
+
+        // 実行したときだけサブクエリが動く
+        assert_eq!(
+            database.execute(
+                "select id from target where id in (select id from staging order by id)",
+                transaction.clone(),
+            )?,
+            QueryResult::Rows {
+                columns: vec!["id".to_string()],
+                rows: vec![vec![ConstantValue::Number(2)]],
+            }
+        );
+        assert!(!temp_files().is_empty());
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
 }
@@ -5,6 +5,21 @@ pub enum DatabaseError {
     UniqueConstraintViolation(UniqueConstraintError),
     ValueNotFound(ValueNotFound),
     TableAlreadyExists(TableAlreadyExists),
+    FieldAlreadyExists(FieldAlreadyExists),
+    InvalidBlockSize(InvalidBlockSize),
+    SchemaTooLarge(SchemaTooLarge),
+    TooManyFields(TooManyFields),
+    TypeMismatch(TypeMismatch),
+    ColumnCountMismatch(ColumnCountMismatch),
+    ParameterCountMismatch(ParameterCountMismatch),
+    FileAccess(FileAccessError),
+    TransactionAborted(TransactionAborted),
+    Parse(ParseError),
+    IncompatibleVersion(IncompatibleVersion),
+    NotNullViolation(NotNullViolation),
+    UnsupportedStatement(UnsupportedStatement),
+    ValueTooLong(ValueTooLong),
+    ActiveTransactions(ActiveTransactions),
 }
 
 impl From<ValueNotFound> for DatabaseError {
@@ -19,6 +34,96 @@ impl From<TableAlreadyExists> for DatabaseError {
     }
 }
 
+impl From<FieldAlreadyExists> for DatabaseError {
+    fn from(e: FieldAlreadyExists) -> Self {
+        DatabaseError::FieldAlreadyExists(e)
+    }
+}
+
+impl From<InvalidBlockSize> for DatabaseError {
+    fn from(e: InvalidBlockSize) -> Self {
+        DatabaseError::InvalidBlockSize(e)
+    }
+}
+
+impl From<SchemaTooLarge> for DatabaseError {
+    fn from(e: SchemaTooLarge) -> Self {
+        DatabaseError::SchemaTooLarge(e)
+    }
+}
+
+impl From<TooManyFields> for DatabaseError {
+    fn from(e: TooManyFields) -> Self {
+        DatabaseError::TooManyFields(e)
+    }
+}
+
+impl From<TypeMismatch> for DatabaseError {
+    fn from(e: TypeMismatch) -> Self {
+        DatabaseError::TypeMismatch(e)
+    }
+}
+
+impl From<ColumnCountMismatch> for DatabaseError {
+    fn from(e: ColumnCountMismatch) -> Self {
+        DatabaseError::ColumnCountMismatch(e)
+    }
+}
+
+impl From<ParameterCountMismatch> for DatabaseError {
+    fn from(e: ParameterCountMismatch) -> Self {
+        DatabaseError::ParameterCountMismatch(e)
+    }
+}
+
+impl From<FileAccessError> for DatabaseError {
+    fn from(e: FileAccessError) -> Self {
+        DatabaseError::FileAccess(e)
+    }
+}
+
+impl From<TransactionAborted> for DatabaseError {
+    fn from(e: TransactionAborted) -> Self {
+        DatabaseError::TransactionAborted(e)
+    }
+}
+
+impl From<ParseError> for DatabaseError {
+    fn from(e: ParseError) -> Self {
+        DatabaseError::Parse(e)
+    }
+}
+
+impl From<IncompatibleVersion> for DatabaseError {
+    fn from(e: IncompatibleVersion) -> Self {
+        DatabaseError::IncompatibleVersion(e)
+    }
+}
+
+impl From<NotNullViolation> for DatabaseError {
+    fn from(e: NotNullViolation) -> Self {
+        DatabaseError::NotNullViolation(e)
+    }
+}
+
+impl From<UnsupportedStatement> for DatabaseError {
+    fn from(e: UnsupportedStatement) -> Self {
+        DatabaseError::UnsupportedStatement(e)
+    }
+}
+
+impl From<ValueTooLong> for DatabaseError {
+    fn from(e: ValueTooLong) -> Self {
+        DatabaseError::ValueTooLong(e)
+    }
+}
+
+impl From<ActiveTransactions> for DatabaseError {
+    fn from(e: ActiveTransactions) -> Self {
+        DatabaseError::ActiveTransactions(e)
+    }
+}
+
 impl From<UniqueConstraintError> for DatabaseError {
     fn from(e: UniqueConstraintError) -> Self {
         DatabaseError::UniqueConstraintViolation(e)
@@ -113,3 +218,531 @@ impl std::error::Error for TableAlreadyExists {
         }
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct FieldAlreadyExists {
+    table_name: String,
+    field_name: String,
+}
+
+impl FieldAlreadyExists {
+    pub fn new(table_name: String, field_name: String) -> Self {
+        FieldAlreadyExists {
+            table_name,
+            field_name,
+        }
+    }
+}
+
+impl fmt::Display for FieldAlreadyExists {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Field '{}' already exists in table '{}'",
+            self.field_name, self.table_name
+        )
+    }
+}
+
+impl std::error::Error for FieldAlreadyExists {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InvalidBlockSize {
+    table_name: String,
+    block_size: usize,
+    slot_size: usize,
+}
+
+impl InvalidBlockSize {
+    pub fn new(table_name: String, block_size: usize, slot_size: usize) -> Self {
+        InvalidBlockSize {
+            table_name,
+            block_size,
+            slot_size,
+        }
+    }
+}
+
+impl fmt::Display for InvalidBlockSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Block size {} of table '{}' is smaller than its record size {}",
+            self.block_size, self.table_name, self.slot_size
+        )
+    }
+}
+
+impl std::error::Error for InvalidBlockSize {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            _ => None,
+        }
+    }
+}
+
+// databaseのblock sizeに1行も入らないschemaのエラー
+#[derive(Debug, Clone)]
+pub struct SchemaTooLarge {
+    table_name: String,
+    slot_size: usize,
+    block_size: usize,
+}
+
+impl SchemaTooLarge {
+    pub fn new(table_name: String, slot_size: usize, block_size: usize) -> Self {
+        SchemaTooLarge {
+            table_name,
+            slot_size,
+            block_size,
+        }
+    }
+}
+
+impl fmt::Display for SchemaTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Record size {} of table '{}' is larger than the block size {}",
+            self.slot_size, self.table_name, self.block_size
+        )
+    }
+}
+
+impl std::error::Error for SchemaTooLarge {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TooManyFields {
+    table_name: String,
+    field_count: usize,
+    max_field_count: usize,
+}
+
+impl TooManyFields {
+    pub fn new(table_name: String, field_count: usize, max_field_count: usize) -> Self {
+        TooManyFields {
+            table_name,
+            field_count,
+            max_field_count,
+        }
+    }
+}
+
+impl fmt::Display for TooManyFields {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Table '{}' has {} fields, but at most {} are supported",
+            self.table_name, self.field_count, self.max_field_count
+        )
+    }
+}
+
+impl std::error::Error for TooManyFields {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeMismatch {
+    field: String,
+    table: String,
+}
+
+impl TypeMismatch {
+    pub fn new(field: String, table: String) -> Self {
+        TypeMismatch { field, table }
+    }
+}
+
+impl fmt::Display for TypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Type mismatch for field '{}' of table '{}'",
+            self.field, self.table
+        )
+    }
+}
+
+impl std::error::Error for TypeMismatch {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ColumnCountMismatch {
+    table: String,
+    expected: usize,
+    actual: usize,
+}
+
+impl ColumnCountMismatch {
+    pub fn new(table: String, expected: usize, actual: usize) -> Self {
+        ColumnCountMismatch {
+            table,
+            expected,
+            actual,
+        }
+    }
+}
+
+impl fmt::Display for ColumnCountMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Table '{}' expects {} values but got {}",
+            self.table, self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for ColumnCountMismatch {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            _ => None,
+        }
+    }
+}
+
+// prepareした文の ? の数と、executeに渡した値の数が合わない
+#[derive(Debug, Clone)]
+pub struct ParameterCountMismatch {
+    expected: usize,
+    actual: usize,
+}
+
+impl ParameterCountMismatch {
+    pub fn new(expected: usize, actual: usize) -> Self {
+        ParameterCountMismatch { expected, actual }
+    }
+}
+
+impl fmt::Display for ParameterCountMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Statement expects {} parameters but got {}",
+            self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for ParameterCountMismatch {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            _ => None,
+        }
+    }
+}
+
+// copyで読み書きするfileや、消せなかったtemp fileのエラー
+#[derive(Debug, Clone)]
+pub struct FileAccessError {
+    path: String,
+    message: String,
+}
+
+impl FileAccessError {
+    pub fn new(path: String, message: String) -> Self {
+        FileAccessError { path, message }
+    }
+}
+
+impl fmt::Display for FileAccessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Cannot access file '{}': {}", self.path, self.message)
+    }
+}
+
+impl std::error::Error for FileAccessError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            _ => None,
+        }
+    }
+}
+
+// lockを待ちきれなかったときにConcurrencyManagerV2が返す
+#[derive(Debug, Clone)]
+pub struct LockAbortError {
+    file_name: String,
+    block_number: u64,
+}
+
+impl LockAbortError {
+    pub fn new(file_name: String, block_number: u64) -> Self {
+        LockAbortError {
+            file_name,
+            block_number,
+        }
+    }
+}
+
+impl fmt::Display for LockAbortError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Lock wait timed out on block {} of '{}'",
+            self.block_number, self.file_name
+        )
+    }
+}
+
+impl std::error::Error for LockAbortError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            _ => None,
+        }
+    }
+}
+
+// transactionはrollbackされたので、statementではなくtransaction全体をやり直す必要がある
+#[derive(Debug, Clone)]
+pub struct TransactionAborted {
+    transaction_id: i32,
+    reason: String,
+}
+
+impl TransactionAborted {
+    pub fn new(transaction_id: i32, reason: String) -> Self {
+        TransactionAborted {
+            transaction_id,
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for TransactionAborted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Transaction {} was aborted and rolled back: {}",
+            self.transaction_id, self.reason
+        )
+    }
+}
+
+impl std::error::Error for TransactionAborted {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            _ => None,
+        }
+    }
+}
+
+// sqlの文法が間違っているときのerror。位置は1から数える
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl ParseError {
+    pub fn new(line: usize, column: usize, message: String) -> Self {
+        ParseError {
+            line,
+            column,
+            message,
+        }
+    }
+
+    pub fn get_line(&self) -> usize {
+        self.line
+    }
+
+    pub fn get_column(&self) -> usize {
+        self.column
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Parse error at line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            _ => None,
+        }
+    }
+}
+
+// system_catalogに記録されたformatのversionが、このbinaryのversionと違う
+#[derive(Debug, Clone)]
+pub struct IncompatibleVersion {
+    found: i32,
+    expected: i32,
+}
+
+impl IncompatibleVersion {
+    pub fn new(found: i32, expected: i32) -> Self {
+        IncompatibleVersion { found, expected }
+    }
+}
+
+impl fmt::Display for IncompatibleVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Database format version {} is not supported (expected version {})",
+            self.found, self.expected
+        )
+    }
+}
+
+impl std::error::Error for IncompatibleVersion {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            _ => None,
+        }
+    }
+}
+
+// not nullのfieldにNULLを書こうとした
+#[derive(Debug, Clone)]
+pub struct NotNullViolation {
+    field: String,
+    table: String,
+}
+
+impl NotNullViolation {
+    pub fn new(field: String, table: String) -> Self {
+        NotNullViolation { field, table }
+    }
+}
+
+impl fmt::Display for NotNullViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "NULL value for not null field '{}' of table '{}'",
+            self.field, self.table
+        )
+    }
+}
+
+impl std::error::Error for NotNullViolation {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            _ => None,
+        }
+    }
+}
+
+// Database::executeでは実行できない文
+#[derive(Debug, Clone)]
+pub struct UnsupportedStatement {
+    statement: String,
+}
+
+impl UnsupportedStatement {
+    pub fn new(statement: String) -> Self {
+        UnsupportedStatement { statement }
+    }
+}
+
+impl fmt::Display for UnsupportedStatement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' is not supported here", self.statement)
+    }
+}
+
+impl std::error::Error for UnsupportedStatement {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            _ => None,
+        }
+    }
+}
+
+// VARCHAR(n)のfieldに確保したbyte数より長い文字列を書こうとした
+#[derive(Debug, Clone)]
+pub struct ValueTooLong {
+    field: String,
+    table: String,
+    max_bytes: usize,
+}
+
+impl ValueTooLong {
+    pub fn new(field: String, table: String, max_bytes: usize) -> Self {
+        ValueTooLong {
+            field,
+            table,
+            max_bytes,
+        }
+    }
+}
+
+impl fmt::Display for ValueTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Value for field '{}' of table '{}' is longer than {} bytes",
+            self.field, self.table, self.max_bytes
+        )
+    }
+}
+
+impl std::error::Error for ValueTooLong {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            _ => None,
+        }
+    }
+}
+
+// 他のtransactionがlockを持っている間はrecoverできない
+#[derive(Debug, Clone)]
+pub struct ActiveTransactions {
+    transaction_ids: Vec<i32>,
+}
+
+impl ActiveTransactions {
+    pub fn new(transaction_ids: Vec<i32>) -> Self {
+        ActiveTransactions { transaction_ids }
+    }
+}
+
+impl fmt::Display for ActiveTransactions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Cannot recover while transactions {:?} are active",
+            self.transaction_ids
+        )
+    }
+}
+
+impl std::error::Error for ActiveTransactions {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            _ => None,
+        }
+    }
+}
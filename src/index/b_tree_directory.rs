@@ -39,10 +39,12 @@ impl BTreeDirectory {
     fn find_child_block(&mut self, search_key: Constant) -> BlockId {
         let mut slot = self.contents.find_slot_before(search_key.clone());
 
-        if self
-            .contents
-            .get_data_value(slot as usize)
-            .equals(search_key.value.clone())
+        // 次のentryのkeyと等しければ、そのkeyから始まる右側のblockに入っている
+        if slot + 1 < self.contents.get_number_of_records()
+            && self
+                .contents
+                .get_data_value((slot + 1) as usize)
+                .equals(search_key.value.clone())
         {
             slot += 1;
         }
@@ -1,4 +1,5 @@
 use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::rc::Rc;
 
 use crate::index::b_tree_directory::BTreeDirectory;
@@ -16,10 +17,28 @@ pub struct BTreeIndex {
     directory_layout: Layout,
     leaf_layout: Layout,
     leaf: Option<BTreeLeaf>,
+    range: Option<BTreeRange>,
     root_block_id: BlockId,
 }
 
+// before_first_rangeで読んでいる途中の位置
+struct BTreeRange {
+    low: Option<Constant>,
+    high: Option<Constant>,
+    // まだ読んでいないleafのblock番号。key順の逆に並べて後ろからpopする
+    leaf_block_numbers: Vec<u64>,
+    page: Option<BTreePage>,
+    slot: i32,
+}
+
 impl BTreeIndex {
+    pub fn file_names(index_name: &str) -> Vec<String> {
+        return vec![
+            format!("{}_leaf", index_name),
+            format!("{}_directory", index_name),
+        ];
+    }
+
     pub fn new(
         transaction: Rc<RefCell<TransactionV2>>,
         index_name: String,
@@ -66,7 +85,13 @@ impl BTreeIndex {
 
             let min_value = match field_type {
                 TableFieldType::INTEGER => Constant::new(ConstantValue::Number(i32::MIN)),
+                TableFieldType::DECIMAL(_, scale) => Constant::new(ConstantValue::Decimal {
+                    value: i32::MIN,
+                    scale: scale as u32,
+                }),
                 TableFieldType::VARCHAR => Constant::new(ConstantValue::String(String::new())),
+                TableFieldType::FLOAT => Constant::new(ConstantValue::Float(f64::NEG_INFINITY)),
+                TableFieldType::DATE => Constant::new(ConstantValue::Date(i64::MIN)),
             };
 
             let directory_entry = DirectoryEntry {
@@ -83,6 +108,7 @@ impl BTreeIndex {
             directory_layout,
             leaf_layout,
             leaf: None,
+            range: None,
             root_block_id,
         }
     }
@@ -115,7 +141,117 @@ impl BTreeIndex {
         ))
     }
 
+    /// `low <= key <= high` のレコードを key 順に読む。`None` の側は制限しない。
+    /// NULL の key はどの範囲にも入らない。
+    pub fn before_first_range(&mut self, low: Option<Constant>, high: Option<Constant>) {
+        self.close();
+        let mut leaf_block_numbers = vec![];
+        self.collect_leaf_block_numbers(self.root_block_id.clone(), &mut leaf_block_numbers);
+
+        // lowを含むleafより前は読まなくてよい
+        if let Some(low) = &low {
+            let mut btree_root = BTreeDirectory::new(
+                self.transaction.clone(),
+                self.directory_layout.clone(),
+                self.root_block_id.clone(),
+            );
+            let block_number = btree_root.search(low.clone());
+            btree_root.close();
+            if let Some(position) = leaf_block_numbers.iter().position(|b| *b == block_number) {
+                leaf_block_numbers.drain(..position);
+            }
+        }
+        leaf_block_numbers.reverse();
+
+        self.range = Some(BTreeRange {
+            low,
+            high,
+            leaf_block_numbers,
+            page: None,
+            slot: -1,
+        });
+    }
+
+    // directoryをkey順にたどって、leafのblock番号を並べる
+    fn collect_leaf_block_numbers(&self, block_id: BlockId, leaf_block_numbers: &mut Vec<u64>) {
+        let mut page = BTreePage::new(
+            self.transaction.clone(),
+            block_id.clone(),
+            self.directory_layout.clone(),
+        );
+        let level = page.get_flag();
+        let child_block_numbers: Vec<u64> = (0..page.get_number_of_records() as usize)
+            .map(|slot| page.get_child_number(slot) as u64)
+            .collect();
+        page.close();
+
+        for child_block_number in child_block_numbers {
+            if level == 0 {
+                leaf_block_numbers.push(child_block_number);
+            } else {
+                let child_block_id =
+                    BlockId::new(block_id.get_file_name().clone(), child_block_number);
+                self.collect_leaf_block_numbers(child_block_id, leaf_block_numbers);
+            }
+        }
+    }
+
+    fn next_in_range(&mut self) -> bool {
+        let Some(range) = &mut self.range else {
+            return false;
+        };
+        loop {
+            if range.page.is_none() {
+                let Some(block_number) = range.leaf_block_numbers.pop() else {
+                    return false;
+                };
+                let leaf_block_id = BlockId::new(self.leaf_table_name.clone(), block_number);
+                range.page = Some(BTreePage::new(
+                    self.transaction.clone(),
+                    leaf_block_id,
+                    self.leaf_layout.clone(),
+                ));
+                range.slot = -1;
+            }
+            let page = range.page.as_mut().unwrap();
+
+            range.slot += 1;
+            if range.slot >= page.get_number_of_records() {
+                // flagが0以上ならoverflow blockに同じkeyの続きが入っている
+                let flag = page.get_flag();
+                page.close();
+                range.page = None;
+                if flag >= 0 {
+                    range.leaf_block_numbers.push(flag as u64);
+                }
+                continue;
+            }
+
+            let data_value = page.get_data_value(range.slot as usize);
+            if data_value.value == ConstantValue::Null {
+                continue;
+            }
+            if let Some(low) = &range.low {
+                if data_value.compare_to(low.value.clone()) == Ordering::Less {
+                    continue;
+                }
+            }
+            if let Some(high) = &range.high {
+                if data_value.compare_to(high.value.clone()) == Ordering::Greater {
+                    page.close();
+                    range.page = None;
+                    range.leaf_block_numbers.clear();
+                    return false;
+                }
+            }
+            return true;
+        }
+    }
+
     pub fn next(&mut self) -> bool {
+        if self.range.is_some() {
+            return self.next_in_range();
+        }
         if let Some(leaf) = &mut self.leaf {
             return leaf.next();
         }
@@ -127,15 +263,49 @@ impl BTreeIndex {
             leaf.close();
         }
         self.leaf = None;
+        if let Some(range) = &mut self.range {
+            if let Some(page) = &mut range.page {
+                page.close();
+            }
+        }
+        self.range = None;
     }
 
     pub fn get_data_record_id(&mut self) -> Option<RecordID> {
+        if let Some(range) = &self.range {
+            let page = range.page.as_ref()?;
+            return Some(page.get_data_record_id(range.slot as usize));
+        }
         if let Some(leaf) = &mut self.leaf {
             return Some(leaf.get_data_record_id());
         }
         None
     }
 
+    // before_first_rangeで読んでいるentryのkey
+    pub fn get_data_value(&self) -> Option<Constant> {
+        let range = self.range.as_ref()?;
+        let page = range.page.as_ref()?;
+        return Some(page.get_data_value(range.slot as usize));
+    }
+
+    // entryをすべて消す。blockは残るので、fileは小さくならない
+    pub fn delete_all(&mut self) {
+        self.before_first_range(None, None);
+        let mut entries = vec![];
+        while self.next() {
+            entries.push((
+                self.get_data_value().unwrap(),
+                self.get_data_record_id().unwrap(),
+            ));
+        }
+        self.close();
+
+        for (data_value, data_record_id) in entries.into_iter() {
+            self.delete(data_value, data_record_id);
+        }
+    }
+
     pub fn insert(&mut self, data_value: Constant, data_record_id: RecordID) {
         let leaf = self.position_at(data_value.clone());
         let optional_directory_entry = leaf.insert(data_record_id);
@@ -231,7 +401,133 @@ impl BTreeIndex {
 fn format_constant(constant: &Constant) -> String {
     match &constant.value {
         ConstantValue::Number(n) => n.to_string(),
+        ConstantValue::Decimal { value, scale } => Constant::format_decimal(*value, *scale),
+        ConstantValue::Float(f) => f.to_string(),
+        ConstantValue::Date(days) => Constant::format_date(*days),
         ConstantValue::String(s) => format!("\"{}\"", s),
+        ConstantValue::Parameter(_) => "?".to_string(),
         ConstantValue::Null => "NULL".to_string(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        database::Database, error::DatabaseError, metadata::index_manager::IndexInfo,
+        metadata::metadata_manager::MetadataManager, query::plan_v2::TablePlanV2,
+        query::predicate::TableNameAndFieldName, record::scan_v2::ScanV2,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_search_duplicate_and_separator_keys() {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+
+        let mut table_schema = TableSchema::new();
+        table_schema.add_integer_field("A".to_string());
+        let leaf_layout = IndexInfo::create_index_layout(&table_schema, "A".to_string());
+        let mut index = BTreeIndex::new(transaction.clone(), "t_a_index".to_string(), leaf_layout);
+
+        // leafが何回かsplitして、いくつかのkeyはdirectoryのkeyになる
+        for i in 0..100 {
+            index.insert(
+                Constant::new(ConstantValue::Number(i)),
+                RecordID::new(0, i),
+            );
+        }
+        for i in 0..2 {
+            index.insert(
+                Constant::new(ConstantValue::Number(5)),
+                RecordID::new(1, i),
+            );
+        }
+
+        let mut count_key = |key: i32| -> i32 {
+            index.before_first(Constant::new(ConstantValue::Number(key)));
+            let mut count = 0;
+            while index.next() {
+                count += 1;
+            }
+            return count;
+        };
+        for key in 0..100 {
+            let expected = if key == 5 { 3 } else { 1 };
+            assert_eq!(count_key(key), expected, "key {}", key);
+        }
+        assert_eq!(count_key(100), 0);
+
+        index.close();
+        transaction.borrow_mut().commit().unwrap();
+    }
+
+    #[test]
+    fn test_before_first_range() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table t (A integer, B varchar(10)); create index t_a_index on t (A)",
+            transaction.clone(),
+        )?;
+        // leafが何回かsplitするように、順番をばらばらにして入れる
+        for i in 0..120 {
+            database.execute(
+                &format!("insert into t (A, B) values ({}, 'x')", (i * 37) % 120),
+                transaction.clone(),
+            )?;
+        }
+        database.execute(
+            "insert into t (A, B) values (null, 'x')",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let mut index_infos =
+            metadata_manager.get_index_info("t".to_string(), transaction.clone())?;
+        let mut index = index_infos.get_mut("A").unwrap().open();
+        let table_plan =
+            TablePlanV2::new("t".to_string(), transaction.clone(), &mut metadata_manager)?;
+        let mut table_scan = table_plan.open_table_scan();
+
+        let mut range = |low: Option<i32>, high: Option<i32>| -> Vec<i32> {
+            let constant = |n: i32| Constant::new(ConstantValue::Number(n));
+            index.before_first_range(low.map(constant), high.map(constant));
+            let mut values = vec![];
+            while index.next() {
+                table_scan.move_to_record_id(index.get_data_record_id().unwrap());
+                values.push(
+                    table_scan
+                        .get_integer(TableNameAndFieldName::new(None, "A".to_string()))
+                        .unwrap(),
+                );
+            }
+            return values;
+        };
+
+        assert_eq!(range(Some(20), Some(45)), (20..=45).collect::<Vec<i32>>());
+        assert_eq!(range(None, Some(3)), vec![0, 1, 2, 3]);
+        assert_eq!(range(Some(115), None), vec![115, 116, 117, 118, 119]);
+        // NULLは入らない
+        assert_eq!(range(None, None), (0..120).collect::<Vec<i32>>());
+        assert_eq!(range(Some(50), Some(40)), Vec::<i32>::new());
+
+        // 範囲scanの後でも等しいkeyの検索ができる
+        index.before_first(Constant::new(ConstantValue::Number(7)));
+        assert!(index.next());
+        assert!(!index.next());
+        index.close();
+        table_scan.close();
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+}
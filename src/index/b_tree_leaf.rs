@@ -19,6 +19,7 @@ pub struct BTreeLeaf {
     search_key: Constant,
     contents: BTreePage,
     current_slot: i32,
+    file_name: String,
 }
 
 impl BTreeLeaf {
@@ -28,6 +29,7 @@ impl BTreeLeaf {
         search_key: Constant,
         block_id: BlockId,
     ) -> BTreeLeaf {
+        let file_name = block_id.get_file_name().clone();
         let contents = BTreePage::new(transaction.clone(), block_id, layout.clone());
 
         let current_slot = contents.find_slot_before(search_key.clone());
@@ -38,6 +40,7 @@ impl BTreeLeaf {
             search_key,
             contents,
             current_slot,
+            file_name,
         }
     }
 
@@ -77,10 +80,24 @@ impl BTreeLeaf {
         }
     }
 
+    // 同じkeyが1つのblockに入りきらないときは、flagにoverflow blockの番号が入っている
     fn try_overflow(&mut self) -> bool {
-        // TODO: Handle overflow by checking if there is a directory entry for the next block and
-        // moving to it if necessary
-        true
+        let flag = self.contents.get_flag();
+        if flag < 0 || self.contents.get_number_of_records() == 0 {
+            return false;
+        }
+
+        let first_key = self.contents.get_data_value(0);
+        if !first_key.equals(self.search_key.value.clone()) {
+            return false;
+        }
+
+        self.contents.close();
+        let next_block_id = BlockId::new(self.file_name.clone(), flag as u64);
+        self.contents =
+            BTreePage::new(self.transaction.clone(), next_block_id, self.layout.clone());
+        self.current_slot = 0;
+        return true;
     }
 
     pub fn insert(&mut self, record_id: RecordID) -> Option<DirectoryEntry> {
@@ -140,6 +140,17 @@ impl BTreePage {
                 };
                 self.set_integer(slot, field_name, integer_value);
             }
+            TableFieldType::DECIMAL(_, scale) => {
+                let decimal_value = match value.value {
+                    ConstantValue::Decimal { value, scale: from } => {
+                        Constant::rescale_decimal(value, from, scale as u32)
+                    }
+                    ConstantValue::Number(num) => Constant::rescale_decimal(num, 0, scale as u32),
+                    _ => panic!("Expected a number for DECIMAL field"),
+                };
+                let decimal_value = decimal_value.expect("Decimal value overflows index field");
+                self.set_integer(slot, field_name, decimal_value);
+            }
             TableFieldType::VARCHAR => {
                 let string_value = match value.value {
                     ConstantValue::String(s) => s,
@@ -147,6 +158,23 @@ impl BTreePage {
                 };
                 self.set_string(slot, field_name, string_value);
             }
+            TableFieldType::FLOAT => {
+                let float_value = Constant::float_value(&value.value)
+                    .unwrap_or_else(|| panic!("Expected a number for FLOAT field"));
+                self.set_float(slot, field_name, float_value);
+            }
+            TableFieldType::DATE => {
+                let ConstantValue::Date(days) = value.value else {
+                    panic!("Expected a date for DATE field");
+                };
+                let position = self.field_position(slot, field_name);
+                self.transaction.borrow_mut().set_long(
+                    self.current_block.clone(),
+                    position,
+                    days,
+                    true,
+                );
+            }
         }
     }
 
@@ -160,6 +188,16 @@ impl BTreePage {
         );
     }
 
+    fn set_float(&mut self, slot: usize, field_name: &str, float_value: f64) {
+        let position = self.field_position(slot, field_name);
+        self.transaction.borrow_mut().set_float(
+            self.current_block.clone(),
+            position,
+            float_value,
+            true,
+        );
+    }
+
     fn set_string(&mut self, slot: usize, field_name: &str, string_value: String) {
         let position = self.field_position(slot, field_name);
         self.transaction.borrow_mut().set_string(
@@ -241,7 +279,8 @@ impl BTreePage {
                 .get_field_type(field_name.to_string())
                 .expect("type must exist for a field listed by the schema");
             match field_type {
-                crate::record::record_page::TableFieldType::INTEGER => {
+                crate::record::record_page::TableFieldType::INTEGER
+                | crate::record::record_page::TableFieldType::DECIMAL(_, _) => {
                     self.transaction.borrow_mut().set_integer(
                         block_id.clone(),
                         position as usize + offset,
@@ -257,6 +296,22 @@ impl BTreePage {
                         false,
                     );
                 }
+                crate::record::record_page::TableFieldType::FLOAT => {
+                    self.transaction.borrow_mut().set_float(
+                        block_id.clone(),
+                        position as usize + offset,
+                        0.0,
+                        false,
+                    );
+                }
+                crate::record::record_page::TableFieldType::DATE => {
+                    self.transaction.borrow_mut().set_long(
+                        block_id.clone(),
+                        position as usize + offset,
+                        0,
+                        false,
+                    );
+                }
             }
         }
     }
@@ -296,10 +351,33 @@ impl BTreePage {
                     let value = self.get_integer(slot, field_name);
                     Constant::new(ConstantValue::Number(value))
                 }
+                crate::record::record_page::TableFieldType::DECIMAL(_, scale) => {
+                    let value = self.get_integer(slot, field_name);
+                    Constant::new(ConstantValue::Decimal {
+                        value,
+                        scale: scale as u32,
+                    })
+                }
                 crate::record::record_page::TableFieldType::VARCHAR => {
                     let value = self.get_string(slot, field_name);
                     Constant::new(ConstantValue::String(value))
                 }
+                crate::record::record_page::TableFieldType::FLOAT => {
+                    let position = self.field_position(slot, field_name);
+                    let value = self
+                        .transaction
+                        .borrow_mut()
+                        .get_float(self.current_block.clone(), position);
+                    Constant::new(ConstantValue::Float(value))
+                }
+                crate::record::record_page::TableFieldType::DATE => {
+                    let position = self.field_position(slot, field_name);
+                    let value = self
+                        .transaction
+                        .borrow_mut()
+                        .get_long(self.current_block.clone(), position);
+                    Constant::new(ConstantValue::Date(value))
+                }
             },
             None => panic!("Field not found in schema"),
         }
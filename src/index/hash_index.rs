@@ -1,8 +1,4 @@
-use std::{
-    cell::RefCell,
-    hash::{DefaultHasher, Hash, Hasher},
-    rc::Rc,
-};
+use std::{cell::RefCell, rc::Rc};
 
 use crate::{
     index::b_tree_index::BTreeIndex,
@@ -22,46 +18,123 @@ pub struct HashIndex {
     layout: Layout,
     search_key: Option<Constant>,
     number_of_buckets: usize,
+    hash_function: HashFunction,
     table_scan: Option<Box<dyn ScanV2>>,
 }
 
-fn calculate_hash<T: Hash>(t: &T) -> u64 {
-    let mut s = DefaultHasher::new();
-    t.hash(&mut s);
-    s.finish()
+// bucketの決め方はfileに残るので、build (Rustのversion) によって変わらないhashを使う
+// どのhashを使ったかはindex_catalogに名前で保存する
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashFunction {
+    // 64bit FNV-1a
+    Fnv1a,
+}
+
+impl HashFunction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashFunction::Fnv1a => "fnv1a",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<HashFunction> {
+        match name {
+            "fnv1a" => Some(HashFunction::Fnv1a),
+            _ => None,
+        }
+    }
+
+    pub fn hash_bytes(&self, bytes: &[u8]) -> u64 {
+        match self {
+            HashFunction::Fnv1a => {
+                let mut hash: u64 = 0xcbf29ce484222325;
+                for byte in bytes {
+                    hash ^= *byte as u64;
+                    hash = hash.wrapping_mul(0x100000001b3);
+                }
+                return hash;
+            }
+        }
+    }
+
+    // 整数はbig endianの4byte、文字列はUTF-8のbyte列をhashする
+    pub fn hash_value(&self, value: &ConstantValue) -> u64 {
+        match value {
+            ConstantValue::String(str) => self.hash_bytes(str.as_bytes()),
+            ConstantValue::Number(n) => self.hash_bytes(&n.to_be_bytes()),
+            // 1.5と1.50、2.0と2が同じbucketに入るように末尾の0を落としてからhashする
+            ConstantValue::Decimal { value, scale } => {
+                let (mut value, mut scale) = (*value, *scale);
+                while scale > 0 && value % 10 == 0 {
+                    value /= 10;
+                    scale -= 1;
+                }
+                if scale == 0 {
+                    self.hash_bytes(&value.to_be_bytes())
+                } else {
+                    let mut bytes = value.to_be_bytes().to_vec();
+                    bytes.extend_from_slice(&scale.to_be_bytes());
+                    self.hash_bytes(&bytes)
+                }
+            }
+            // 2.0と2が同じbucketに入るように、整数になるfloatは整数としてhashする
+            ConstantValue::Float(f) => {
+                if f.fract() == 0.0 && *f >= i32::MIN as f64 && *f <= i32::MAX as f64 {
+                    self.hash_bytes(&(*f as i32).to_be_bytes())
+                } else {
+                    self.hash_bytes(&f.to_bits().to_be_bytes())
+                }
+            }
+            ConstantValue::Date(days) => self.hash_bytes(&days.to_be_bytes()),
+            ConstantValue::Null => panic!("Null value cannot be hashed"),
+            ConstantValue::Parameter(index) => panic!("Parameter {} is not bound", index + 1),
+        }
+    }
 }
 
 impl HashIndex {
+    // create index ... with (buckets = N) が無いときのbucketの数
+    pub const DEFAULT_NUMBER_OF_BUCKETS: usize = 10;
+
+    // bucketごとのtableのfile
+    pub fn file_names(index_name: &str, number_of_buckets: usize) -> Vec<String> {
+        return (0..number_of_buckets)
+            .map(|bucket_index| format!("{}{}.tbl", index_name, bucket_index))
+            .collect();
+    }
+
     pub fn new(
         transaction: Rc<RefCell<TransactionV2>>,
         index_name: String,
         layout: Layout,
+        hash_function: HashFunction,
+        number_of_buckets: usize,
     ) -> Self {
         HashIndex {
             transaction,
             index_name,
             layout,
             search_key: None,
-            number_of_buckets: 10,
+            // 0個ではbucketを決められないので、少なくとも1個にする
+            number_of_buckets: number_of_buckets.max(1),
+            hash_function,
             table_scan: None,
         }
     }
 
+    pub fn bucket_of(&self, value: &ConstantValue) -> usize {
+        return (self.hash_function.hash_value(value) % self.number_of_buckets as u64) as usize;
+    }
+
     pub fn before_first(&mut self, search_key: Constant) {
         self.close();
         self.search_key = Some(search_key);
 
-        let hash_value = match &self.search_key {
-            Some(key) => match key.value {
-                ConstantValue::String(ref str) => calculate_hash(str),
-                ConstantValue::Number(n) => calculate_hash(&n),
-                ConstantValue::Null => panic!("Null value cannot be hashed"),
-            },
+        let bucket_index = match &self.search_key {
+            Some(key) => self.bucket_of(&key.value),
             None => panic!("Search key must be set before calling before_first"),
         };
 
-        let bucket_index = (hash_value as usize) % self.number_of_buckets;
-
         let table_name = format!("{}{}", self.index_name.clone(), bucket_index);
 
         let table_scan = TableScan::new(table_name, self.transaction.clone(), self.layout.clone());
@@ -141,7 +214,110 @@ impl HashIndex {
         // Logic to close the index
     }
 
-    pub fn get_search_cost(number_of_blocks: u32) -> u32 {
-        return number_of_blocks / 10;
+    pub fn get_search_cost(number_of_blocks: u32, number_of_buckets: usize) -> u32 {
+        return number_of_blocks / number_of_buckets.max(1) as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{database::Database, error::DatabaseError, metadata::metadata_manager::MetadataManager};
+
+    use super::*;
+
+    #[test]
+    fn test_hash_index_bucket_is_stable() {
+        // FNV-1aの公開されている値
+        assert_eq!(HashFunction::Fnv1a.hash_bytes(b""), 0xcbf29ce484222325);
+        assert_eq!(HashFunction::Fnv1a.hash_bytes(b"a"), 0xaf63dc4c8601ec8c);
+
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+
+        let mut schema = TableSchema::new();
+        schema.add_integer_field("data_value".to_string());
+        let index = HashIndex::new(
+            transaction.clone(),
+            "stable_idx".to_string(),
+            Layout::new(schema),
+            HashFunction::Fnv1a,
+            HashIndex::DEFAULT_NUMBER_OF_BUCKETS,
+        );
+
+        // 実行ごと、buildごとに同じbucketになる
+        assert_eq!(index.bucket_of(&ConstantValue::Number(1)), 0);
+        assert_eq!(index.bucket_of(&ConstantValue::Number(42)), 5);
+        assert_eq!(
+            index.bucket_of(&ConstantValue::String("alice".to_string())),
+            3
+        );
+        assert_eq!(
+            index.bucket_of(&ConstantValue::String("bob".to_string())),
+            2
+        );
+        assert_eq!(
+            index.bucket_of(&ConstantValue::Decimal {
+                value: 4200,
+                scale: 2
+            }),
+            index.bucket_of(&ConstantValue::Number(42))
+        );
+
+        assert_eq!(
+            HashFunction::from_name(HashFunction::Fnv1a.name()),
+            Some(HashFunction::Fnv1a)
+        );
+        transaction.borrow_mut().commit().unwrap();
+    }
+
+    #[test]
+    fn test_hash_index_with_custom_buckets() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table t (A integer, B varchar(10)); \
+             create index t_a_index on t (A) with (buckets = 3); \
+             create index t_b_index on t (B)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let metadata_manager = MetadataManager::new(transaction.clone())?;
+        let index_infos = metadata_manager.get_index_info("t".to_string(), transaction.clone())?;
+        assert_eq!(index_infos["A"].get_number_of_buckets(), 3);
+        assert_eq!(
+            index_infos["B"].get_number_of_buckets(),
+            HashIndex::DEFAULT_NUMBER_OF_BUCKETS
+        );
+
+        let mut index = index_infos["A"].open_hash_index();
+        for n in 0..30 {
+            assert!(index.bucket_of(&ConstantValue::Number(n)) < 3);
+            index.insert(
+                Constant::new(ConstantValue::Number(n)),
+                RecordID::new(n as u64, 0),
+            );
+        }
+        for n in [0, 7, 29] {
+            index.before_first(Constant::new(ConstantValue::Number(n)));
+            assert!(index.next()?);
+            assert_eq!(
+                index.get_data_record_id()?,
+                Some(RecordID::new(n as u64, 0))
+            );
+            assert!(!index.next()?);
+        }
+        index.close();
+        assert_eq!(HashIndex::file_names("t_a_index", 3).len(), 3);
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
     }
 }
@@ -25,14 +25,15 @@ use clap::Parser;
 use storage::page::Page;
 use crate::query::parser::parse_sql;
 
-use crate::database::Database;
+use crate::database::{Database, DatabaseConfig};
+use crate::error::DatabaseError;
+use crate::index::hash_index::HashIndex;
 use crate::query::index_update_planner::IndexUpdatePlanner;
 use crate::metadata::metadata_manager::MetadataManager;
-use crate::query::parser::{ParsedSQL, QueryData};
-use crate::query::plan_v2::{create_query_plan, execute_create_table};
-use crate::query::predicate::{ConstantValue, TableNameAndFieldName};
-use crate::query::predicate_v3::PredicateV2;
-use crate::query::query_handler::handle_select_query;
+use crate::query::parser::{ExplainFormat, ParsedSQL};
+use crate::query::predicate::ConstantValue;
+use crate::query::plan_v2::{create_modify_plan, create_query_plan, execute_create_table, PlanV2};
+use crate::query::query_handler::{export_csv, format_result_table, handle_select_query};
 use crate::tx::transaction_v2::TransactionV2;
 
 #[derive(Parser)]
@@ -42,6 +43,18 @@ struct Args {
     file: Option<String>,
 }
 
+fn print_plan(plan: &dyn PlanV2, format: &ExplainFormat) {
+    match format {
+        ExplainFormat::Text => {
+            println!("Query Plan:");
+            print!("{}", plan.explain());
+        }
+        ExplainFormat::Json => {
+            println!("{}", plan.get_child_plans().to_json());
+        }
+    }
+}
+
 fn handle_parsed_sql(
     parsed_sql: &ParsedSQL,
     metadata_manager: &mut MetadataManager,
@@ -52,7 +65,7 @@ fn handle_parsed_sql(
         ParsedSQL::Query(select_query) => {
             handle_select_query(select_query.clone(), metadata_manager, transaction.clone());
         }
-        ParsedSQL::Explain(query_data) => {
+        ParsedSQL::Explain { query_data, format } => {
             let table_exist =
                 metadata_manager.validate_select_sql(&query_data, transaction.clone());
 
@@ -64,9 +77,23 @@ fn handle_parsed_sql(
             let plan =
                 create_query_plan(&query_data, transaction.clone(), metadata_manager).unwrap();
 
-            let plan_tree = plan.get_child_plans();
-            println!("Query Plan:");
-            plan_tree.print_tree();
+            print_plan(plan.as_ref(), format);
+        }
+        ParsedSQL::ExplainDml { statement, format } => {
+            let (table_name, predicate) = match statement.as_ref() {
+                ParsedSQL::Delete(delete_data) => (&delete_data.table_name, &delete_data.predicate),
+                ParsedSQL::Update(update_data) => (&update_data.table_name, &update_data.predicate),
+                _ => panic!("Expected a Delete or Update variant in explain"),
+            };
+
+            let plan =
+                create_modify_plan(table_name, predicate, transaction.clone(), metadata_manager);
+            let Ok(plan) = plan else {
+                eprintln!("Error explaining statement: {:?}", plan.err());
+                return;
+            };
+
+            print_plan(plan.as_ref(), format);
         }
         ParsedSQL::Insert(insert_data) => {
             // execute_insert(transaction.clone(), metadata_manager, insert_data.clone());
@@ -76,12 +103,10 @@ fn handle_parsed_sql(
                 metadata_manager,
             );
 
-            let Ok(()) = result else {
+            let Ok(_) = result else {
                 eprintln!("Error executing insert: {:?}", result.err());
                 return;
             };
-
-            transaction.borrow_mut().commit();
         }
         ParsedSQL::Delete(delete_data) => {
             let result = index_update_planner.execute_delete(
@@ -90,12 +115,34 @@ fn handle_parsed_sql(
                 metadata_manager,
             );
 
-            let Ok(()) = result else {
+            let Ok(_) = result else {
                 eprintln!("Error executing delete: {:?}", result.err());
                 return;
             };
+        }
+        ParsedSQL::Copy(copy_data) => {
+            let result = index_update_planner.execute_copy(
+                copy_data.clone(),
+                transaction.clone(),
+                metadata_manager,
+            );
+
+            let Ok(count) = result else {
+                eprintln!("Error executing copy: {:?}", result.err());
+                return;
+            };
+
+            println!("COPY {}", count);
+        }
+        ParsedSQL::CopyTo(copy_to_data) => {
+            let result = export_csv(copy_to_data, metadata_manager, transaction.clone());
+
+            let Ok(count) = result else {
+                eprintln!("Error executing copy: {:?}", result.err());
+                return;
+            };
 
-            transaction.borrow_mut().commit();
+            println!("COPY {}", count);
         }
         ParsedSQL::CreateTable(create_table_data) => {
             let result = execute_create_table(
@@ -115,68 +162,91 @@ fn handle_parsed_sql(
                 transaction.clone(),
                 metadata_manager,
             );
-            let Ok(()) = result else {
+            let Ok(_) = result else {
                 eprintln!("Error executing update: {:?}", result.err());
                 return;
             };
         }
         ParsedSQL::DescribeTable { table_name } => {
-            let layout = metadata_manager
-                .get_layout(table_name.clone(), transaction.clone())
-                .unwrap();
+            let layout = metadata_manager.get_layout(table_name.clone(), transaction.clone());
+            let Ok(layout) = layout else {
+                eprintln!("Table '{}' does not exist", table_name);
+                return;
+            };
 
             println!("schema for table '{:?}'", layout.schema);
         }
+        ParsedSQL::DropTable { table_name } => {
+            let result = metadata_manager.drop_table(table_name.clone(), transaction.clone());
+            let Ok(()) = result else {
+                eprintln!("Error executing drop table: {:?}", result.err());
+                return;
+            };
+        }
+        ParsedSQL::Vacuum { table_name } => {
+            let result = metadata_manager.vacuum_table(table_name.clone(), transaction.clone());
+            let Ok(blocks) = result else {
+                eprintln!("Error executing vacuum: {:?}", result.err());
+                return;
+            };
+
+            println!("{} blocks reclaimed", blocks);
+        }
+        ParsedSQL::Reindex { table_name } => {
+            let result = index_update_planner.execute_reindex(
+                table_name.clone(),
+                transaction.clone(),
+                metadata_manager,
+            );
+            let Ok(count) = result else {
+                eprintln!("Error executing reindex: {:?}", result.err());
+                return;
+            };
+
+            println!("{} indexes rebuilt", count);
+        }
+        ParsedSQL::AlterTable(alter_table_data) => {
+            let result = metadata_manager.add_column(
+                alter_table_data.table_name.clone(),
+                alter_table_data.field_name.clone(),
+                alter_table_data.field_info.clone(),
+                transaction.clone(),
+            );
+            let Ok(()) = result else {
+                eprintln!("Error executing alter table: {:?}", result.err());
+                return;
+            };
+        }
         ParsedSQL::CreateIndex(create_index_data) => {
-            metadata_manager.create_index(
+            let result = metadata_manager.create_index_with_buckets(
                 create_index_data.index_name.clone(),
                 create_index_data.table_name.clone(),
                 create_index_data.field_name.clone(),
+                create_index_data
+                    .number_of_buckets
+                    .unwrap_or(HashIndex::DEFAULT_NUMBER_OF_BUCKETS),
                 transaction.clone(),
             );
+            let Ok(()) = result else {
+                eprintln!("Error executing create index: {:?}", result.err());
+                return;
+            };
         }
 
         ParsedSQL::ShowTables => {
-            let select_query = QueryData::new(
-                vec!["table_catalog".to_string()],
-                vec![TableNameAndFieldName::new(None, "table_name".to_string())],
-                PredicateV2::new(vec![]),
-                vec![],
-                vec![],
-                vec![],
-            );
-
-            let mut plan =
-                create_query_plan(&select_query, transaction.clone(), metadata_manager).unwrap();
-            let mut scan = plan.open().unwrap();
-            scan.move_to_before_first();
-            while scan.next().unwrap() {
-                let results = select_query
-                    .field_name_list
-                    .iter()
-                    .map(|field_name| {
-                        let value = scan.get_value(field_name.clone());
-                        return value;
-                    })
-                    .filter(|v| {
-                        if let Some(constant_value) = v {
-                            return match constant_value.clone() {
-                                ConstantValue::String(s) => {
-                                    s != "table_catalog"
-                                        && s != "field_catalog"
-                                        && s != "index_catalog"
-                                }
-                                _ => true,
-                            };
-                        }
-                        return false;
-                    })
-                    // 空になったvectorは除外
-                    .collect::<Vec<_>>();
-
-                if results.len() == 0 {
-                    continue;
-                }
+            let rows = metadata_manager
+                .list_tables(false, transaction.clone())
+                .into_iter()
+                .map(|table_name| vec![ConstantValue::String(table_name)])
+                .collect::<Vec<Vec<ConstantValue>>>();
+            println!("{}", format_result_table(&["table_name".to_string()], &rows));
+        }
+        ParsedSQL::ShowIndexes => {
+            for index in metadata_manager.list_indexes(transaction.clone()) {
+                println!(
+                    "{} on {} ({})",
+                    index.index_name, index.table_name, index.field_name
+                );
             }
         }
         _ => panic!("Expected a Query variant from parse_sql"),
@@ -184,28 +254,152 @@ fn handle_parsed_sql(
     // ここにParsedSQLを処理するコードを追加
 }
 
+// REPLで今使っているtransaction
+struct Session {
+    transaction: Rc<RefCell<TransactionV2>>,
+    transaction_id: i32,
+    // beginからcommit / rollbackまでの間はtrue
+    in_transaction: bool,
+    // \json onのときはselectの結果をjsonで表示する
+    json_output: bool,
+}
+
+impl Session {
+    // commitやrollbackの後は、同じtransactionを使い回さずに新しいものを始める
+    fn start_new_transaction(&mut self, database: &Database) {
+        self.transaction_id += 1;
+        self.transaction = database.new_transaction(self.transaction_id);
+    }
+
+    // lockを待ちきれなかった変更はcommitでrollbackされるので、エラーを表示して新しく始める
+    fn commit(&mut self, database: &Database) {
+        let result = self.transaction.borrow_mut().commit();
+        if let Err(e) = result {
+            eprintln!("{:?}", e);
+            self.start_new_transaction(database);
+            self.in_transaction = false;
+        }
+    }
+}
+
+// lockを待ちきれなかったらtransactionはrollbackされるので、新しく始め直す
+// beginで始めたtransactionの中ではcommitしない
+fn run_parsed_sql(
+    parsed_sql: &ParsedSQL,
+    metadata_manager: &mut MetadataManager,
+    index_update_planner: &mut IndexUpdatePlanner,
+    database: &Database,
+    session: &mut Session,
+) {
+    let transaction = session.transaction.clone();
+    let result = TransactionV2::abort_on_lock_failure(transaction.clone(), || {
+        handle_parsed_sql(
+            parsed_sql,
+            metadata_manager,
+            transaction.clone(),
+            index_update_planner,
+        );
+        return Ok(());
+    });
+    if let Err(e) = result {
+        eprintln!("{:?}", e);
+        session.start_new_transaction(database);
+        session.in_transaction = false;
+        return;
+    }
+    if !session.in_transaction {
+        session.commit(database);
+    }
+}
+
+// begin / commit / rollbackを処理したときはtrueを返す
+fn handle_transaction_control(
+    parsed_sql: &ParsedSQL,
+    database: &Database,
+    session: &mut Session,
+) -> bool {
+    match parsed_sql {
+        ParsedSQL::Begin => {
+            if session.in_transaction {
+                println!("A transaction is already in progress");
+                return true;
+            }
+            // それまでの文はcommit済みなので、rollbackで戻らないよう新しく始める
+            session.commit(database);
+            session.start_new_transaction(database);
+            session.in_transaction = true;
+        }
+        ParsedSQL::Commit | ParsedSQL::Rollback => {
+            if !session.in_transaction {
+                println!("No transaction in progress");
+                return true;
+            }
+            if let ParsedSQL::Commit = parsed_sql {
+                session.commit(database);
+            } else {
+                session.transaction.borrow_mut().rollback();
+            }
+            session.start_new_transaction(database);
+            session.in_transaction = false;
+        }
+        _ => return false,
+    }
+    return true;
+}
+
 fn main() -> std::io::Result<()> {
     let directory_path_name = format!("test_data_{}", uuid::Uuid::new_v4());
     let directory_path = Path::new(&directory_path_name);
-    let database = Database::new(directory_path);
+    // recoverできなかったときは、versionが違うときも含めてエラーを表示して終わる
+    let database = match Database::open(directory_path, DatabaseConfig::default()) {
+        Ok(database) => database,
+        Err(e) => {
+            eprintln!("{:?}", e);
+            return Ok(());
+        }
+    };
 
     let transaction = database.new_transaction(1);
-    let mut metadata_manager = Rc::new(RefCell::new(
-        MetadataManager::new(transaction.clone()).unwrap(),
-    ));
+    // catalogを読めなかったときは、lockを待ちきれなかったときも含めてエラーを表示して終わる
+    let metadata_manager = TransactionV2::abort_on_lock_failure(transaction.clone(), || {
+        return MetadataManager::new(transaction.clone());
+    });
+    let mut metadata_manager = match metadata_manager {
+        Ok(metadata_manager) => Rc::new(RefCell::new(metadata_manager)),
+        Err(e) => {
+            eprintln!("{:?}", e);
+            return Ok(());
+        }
+    };
+    let mut session = Session {
+        transaction,
+        transaction_id: 1,
+        in_transaction: false,
+        json_output: false,
+    };
 
     let mut index_update_planner = crate::query::index_update_planner::IndexUpdatePlanner::new();
     let args = Args::parse();
 
     if let Some(file_path) = args.file {
         let sql = std::fs::read_to_string(file_path).expect("Failed to read SQL file");
-        let parsed_sql_list = parse_sql(sql);
+        let parsed_sql_list = match parse_sql(sql) {
+            Ok(parsed_sql_list) => parsed_sql_list,
+            Err(e) => {
+                println!("{}", e);
+                return Ok(());
+            }
+        };
         for parsed_sql in &parsed_sql_list {
-            handle_parsed_sql(
+            if handle_transaction_control(parsed_sql, &database, &mut session) {
+                continue;
+            }
+            run_parsed_sql(
                 parsed_sql,
                 &mut metadata_manager.borrow_mut(),
-                transaction.clone(),
                 &mut index_update_planner,
+                &database,
+                &mut session,
             );
         }
         return Ok(());
@@ -237,7 +431,11 @@ fn main() -> std::io::Result<()> {
         "varchar".into(),
         "show".into(),
         "tables".into(),
+        "indexes".into(),
         "describe".into(),
+        "begin".into(),
+        "commit".into(),
+        "rollback".into(),
         "max".into(),
         "min".into(),
         "count".into(),
@@ -311,12 +509,77 @@ fn main() -> std::io::Result<()> {
                     continue;
                 }
 
-                let parsed_sql = parse_sql(buffer.to_string());
-                handle_parsed_sql(
+                if buffer == ".locks" {
+                    for (transaction_id, blocks) in database.held_locks() {
+                        println!("transaction {}:", transaction_id);
+                        for (block_id, mode) in blocks {
+                            println!("  {} {}", mode, block_id);
+                        }
+                    }
+                    continue;
+                }
+
+                if let Some(mode) = buffer.strip_prefix("\\json") {
+                    match mode.trim() {
+                        "on" => session.json_output = true,
+                        "off" => session.json_output = false,
+                        _ => eprintln!("Usage: \\json on|off"),
+                    }
+                    continue;
+                }
+
+                // \i file.sql でfileの文をまとめて1つのtransactionで実行する
+                if let Some(file_path) = buffer.strip_prefix("\\i ") {
+                    if session.in_transaction {
+                        eprintln!("Cannot run a script inside a transaction");
+                        continue;
+                    }
+                    // scriptが失敗してrollbackしても、それまでの文が戻らないようにする
+                    session.commit(&database);
+                    session.start_new_transaction(&database);
+                    let result = database
+                        .run_script(Path::new(file_path.trim()), session.transaction.clone());
+                    let Ok(_) = result else {
+                        eprintln!("Error running script: {:?}", result.err());
+                        continue;
+                    };
+                    println!("Script {} executed", file_path.trim());
+                    continue;
+                }
+
+                // 文法の間違いはpromptに戻って入力し直せるようにする
+                let parsed_sql = match parse_sql(buffer.to_string()) {
+                    Ok(parsed_sql) => parsed_sql,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                };
+                if handle_transaction_control(&parsed_sql[0], &database, &mut session) {
+                    continue;
+                }
+                if let (true, ParsedSQL::Query(_)) = (session.json_output, &parsed_sql[0]) {
+                    let result = database.execute_json(buffer, session.transaction.clone());
+                    match result {
+                        Ok(json) => println!("{}", json),
+                        Err(DatabaseError::TransactionAborted(e)) => {
+                            eprintln!("{:?}", e);
+                            session.start_new_transaction(&database);
+                            session.in_transaction = false;
+                        }
+                        Err(e) => eprintln!("Error executing query: {:?}", e),
+                    }
+                    if !session.in_transaction {
+                        session.commit(&database);
+                    }
+                    continue;
+                }
+                run_parsed_sql(
                     &parsed_sql[0],
                     &mut metadata_manager.borrow_mut(),
-                    transaction.clone(),
                     &mut index_update_planner,
+                    &database,
+                    &mut session,
                 );
             }
             Ok(Signal::CtrlC) => {
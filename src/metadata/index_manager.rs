@@ -2,6 +2,7 @@ use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use crate::{
     index::b_tree_index::BTreeIndex,
+    index::hash_index::{HashFunction, HashIndex},
     error::ValueNotFound,
     query::predicate::TableNameAndFieldName,
     record::record_page::{Layout, TableFieldType, TableSchema},
@@ -19,6 +20,9 @@ pub struct IndexManager {
 }
 
 impl IndexManager {
+    // index_catalogに書ける名前の長さ
+    pub const NAME_LENGTH: i32 = 20;
+
     pub fn new(
         table_manager: Rc<RefCell<TableManagerV2>>,
         stat_manager: Rc<RefCell<StatManagerV2>>,
@@ -29,11 +33,13 @@ impl IndexManager {
             .check_if_table_exists("index_catalog".to_string(), transaction.clone());
 
         if !table_exists {
-            let field_length = 20; // Example field length
+            let field_length = Self::NAME_LENGTH;
             let mut schema = TableSchema::new();
             schema.add_string_field("index_name".to_string(), field_length);
             schema.add_string_field("table_name".to_string(), field_length);
             schema.add_string_field("field_name".to_string(), field_length);
+            schema.add_string_field("hash_function".to_string(), field_length);
+            schema.add_integer_field("number_of_buckets".to_string());
             let _ = table_manager.borrow_mut().create_table(
                 "index_catalog".to_string(),
                 &schema,
@@ -58,6 +64,25 @@ impl IndexManager {
         table_name: String,
         field_name: String,
         transaction: Rc<RefCell<TransactionV2>>,
+    ) {
+        self.create_index_with_hash_function(
+            index_name,
+            table_name,
+            field_name,
+            HashFunction::Fnv1a,
+            HashIndex::DEFAULT_NUMBER_OF_BUCKETS,
+            transaction,
+        );
+    }
+
+    pub fn create_index_with_hash_function(
+        &mut self,
+        index_name: String,
+        table_name: String,
+        field_name: String,
+        hash_function: HashFunction,
+        number_of_buckets: usize,
+        transaction: Rc<RefCell<TransactionV2>>,
     ) {
         let mut table_scan = TableScan::new(
             "index_catalog".to_string(),
@@ -68,9 +93,78 @@ impl IndexManager {
         table_scan.set_string("index_name".to_string(), index_name.clone());
         table_scan.set_string("table_name".to_string(), table_name.clone());
         table_scan.set_string("field_name".to_string(), field_name.clone());
+        table_scan.set_string(
+            "hash_function".to_string(),
+            hash_function.name().to_string(),
+        );
+        table_scan.set_integer("number_of_buckets".to_string(), number_of_buckets as i32);
         table_scan.close();
+    }
+
+    // tableのindexをcatalogから消し、indexのfileはcommitのときに消す
+    pub fn drop_indexes(
+        &self,
+        table_name: String,
+        transaction: Rc<RefCell<TransactionV2>>,
+    ) -> Result<(), ValueNotFound> {
+        let mut table_scan = TableScan::new(
+            "index_catalog".to_string(),
+            transaction.clone(),
+            self.layout.clone(),
+        );
+
+        while table_scan.next()? {
+            let index_table_name =
+                table_scan.get_string(TableNameAndFieldName::new(None, "table_name".to_string()));
+            if index_table_name.as_ref() != Some(&table_name) {
+                continue;
+            }
+
+            let index_name =
+                table_scan.get_string(TableNameAndFieldName::new(None, "index_name".to_string()));
+            let number_of_buckets = read_number_of_buckets(&mut table_scan);
+            if let Some(index_name) = index_name {
+                for file_name in BTreeIndex::file_names(&index_name)
+                    .into_iter()
+                    .chain(HashIndex::file_names(&index_name, number_of_buckets))
+                {
+                    transaction.borrow_mut().register_dropped_file(file_name);
+                }
+            }
+            table_scan.delete();
+        }
+        table_scan.close();
+        return Ok(());
+    }
 
-        transaction.borrow_mut().commit();
+    pub fn list_indexes(&self, transaction: Rc<RefCell<TransactionV2>>) -> Vec<IndexCatalogEntry> {
+        let mut table_scan = TableScan::new(
+            "index_catalog".to_string(),
+            transaction.clone(),
+            self.layout.clone(),
+        );
+
+        let mut index_list = vec![];
+        while table_scan.next().unwrap() {
+            let index_name =
+                table_scan.get_string(TableNameAndFieldName::new(None, "index_name".to_string()));
+            let table_name =
+                table_scan.get_string(TableNameAndFieldName::new(None, "table_name".to_string()));
+            let field_name =
+                table_scan.get_string(TableNameAndFieldName::new(None, "field_name".to_string()));
+
+            if let (Some(index_name), Some(table_name), Some(field_name)) =
+                (index_name, table_name, field_name)
+            {
+                index_list.push(IndexCatalogEntry {
+                    index_name,
+                    table_name,
+                    field_name,
+                });
+            }
+        }
+        table_scan.close();
+        return index_list;
     }
 
     pub fn get_index_info(
@@ -96,6 +190,16 @@ impl IndexManager {
                 let field_name = table_scan
                     .get_string(TableNameAndFieldName::new(None, "field_name".to_string()))
                     .unwrap();
+                let hash_function_name = table_scan
+                    .get_string(TableNameAndFieldName::new(None, "hash_function".to_string()))
+                    .unwrap();
+                let Some(hash_function) = HashFunction::from_name(&hash_function_name) else {
+                    return Err(ValueNotFound::new(
+                        format!("hash function '{}'", hash_function_name),
+                        Some(index_name),
+                    ));
+                };
+                let number_of_buckets = read_number_of_buckets(&mut table_scan);
 
                 let layout = self
                     .table_manager
@@ -111,6 +215,8 @@ impl IndexManager {
                     field_name.clone(),
                     layout.schema.clone(),
                     stat_info,
+                    hash_function,
+                    number_of_buckets,
                     transaction.clone(),
                 );
                 field_name_index_info_map.insert(field_name.clone(), index_info);
@@ -122,12 +228,29 @@ impl IndexManager {
     }
 }
 
+// bucketの数を保存していなかった頃のcatalogの行はdefaultの数とみなす
+fn read_number_of_buckets(table_scan: &mut TableScan) -> usize {
+    return table_scan
+        .get_integer(TableNameAndFieldName::new(None, "number_of_buckets".to_string()))
+        .map(|number_of_buckets| number_of_buckets as usize)
+        .unwrap_or(HashIndex::DEFAULT_NUMBER_OF_BUCKETS);
+}
+
+// index_catalogの1行分
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexCatalogEntry {
+    pub index_name: String,
+    pub table_name: String,
+    pub field_name: String,
+}
+
 #[derive(Clone)]
 pub struct IndexInfo {
     index_name: String,
     field_name: String,
-    schema: TableSchema,
     stat_info: StatInfoV2,
+    hash_function: HashFunction,
+    number_of_buckets: usize,
     transaction: Rc<RefCell<TransactionV2>>,
     index_layout: Layout,
 }
@@ -138,6 +261,8 @@ impl IndexInfo {
         field_name: String,
         tableSchema: TableSchema,
         stat_info: StatInfoV2,
+        hash_function: HashFunction,
+        number_of_buckets: usize,
         transaction: Rc<RefCell<TransactionV2>>,
     ) -> Self {
         let index_layout = IndexInfo::create_index_layout(&tableSchema, field_name.clone());
@@ -145,37 +270,53 @@ impl IndexInfo {
         IndexInfo {
             index_name,
             field_name,
-            schema: tableSchema,
             index_layout,
             stat_info,
+            hash_function,
+            number_of_buckets,
             transaction,
         }
     }
 
-    pub fn open(&mut self) -> BTreeIndex {
-        self.schema = TableSchema::new();
+    pub fn get_hash_function(&self) -> HashFunction {
+        self.hash_function
+    }
 
+    pub fn get_number_of_buckets(&self) -> usize {
+        self.number_of_buckets
+    }
+
+    pub fn get_field_name(&self) -> String {
+        self.field_name.clone()
+    }
+
+    pub fn open(&self) -> BTreeIndex {
         BTreeIndex::new(
             self.transaction.clone(),
             self.index_name.clone(),
             self.index_layout.clone(),
         )
-        //
-        // HashIndex::new(
-        //     self.transaction.clone(),
-        //     self.index_name.clone(),
-        //     self.index_layout.clone(),
-        // )
-        //
+    }
+
+    // catalogに保存されたhash関数とbucketの数でbucketを決める
+    pub fn open_hash_index(&self) -> HashIndex {
+        HashIndex::new(
+            self.transaction.clone(),
+            self.index_name.clone(),
+            self.index_layout.clone(),
+            self.hash_function,
+            self.number_of_buckets,
+        )
     }
 
     pub fn blocks_accessed(&self) -> u32 {
-        let record_per_block =
-            self.transaction.borrow().get_block_size() as i32 / self.index_layout.get_slot_size();
+        let record_per_block = self
+            .index_layout
+            .records_per_block(self.transaction.borrow().get_block_size());
         let number_of_blocks = self.stat_info.get_num_records() / record_per_block as u32;
         BTreeIndex::search_cost(number_of_blocks as i32, record_per_block as i32) as u32
         //
-        // return HashIndex::get_search_cost(number_of_blocks as u32);
+        // return HashIndex::get_search_cost(number_of_blocks as u32, self.number_of_buckets);
         //
     }
 
@@ -207,9 +348,18 @@ impl IndexInfo {
                 TableFieldType::INTEGER => {
                     schema.add_integer_field("data_value".to_string());
                 }
+                TableFieldType::DECIMAL(precision, scale) => {
+                    schema.add_decimal_field("data_value".to_string(), precision, scale);
+                }
                 TableFieldType::VARCHAR => {
                     schema.add_string_field("data_value".to_string(), 20);
                 }
+                TableFieldType::FLOAT => {
+                    schema.add_float_field("data_value".to_string());
+                }
+                TableFieldType::DATE => {
+                    schema.add_date_field("data_value".to_string());
+                }
             },
             None => panic!("Field {} not found in table schema", field_name),
         }
@@ -232,11 +382,13 @@ mod tests {
         metadata::index_manager::IndexManager, storage::log_manager_v2::LogManagerV2, record::record_page::TableSchema,
         record::scan_v2::ScanV2, metadata::stat_manager_v2::StatManagerV2, storage::file_manager::FileManager,
         metadata::table_manager_v2::TableManagerV2, record::table_scan_v2::TableScan, tx::transaction_v2::TransactionV2,
+        index::hash_index::HashFunction,
     };
 
     #[test]
     fn test_index_mgr() {
-        let test_dir_name = format!("test_data_{}", uuid::Uuid::new_v4());
+        let test_directory = crate::database::TestDirectory::new();
+        let test_dir_name = test_directory.path_name();
         let test_dir = Path::new(&test_dir_name);
         let block_size = 400;
 
@@ -297,7 +449,7 @@ mod tests {
         table_scan.set_string("name".to_string(), "Alice4".to_string());
 
         table_scan.close();
-        transaction.borrow_mut().commit();
+        transaction.borrow_mut().commit().unwrap();
 
         let stat_manager = Rc::new(RefCell::new(StatManagerV2::new(table_manager.clone())));
         _ = stat_manager
@@ -340,6 +492,10 @@ mod tests {
                 assert!(index_info.index_name == "nameIdx");
             }
 
+            // catalogから読んだhash関数
+            assert_eq!(index_info.get_hash_function(), HashFunction::Fnv1a);
+            assert_eq!(index_info.get_number_of_buckets(), 10);
+
             println!(
                 "Index on field: {}, blocks accessed: {}, records output: {}, distinct values: {}",
                 field_name,
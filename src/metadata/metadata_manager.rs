@@ -1,10 +1,21 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    rc::Rc,
+};
 
 use crate::{
-    error::{TableAlreadyExists, ValueNotFound},
-    metadata::index_manager::{self, IndexInfo, IndexManager},
+    error::{
+        DatabaseError, IncompatibleVersion, NotNullViolation, UniqueConstraintError, ValueNotFound,
+    },
+    index::hash_index::{HashFunction, HashIndex},
+    metadata::index_manager::{self, IndexCatalogEntry, IndexInfo, IndexManager},
     query::parser::QueryData,
-    record::record_page::{Layout, TableSchema},
+    query::predicate::{Constant, ConstantValue, TableNameAndFieldName},
+    record::record_page::{Layout, TableFieldInfo, TableSchema},
+    record::scan_v2::ScanV2,
+    record::table_scan_v2::{RecordID, TableScan},
     metadata::stat_manager_v2::{StatInfoV2, StatManagerV2},
     metadata::table_manager_v2::TableManagerV2,
     tx::transaction_v2,
@@ -19,12 +30,72 @@ pub struct MetadataManager {
 }
 
 impl MetadataManager {
+    // システムが内部で使うカタログテーブル
+    pub const CATALOG_TABLE_NAMES: [&'static str; 6] = [
+        "table_catalog",
+        "field_catalog",
+        "default_catalog",
+        "index_catalog",
+        "view_catelog",
+        "system_catalog",
+    ];
+
+    // catalogやfileの形を変えたら上げる。違うversionのdatabaseは開かない
+    // 1: system_catalogを足した。table_catalogのblock_sizeとfield_catalogのfield_scaleもここから
+    //    それより前のdatabaseはsystem_catalogが無いのでversion 0になり、開かない
+    // 2: field_catalogにnot nullとdefaultのfieldを足した
+    // 3: field_catalogにunique / primary keyのfieldを足した
+    // 4: add columnで既存のblockを書き直さなくなり、field_catalogにfieldの最初のblockを足した
+    // 5: defaultをdefault_catalogに移し、field_catalogのnot nullとkeyをfield_constraintにまとめた
+    pub const FORMAT_VERSION: i32 = 5;
+    const SYSTEM_CATALOG_TABLE_NAME: &'static str = "system_catalog";
+    const SYSTEM_CATALOG_VERSION_FIELD: &'static str = "version";
+    // catalogを作るtransactionの番号
+    // Databaseのrecoverなどが使う番号や、呼び出し側が付ける番号と重ならないように負の数にする
+    const CATALOG_TRANSACTION_ID: i32 = -2;
+
     pub fn new(
         transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
-    ) -> Result<Self, ValueNotFound> {
-        let table_manager = Rc::new(RefCell::new(TableManagerV2::new(transaction.clone(), true)));
+    ) -> Result<Self, DatabaseError> {
+        // catalogがまだ無い場合は新しいdatabase
+        let is_new_database = transaction
+            .borrow()
+            .get_size("table_catalog.tbl".to_string())
+            == 0;
+        if !is_new_database {
+            return Self::open_catalogs(transaction, false);
+        }
+
+        // catalogの作成は呼び出し側のtransactionとは別にcommitする
+        // 呼び出し側がrollbackしてもcatalogは消えず、呼び出し側の変更を勝手にcommitすることもない
+        let catalog_transaction = transaction
+            .borrow()
+            .new_transaction(Self::CATALOG_TRANSACTION_ID);
+        let result = Self::open_catalogs(catalog_transaction.clone(), true);
+        match &result {
+            Ok(_) => catalog_transaction.borrow_mut().commit()?,
+            Err(_) => catalog_transaction.borrow_mut().rollback(),
+        }
+        return result;
+    }
+
+    fn open_catalogs(
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+        is_new_database: bool,
+    ) -> Result<Self, DatabaseError> {
+        // table_catalogとfield_catalogは新しいdatabaseのときだけ作る
+        let table_manager = Rc::new(RefCell::new(TableManagerV2::new(
+            transaction.clone(),
+            is_new_database,
+        )));
         let stat_manager = Rc::new(RefCell::new(StatManagerV2::new(table_manager.clone())));
 
+        Self::check_format_version(
+            &table_manager.borrow(),
+            is_new_database,
+            transaction.clone(),
+        )?;
+
         let _index_manager = crate::metadata::index_manager::IndexManager::new(
             table_manager.clone(),
             stat_manager.clone(),
@@ -33,29 +104,16 @@ impl MetadataManager {
 
         let index_manager = Rc::new(RefCell::new(_index_manager));
 
-        let view_manager = Rc::new(RefCell::new(crate::metadata::view_manager::ViewManager::new(
-            true,
-            table_manager.clone(),
-            transaction.clone(),
-        )));
-
-        let copied_table_manager = table_manager.clone();
-
-        let borrowed_table_manager = copied_table_manager.borrow_mut();
-
-        //  すでにテーブルが存在する場合はエラーを無視する
-        let _table_catalog_result = borrowed_table_manager.create_table(
-            "table_catalog".to_string(),
-            &borrowed_table_manager.table_catalog_layout.schema.clone(),
-            transaction.clone(),
-        );
-
-        //  すでにテーブルが存在する場合はエラーを無視する
-        let _table_field_result = borrowed_table_manager.create_table(
-            "field_catalog".to_string(),
-            &borrowed_table_manager.field_catalog_layout.schema.clone(),
-            transaction.clone(),
-        );
+        let view_catalog_exists = table_manager
+            .borrow()
+            .check_if_table_exists("view_catelog".to_string(), transaction.clone());
+        let view_manager = Rc::new(RefCell::new(
+            crate::metadata::view_manager::ViewManager::new(
+                !view_catalog_exists,
+                table_manager.clone(),
+                transaction.clone(),
+            ),
+        ));
 
         Ok(MetadataManager {
             table_manager: table_manager,
@@ -65,15 +123,169 @@ impl MetadataManager {
         })
     }
 
+    // recoveryの前に、tableごとのblock sizeをcatalogから読んでFileManagerに登録する
+    // versionが違うcatalogはblock_sizeの位置がわからないので、何も登録せずにエラーにする
+    pub fn register_file_block_sizes(
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+    ) -> Result<(), DatabaseError> {
+        let is_new_database = transaction
+            .borrow()
+            .get_size("table_catalog.tbl".to_string())
+            == 0;
+        if is_new_database {
+            return Ok(());
+        }
+
+        let table_manager = TableManagerV2::new(transaction.clone(), false);
+        Self::check_format_version(&table_manager, false, transaction.clone())?;
+        table_manager.register_file_block_sizes(transaction)?;
+        return Ok(());
+    }
+
+    // system_catalogにformatのversionを1行だけ持つ。versionを書くのは新しいdatabaseのときだけ
+    // versionが無いのはsystem_catalogより前に作られたdatabaseで、
+    // catalogの形が違うのでversion 0として扱う
+    fn check_format_version(
+        table_manager: &TableManagerV2,
+        is_new_database: bool,
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+    ) -> Result<(), DatabaseError> {
+        if is_new_database {
+            let mut schema = TableSchema::new();
+            schema.add_integer_field(Self::SYSTEM_CATALOG_VERSION_FIELD.to_string());
+            table_manager.create_table(
+                Self::SYSTEM_CATALOG_TABLE_NAME.to_string(),
+                &schema,
+                transaction.clone(),
+            )?;
+            let layout = table_manager.get_layout(
+                Self::SYSTEM_CATALOG_TABLE_NAME.to_string(),
+                transaction.clone(),
+            )?;
+            let mut scan = TableScan::new(
+                Self::SYSTEM_CATALOG_TABLE_NAME.to_string(),
+                transaction,
+                layout,
+            );
+            scan.insert();
+            scan.set_integer(
+                Self::SYSTEM_CATALOG_VERSION_FIELD.to_string(),
+                Self::FORMAT_VERSION,
+            );
+            scan.close();
+            return Ok(());
+        }
+
+        // 古いcatalogは今のlayoutでは読めないので、system_catalogのfileがあるかだけを見る
+        let system_catalog_size = transaction
+            .borrow()
+            .get_size(format!("{}.tbl", Self::SYSTEM_CATALOG_TABLE_NAME));
+        let version = if system_catalog_size == 0 {
+            None
+        } else {
+            Self::read_format_version(table_manager, transaction)?
+        };
+        match version {
+            Some(version) if version == Self::FORMAT_VERSION => return Ok(()),
+            Some(version) => {
+                return Err(IncompatibleVersion::new(version, Self::FORMAT_VERSION).into());
+            }
+            None => return Err(IncompatibleVersion::new(0, Self::FORMAT_VERSION).into()),
+        }
+    }
+
+    fn read_format_version(
+        table_manager: &TableManagerV2,
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+    ) -> Result<Option<i32>, ValueNotFound> {
+        let layout = table_manager
+            .get_layout(Self::SYSTEM_CATALOG_TABLE_NAME.to_string(), transaction.clone())?;
+        let mut scan = TableScan::new(
+            Self::SYSTEM_CATALOG_TABLE_NAME.to_string(),
+            transaction,
+            layout,
+        );
+        let mut version = None;
+        if scan.next()? {
+            version = scan.get_integer(TableNameAndFieldName::new(
+                None,
+                Self::SYSTEM_CATALOG_VERSION_FIELD.to_string(),
+            ));
+        }
+        scan.close();
+        return Ok(version);
+    }
+
+    // databaseを作ったときのformatのversion
+    pub fn get_format_version(
+        &self,
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+    ) -> Result<i32, ValueNotFound> {
+        let version = Self::read_format_version(&self.table_manager.borrow(), transaction)?;
+        return version.ok_or(ValueNotFound::new(
+            Self::SYSTEM_CATALOG_VERSION_FIELD.to_string(),
+            Some(Self::SYSTEM_CATALOG_TABLE_NAME.to_string()),
+        ));
+    }
+
     pub fn create_table(
         &mut self,
         table_name: String,
         schema: &TableSchema,
         transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
-    ) -> Result<(), TableAlreadyExists> {
+    ) -> Result<(), DatabaseError> {
         self.table_manager
             .borrow_mut()
-            .create_table(table_name, schema, transaction)
+            .create_table(table_name.clone(), schema, transaction.clone())?;
+        return self.create_key_indexes(table_name, schema, transaction);
+    }
+
+    pub fn create_table_with_block_size(
+        &mut self,
+        table_name: String,
+        schema: &TableSchema,
+        block_size: usize,
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+    ) -> Result<(), DatabaseError> {
+        self.table_manager.borrow_mut().create_table_with_block_size(
+            table_name.clone(),
+            schema,
+            block_size,
+            transaction.clone(),
+        )?;
+        return self.create_key_indexes(table_name, schema, transaction);
+    }
+
+    // unique / primary keyのfieldには、重複を探すためのindexを作っておく
+    fn create_key_indexes(
+        &mut self,
+        table_name: String,
+        schema: &TableSchema,
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+    ) -> Result<(), DatabaseError> {
+        for field_name in schema.fields.iter() {
+            if schema.get_key_constraint(field_name).is_none() {
+                continue;
+            }
+            self.create_index(
+                Self::key_index_name(&table_name, field_name),
+                table_name.clone(),
+                field_name.clone(),
+                transaction.clone(),
+            )?;
+        }
+        return Ok(());
+    }
+
+    // catalogに入りきらない長さの名前は、tableとfieldのhashから作る
+    fn key_index_name(table_name: &String, field_name: &String) -> String {
+        let index_name = format!("{}_{}_key", table_name, field_name);
+        if index_name.len() <= IndexManager::NAME_LENGTH as usize {
+            return index_name;
+        }
+        let mut hasher = DefaultHasher::new();
+        (table_name, field_name).hash(&mut hasher);
+        return format!("key_{:016x}", hasher.finish());
     }
 
     pub fn validate_select_sql(
@@ -81,9 +293,32 @@ impl MetadataManager {
         query_data: &QueryData,
         transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
     ) -> bool {
-        self.table_manager
-            .borrow()
-            .check_if_table_exists(query_data.table_name_list[0].clone(), transaction.clone());
+        // valuesだけのfromはplanを作るときに確認する
+        if query_data.table_name_list.is_empty() {
+            return true;
+        }
+
+        // fromのtableはselect *のときも確かめる
+        for table_name in query_data.table_name_list.iter() {
+            let exists = self
+                .table_manager
+                .borrow()
+                .check_if_table_exists(table_name.clone(), transaction.clone())
+                || self
+                    .get_view_definition(table_name.clone(), transaction.clone())
+                    .is_some();
+            if !exists {
+                return false;
+            }
+        }
+
+        // select *, t.* や集約だけのselect listのfieldはplanを作るときに確認する
+        if query_data.all_columns
+            || query_data.field_name_list.is_empty()
+            || query_data.field_name_list[0].field_name == QueryData::ALL_FIELDS
+        {
+            return true;
+        }
 
         match query_data.field_name_list[0].table_name {
             Some(ref table_name) => {
@@ -145,19 +380,262 @@ impl MetadataManager {
             .get_table_stats(table_name, transaction, layout)
     }
 
+    // 統計のhistogramのbucketの数
+    pub fn set_histogram_buckets(&mut self, histogram_buckets: usize) {
+        self.stat_manager
+            .borrow_mut()
+            .set_histogram_buckets(histogram_buckets);
+    }
+
+    // insert/deleteした行数を統計に反映する
+    pub fn record_modifications(
+        &mut self,
+        table_name: String,
+        inserted_records: u32,
+        deleted_records: u32,
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+    ) -> Result<(), ValueNotFound> {
+        let layout = self.get_layout(table_name.clone(), transaction.clone())?;
+        self.stat_manager.borrow_mut().record_modifications(
+            table_name,
+            inserted_records,
+            deleted_records,
+            transaction,
+            layout,
+        )
+    }
+
     pub fn create_index(
         &mut self,
         index_name: String,
         table_name: String,
         field_name: String,
         transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
-    ) {
-        self.index_manager.borrow_mut().create_index(
+    ) -> Result<(), ValueNotFound> {
+        self.create_index_with_buckets(
             index_name,
             table_name,
             field_name,
+            HashIndex::DEFAULT_NUMBER_OF_BUCKETS,
             transaction,
-        );
+        )
+    }
+
+    // hash indexのbucketの数を指定してindexを作る
+    pub fn create_index_with_buckets(
+        &mut self,
+        index_name: String,
+        table_name: String,
+        field_name: String,
+        number_of_buckets: usize,
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+    ) -> Result<(), ValueNotFound> {
+        // 存在しないtableやfieldのindexはcatalogに登録しない
+        let layout = self.get_layout(table_name.clone(), transaction.clone())?;
+        if !layout.schema.has_field(field_name.clone()) {
+            return Err(ValueNotFound::new(field_name, Some(table_name)));
+        }
+
+        self.index_manager
+            .borrow_mut()
+            .create_index_with_hash_function(
+                index_name,
+                table_name,
+                field_name,
+                HashFunction::Fnv1a,
+                number_of_buckets,
+                transaction,
+            );
+        return Ok(());
+    }
+
+    pub fn drop_table(
+        &mut self,
+        table_name: String,
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+    ) -> Result<(), ValueNotFound> {
+        // catalogのtableは消させない
+        if Self::CATALOG_TABLE_NAMES.contains(&table_name.as_str()) {
+            return Err(ValueNotFound::new(table_name, None));
+        }
+
+        self.table_manager
+            .borrow()
+            .drop_table(table_name.clone(), transaction.clone())?;
+        self.index_manager
+            .borrow()
+            .drop_indexes(table_name.clone(), transaction)?;
+        self.stat_manager.borrow_mut().remove_table_stats(&table_name);
+        return Ok(());
+    }
+
+    pub fn add_column(
+        &mut self,
+        table_name: String,
+        field_name: String,
+        field_info: TableFieldInfo,
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+    ) -> Result<(), DatabaseError> {
+        if Self::CATALOG_TABLE_NAMES.contains(&table_name.as_str()) {
+            return Err(ValueNotFound::new(table_name, None).into());
+        }
+
+        // 既存の行は足したfieldをdefaultかNULLとして読むので、
+        // defaultの無いnot nullのfieldは空のtableにしか足せない
+        // 既存の行には全部同じ値が入るので、2行以上あればuniqueにもできない
+        let has_key = field_info.key.is_some();
+        let reads_null = !field_info.nullable && field_info.default.is_none();
+        let record_ids = match has_key || reads_null {
+            true => self.collect_record_ids(table_name.clone(), transaction.clone())?,
+            false => vec![],
+        };
+        if reads_null && !record_ids.is_empty() {
+            return Err(NotNullViolation::new(field_name, table_name).into());
+        }
+        if has_key && record_ids.len() > 1 {
+            return Err(UniqueConstraintError::new(field_name, table_name).into());
+        }
+
+        self.table_manager.borrow().add_column(
+            table_name.clone(),
+            field_name.clone(),
+            field_info,
+            transaction.clone(),
+        )?;
+
+        // 既存の行はdefaultの値で読めるので、作ったindexにもそのentryを入れる
+        if has_key {
+            self.create_index(
+                Self::key_index_name(&table_name, &field_name),
+                table_name.clone(),
+                field_name,
+                transaction.clone(),
+            )?;
+            let record_ids = record_ids
+                .into_iter()
+                .map(|record_id| (record_id.clone(), record_id))
+                .collect::<Vec<(RecordID, RecordID)>>();
+            self.move_index_entries(table_name.clone(), &record_ids, transaction)?;
+        }
+
+        self.stat_manager
+            .borrow_mut()
+            .remove_table_stats(&table_name);
+        return Ok(());
+    }
+
+    // add columnより前からあるblockの行を今のlayoutのblockに移し、indexのentryも付け替える
+    // field_nameを書く前に呼ぶ。渡した順に新しいRecordIDを返す
+    pub fn move_to_current_layout(
+        &self,
+        table_name: String,
+        field_name: &str,
+        record_ids: Vec<RecordID>,
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+    ) -> Result<Vec<RecordID>, DatabaseError> {
+        let layout = self.get_layout(table_name.clone(), transaction.clone())?;
+        let first_block = layout.get_first_block(field_name);
+        if record_ids
+            .iter()
+            .all(|record_id| record_id.get_block_number() >= first_block)
+        {
+            return Ok(record_ids);
+        }
+
+        let moved_record_ids = self.table_manager.borrow().move_to_current_layout(
+            table_name.clone(),
+            field_name,
+            &record_ids,
+            transaction.clone(),
+        )?;
+        let changed_record_ids = moved_record_ids
+            .iter()
+            .filter(|(old_record_id, new_record_id)| old_record_id != new_record_id)
+            .cloned()
+            .collect::<Vec<(RecordID, RecordID)>>();
+        self.move_index_entries(table_name, &changed_record_ids, transaction)?;
+        return Ok(moved_record_ids
+            .into_iter()
+            .map(|(_, new_record_id)| new_record_id)
+            .collect());
+    }
+
+    // deleteで空いたslotを詰めて、減ったblockの数を返す
+    pub fn vacuum_table(
+        &mut self,
+        table_name: String,
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+    ) -> Result<usize, DatabaseError> {
+        if Self::CATALOG_TABLE_NAMES.contains(&table_name.as_str()) {
+            return Err(ValueNotFound::new(table_name, None).into());
+        }
+
+        let (moved_record_ids, reclaimed_blocks) = self
+            .table_manager
+            .borrow()
+            .vacuum_table(table_name.clone(), transaction.clone())?;
+
+        self.move_index_entries(table_name.clone(), &moved_record_ids, transaction)?;
+        self.stat_manager.borrow_mut().remove_table_stats(&table_name);
+        return Ok(reclaimed_blocks);
+    }
+
+    fn collect_record_ids(
+        &self,
+        table_name: String,
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+    ) -> Result<Vec<RecordID>, ValueNotFound> {
+        let layout = self.get_layout(table_name.clone(), transaction.clone())?;
+        let mut scan = TableScan::new(table_name, transaction, layout);
+        let mut record_ids = vec![];
+        while scan.next()? {
+            record_ids.push(scan.get_record_id());
+        }
+        scan.close();
+        return Ok(record_ids);
+    }
+
+    // recordが書き直されて位置が変わったので、indexのentryを新しいRecordIDに付け替える
+    // 前のRecordIDを別のrecordが使うことがあるので、古いentryを全部消してから入れ直す
+    fn move_index_entries(
+        &self,
+        table_name: String,
+        moved_record_ids: &[(RecordID, RecordID)],
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+    ) -> Result<(), ValueNotFound> {
+        let mut indexes = self.get_index_info(table_name.clone(), transaction.clone())?;
+        if indexes.is_empty() {
+            return Ok(());
+        }
+
+        let layout = self.get_layout(table_name.clone(), transaction.clone())?;
+        let mut scan = TableScan::new(table_name, transaction, layout);
+        for (indexed_field, index_info) in indexes.iter_mut() {
+            let mut entries = vec![];
+            for (old_record_id, new_record_id) in moved_record_ids.iter() {
+                scan.move_to_record_id(new_record_id.clone());
+                let value = scan.get_value(TableNameAndFieldName::new(None, indexed_field.clone()));
+                let Some(value) = value else {
+                    continue;
+                };
+                // NULLはindexに入っていない
+                if value == ConstantValue::Null {
+                    continue;
+                }
+                entries.push((Constant::new(value), old_record_id, new_record_id));
+            }
+
+            let mut index = index_info.open();
+            for (value, old_record_id, _) in entries.iter() {
+                index.delete(value.clone(), (*old_record_id).clone());
+            }
+            for (value, _, new_record_id) in entries.into_iter() {
+                index.insert(value, new_record_id.clone());
+            }
+            index.close();
+        }
+        scan.close();
+        return Ok(());
     }
 
     pub fn get_index_info(
@@ -169,4 +647,753 @@ impl MetadataManager {
             .borrow()
             .get_index_info(table_name, transaction)
     }
+
+    pub fn list_tables(
+        &self,
+        include_catalogs: bool,
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+    ) -> Vec<String> {
+        self.table_manager
+            .borrow()
+            .list_tables(transaction)
+            .into_iter()
+            .filter(|table_name| {
+                include_catalogs || !Self::CATALOG_TABLE_NAMES.contains(&table_name.as_str())
+            })
+            .collect()
+    }
+
+    pub fn list_indexes(
+        &self,
+        transaction: Rc<RefCell<crate::tx::transaction_v2::TransactionV2>>,
+    ) -> Vec<IndexCatalogEntry> {
+        self.index_manager.borrow().list_indexes(transaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        database::Database, record::record_page::TableSchema, record::scan_v2::ScanV2,
+        record::table_scan_v2::TableScan,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_list_tables_and_indexes() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let mut schema = TableSchema::new();
+        schema.add_integer_field("A".to_string());
+        schema.add_string_field("B".to_string(), 10);
+
+        for table_name in ["list_table_1", "list_table_2", "list_table_3"] {
+            metadata_manager
+                .create_table(table_name.to_string(), &schema, transaction.clone())
+                .unwrap();
+        }
+
+        metadata_manager.create_index(
+            "list_index_1".to_string(),
+            "list_table_1".to_string(),
+            "A".to_string(),
+            transaction.clone(),
+        )?;
+
+        let user_tables = metadata_manager.list_tables(false, transaction.clone());
+        assert_eq!(
+            user_tables,
+            vec!["list_table_1", "list_table_2", "list_table_3"]
+        );
+
+        let all_tables = metadata_manager.list_tables(true, transaction.clone());
+        assert_eq!(
+            all_tables.len(),
+            3 + MetadataManager::CATALOG_TABLE_NAMES.len()
+        );
+        for table_name in MetadataManager::CATALOG_TABLE_NAMES
+            .iter()
+            .chain(["list_table_1", "list_table_2", "list_table_3"].iter())
+        {
+            assert!(all_tables.contains(&table_name.to_string()));
+        }
+
+        let index_list = metadata_manager.list_indexes(transaction.clone());
+        assert_eq!(
+            index_list,
+            vec![IndexCatalogEntry {
+                index_name: "list_index_1".to_string(),
+                table_name: "list_table_1".to_string(),
+                field_name: "A".to_string(),
+            }]
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_ddl_and_dml_rollback_together() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+
+        let mut schema = TableSchema::new();
+        schema.add_integer_field("A".to_string());
+        schema.add_string_field("B".to_string(), 10);
+
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        metadata_manager
+            .create_table("committed_table".to_string(), &schema, transaction.clone())
+            .unwrap();
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        metadata_manager
+            .create_table("rollback_table".to_string(), &schema, transaction.clone())
+            .unwrap();
+        metadata_manager.create_index(
+            "rollback_index".to_string(),
+            "rollback_table".to_string(),
+            "A".to_string(),
+            transaction.clone(),
+        )?;
+
+        let layout = metadata_manager.get_layout("rollback_table".to_string(), transaction.clone())?;
+        let mut table_scan =
+            TableScan::new("rollback_table".to_string(), transaction.clone(), layout);
+        table_scan.insert();
+        table_scan.set_integer("A".to_string(), 1);
+        table_scan.set_string("B".to_string(), "rollback".to_string());
+        table_scan.close();
+
+        // commitされるまでは同じtransactionから見える
+        assert_eq!(
+            metadata_manager.list_tables(false, transaction.clone()),
+            vec!["committed_table", "rollback_table"]
+        );
+
+        transaction.borrow_mut().rollback();
+
+        let transaction = database.new_transaction(3);
+        let metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        assert_eq!(
+            metadata_manager.list_tables(false, transaction.clone()),
+            vec!["committed_table"]
+        );
+        assert_eq!(metadata_manager.list_indexes(transaction.clone()), vec![]);
+        assert!(metadata_manager
+            .get_layout("rollback_table".to_string(), transaction.clone())
+            .is_err());
+
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_creating_catalogs_does_not_commit_caller() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+
+        // catalogを作る前に、呼び出し側のtransactionが書き込んでおく
+        let transaction = database.new_transaction(1);
+        let block_id = transaction.borrow_mut().append("caller_file.tbl");
+        transaction.borrow_mut().pin(block_id.clone());
+        transaction
+            .borrow_mut()
+            .set_integer(block_id.clone(), 0, 100, true);
+
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let mut schema = TableSchema::new();
+        schema.add_integer_field("A".to_string());
+        metadata_manager
+            .create_table("rollback_table".to_string(), &schema, transaction.clone())
+            .unwrap();
+        transaction.borrow_mut().rollback();
+
+        // 呼び出し側の変更はどちらも戻り、catalogだけが残る
+        let transaction = database.new_transaction(2);
+        transaction.borrow_mut().pin(block_id.clone());
+        assert_eq!(transaction.borrow_mut().get_integer(block_id.clone(), 0), 0);
+        let metadata_manager = MetadataManager::new(transaction.clone())?;
+        assert_eq!(
+            metadata_manager.get_format_version(transaction.clone())?,
+            MetadataManager::FORMAT_VERSION
+        );
+        assert_eq!(
+            metadata_manager.list_tables(false, transaction.clone()),
+            Vec::<String>::new()
+        );
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_reopen_catalogs_and_format_version() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+
+        let transaction = database.new_transaction(1);
+        let metadata_manager = MetadataManager::new(transaction.clone())?;
+        assert_eq!(
+            metadata_manager.get_format_version(transaction.clone())?,
+            MetadataManager::FORMAT_VERSION
+        );
+        transaction.borrow_mut().commit()?;
+
+        // 2回目以降は既存のcatalogを使い、同じcatalogを作り直さない
+        let transaction = database.new_transaction(2);
+        let metadata_manager = MetadataManager::new(transaction.clone())?;
+        let mut table_names = metadata_manager.list_tables(true, transaction.clone());
+        table_names.sort();
+        let mut catalog_names = MetadataManager::CATALOG_TABLE_NAMES.to_vec();
+        catalog_names.sort();
+        assert_eq!(table_names, catalog_names);
+
+        // 別のversionで作られたことにする
+        let layout =
+            metadata_manager.get_layout("system_catalog".to_string(), transaction.clone())?;
+        let mut table_scan =
+            TableScan::new("system_catalog".to_string(), transaction.clone(), layout);
+        assert!(table_scan.next()?);
+        table_scan.set_integer("version".to_string(), MetadataManager::FORMAT_VERSION + 1);
+        table_scan.close();
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(3);
+        let result = MetadataManager::new(transaction.clone());
+        let Err(DatabaseError::IncompatibleVersion(e)) = result else {
+            panic!("Expected IncompatibleVersion");
+        };
+        assert!(e.to_string().contains(&format!("{}", MetadataManager::FORMAT_VERSION + 1)));
+        transaction.borrow_mut().rollback();
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_database_without_format_version() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(1);
+        MetadataManager::new(transaction.clone())?;
+        transaction.borrow_mut().commit()?;
+        drop(database);
+
+        // system_catalogより前に作られたdatabaseはcatalogの形が違うので開かない
+        std::fs::remove_file(directory_path.join("system_catalog.tbl")).unwrap();
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(2);
+        let result = MetadataManager::new(transaction.clone());
+        let Err(DatabaseError::IncompatibleVersion(e)) = result else {
+            panic!("Expected IncompatibleVersion");
+        };
+        assert_eq!(
+            e.to_string(),
+            IncompatibleVersion::new(0, MetadataManager::FORMAT_VERSION).to_string()
+        );
+        transaction.borrow_mut().rollback();
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_create_index_on_missing_target() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let mut schema = TableSchema::new();
+        schema.add_integer_field("A".to_string());
+        metadata_manager
+            .create_table("index_target".to_string(), &schema, transaction.clone())
+            .unwrap();
+
+        let result = metadata_manager.create_index(
+            "missing_table_index".to_string(),
+            "missing_table".to_string(),
+            "A".to_string(),
+            transaction.clone(),
+        );
+        assert!(result.is_err());
+
+        let result = metadata_manager.create_index(
+            "missing_field_index".to_string(),
+            "index_target".to_string(),
+            "B".to_string(),
+            transaction.clone(),
+        );
+        assert!(result.is_err());
+
+        // どちらもcatalogには残らない
+        assert_eq!(metadata_manager.list_indexes(transaction.clone()), vec![]);
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_validate_select_star_on_missing_table() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let mut schema = TableSchema::new();
+        schema.add_integer_field("A".to_string());
+        metadata_manager
+            .create_table("star_table".to_string(), &schema, transaction.clone())
+            .unwrap();
+        metadata_manager.create_view(
+            "star_view".to_string(),
+            "select A from star_table".to_string(),
+            transaction.clone(),
+        );
+
+        let parse_query = |sql: &str| match crate::query::parser::parse_sql(sql.to_string())
+            .unwrap()
+            .remove(0)
+        {
+            crate::query::parser::ParsedSQL::Query(query_data) => query_data,
+            _ => panic!("not a query: {}", sql),
+        };
+
+        for (sql, expected) in [
+            ("select * from star_table", true),
+            ("select * from star_view", true),
+            ("select * from missing_table", false),
+            ("select * from star_table, missing_table", false),
+        ] {
+            let query_data = parse_query(sql);
+            assert!(query_data.all_columns);
+            assert_eq!(
+                metadata_manager.validate_select_sql(&query_data, transaction.clone()),
+                expected,
+                "{}",
+                sql
+            );
+        }
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_drop_table() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table drop_target (A integer, B varchar(10)); \
+             create index drop_target_index on drop_target (A); \
+             insert into drop_target (A, B) values (1, 'one')",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+        assert!(directory_path.join("drop_target.tbl").exists());
+        assert!(directory_path.join("drop_target_index_leaf").exists());
+
+        // rollbackすればcatalogもfileも元に戻る
+        let transaction = database.new_transaction(2);
+        database.execute("drop table drop_target", transaction.clone())?;
+        transaction.borrow_mut().rollback();
+
+        let transaction = database.new_transaction(3);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        assert!(metadata_manager
+            .get_layout("drop_target".to_string(), transaction.clone())
+            .is_ok());
+        assert!(directory_path.join("drop_target.tbl").exists());
+
+        metadata_manager.drop_table("drop_target".to_string(), transaction.clone())?;
+        // 存在しないtableとcatalogのtableは消せない
+        assert!(metadata_manager
+            .drop_table("drop_target".to_string(), transaction.clone())
+            .is_err());
+        assert!(metadata_manager
+            .drop_table("table_catalog".to_string(), transaction.clone())
+            .is_err());
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(4);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        assert!(metadata_manager
+            .get_layout("drop_target".to_string(), transaction.clone())
+            .is_err());
+        assert!(!metadata_manager.table_manager.borrow().check_if_field_exists(
+            "drop_target".to_string(),
+            "A".to_string(),
+            transaction.clone()
+        ));
+        assert_eq!(
+            metadata_manager.list_tables(false, transaction.clone()),
+            Vec::<String>::new()
+        );
+        assert_eq!(metadata_manager.list_indexes(transaction.clone()), vec![]);
+        assert!(!directory_path.join("drop_target.tbl").exists());
+        assert!(!directory_path.join("drop_target_index_leaf").exists());
+
+        // 同じ名前で作り直したtableは空
+        let mut schema = TableSchema::new();
+        schema.add_integer_field("A".to_string());
+        metadata_manager
+            .create_table("drop_target".to_string(), &schema, transaction.clone())
+            .unwrap();
+        let layout = metadata_manager.get_layout("drop_target".to_string(), transaction.clone())?;
+        let mut table_scan = TableScan::new("drop_target".to_string(), transaction.clone(), layout);
+        assert!(!table_scan.next()?);
+        table_scan.close();
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_add_column() -> Result<(), crate::error::DatabaseError> {
+        use crate::{
+            error::DatabaseError,
+            query::cursor::Cursor,
+            query::parser::{parse_sql, ParsedSQL},
+            query::plan_v2::create_query_plan,
+        };
+
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+
+        // 1つのblockに2行しか入らないので、行は複数のblockにまたがる
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table alter_target (A integer, B varchar(10)) with (block_size = 128); \
+             create index alter_target_index on alter_target (A); \
+             insert into alter_target (A, B) values (1, 'one'); \
+             insert into alter_target (A, B) values (2, 'two'); \
+             insert into alter_target (A, B) values (3, 'three'); \
+             insert into alter_target (A, B) values (4, 'four'); \
+             insert into alter_target (A, B) values (5, 'five')",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        database.execute(
+            "alter table alter_target add column C integer; \
+             alter table alter_target add column D varchar(5); \
+             alter table alter_target add column F date",
+            transaction.clone(),
+        )?;
+        let result = database.execute(
+            "alter table alter_target add column B integer",
+            transaction.clone(),
+        );
+        assert!(matches!(result, Err(DatabaseError::FieldAlreadyExists(_))));
+        // blockに1行も入らなくなるfieldは足せない
+        let result = database.execute(
+            "alter table alter_target add column E varchar(20)",
+            transaction.clone(),
+        );
+        assert!(matches!(result, Err(DatabaseError::SchemaTooLarge(_))));
+        let result = database.execute(
+            "alter table missing_table add column C integer",
+            transaction.clone(),
+        );
+        assert!(matches!(result, Err(DatabaseError::ValueNotFound(_))));
+        database.execute(
+            "insert into alter_target (A, B, C, D) values (6, 'six', 60, 'x')",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let fetch = |sql: &str| -> Result<Vec<Vec<ConstantValue>>, DatabaseError> {
+            let transaction = database.new_transaction(3);
+            let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+            let parsed_sql_list = parse_sql(sql.to_string()).unwrap();
+            let ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+                panic!("Expected a Query variant from parse_sql");
+            };
+            let plan = create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+            let mut cursor = Cursor::open(plan, transaction.clone())?;
+            let rows = cursor.fetch(100)?;
+            cursor.close();
+            transaction.borrow_mut().commit()?;
+            return Ok(rows);
+        };
+
+        // 既存の行の新しいfieldはNULLで読める
+        let rows = fetch("select A, B, C, D, F from alter_target order by A")?;
+        let expected = [
+            (1, "one", None, None),
+            (2, "two", None, None),
+            (3, "three", None, None),
+            (4, "four", None, None),
+            (5, "five", None, None),
+            (6, "six", Some(60), Some("x")),
+        ]
+        .iter()
+        .map(|(a, b, c, d)| {
+            vec![
+                ConstantValue::Number(*a),
+                ConstantValue::String(b.to_string()),
+                c.map_or(ConstantValue::Null, ConstantValue::Number),
+                d.map_or(ConstantValue::Null, |d| ConstantValue::String(d.to_string())),
+                ConstantValue::Null,
+            ]
+        })
+        .collect::<Vec<Vec<ConstantValue>>>();
+        assert_eq!(rows, expected);
+
+        // indexは既存の行をそのまま指している
+        let rows = fetch("select A, B, C, D, F from alter_target where A = 4")?;
+        assert_eq!(rows, vec![expected[3].clone()]);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_add_column_keeps_old_blocks() -> Result<(), crate::error::DatabaseError> {
+        use crate::database::QueryResult;
+
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+
+        // 1つのblockに2行しか入らないので、5行で3 blockになる
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table lazy_target (A integer, B varchar(10)) with (block_size = 128); \
+             create index lazy_target_index on lazy_target (A)",
+            transaction.clone(),
+        )?;
+        for a in 1..=5 {
+            database.execute(
+                &format!("insert into lazy_target (A, B) values ({}, 'row{}')", a, a),
+                transaction.clone(),
+            )?;
+        }
+        transaction.borrow_mut().commit()?;
+
+        let block_count = || {
+            let transaction = database.new_transaction(10);
+            let block_count = transaction.borrow().get_size("lazy_target.tbl".to_string());
+            transaction.borrow_mut().commit().unwrap();
+            return block_count;
+        };
+        let fetch = |sql: &str| -> Result<Vec<Vec<ConstantValue>>, crate::error::DatabaseError> {
+            let transaction = database.new_transaction(11);
+            let result = database.execute(sql, transaction.clone())?;
+            transaction.borrow_mut().commit()?;
+            let QueryResult::Rows { rows, .. } = result else {
+                panic!("Expected rows");
+            };
+            return Ok(rows);
+        };
+        let row = |a: i32, c: i32| vec![ConstantValue::Number(a), ConstantValue::Number(c)];
+        assert_eq!(block_count(), 3);
+
+        // 既存のblockは書き直さず、足したfieldはdefaultで読める
+        let transaction = database.new_transaction(2);
+        database.execute(
+            "alter table lazy_target add column C integer default 7",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+        assert_eq!(block_count(), 3);
+        assert_eq!(
+            fetch("select A, C from lazy_target order by A")?,
+            (1..=5)
+                .map(|a| row(a, 7))
+                .collect::<Vec<Vec<ConstantValue>>>()
+        );
+
+        // 新しい行は古いblockの空きには入らない
+        let transaction = database.new_transaction(3);
+        database.execute(
+            "insert into lazy_target (A, B, C) values (6, 'row6', 60)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+        assert_eq!(block_count(), 4);
+
+        // 古いblockの行に足したfieldを書くと、行は新しいblockに移ってindexも付け替わる
+        let transaction = database.new_transaction(4);
+        database.execute(
+            "update lazy_target set C = 20 where A = 2",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+        assert_eq!(
+            fetch("select A, C from lazy_target where A = 2")?,
+            vec![row(2, 20)]
+        );
+        assert_eq!(
+            fetch("select A, C from lazy_target order by A")?,
+            vec![
+                row(1, 7),
+                row(2, 20),
+                row(3, 7),
+                row(4, 7),
+                row(5, 7),
+                row(6, 60)
+            ]
+        );
+
+        // vacuumは全部のblockを今のlayoutで書き直す
+        let transaction = database.new_transaction(5);
+        database.execute("vacuum lazy_target", transaction.clone())?;
+        database.execute(
+            "update lazy_target set C = 30 where A = 3",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+        assert_eq!(block_count(), 3);
+        assert_eq!(
+            fetch("select A, C from lazy_target order by A")?,
+            vec![
+                row(1, 7),
+                row(2, 20),
+                row(3, 30),
+                row(4, 7),
+                row(5, 7),
+                row(6, 60)
+            ]
+        );
+        assert_eq!(
+            fetch("select A, C from lazy_target where A = 3")?,
+            vec![row(3, 30)]
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_vacuum_table() -> Result<(), crate::error::DatabaseError> {
+        use crate::{
+            database::QueryResult,
+            error::DatabaseError,
+            query::cursor::Cursor,
+            query::parser::{parse_sql, ParsedSQL},
+            query::plan_v2::create_query_plan,
+        };
+
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+
+        // 1つのblockに2行しか入らないので、12行で6 blockになる
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table vacuum_target (A integer, B varchar(10)) with (block_size = 128); \
+             create index vacuum_target_index on vacuum_target (A)",
+            transaction.clone(),
+        )?;
+        for a in 1..=12 {
+            database.execute(
+                &format!(
+                    "insert into vacuum_target (A, B) values ({}, 'row{}')",
+                    a, a
+                ),
+                transaction.clone(),
+            )?;
+        }
+        database.execute(
+            "delete from vacuum_target where A <> 3 and A <> 7 and A <> 10 and A <> 12",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let block_count = || {
+            let transaction = database.new_transaction(10);
+            let block_count = transaction
+                .borrow()
+                .get_size("vacuum_target.tbl".to_string());
+            transaction.borrow_mut().commit().unwrap();
+            return block_count;
+        };
+        assert_eq!(block_count(), 6);
+
+        // 残った4行を2 blockに詰めて、末尾の4 blockはcommitのときに切り詰める
+        let transaction = database.new_transaction(2);
+        let result = database.execute("vacuum vacuum_target", transaction.clone())?;
+        assert_eq!(result, QueryResult::BlocksReclaimed(4));
+        transaction.borrow_mut().commit()?;
+        assert_eq!(block_count(), 2);
+
+        let fetch = |sql: &str| -> Result<Vec<Vec<ConstantValue>>, DatabaseError> {
+            let transaction = database.new_transaction(3);
+            let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+            let parsed_sql_list = parse_sql(sql.to_string()).unwrap();
+            let ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+                panic!("Expected a Query variant from parse_sql");
+            };
+            let plan = create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+            let mut cursor = Cursor::open(plan, transaction.clone())?;
+            let rows = cursor.fetch(100)?;
+            cursor.close();
+            transaction.borrow_mut().commit()?;
+            return Ok(rows);
+        };
+        let row = |a: i32| {
+            vec![
+                ConstantValue::Number(a),
+                ConstantValue::String(format!("row{}", a)),
+            ]
+        };
+        assert_eq!(
+            fetch("select A, B from vacuum_target order by A")?,
+            vec![row(3), row(7), row(10), row(12)]
+        );
+        // indexは詰めた後の行を指している
+        for a in [3, 7, 10, 12] {
+            assert_eq!(
+                fetch(&format!("select A, B from vacuum_target where A = {}", a))?,
+                vec![row(a)]
+            );
+        }
+
+        // rollbackしたときは行もblockも元のまま
+        let transaction = database.new_transaction(4);
+        database.execute(
+            "delete from vacuum_target where A < 10; vacuum vacuum_target",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().rollback();
+        assert_eq!(block_count(), 2);
+        assert_eq!(
+            fetch("select A, B from vacuum_target where A = 7")?,
+            vec![row(7)]
+        );
+
+        // もう詰まっているので減らない
+        let transaction = database.new_transaction(5);
+        let result = database.execute("vacuum vacuum_target", transaction.clone())?;
+        assert_eq!(result, QueryResult::BlocksReclaimed(0));
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
 }
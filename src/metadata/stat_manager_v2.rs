@@ -1,7 +1,13 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 use crate::{
     error::ValueNotFound, query::predicate::TableNameAndFieldName, record::record_page, record::scan_v2::ScanV2,
+    query::predicate::ConstantValue, query::predicate_v3::CompareOp,
+    record::record_page::TableFieldType,
     metadata::table_manager_v2::TableManagerV2, record::table_scan_v2::TableScan, tx::transaction_v2::TransactionV2,
 };
 
@@ -9,12 +15,124 @@ pub struct StatManagerV2 {
     table_manager: Rc<RefCell<TableManagerV2>>,
     table_stats: HashMap<String, StatInfoV2>,
     num_calls: u32,
+    // 最後に数え直してからinsert/deleteした行の数
+    num_modifications: HashMap<String, u32>,
+    histogram_buckets: usize,
 }
 
 #[derive(Debug, Clone)]
 pub struct StatInfoV2 {
     num_records: u32,
     num_blocks: u32,
+    // fieldごとのdistinctな値の数。数え直すまでは古いままなことがある
+    distinct_values: HashMap<String, u32>,
+    // INTEGERのfieldごとのhistogram
+    histograms: HashMap<String, Histogram>,
+}
+
+// 値の範囲を同じ幅で区切り、bucketごとに行数とdistinctな値の数を持つ
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    min: i32,
+    max: i32,
+    // NULLの行も含めたtableの行数
+    num_records: u32,
+    bucket_records: Vec<u32>,
+    bucket_distinct_values: Vec<u32>,
+}
+
+impl Histogram {
+    // NULLでない値が無いときはNone
+    pub fn new(values: &[i32], num_records: u32, number_of_buckets: usize) -> Option<Self> {
+        let min = *values.iter().min()?;
+        let max = *values.iter().max()?;
+        let mut histogram = Histogram {
+            min,
+            max,
+            num_records,
+            bucket_records: vec![0; number_of_buckets.max(1)],
+            bucket_distinct_values: vec![0; number_of_buckets.max(1)],
+        };
+
+        let mut distinct_values: Vec<HashSet<i32>> =
+            vec![HashSet::new(); histogram.bucket_records.len()];
+        for value in values.iter() {
+            let bucket = histogram.bucket_index(*value as i64);
+            histogram.bucket_records[bucket] += 1;
+            distinct_values[bucket].insert(*value);
+        }
+        for (bucket, values) in distinct_values.iter().enumerate() {
+            histogram.bucket_distinct_values[bucket] = values.len() as u32;
+        }
+        return Some(histogram);
+    }
+
+    fn bucket_width(&self) -> f64 {
+        return (self.max as i64 - self.min as i64 + 1) as f64 / self.bucket_records.len() as f64;
+    }
+
+    fn bucket_index(&self, value: i64) -> usize {
+        let index = ((value - self.min as i64) as f64 / self.bucket_width()) as usize;
+        return index.min(self.bucket_records.len() - 1);
+    }
+
+    pub fn distinct_values(&self) -> u32 {
+        return self.bucket_distinct_values.iter().sum();
+    }
+
+    // NULLでない行の割合
+    fn non_null_fraction(&self) -> f64 {
+        let records: u32 = self.bucket_records.iter().sum();
+        return records as f64 / self.num_records.max(1) as f64;
+    }
+
+    // valueと等しい行の割合。bucketの中では値ごとに同じ数の行があるとみなす
+    pub fn equal_fraction(&self, value: i32) -> f64 {
+        if value < self.min || value > self.max {
+            return 0.0;
+        }
+        let bucket = self.bucket_index(value as i64);
+        let records = self.bucket_records[bucket] as f64
+            / self.bucket_distinct_values[bucket].max(1) as f64;
+        return records / self.num_records.max(1) as f64;
+    }
+
+    // valueより小さい行の割合。bucketの中では値が均等に散らばっているとみなす
+    pub fn less_than_fraction(&self, value: i64) -> f64 {
+        if value <= self.min as i64 {
+            return 0.0;
+        }
+        if value > self.max as i64 {
+            return self.non_null_fraction();
+        }
+        let bucket = self.bucket_index(value);
+        let mut records: f64 = self.bucket_records[..bucket].iter().sum::<u32>() as f64;
+        let bucket_start = self.min as f64 + bucket as f64 * self.bucket_width();
+        records += self.bucket_records[bucket] as f64 * (value as f64 - bucket_start)
+            / self.bucket_width();
+        return records / self.num_records.max(1) as f64;
+    }
+
+    // field op value を満たす行の割合。見積もれない比較はNone
+    pub fn selectivity(&self, op: &CompareOp, value: i32) -> Option<f64> {
+        let value_i64 = value as i64;
+        let fraction = match op {
+            CompareOp::Equal | CompareOp::NotDistinctFrom => self.equal_fraction(value),
+            CompareOp::NotEqual | CompareOp::DistinctFrom => {
+                self.non_null_fraction() - self.equal_fraction(value)
+            }
+            CompareOp::LessThan => self.less_than_fraction(value_i64),
+            CompareOp::LessThanOrEqual => self.less_than_fraction(value_i64 + 1),
+            CompareOp::GreaterThan => {
+                self.non_null_fraction() - self.less_than_fraction(value_i64 + 1)
+            }
+            CompareOp::GreaterThanOrEqual => {
+                self.non_null_fraction() - self.less_than_fraction(value_i64)
+            }
+            CompareOp::Like => return None,
+        };
+        return Some(fraction.clamp(0.0, 1.0));
+    }
 }
 
 impl StatInfoV2 {
@@ -22,6 +140,8 @@ impl StatInfoV2 {
         StatInfoV2 {
             num_records,
             num_blocks,
+            distinct_values: HashMap::new(),
+            histograms: HashMap::new(),
         }
     }
 
@@ -32,21 +152,43 @@ impl StatInfoV2 {
         self.num_blocks
     }
 
+    pub fn get_histogram(&self, field_name: &str) -> Option<&Histogram> {
+        return self.histograms.get(field_name);
+    }
+
     pub fn distinct_value(&self, field_name: String) -> u32 {
-        // this is wildly inaccurate
-        return 1 + self.num_records / 3;
+        if let Some(histogram) = self.histograms.get(&field_name) {
+            return histogram.distinct_values().clamp(1, self.num_records.max(1));
+        }
+        match self.distinct_values.get(&field_name) {
+            // 0にすると割り算に使えないので、最低でも1にする
+            Some(distinct_value) => return (*distinct_value).clamp(1, self.num_records.max(1)),
+            // this is wildly inaccurate
+            None => return 1 + self.num_records / 3,
+        }
     }
 }
 
 impl StatManagerV2 {
+    // この数の行をinsert/deleteしたら、tableを読み直して数え直す
+    pub const REFRESH_THRESHOLD: u32 = 100;
+    pub const DEFAULT_HISTOGRAM_BUCKETS: usize = 10;
+
     pub fn new(table_manager: Rc<RefCell<TableManagerV2>>) -> Self {
         StatManagerV2 {
             table_manager,
             table_stats: HashMap::new(),
             num_calls: 0,
+            num_modifications: HashMap::new(),
+            histogram_buckets: Self::DEFAULT_HISTOGRAM_BUCKETS,
         }
     }
 
+    // 次に数え直すときから使う
+    pub fn set_histogram_buckets(&mut self, histogram_buckets: usize) {
+        self.histogram_buckets = histogram_buckets.max(1);
+    }
+
     pub fn get_table_stats(
         &mut self,
         table_name: String,
@@ -71,6 +213,53 @@ impl StatManagerV2 {
         return Ok(stat_info);
     }
 
+    pub fn remove_table_stats(&mut self, table_name: &str) {
+        self.table_stats.remove(table_name);
+        self.num_modifications.remove(table_name);
+    }
+
+    // tableを読み直して、行数、block数、distinctな値の数を数え直す
+    pub fn refresh_statistics(
+        &mut self,
+        table_name: String,
+        transaction: Rc<RefCell<TransactionV2>>,
+        layout: crate::record::record_page::Layout,
+    ) -> Result<StatInfoV2, ValueNotFound> {
+        let stat_info = self.calc_table_stats(table_name.clone(), transaction, layout)?;
+        self.table_stats.insert(table_name.clone(), stat_info.clone());
+        self.num_modifications.remove(&table_name);
+        return Ok(stat_info);
+    }
+
+    // insert/deleteした行数を記録する
+    // 読み直すまでは行数だけを足し引きし、REFRESH_THRESHOLDを超えたら読み直す
+    pub fn record_modifications(
+        &mut self,
+        table_name: String,
+        inserted_records: u32,
+        deleted_records: u32,
+        transaction: Rc<RefCell<TransactionV2>>,
+        layout: crate::record::record_page::Layout,
+    ) -> Result<(), ValueNotFound> {
+        let Some(stat_info) = self.table_stats.get_mut(&table_name) else {
+            // まだ数えていないtableは、次に使うときに数える
+            return Ok(());
+        };
+        stat_info.num_records = stat_info
+            .num_records
+            .saturating_add(inserted_records)
+            .saturating_sub(deleted_records);
+
+        let num_modifications = self.num_modifications.entry(table_name.clone()).or_insert(0);
+        *num_modifications = num_modifications
+            .saturating_add(inserted_records)
+            .saturating_add(deleted_records);
+        if *num_modifications >= Self::REFRESH_THRESHOLD {
+            self.refresh_statistics(table_name, transaction, layout)?;
+        }
+        return Ok(());
+    }
+
     pub fn refresh_table_stats(
         &mut self,
         transaction: Rc<RefCell<TransactionV2>>,
@@ -98,12 +287,11 @@ impl StatManagerV2 {
                         .table_manager
                         .borrow()
                         .get_layout(table_name.clone(), transaction.clone())?;
-                    let table_stat = self.calc_table_stats(
+                    self.refresh_statistics(
                         table_name.clone(),
                         transaction.clone(),
                         layout.clone(),
                     )?;
-                    self.table_stats.insert(table_name.clone(), table_stat);
                 }
                 None => continue,
             }
@@ -122,6 +310,8 @@ impl StatManagerV2 {
     ) -> Result<StatInfoV2, ValueNotFound> {
         let mut num_records = 0;
         let mut num_blocks = 0;
+        let mut values: HashMap<String, HashSet<String>> = HashMap::new();
+        let mut integer_values: HashMap<String, Vec<i32>> = HashMap::new();
 
         let mut table_scan =
             TableScan::new(table_name.clone(), transaction.clone(), layout.clone());
@@ -129,12 +319,38 @@ impl StatManagerV2 {
         while table_scan.next()? {
             num_records += 1;
             num_blocks = (table_scan.get_record_id().get_block_number() + 1) as u32;
+            for field in layout.schema.fields.iter() {
+                let value = table_scan.get_value(TableNameAndFieldName::new(None, field.clone()));
+                if let Some(ConstantValue::Number(n)) = value {
+                    integer_values.entry(field.clone()).or_default().push(n);
+                }
+                values
+                    .entry(field.clone())
+                    .or_default()
+                    .insert(format!("{:?}", value));
+            }
         }
         table_scan.close();
 
+        let mut histograms = HashMap::new();
+        for field in layout.schema.fields.iter() {
+            if layout.schema.get_field_type(field.clone()) != Some(TableFieldType::INTEGER) {
+                continue;
+            }
+            let values = integer_values.remove(field).unwrap_or_default();
+            if let Some(histogram) = Histogram::new(&values, num_records, self.histogram_buckets) {
+                histograms.insert(field.clone(), histogram);
+            }
+        }
+
         return Ok(StatInfoV2 {
             num_records,
             num_blocks,
+            histograms,
+            distinct_values: values
+                .into_iter()
+                .map(|(field, values)| (field, values.len() as u32))
+                .collect(),
         });
     }
 }
@@ -159,7 +375,8 @@ mod tests {
 
     #[test]
     fn test_stat_v2() {
-        let test_dir_name = format!("test_data_{}", uuid::Uuid::new_v4());
+        let test_directory = crate::database::TestDirectory::new();
+        let test_dir_name = test_directory.path_name();
         let test_dir = Path::new(&test_dir_name);
         let block_size = 400;
 
@@ -206,7 +423,202 @@ mod tests {
             .unwrap();
 
         println!("Stat info: {:?}", stat_info);
-        assert!(stat_info.get_num_blocks() == 4);
-        assert!(stat_info.get_num_records() == 7);
+        // catalogの3つのtableのfieldの数。field_catalogは1つのblockに2行入る
+        assert!(stat_info.get_num_blocks() == 7);
+        assert!(stat_info.get_num_records() == 14);
+    }
+
+    #[test]
+    fn test_stats_follow_inserts_and_deletes() -> Result<(), crate::error::DatabaseError> {
+        use crate::{
+            database::Database,
+            metadata::metadata_manager::MetadataManager,
+            query::index_update_planner::IndexUpdatePlanner,
+            query::parser::{parse_sql, ParsedSQL},
+        };
+
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute("create table t (A integer, B varchar(10))", transaction.clone())?;
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let mut index_update_planner = IndexUpdatePlanner::new();
+        let mut execute = |sql: String, metadata_manager: &mut MetadataManager| {
+            for parsed_sql in parse_sql(sql).unwrap() {
+                match parsed_sql {
+                    ParsedSQL::Insert(insert_data) => {
+                        index_update_planner.execute_insert(
+                            insert_data,
+                            transaction.clone(),
+                            metadata_manager,
+                        )?;
+                    }
+                    ParsedSQL::Delete(delete_data) => {
+                        index_update_planner.execute_delete(
+                            delete_data,
+                            transaction.clone(),
+                            metadata_manager,
+                        )?;
+                    }
+                    _ => panic!("Expected an insert or delete"),
+                }
+            }
+            return Ok::<(), crate::error::DatabaseError>(());
+        };
+        let stats = |metadata_manager: &mut MetadataManager| {
+            let layout = metadata_manager.get_layout("t".to_string(), transaction.clone())?;
+            metadata_manager.get_table_stats("t".to_string(), transaction.clone(), layout)
+        };
+
+        assert_eq!(stats(&mut metadata_manager)?.get_num_records(), 0);
+
+        // 数え直す前でも行数は増える
+        execute(
+            "insert into t (A, B) values (1, 'x'); insert into t (A, B) values (2, 'x')"
+                .to_string(),
+            &mut metadata_manager,
+        )?;
+        assert_eq!(stats(&mut metadata_manager)?.get_num_records(), 2);
+
+        // REFRESH_THRESHOLDを超えると、block数とdistinctな値の数も数え直す
+        for i in 0..StatManagerV2::REFRESH_THRESHOLD {
+            execute(
+                format!("insert into t (A, B) values ({}, 'y')", i % 10),
+                &mut metadata_manager,
+            )?;
+        }
+        let stat_info = stats(&mut metadata_manager)?;
+        assert_eq!(
+            stat_info.get_num_records(),
+            2 + StatManagerV2::REFRESH_THRESHOLD
+        );
+        assert!(stat_info.get_num_blocks() > 1);
+        assert_eq!(stat_info.distinct_value("A".to_string()), 10);
+        assert_eq!(stat_info.distinct_value("B".to_string()), 2);
+
+        execute("delete from t where A = 1".to_string(), &mut metadata_manager)?;
+        assert_eq!(
+            stats(&mut metadata_manager)?.get_num_records(),
+            2 + StatManagerV2::REFRESH_THRESHOLD - 11
+        );
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_stats_of_table_with_block_size() -> Result<(), crate::error::DatabaseError> {
+        use crate::{database::Database, metadata::metadata_manager::MetadataManager};
+
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table default_size (A integer); \
+             create table large_size (A integer) with (block_size = 1024)",
+            transaction.clone(),
+        )?;
+        for i in 0..100 {
+            database.execute(
+                &format!(
+                    "insert into default_size (A) values ({}); \
+                     insert into large_size (A) values ({})",
+                    i, i
+                ),
+                transaction.clone(),
+            )?;
+        }
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let slot_size = metadata_manager
+            .get_layout("large_size".to_string(), transaction.clone())?
+            .get_slot_size() as u32;
+
+        // block sizeが大きいtableほど1つのblockに多くの行が入る
+        for (table_name, block_size) in [("default_size", 400), ("large_size", 1024)] {
+            let layout =
+                metadata_manager.get_layout(table_name.to_string(), transaction.clone())?;
+            let stat_info = metadata_manager.get_table_stats(
+                table_name.to_string(),
+                transaction.clone(),
+                layout,
+            )?;
+            assert_eq!(stat_info.get_num_records(), 100);
+            assert_eq!(
+                stat_info.get_num_blocks(),
+                100_u32.div_ceil(block_size / slot_size)
+            );
+        }
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_histogram_estimates() -> Result<(), crate::error::DatabaseError> {
+        use crate::{
+            database::Database,
+            metadata::metadata_manager::MetadataManager,
+            query::parser::{parse_sql, ParsedSQL},
+            query::plan_v2::{PlanV2, SelectPlanV2, TablePlanV2},
+        };
+
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute("create table t (A integer, B varchar(10))", transaction.clone())?;
+        for i in 0..100 {
+            database.execute(
+                &format!("insert into t (A, B) values ({}, 'x')", i),
+                transaction.clone(),
+            )?;
+        }
+        for _ in 0..10 {
+            database.execute("insert into t (A, B) values (null, 'y')", transaction.clone())?;
+        }
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        metadata_manager.set_histogram_buckets(4);
+
+        let layout = metadata_manager.get_layout("t".to_string(), transaction.clone())?;
+        let stat_info =
+            metadata_manager.get_table_stats("t".to_string(), transaction.clone(), layout)?;
+        let histogram = stat_info.get_histogram("A").unwrap();
+        assert_eq!(histogram.bucket_records, vec![25, 25, 25, 25]);
+        assert_eq!(stat_info.distinct_value("A".to_string()), 100);
+        // varcharのfieldにはhistogramを作らない
+        assert!(stat_info.get_histogram("B").is_none());
+        assert_eq!(stat_info.distinct_value("B".to_string()), 2);
+
+        let mut records_output = |sql: &str| -> Result<u32, ValueNotFound> {
+            let parsed_sql_list = parse_sql(sql.to_string()).unwrap();
+            let ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+                panic!("Expected a Query variant from parse_sql");
+            };
+            let plan =
+                TablePlanV2::new("t".to_string(), transaction.clone(), &mut metadata_manager)?;
+            let plan = SelectPlanV2::new(Box::new(plan), query_data.predicate.clone());
+            return Ok(plan.records_output());
+        };
+        // NULLの10行も含めた110行から見積もる
+        assert_eq!(records_output("select A from t where A = 7")?, 1);
+        assert_eq!(records_output("select A from t where A < 25")?, 27);
+        assert_eq!(records_output("select A from t where 50 <= A")?, 55);
+        assert_eq!(records_output("select A from t where A > 1000")?, 0);
+        // histogramの無いfieldはdistinctな値の数で割る
+        assert_eq!(records_output("select A from t where B = 'x'")?, 55);
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
     }
 }
@@ -1,30 +1,46 @@
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use crate::{
-    error::{TableAlreadyExists, ValueNotFound},
-    query::predicate::TableNameAndFieldName,
-    record::record_page::{self, TableFieldType, TableSchema},
+    error::{
+        DatabaseError, FieldAlreadyExists, InvalidBlockSize, SchemaTooLarge, TableAlreadyExists,
+        TooManyFields, TypeMismatch, ValueNotFound,
+    },
+    query::predicate::{Constant, ConstantValue, TableNameAndFieldName},
+    record::record_page::{self, KeyConstraint, Layout, TableFieldInfo, TableFieldType, TableSchema},
+    record::record_page_v2::RecordPage,
     record::scan_v2::ScanV2,
-    record::table_scan_v2::TableScan,
+    record::table_scan_v2::{RecordID, TableScan},
+    storage::block::BlockId,
     tx::transaction_v2::TransactionV2,
 };
 
 pub struct TableManagerV2 {
     pub table_catalog_layout: crate::record::record_page::Layout,
     pub field_catalog_layout: crate::record::record_page::Layout,
+    pub default_catalog_layout: crate::record::record_page::Layout,
 }
 
 impl TableManagerV2 {
     const TABLE_CATALOG_TABLE_NAME: &'static str = "table_catalog";
     const FIELD_CATALOG_TABLE_NAME: &'static str = "field_catalog";
+    const DEFAULT_CATALOG_TABLE_NAME: &'static str = "default_catalog";
 
     const TABLE_CATALOG_TABLE_NAME_FIELD: &'static str = "table_name";
     const TABLE_CATALOG_SLOT_SIZE_FIELD: &'static str = "slot_size";
+    const TABLE_CATALOG_BLOCK_SIZE_FIELD: &'static str = "block_size";
+    const FIELD_DEFAULT_LENGTH: i32 = 20;
+    // field_constraintのbit
+    const FIELD_CONSTRAINT_NOT_NULL: i32 = 1;
+    const FIELD_CONSTRAINT_UNIQUE: i32 = 2;
+    const FIELD_CONSTRAINT_PRIMARY_KEY: i32 = 4;
 
     pub fn new(transaction: Rc<RefCell<TransactionV2>>, is_new: bool) -> TableManagerV2 {
         let mut table_catalog_schema = TableSchema::new();
         table_catalog_schema.add_string_field("table_name".to_string(), 20);
         table_catalog_schema.add_integer_field("slot_size".to_string());
+        // with (block_size = n) のblock size。このfieldでtable_catalogの形が変わるので、
+        // これが無いdatabaseはMetadataManager::FORMAT_VERSIONの確認で開かない
+        table_catalog_schema.add_integer_field("block_size".to_string());
 
         let table_catalog_layout = crate::record::record_page::Layout::new(table_catalog_schema.clone());
 
@@ -34,30 +50,63 @@ impl TableManagerV2 {
         field_catalog_schema.add_integer_field("field_type".to_string());
         field_catalog_schema.add_integer_field("field_length".to_string());
         field_catalog_schema.add_integer_field("field_offset".to_string());
+        // DECIMALのscale。precisionはfield_lengthに入れる
+        field_catalog_schema.add_integer_field("field_scale".to_string());
+        // not null, unique, primary keyのbitを足したもの
+        // 書かれていないslotは0で読めるので、nullableで制約なしになる
+        field_catalog_schema.add_integer_field("field_constraint".to_string());
+        // add columnで足したfieldが入っている最初のblock。create tableのfieldは0
+        field_catalog_schema.add_integer_field("field_first_block".to_string());
         let table_field_schema = crate::record::record_page::Layout::new(field_catalog_schema.clone());
 
+        // defaultのあるfieldだけ、値をfieldの型に合わせた文字列で持つ
+        // field_catalogに置くと1つのblockに1行しか入らなくなるので分けている
+        let mut default_catalog_schema = TableSchema::new();
+        default_catalog_schema.add_string_field("table_name".to_string(), 20);
+        default_catalog_schema.add_string_field("field_name".to_string(), 20);
+        default_catalog_schema
+            .add_string_field("default_value".to_string(), Self::FIELD_DEFAULT_LENGTH);
+        let default_catalog_layout = Layout::new(default_catalog_schema.clone());
+
         let table_manager = TableManagerV2 {
             table_catalog_layout,
             field_catalog_layout: table_field_schema,
+            default_catalog_layout,
         };
 
         if is_new {
-            table_manager.create_table(
+            // 新しいdatabaseなので、同じ名前のtableは無い
+            let _ = table_manager.create_table(
                 Self::TABLE_CATALOG_TABLE_NAME.to_string(),
                 &table_catalog_schema,
                 transaction.clone(),
             );
 
-            table_manager.create_table(
+            let _ = table_manager.create_table(
                 Self::FIELD_CATALOG_TABLE_NAME.to_string(),
                 &field_catalog_schema,
                 transaction.clone(),
             );
+
+            let _ = table_manager.create_table(
+                Self::DEFAULT_CATALOG_TABLE_NAME.to_string(),
+                &default_catalog_schema,
+                transaction.clone(),
+            );
         }
 
         table_manager
     }
 
+    // 書き込まれていないfieldはnull bitが立っているので、NULLを未記入として扱う
+    fn read_catalog_name(scan: &mut TableScan, field_name: &str) -> Option<String> {
+        let value = scan.get_value(TableNameAndFieldName::new(None, field_name.to_string()))?;
+        match value {
+            ConstantValue::String(name) => return Some(name),
+            _ => return None,
+        }
+    }
+
     pub fn check_if_field_exists(
         &self,
         table_name: String,
@@ -71,10 +120,8 @@ impl TableManagerV2 {
         );
 
         while field_scan.next().unwrap() {
-            let t_name =
-                field_scan.get_string(TableNameAndFieldName::new(None, "table_name".to_string()));
-            let f_name =
-                field_scan.get_string(TableNameAndFieldName::new(None, "field_name".to_string()));
+            let t_name = Self::read_catalog_name(&mut field_scan, "table_name");
+            let f_name = Self::read_catalog_name(&mut field_scan, "field_name");
 
             match t_name {
                 Some(t_name) => {
@@ -109,8 +156,7 @@ impl TableManagerV2 {
         );
 
         while table_scan.next().unwrap() {
-            let name =
-                table_scan.get_string(TableNameAndFieldName::new(None, "table_name".to_string()));
+            let name = Self::read_catalog_name(&mut table_scan, "table_name");
 
             match name {
                 Some(name) => {
@@ -126,14 +172,111 @@ impl TableManagerV2 {
         return false;
     }
 
+    pub fn list_tables(&self, transaction: Rc<RefCell<TransactionV2>>) -> Vec<String> {
+        let mut table_scan = TableScan::new(
+            Self::TABLE_CATALOG_TABLE_NAME.to_string(),
+            transaction.clone(),
+            self.table_catalog_layout.clone(),
+        );
+
+        let mut table_names = vec![];
+        while table_scan.next().unwrap() {
+            let name =
+                Self::read_catalog_name(&mut table_scan, Self::TABLE_CATALOG_TABLE_NAME_FIELD);
+            if let Some(name) = name {
+                table_names.push(name);
+            }
+        }
+        table_scan.close();
+        return table_names;
+    }
+
+    // catalogに記録された全部のtableのblock sizeをFileManagerに登録する
+    // recoveryはlayoutを読まずにtableのfileに書き戻すので、その前に呼ぶ
+    pub fn register_file_block_sizes(
+        &self,
+        transaction: Rc<RefCell<TransactionV2>>,
+    ) -> Result<(), ValueNotFound> {
+        let mut table_scan = TableScan::new(
+            Self::TABLE_CATALOG_TABLE_NAME.to_string(),
+            transaction.clone(),
+            self.table_catalog_layout.clone(),
+        );
+
+        while table_scan.next()? {
+            let name =
+                Self::read_catalog_name(&mut table_scan, Self::TABLE_CATALOG_TABLE_NAME_FIELD);
+            let block_size = table_scan.get_integer(TableNameAndFieldName::new(
+                None,
+                Self::TABLE_CATALOG_BLOCK_SIZE_FIELD.to_string(),
+            ));
+            if let (Some(name), Some(block_size)) = (name, block_size) {
+                if block_size > 0 {
+                    transaction
+                        .borrow()
+                        .set_file_block_size(&format!("{}.tbl", name), block_size as usize);
+                }
+            }
+        }
+        table_scan.close();
+        return Ok(());
+    }
+
     pub fn create_table(
         &self,
         table_name: String,
         schema: &TableSchema,
         transaction: Rc<RefCell<TransactionV2>>,
-    ) -> Result<(), TableAlreadyExists> {
+    ) -> Result<(), DatabaseError> {
+        return self.create_table_inner(table_name, schema, None, transaction);
+    }
+
+    // block_sizeはこのtableのfileにだけ使われる。logやcatalogはdatabaseのblock_sizeのまま
+    pub fn create_table_with_block_size(
+        &self,
+        table_name: String,
+        schema: &TableSchema,
+        block_size: usize,
+        transaction: Rc<RefCell<TransactionV2>>,
+    ) -> Result<(), DatabaseError> {
+        return self.create_table_inner(table_name, schema, Some(block_size), transaction);
+    }
+
+    // block_sizeがNoneのときは、databaseのblock sizeを使う
+    fn create_table_inner(
+        &self,
+        table_name: String,
+        schema: &TableSchema,
+        block_size: Option<usize>,
+        transaction: Rc<RefCell<TransactionV2>>,
+    ) -> Result<(), DatabaseError> {
+        // null bitmapに入らないfieldはnullを記録できない
+        if schema.fields.len() > Layout::MAX_FIELD_COUNT {
+            return Err(
+                TooManyFields::new(table_name, schema.fields.len(), Layout::MAX_FIELD_COUNT).into(),
+            );
+        }
+
         let layout = crate::record::record_page::Layout::new(schema.clone());
 
+        for field_name in schema.fields.iter() {
+            Self::check_default(&table_name, field_name, &schema.field_infos[field_name])?;
+        }
+
+        // 1つのrecordも入らないblock sizeでは、slotがblockの外に書かれてしまう
+        // 指定したblock sizeが小さすぎるのか、schemaが大きすぎるのかでエラーを分ける
+        let slot_size = layout.get_slot_size() as usize;
+        let block_size = match block_size {
+            Some(block_size) if block_size < slot_size => {
+                return Err(InvalidBlockSize::new(table_name, block_size, slot_size).into());
+            }
+            Some(block_size) => block_size,
+            None => transaction.borrow().get_block_size(),
+        };
+        if block_size < slot_size {
+            return Err(SchemaTooLarge::new(table_name, slot_size, block_size).into());
+        }
+
         let mut table_scan = TableScan::new(
             Self::TABLE_CATALOG_TABLE_NAME.to_string(),
             transaction.clone(),
@@ -142,7 +285,7 @@ impl TableManagerV2 {
 
         if self.check_if_table_exists(table_name.clone(), transaction.clone()) {
             table_scan.close();
-            return Err(TableAlreadyExists::new(table_name));
+            return Err(TableAlreadyExists::new(table_name).into());
         }
 
         table_scan.insert();
@@ -152,9 +295,15 @@ impl TableManagerV2 {
         );
         let slot_size = layout.get_slot_size() as i32;
         table_scan.set_integer(Self::TABLE_CATALOG_SLOT_SIZE_FIELD.to_string(), slot_size);
+        table_scan.set_integer(
+            Self::TABLE_CATALOG_BLOCK_SIZE_FIELD.to_string(),
+            block_size as i32,
+        );
         table_scan.close();
 
-        transaction.borrow_mut().commit();
+        transaction
+            .borrow()
+            .set_file_block_size(&format!("{}.tbl", table_name), block_size);
 
         let mut field_scan = TableScan::new(
             Self::FIELD_CATALOG_TABLE_NAME.to_string(),
@@ -164,22 +313,413 @@ impl TableManagerV2 {
 
         // schemaのfieldsをループして、各フィールドの情報を挿入
         for (_, field_name) in schema.fields.iter().enumerate() {
-            let field_type = schema.get_field_type(field_name.to_string()).unwrap();
-            let field_length = schema.get_field_length(field_name.to_string()).unwrap();
-            let field_offset = layout.get_offset(&field_name).unwrap();
+            Self::insert_field_catalog_row(&mut field_scan, &table_name, field_name, &layout);
+        }
+
+        field_scan.close();
+
+        for field_name in schema.fields.iter() {
+            self.insert_default_catalog_row(
+                &table_name,
+                field_name,
+                &schema.field_infos[field_name],
+                transaction.clone(),
+            );
+        }
+
+        // commitは呼び出し側のtransactionに任せる
+        return Ok(());
+    }
+
+    fn insert_field_catalog_row(
+        field_scan: &mut TableScan,
+        table_name: &String,
+        field_name: &String,
+        layout: &Layout,
+    ) {
+        let schema = &layout.schema;
+        let field_type = schema.get_field_type(field_name.to_string()).unwrap();
+        let field_length = schema.get_field_length(field_name.to_string()).unwrap();
+        let field_offset = layout.get_offset(field_name).unwrap();
+        let field_scale = match field_type {
+            TableFieldType::DECIMAL(_, scale) => scale,
+            _ => 0,
+        };
+        let field_info = &schema.field_infos[field_name];
+
+        field_scan.insert();
+        field_scan.set_string("table_name".to_string(), table_name.clone());
+        field_scan.set_string("field_name".to_string(), field_name.clone());
+        field_scan.set_integer("field_type".to_string(), field_type.into());
+        field_scan.set_integer("field_length".to_string(), field_length);
+        field_scan.set_integer("field_offset".to_string(), field_offset);
+        field_scan.set_integer("field_scale".to_string(), field_scale);
+        let mut field_constraint = match field_info.key {
+            None => 0,
+            Some(KeyConstraint::Unique) => Self::FIELD_CONSTRAINT_UNIQUE,
+            Some(KeyConstraint::PrimaryKey) => Self::FIELD_CONSTRAINT_PRIMARY_KEY,
+        };
+        if !field_info.nullable {
+            field_constraint |= Self::FIELD_CONSTRAINT_NOT_NULL;
+        }
+        field_scan.set_integer("field_constraint".to_string(), field_constraint);
+        field_scan.set_integer(
+            "field_first_block".to_string(),
+            layout.get_first_block(field_name) as i32,
+        );
+    }
+
+    // defaultの無いfieldは行を作らない
+    fn insert_default_catalog_row(
+        &self,
+        table_name: &String,
+        field_name: &String,
+        field_info: &TableFieldInfo,
+        transaction: Rc<RefCell<TransactionV2>>,
+    ) {
+        let Some(default) = &field_info.default else {
+            return;
+        };
+        let Some(text) = Self::encode_default(field_info, default) else {
+            return;
+        };
+
+        let mut default_scan = TableScan::new(
+            Self::DEFAULT_CATALOG_TABLE_NAME.to_string(),
+            transaction,
+            self.default_catalog_layout.clone(),
+        );
+        default_scan.insert();
+        default_scan.set_string("table_name".to_string(), table_name.clone());
+        default_scan.set_string("field_name".to_string(), field_name.clone());
+        default_scan.set_string("default_value".to_string(), text);
+        default_scan.close();
+    }
+
+    // defaultがfieldの型に合わなければTypeMismatch
+    fn check_default(
+        table_name: &String,
+        field_name: &String,
+        field_info: &TableFieldInfo,
+    ) -> Result<(), DatabaseError> {
+        let Some(default) = &field_info.default else {
+            return Ok(());
+        };
+        if Self::encode_default(field_info, default).is_none() {
+            return Err(TypeMismatch::new(field_name.clone(), table_name.clone()).into());
+        }
+        return Ok(());
+    }
+
+    // fieldの型に揃えてから文字列にする。DECIMALはfieldのscaleをかけた整数
+    // 型が合わないか、catalogやfieldに入りきらない文字列ならNone
+    fn encode_default(field_info: &TableFieldInfo, default: &Constant) -> Option<String> {
+        let text = match (&field_info.field_type, &default.value) {
+            (TableFieldType::INTEGER, ConstantValue::Number(n)) => n.to_string(),
+            (TableFieldType::DECIMAL(precision, scale), ConstantValue::Number(n)) => {
+                let value = Constant::rescale_decimal(*n, 0, *scale as u32)?;
+                Self::fit_decimal_digits(value, *precision)?.to_string()
+            }
+            (
+                TableFieldType::DECIMAL(precision, scale),
+                ConstantValue::Decimal { value, scale: from },
+            ) => {
+                let value = Constant::rescale_decimal(*value, *from, *scale as u32)?;
+                Self::fit_decimal_digits(value, *precision)?.to_string()
+            }
+            (TableFieldType::FLOAT, value) => format!("{:?}", Constant::float_value(value)?),
+            (TableFieldType::DATE, ConstantValue::Date(days)) => days.to_string(),
+            (TableFieldType::VARCHAR, ConstantValue::String(string)) => {
+                if string.chars().count() > field_info.field_length as usize {
+                    return None;
+                }
+                string.clone()
+            }
+            _ => return None,
+        };
+        if text.chars().count() > Self::FIELD_DEFAULT_LENGTH as usize {
+            return None;
+        }
+        return Some(text);
+    }
+
+    // insertのときと同じく、precisionの桁数に収まるか確かめる
+    fn fit_decimal_digits(value: i32, precision: i32) -> Option<i32> {
+        if precision <= 0 {
+            return Some(value);
+        }
+        match 10_i64.checked_pow(precision as u32) {
+            Some(limit) if (value as i64).abs() >= limit => return None,
+            _ => return Some(value),
+        }
+    }
+
+    fn decode_default(field_type: &TableFieldType, text: &str) -> Option<Constant> {
+        let value = match field_type {
+            TableFieldType::INTEGER => ConstantValue::Number(text.parse::<i32>().ok()?),
+            TableFieldType::DECIMAL(_, scale) => ConstantValue::Decimal {
+                value: text.parse::<i32>().ok()?,
+                scale: *scale as u32,
+            },
+            TableFieldType::FLOAT => ConstantValue::Float(text.parse::<f64>().ok()?),
+            TableFieldType::DATE => ConstantValue::Date(text.parse::<i64>().ok()?),
+            TableFieldType::VARCHAR => ConstantValue::String(text.to_string()),
+        };
+        return Some(Constant::new(value));
+    }
+
+    // alter table ... add column。fieldはslotの最後に足して、slot_sizeを広げる
+    // 既存のblockは書き直さず、今あるblockの数をfieldの最初のblockとして記録する
+    // 古いblockはそのときのslot_sizeのまま読むので、slotの位置はずれず、
+    // 足したfieldはdefaultかNULLとして読める。新しい行は古いblockには入れない
+    pub fn add_column(
+        &self,
+        table_name: String,
+        field_name: String,
+        field_info: TableFieldInfo,
+        transaction: Rc<RefCell<TransactionV2>>,
+    ) -> Result<(), DatabaseError> {
+        let old_layout = self.get_layout(table_name.clone(), transaction.clone())?;
+        if old_layout.has_field(field_name.clone()) {
+            return Err(FieldAlreadyExists::new(table_name, field_name).into());
+        }
+        let field_count = old_layout.schema.fields.len() + 1;
+        if field_count > Layout::MAX_FIELD_COUNT {
+            return Err(
+                TooManyFields::new(table_name, field_count, Layout::MAX_FIELD_COUNT).into(),
+            );
+        }
+
+        Self::check_default(&table_name, &field_name, &field_info)?;
+
+        let mut schema = old_layout.schema.clone();
+        schema.add_field(
+            field_name.clone(),
+            field_info.field_type.clone(),
+            field_info.field_length,
+        );
+        schema.set_field_constraints(&field_name, field_info.nullable, field_info.default.clone());
+        schema.set_key_constraint(&field_name, field_info.key);
+        let mut offsets = HashMap::new();
+        for field in old_layout.schema.fields.iter() {
+            offsets.insert(field.clone(), old_layout.get_offset(field).unwrap());
+        }
+        let field_offset = old_layout.get_slot_size();
+        offsets.insert(field_name.clone(), field_offset);
+        let slot_size = field_offset + Layout::get_length_in_bytes(&schema, field_name.clone());
+        let mut layout = Layout::new_with_offset_and_size(schema, offsets, slot_size);
+
+        // 広げたslotが1つもblockに入らなくなるfieldは足せない
+        let file_name = format!("{}.tbl", table_name);
+        let block_size = transaction.borrow().get_file_block_size(&file_name);
+        if block_size < slot_size as usize {
+            return Err(SchemaTooLarge::new(table_name, slot_size as usize, block_size).into());
+        }
+
+        let block_count = transaction.borrow().get_size(file_name);
+        layout.set_first_block(&field_name, block_count as u64);
+
+        let mut table_scan = TableScan::new(
+            Self::TABLE_CATALOG_TABLE_NAME.to_string(),
+            transaction.clone(),
+            self.table_catalog_layout.clone(),
+        );
+        while table_scan.next()? {
+            let name =
+                Self::read_catalog_name(&mut table_scan, Self::TABLE_CATALOG_TABLE_NAME_FIELD);
+            if name.as_ref() == Some(&table_name) {
+                table_scan.set_integer(Self::TABLE_CATALOG_SLOT_SIZE_FIELD.to_string(), slot_size);
+                break;
+            }
+        }
+        table_scan.close();
+
+        let mut field_scan = TableScan::new(
+            Self::FIELD_CATALOG_TABLE_NAME.to_string(),
+            transaction.clone(),
+            self.field_catalog_layout.clone(),
+        );
+        Self::insert_field_catalog_row(&mut field_scan, &table_name, &field_name, &layout);
+        field_scan.close();
+        self.insert_default_catalog_row(&table_name, &field_name, &field_info, transaction);
+
+        return Ok(());
+    }
+
+    // add columnより前からあるblockの行には足したfieldを書けないので、
+    // 今のlayoutのblockに入れ直す。field_nameが入っているblockの行はそのまま
+    // 渡した順に (前のRecordID, 新しいRecordID) を返す
+    pub fn move_to_current_layout(
+        &self,
+        table_name: String,
+        field_name: &str,
+        record_ids: &[RecordID],
+        transaction: Rc<RefCell<TransactionV2>>,
+    ) -> Result<Vec<(RecordID, RecordID)>, DatabaseError> {
+        let layout = self.get_layout(table_name.clone(), transaction.clone())?;
+        let first_block = layout.get_first_block(field_name);
+
+        let mut moved_record_ids = vec![];
+        let mut scan = TableScan::new(table_name, transaction, layout.clone());
+        for record_id in record_ids.iter() {
+            if record_id.get_block_number() >= first_block {
+                moved_record_ids.push((record_id.clone(), record_id.clone()));
+                continue;
+            }
+
+            scan.move_to_record_id(record_id.clone());
+            let values = layout
+                .schema
+                .fields
+                .iter()
+                .map(|field| {
+                    scan.get_value(TableNameAndFieldName::new(None, field.clone()))
+                        .unwrap_or(ConstantValue::Null)
+                })
+                .collect::<Vec<ConstantValue>>();
+            scan.delete();
 
-            field_scan.insert();
-            field_scan.set_string("table_name".to_string(), table_name.clone());
-            field_scan.set_string("field_name".to_string(), field_name.clone());
-            field_scan.set_integer("field_type".to_string(), field_type.into());
-            field_scan.set_integer("field_length".to_string(), field_length);
-            field_scan.set_integer("field_offset".to_string(), field_offset);
+            scan.insert();
+            for (field, value) in layout.schema.fields.iter().zip(values.into_iter()) {
+                scan.set_value(field.clone(), value);
+            }
+            moved_record_ids.push((record_id.clone(), scan.get_record_id()));
         }
+        scan.close();
+
+        return Ok(moved_record_ids);
+    }
 
+    // 全部のblockを今のlayoutで書き直したので、fieldの最初のblockを0に戻す
+    fn reset_first_blocks(
+        &self,
+        table_name: &String,
+        transaction: Rc<RefCell<TransactionV2>>,
+    ) -> Result<(), ValueNotFound> {
+        let mut field_scan = TableScan::new(
+            Self::FIELD_CATALOG_TABLE_NAME.to_string(),
+            transaction,
+            self.field_catalog_layout.clone(),
+        );
+        while field_scan.next()? {
+            let name =
+                Self::read_catalog_name(&mut field_scan, Self::TABLE_CATALOG_TABLE_NAME_FIELD);
+            if name.as_ref() == Some(table_name) {
+                field_scan.set_integer("field_first_block".to_string(), 0);
+            }
+        }
         field_scan.close();
+        return Ok(());
+    }
+
+    // 使っているrecordだけを前のblockから詰めて入れ直す
+    // 動いたrecordの (前のRecordID, 新しいRecordID) と、減るblockの数を返す
+    // 空になった末尾のblockはcommitのときに切り詰める
+    pub fn vacuum_table(
+        &self,
+        table_name: String,
+        transaction: Rc<RefCell<TransactionV2>>,
+    ) -> Result<(Vec<(RecordID, RecordID)>, usize), DatabaseError> {
+        let layout = self.get_layout(table_name.clone(), transaction.clone())?;
 
-        transaction.borrow_mut().commit();
+        let mut records = vec![];
+        let mut scan = TableScan::new(table_name.clone(), transaction.clone(), layout.clone());
+        while scan.next()? {
+            let values = layout
+                .schema
+                .fields
+                .iter()
+                .map(|field| {
+                    scan.get_value(TableNameAndFieldName::new(None, field.clone()))
+                        .unwrap_or(ConstantValue::Null)
+                })
+                .collect::<Vec<ConstantValue>>();
+            records.push((scan.get_record_id(), values));
+        }
+        scan.close();
 
+        // add columnより前からあるblockも、ここで今のlayoutに書き直す
+        let old_layout = layout;
+        let layout = old_layout.without_first_blocks();
+        let file_name = format!("{}.tbl", table_name);
+        let block_count = transaction.borrow().get_size(file_name.clone());
+        for block_number in 0..block_count {
+            let block_id = BlockId::new(file_name.clone(), block_number as u64);
+            let mut record_page =
+                RecordPage::new(transaction.clone(), layout.clone(), block_id.clone());
+            if old_layout.layout_of_block(block_number as u64).is_some() {
+                record_page.clear();
+            } else {
+                record_page.format();
+            }
+            transaction.borrow_mut().unpin(block_id);
+        }
+        if layout != old_layout {
+            self.reset_first_blocks(&table_name, transaction.clone())?;
+        }
+
+        let mut moved_record_ids = vec![];
+        let mut scan = TableScan::new(table_name, transaction.clone(), layout.clone());
+        for (old_record_id, values) in records.into_iter() {
+            scan.insert();
+            for (field, value) in layout.schema.fields.iter().zip(values.into_iter()) {
+                scan.set_value(field.clone(), value);
+            }
+            moved_record_ids.push((old_record_id, scan.get_record_id()));
+        }
+        scan.close();
+
+        // TableScanは空のfileにblockを足すので、最初のblockは残す
+        let used_block_count = moved_record_ids
+            .iter()
+            .map(|(_, record_id)| record_id.get_block_number() as usize + 1)
+            .max()
+            .unwrap_or(1);
+        if used_block_count < block_count {
+            transaction
+                .borrow_mut()
+                .register_truncated_file(file_name, used_block_count);
+        }
+
+        return Ok((
+            moved_record_ids,
+            block_count.saturating_sub(used_block_count),
+        ));
+    }
+
+    // catalogの行を消し、tableのfileはcommitのときに消す
+    pub fn drop_table(
+        &self,
+        table_name: String,
+        transaction: Rc<RefCell<TransactionV2>>,
+    ) -> Result<(), ValueNotFound> {
+        if !self.check_if_table_exists(table_name.clone(), transaction.clone()) {
+            return Err(ValueNotFound::new(
+                table_name,
+                Some(Self::TABLE_CATALOG_TABLE_NAME.to_string()),
+            ));
+        }
+
+        for (catalog_name, layout) in [
+            (Self::TABLE_CATALOG_TABLE_NAME, &self.table_catalog_layout),
+            (Self::FIELD_CATALOG_TABLE_NAME, &self.field_catalog_layout),
+            (Self::DEFAULT_CATALOG_TABLE_NAME, &self.default_catalog_layout),
+        ] {
+            let mut scan =
+                TableScan::new(catalog_name.to_string(), transaction.clone(), layout.clone());
+            while scan.next()? {
+                let name =
+                    Self::read_catalog_name(&mut scan, Self::TABLE_CATALOG_TABLE_NAME_FIELD);
+                if name.as_ref() == Some(&table_name) {
+                    scan.delete();
+                }
+            }
+            scan.close();
+        }
+
+        transaction
+            .borrow_mut()
+            .register_dropped_file(format!("{}.tbl", table_name));
         return Ok(());
     }
 
@@ -195,10 +735,10 @@ impl TableManagerV2 {
         );
 
         let mut slot_size: Option<i32> = None;
+        let mut block_size: Option<i32> = None;
 
         while table_scan.next()? {
-            let name =
-                table_scan.get_string(TableNameAndFieldName::new(None, "table_name".to_string()));
+            let name = Self::read_catalog_name(&mut table_scan, "table_name");
 
             match name {
                 Some(name) => {
@@ -210,6 +750,10 @@ impl TableManagerV2 {
                         match size {
                             Some(size) => {
                                 slot_size = Some(size);
+                                block_size = table_scan.get_integer(TableNameAndFieldName::new(
+                                    None,
+                                    Self::TABLE_CATALOG_BLOCK_SIZE_FIELD.to_string(),
+                                ));
                                 break;
                             }
                             None => continue,
@@ -230,25 +774,37 @@ impl TableManagerV2 {
 
         table_scan.close();
 
+        // tableのfileを読む前に、catalogに記録されたblock sizeを登録しておく
+        if let Some(block_size) = block_size {
+            if block_size > 0 {
+                transaction
+                    .borrow()
+                    .set_file_block_size(&format!("{}.tbl", table_name), block_size as usize);
+            }
+        }
+
         let mut table_schema = TableSchema::new();
 
         let mut field_scan = TableScan::new(
             "field_catalog".to_string(),
-            transaction,
+            transaction.clone(),
             self.field_catalog_layout.clone(),
         );
 
         let mut offsets = HashMap::new();
+        let mut first_blocks = HashMap::new();
 
         while field_scan.next()? {
-            let name =
-                field_scan.get_string(TableNameAndFieldName::new(None, "table_name".to_string()));
+            let name = Self::read_catalog_name(&mut field_scan, "table_name");
 
             match name {
                 Some(name) => {
                     if name == table_name {
-                        let field_name = field_scan
-                            .get_string(TableNameAndFieldName::new(None, "field_name".to_string()));
+                        let Some(field_name) =
+                            Self::read_catalog_name(&mut field_scan, "field_name")
+                        else {
+                            continue;
+                        };
                         let field_type = field_scan.get_integer(TableNameAndFieldName::new(
                             None,
                             "field_type".to_string(),
@@ -261,12 +817,50 @@ impl TableManagerV2 {
                             None,
                             "field_offset".to_string(),
                         ));
-                        offsets.insert(field_name.clone().unwrap(), field_offset.unwrap());
+                        let field_scale = field_scan.get_integer(TableNameAndFieldName::new(
+                            None,
+                            "field_scale".to_string(),
+                        ));
+                        let field_constraint = field_scan
+                            .get_integer(TableNameAndFieldName::new(
+                                None,
+                                "field_constraint".to_string(),
+                            ))
+                            .unwrap_or(0);
+                        let field_key =
+                            if field_constraint & Self::FIELD_CONSTRAINT_PRIMARY_KEY != 0 {
+                                Some(KeyConstraint::PrimaryKey)
+                            } else if field_constraint & Self::FIELD_CONSTRAINT_UNIQUE != 0 {
+                                Some(KeyConstraint::Unique)
+                            } else {
+                                None
+                            };
+                        let field_first_block = field_scan.get_integer(TableNameAndFieldName::new(
+                            None,
+                            "field_first_block".to_string(),
+                        ));
+                        if let Some(first_block) = field_first_block {
+                            first_blocks.insert(field_name.clone(), first_block as u64);
+                        }
+                        offsets.insert(field_name.clone(), field_offset.unwrap());
+                        let field_type = match TableFieldType::from(field_type.unwrap()) {
+                            TableFieldType::DECIMAL(_, _) => TableFieldType::DECIMAL(
+                                field_length.unwrap(),
+                                field_scale.unwrap_or(0),
+                            ),
+                            field_type => field_type,
+                        };
                         table_schema.add_field(
-                            field_name.unwrap(),
-                            TableFieldType::from(field_type.unwrap()),
+                            field_name.clone(),
+                            field_type,
                             field_length.unwrap() as i32,
                         );
+                        table_schema.set_field_constraints(
+                            &field_name,
+                            field_constraint & Self::FIELD_CONSTRAINT_NOT_NULL == 0,
+                            None,
+                        );
+                        table_schema.set_key_constraint(&field_name, field_key);
                     }
                 }
                 None => continue,
@@ -275,59 +869,59 @@ impl TableManagerV2 {
 
         field_scan.close();
 
-        return Ok(crate::record::record_page::Layout::new_with_offset_and_size(
+        let mut default_scan = TableScan::new(
+            Self::DEFAULT_CATALOG_TABLE_NAME.to_string(),
+            transaction,
+            self.default_catalog_layout.clone(),
+        );
+        while default_scan.next()? {
+            let name = Self::read_catalog_name(&mut default_scan, "table_name");
+            if name.as_ref() != Some(&table_name) {
+                continue;
+            }
+            let Some(field_name) = Self::read_catalog_name(&mut default_scan, "field_name") else {
+                continue;
+            };
+            let Some(field_type) = table_schema.get_field_type(field_name.clone()) else {
+                continue;
+            };
+            let default = match default_scan.get_value(TableNameAndFieldName::new(
+                None,
+                "default_value".to_string(),
+            )) {
+                Some(ConstantValue::String(text)) => Self::decode_default(&field_type, &text),
+                _ => None,
+            };
+            let nullable = table_schema.is_nullable(&field_name);
+            table_schema.set_field_constraints(&field_name, nullable, default);
+        }
+        default_scan.close();
+
+        let mut layout = crate::record::record_page::Layout::new_with_offset_and_size(
             table_schema,
             offsets,
             slot_size,
-        ));
+        );
+        for (field_name, first_block) in first_blocks.into_iter() {
+            layout.set_first_block(&field_name, first_block);
+        }
+        return Ok(layout);
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{
-        cell::RefCell,
-        fs::remove_file,
-        path::Path,
-        rc::Rc,
-        sync::{Arc, Mutex},
-    };
-
-    use crate::{
-        buffer::buffer_manager_v2::BufferManagerV2, tx::concurrency_manager::LockTable,
-        storage::file_manager::FileManager, storage::log_manager_v2::LogManagerV2, record::record_page::TableSchema,
-    };
+    use std::path::Path;
+
+    use crate::record::record_page::TableSchema;
 
     use super::*;
 
     #[test]
     fn test_table_mgr() {
-        let test_dir = Path::new("test_data");
-        let block_size = 400;
-
-        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
-
-        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
-        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
-            file_manager.clone(),
-            log_file_name.clone(),
-        )));
-
-        let buffer_manager = Arc::new(Mutex::new(BufferManagerV2::new(
-            3,
-            file_manager.clone(),
-            log_manager.clone(),
-        )));
-
-        let lock_table = Arc::new(Mutex::new(LockTable::new()));
-
-        let transaction = Rc::new(RefCell::new(TransactionV2::new(
-            1,
-            file_manager.clone(),
-            buffer_manager.clone(),
-            lock_table.clone(),
-            log_manager.clone(),
-        )));
+        let test_directory = crate::database::TestDirectory::new();
+        let database = crate::database::Database::new(Path::new(&test_directory.path_name()));
+        let transaction = database.new_transaction(1);
 
         let table_manager = TableManagerV2::new(transaction.clone(), true);
 
@@ -338,7 +932,9 @@ mod tests {
 
         let test_table_name = format!("test_table_{}", uuid::Uuid::new_v4());
 
-        table_manager.create_table(test_table_name.clone(), &schema, transaction.clone());
+        table_manager
+            .create_table(test_table_name.clone(), &schema, transaction.clone())
+            .unwrap();
         let layout = table_manager.get_layout(test_table_name, transaction.clone());
 
         let layout = layout.unwrap();
@@ -357,22 +953,168 @@ mod tests {
 
             if i == 0 {
                 assert_eq!(field, "A");
-                assert_eq!(offset, 4);
+                assert_eq!(offset, 8);
                 assert_eq!(field_type, TableFieldType::INTEGER);
                 assert_eq!(field_length, 0);
             } else if i == 1 {
                 assert_eq!(field, "B");
-                assert_eq!(offset, 8);
+                assert_eq!(offset, 12);
                 assert_eq!(field_type, TableFieldType::VARCHAR);
                 assert_eq!(field_length, 9);
             } else if i == 2 {
                 assert_eq!(field, "C");
-                assert_eq!(offset, 48);
+                assert_eq!(offset, 52);
                 assert_eq!(field_type, TableFieldType::INTEGER);
                 assert_eq!(field_length, 0);
             }
         }
+    }
+
+    #[test]
+    fn test_unwritten_name_in_catalog_is_not_a_table() {
+        let test_directory = crate::database::TestDirectory::new();
+        let test_dir_name = test_directory.path_name();
+        let database = crate::database::Database::new(Path::new(&test_dir_name));
+        let transaction = database.new_transaction(1);
+
+        let table_manager = TableManagerV2::new(transaction.clone(), true);
+
+        let mut schema = TableSchema::new();
+        schema.add_integer_field("A".to_string());
+        table_manager
+            .create_table("real_table".to_string(), &schema, transaction.clone())
+            .unwrap();
+
+        // table名を書かないまま残ったcatalogの行
+        let mut table_scan = TableScan::new(
+            "table_catalog".to_string(),
+            transaction.clone(),
+            table_manager.table_catalog_layout.clone(),
+        );
+        table_scan.insert();
+        table_scan.set_integer("slot_size".to_string(), 8);
+        table_scan.close();
+
+        let mut field_scan = TableScan::new(
+            "field_catalog".to_string(),
+            transaction.clone(),
+            table_manager.field_catalog_layout.clone(),
+        );
+        field_scan.insert();
+        field_scan.set_string("table_name".to_string(), "real_table".to_string());
+        field_scan.set_integer("field_type".to_string(), 0);
+        field_scan.set_integer("field_offset".to_string(), 4);
+        field_scan.close();
 
-        remove_file(test_dir.join(log_file_name)).unwrap();
+        assert!(!table_manager.check_if_table_exists("".to_string(), transaction.clone()));
+        assert!(!table_manager
+            .list_tables(transaction.clone())
+            .contains(&"".to_string()));
+        assert!(table_manager
+            .get_layout("".to_string(), transaction.clone())
+            .is_err());
+        assert!(!table_manager.check_if_field_exists(
+            "real_table".to_string(),
+            "".to_string(),
+            transaction.clone()
+        ));
+
+        // 本物のtableはfield名の無い行に影響されない
+        let layout = table_manager
+            .get_layout("real_table".to_string(), transaction.clone())
+            .unwrap();
+        assert_eq!(layout.schema.fields, vec!["A".to_string()]);
+
+        transaction.borrow_mut().commit().unwrap();
+    }
+
+    #[test]
+    fn test_create_table_larger_than_block_is_rejected() {
+        let test_directory = crate::database::TestDirectory::new();
+        let test_dir_name = test_directory.path_name();
+        let database = crate::database::Database::new(Path::new(&test_dir_name));
+        let transaction = database.new_transaction(1);
+
+        let table_manager = TableManagerV2::new(transaction.clone(), true);
+
+        let mut schema = TableSchema::new();
+        schema.add_integer_field("id".to_string());
+        schema.add_string_field("body".to_string(), 1000);
+
+        let result = table_manager.create_table_with_block_size(
+            "long_text".to_string(),
+            &schema,
+            400,
+            transaction.clone(),
+        );
+        let Err(DatabaseError::InvalidBlockSize(e)) = result else {
+            panic!("Expected InvalidBlockSize, got {:?}", result);
+        };
+        assert!(e.to_string().contains("'long_text'"));
+        assert!(e.to_string().contains("400"));
+
+        // catalogには何も書かれない
+        assert!(
+            !table_manager.check_if_table_exists("long_text".to_string(), transaction.clone())
+        );
+
+        // databaseのblock sizeでも同じように確認する
+        let block_size = transaction.borrow().get_block_size();
+        let mut schema = TableSchema::new();
+        schema.add_string_field("body".to_string(), block_size as i32);
+        // block sizeを指定していないので、schemaが大きすぎるエラーになる
+        let result =
+            table_manager.create_table("too_wide".to_string(), &schema, transaction.clone());
+        let Err(DatabaseError::SchemaTooLarge(e)) = result else {
+            panic!("Expected SchemaTooLarge, got {:?}", result);
+        };
+        assert!(e.to_string().contains("'too_wide'"));
+        assert!(e.to_string().contains(&block_size.to_string()));
+        assert!(
+            !table_manager.check_if_table_exists("too_wide".to_string(), transaction.clone())
+        );
+        transaction.borrow_mut().commit().unwrap();
+    }
+
+    #[test]
+    fn test_too_many_fields_is_rejected() {
+        let test_directory = crate::database::TestDirectory::new();
+        let test_dir_name = test_directory.path_name();
+        let database = crate::database::Database::new(Path::new(&test_dir_name));
+        let transaction = database.new_transaction(1);
+
+        let table_manager = TableManagerV2::new(transaction.clone(), true);
+
+        let mut schema = TableSchema::new();
+        for i in 0..Layout::MAX_FIELD_COUNT + 1 {
+            schema.add_integer_field(format!("f{}", i));
+        }
+        let result = table_manager.create_table("wide".to_string(), &schema, transaction.clone());
+        let Err(DatabaseError::TooManyFields(e)) = result else {
+            panic!("Expected TooManyFields, got {:?}", result);
+        };
+        assert!(e.to_string().contains("'wide'"));
+        assert!(!table_manager.check_if_table_exists("wide".to_string(), transaction.clone()));
+
+        // 32個までなら作れて、それ以上はadd columnできない
+        let mut schema = TableSchema::new();
+        for i in 0..Layout::MAX_FIELD_COUNT {
+            schema.add_integer_field(format!("f{}", i));
+        }
+        table_manager
+            .create_table("full".to_string(), &schema, transaction.clone())
+            .unwrap();
+        let result = table_manager.add_column(
+            "full".to_string(),
+            "extra".to_string(),
+            TableFieldInfo::new(TableFieldType::INTEGER, 0),
+            transaction.clone(),
+        );
+        assert!(matches!(result, Err(DatabaseError::TooManyFields(_))));
+        let layout = table_manager
+            .get_layout("full".to_string(), transaction.clone())
+            .unwrap();
+        assert_eq!(layout.schema.fields.len(), Layout::MAX_FIELD_COUNT);
+        transaction.borrow_mut().commit().unwrap();
     }
 }
@@ -46,7 +46,6 @@ impl ViewManager {
         table_scan.set_string("view_name".to_string(), view_name);
         table_scan.set_string("view_definition".to_string(), view_definition);
         table_scan.close();
-        transaction.borrow_mut().commit();
     }
 
     pub fn get_view_definition(
@@ -82,51 +81,19 @@ impl ViewManager {
 
 #[cfg(test)]
 mod tests {
-    use std::{
-        cell::RefCell,
-        path::Path,
-        rc::Rc,
-        sync::{Arc, Mutex},
-    };
+    use std::{cell::RefCell, path::Path, rc::Rc};
 
     use crate::{
-        buffer::buffer_manager_v2::BufferManagerV2,
-        tx::concurrency_manager::LockTable,
-        storage::file_manager::FileManager,
-        storage::log_manager_v2::LogManagerV2,
+        database::Database,
         metadata::table_manager_v2::TableManagerV2,
-        tx::transaction_v2::TransactionV2,
         metadata::view_manager::ViewManager,
     };
 
     #[test]
     fn test_view_mgr() {
-        let test_dir = Path::new("test_data");
-        let block_size = 400;
-
-        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
-
-        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
-        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
-            file_manager.clone(),
-            log_file_name.clone(),
-        )));
-
-        let buffer_manager = Arc::new(Mutex::new(BufferManagerV2::new(
-            100,
-            file_manager.clone(),
-            log_manager.clone(),
-        )));
-
-        let lock_table = Arc::new(Mutex::new(LockTable::new()));
-
-        let transaction = Rc::new(RefCell::new(TransactionV2::new(
-            1,
-            file_manager.clone(),
-            buffer_manager.clone(),
-            lock_table.clone(),
-            log_manager.clone(),
-        )));
+        let test_directory = crate::database::TestDirectory::new();
+        let database = Database::new(Path::new(&test_directory.path_name()));
+        let transaction = database.new_transaction(1);
 
         let table_manager = Rc::new(RefCell::new(TableManagerV2::new(transaction.clone(), true)));
 
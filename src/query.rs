@@ -1,11 +1,19 @@
+pub mod csv;
+pub mod cursor;
+pub mod distinct_plan;
+pub mod extend_plan;
 pub mod group_by;
+pub mod index_join_plan;
 pub mod index_join_scan;
 pub mod index_select_plan;
 pub mod index_update_planner;
+pub mod json;
 pub mod materialize;
+pub mod merge_join_plan;
 pub mod parser;
 pub mod plan_v2;
 pub mod predicate;
 pub mod predicate_v3;
 pub mod query_handler;
 pub mod sort_plan;
+pub mod values_plan;
@@ -0,0 +1,138 @@
+use crate::query::predicate::{Constant, ConstantValue};
+
+// RFC 4180形式のcsvの読み書き
+// NULLは空の項目、空文字列は "" として区別する
+
+pub fn format_csv_value(value: &ConstantValue) -> String {
+    match value {
+        ConstantValue::Null => return "".to_string(),
+        ConstantValue::Parameter(index) => panic!("Parameter {} is not bound", index + 1),
+        ConstantValue::Number(i) => return i.to_string(),
+        ConstantValue::Decimal { value, scale } => {
+            return Constant::format_decimal(*value, *scale);
+        }
+        ConstantValue::Float(f) => return f.to_string(),
+        ConstantValue::Date(days) => return Constant::format_date(*days),
+        ConstantValue::String(s) => {
+            let needs_quote = s.is_empty()
+                || s.contains(',')
+                || s.contains('"')
+                || s.contains('\n')
+                || s.contains('\r');
+            if !needs_quote {
+                return s.clone();
+            }
+            return format!("\"{}\"", s.replace('"', "\"\""));
+        }
+    }
+}
+
+pub fn format_csv_record(fields: &[String]) -> String {
+    return format!("{}\r\n", fields.join(","));
+}
+
+// 項目の中の改行も扱えるように、行ではなくファイル全体を読む
+// 囲まれていない空の項目はNone
+pub fn parse_csv(content: &str) -> Vec<Vec<Option<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+    let mut has_field = false;
+
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                field.push(c);
+            }
+            continue;
+        }
+
+        match c {
+            '"' => {
+                in_quotes = true;
+                quoted = true;
+                has_field = true;
+            }
+            ',' => {
+                record.push(finish_field(&mut field, &mut quoted));
+                has_field = true;
+            }
+            '\r' | '\n' => {
+                if c == '\r' && chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                // 空行は読み飛ばす
+                if has_field || !field.is_empty() {
+                    record.push(finish_field(&mut field, &mut quoted));
+                    records.push(std::mem::take(&mut record));
+                }
+                has_field = false;
+            }
+            _ => {
+                field.push(c);
+            }
+        }
+    }
+
+    if has_field || !field.is_empty() {
+        record.push(finish_field(&mut field, &mut quoted));
+        records.push(record);
+    }
+
+    return records;
+}
+
+fn finish_field(field: &mut String, quoted: &mut bool) -> Option<String> {
+    let value = std::mem::take(field);
+    let was_quoted = *quoted;
+    *quoted = false;
+    if value.is_empty() && !was_quoted {
+        return None;
+    }
+    return Some(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_round_trip() {
+        let values = vec![
+            ConstantValue::Number(1),
+            ConstantValue::String("a, \"b\"\nc".to_string()),
+            ConstantValue::Null,
+            ConstantValue::String("".to_string()),
+            ConstantValue::Decimal {
+                value: -125,
+                scale: 2,
+            },
+        ];
+        let fields: Vec<String> = values.iter().map(format_csv_value).collect();
+        let content = format_csv_record(&fields) + &format_csv_record(&["x".to_string()]);
+
+        assert_eq!(
+            parse_csv(&content),
+            vec![
+                vec![
+                    Some("1".to_string()),
+                    Some("a, \"b\"\nc".to_string()),
+                    None,
+                    Some("".to_string()),
+                    Some("-1.25".to_string()),
+                ],
+                vec![Some("x".to_string())],
+            ]
+        );
+    }
+}
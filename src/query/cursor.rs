@@ -0,0 +1,192 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    error::{DatabaseError, ValueNotFound},
+    query::plan_v2::PlanV2,
+    query::predicate::{ConstantValue, TableNameAndFieldName},
+    record::record_page::TableSchema,
+    record::scan_v2::ScanV2,
+    tx::transaction_v2::TransactionV2,
+};
+
+// schemaのfieldの順に並んだ1行分の値
+pub type Row = Vec<ConstantValue>;
+
+// planを開いたままにして、fetchのたびに少しずつ行を取り出す
+// scanが持っているbufferのpinはclose (またはdrop) まで保持される
+pub struct Cursor {
+    schema: TableSchema,
+    scan: Option<Box<dyn ScanV2>>,
+    // lockを待ちきれなかったときにrollbackするtransaction
+    transaction: Rc<RefCell<TransactionV2>>,
+}
+
+impl Cursor {
+    pub fn open(
+        plan: Box<dyn PlanV2>,
+        transaction: Rc<RefCell<TransactionV2>>,
+    ) -> Result<Cursor, DatabaseError> {
+        let schema = plan.get_schema().clone();
+        let scan = TransactionV2::abort_on_lock_failure(transaction.clone(), || {
+            return Ok(plan.open()?);
+        })?;
+        return Ok(Cursor {
+            schema,
+            scan: Some(scan),
+            transaction,
+        });
+    }
+
+    pub fn get_schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.scan.is_none()
+    }
+
+    // 最大n行を返す。n行より少なければscanを最後まで読んだということ
+    // lockを待ちきれずに読んだ値は正しくないので、transactionをrollbackしてTransactionAbortedを返す
+    pub fn fetch(&mut self, n: usize) -> Result<Vec<Row>, DatabaseError> {
+        let transaction = self.transaction.clone();
+        let result = TransactionV2::abort_on_lock_failure(transaction, || {
+            return Ok(self.fetch_rows(n)?);
+        });
+        // rollbackでbufferのpinは外れているので、scanも閉じておく
+        if let Err(DatabaseError::TransactionAborted(_)) = &result {
+            self.close();
+        }
+        return result;
+    }
+
+    fn fetch_rows(&mut self, n: usize) -> Result<Vec<Row>, ValueNotFound> {
+        let mut rows = Vec::new();
+
+        let Some(scan) = self.scan.as_mut() else {
+            return Ok(rows);
+        };
+
+        while rows.len() < n && scan.next()? {
+            let mut row = Vec::new();
+            for field in self.schema.fields.iter() {
+                let value = scan.get_value(TableNameAndFieldName::new(None, field.clone()));
+                match value {
+                    Some(value) => row.push(value),
+                    None => return Err(ValueNotFound::new(field.clone(), None)),
+                }
+            }
+            rows.push(row);
+        }
+
+        return Ok(rows);
+    }
+
+    pub fn close(&mut self) {
+        if let Some(mut scan) = self.scan.take() {
+            scan.close();
+        }
+    }
+}
+
+impl Drop for Cursor {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        database::Database,
+        metadata::metadata_manager::MetadataManager,
+        query::parser::{parse_sql, ParsedSQL},
+        query::plan_v2::{create_query_plan, execute_create_table, execute_insert},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_cursor_fetch_in_batches() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let parsed_sql_list =
+            parse_sql("create table cursor_table (id integer, name varchar(10))".to_string())
+                .unwrap();
+        let ParsedSQL::CreateTable(create_table_data) = &parsed_sql_list[0] else {
+            panic!("Expected a CreateTable variant from parse_sql");
+        };
+        execute_create_table(
+            transaction.clone(),
+            &mut metadata_manager,
+            create_table_data.clone(),
+        )
+        .unwrap();
+
+        for id in 0..10 {
+            let parsed_sql_list = parse_sql(format!(
+                "insert into cursor_table (id, name) values ({}, 'name {}')",
+                id, id
+            ))
+            .unwrap();
+            let ParsedSQL::Insert(insert_data) = &parsed_sql_list[0] else {
+                panic!("Expected a Insert variant from parse_sql");
+            };
+            execute_insert(
+                transaction.clone(),
+                &mut metadata_manager,
+                insert_data.clone(),
+            )
+            .unwrap();
+        }
+        transaction.borrow_mut().commit()?;
+
+        let parsed_sql_list = parse_sql("select id, name from cursor_table".to_string()).unwrap();
+        let ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+        let plan = create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+
+        let available_before_open = transaction.borrow().get_available_buffer_size();
+
+        let mut cursor = Cursor::open(plan, transaction.clone())?;
+
+        let first_batch = cursor.fetch(5)?;
+        // fetchの間もscanのpinは残っている
+        assert!(transaction.borrow().get_available_buffer_size() < available_before_open);
+        let second_batch = cursor.fetch(5)?;
+        assert_eq!(cursor.fetch(5)?, Vec::<Row>::new());
+
+        let ids: Vec<ConstantValue> = first_batch
+            .iter()
+            .chain(second_batch.iter())
+            .map(|row| row[0].clone())
+            .collect();
+        assert_eq!(first_batch.len(), 5);
+        assert_eq!(second_batch.len(), 5);
+        assert_eq!(ids, (0..10).map(ConstantValue::Number).collect::<Vec<_>>());
+        assert_eq!(
+            first_batch[0][1],
+            ConstantValue::String("name 0".to_string())
+        );
+
+        cursor.close();
+        assert!(cursor.is_closed());
+        assert_eq!(
+            transaction.borrow().get_available_buffer_size(),
+            available_before_open
+        );
+        // close後のfetchは空
+        assert_eq!(cursor.fetch(5)?, Vec::<Row>::new());
+
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+}
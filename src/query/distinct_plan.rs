@@ -0,0 +1,300 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    error::ValueNotFound,
+    query::plan_v2::{PlanTreeNodeForDebug, PlanV2},
+    query::predicate::{ConstantValue, TableNameAndFieldName},
+    query::sort_plan::{SortDirection, SortPlan},
+    record::record_page::TableSchema,
+    record::scan_v2::ScanV2,
+    record::table_scan_v2::RecordID,
+    tx::transaction_v2::TransactionV2,
+};
+
+// select distinct の重複した行を取り除くplan
+// fieldの値で並べ替えてから、直前の行と同じ値の行を飛ばす
+pub struct DistinctPlan {
+    sort_plan: SortPlan,
+    fields: Vec<TableNameAndFieldName>,
+}
+
+impl DistinctPlan {
+    pub fn new(
+        transaction: Rc<RefCell<TransactionV2>>,
+        plan: Box<dyn PlanV2>,
+        fields: Vec<String>,
+    ) -> Self {
+        // 並べ替えた後のtemp tableにはtable名が無いので、field名だけで参照する
+        let fields = fields
+            .into_iter()
+            .map(|field| TableNameAndFieldName::new(None, field))
+            .collect::<Vec<TableNameAndFieldName>>();
+        let sort_fields = fields
+            .iter()
+            .map(|field| (field.clone(), SortDirection::Ascending))
+            .collect();
+        DistinctPlan {
+            sort_plan: SortPlan::new(transaction, plan, sort_fields),
+            fields,
+        }
+    }
+}
+
+impl PlanV2 for DistinctPlan {
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+        let scan = self.sort_plan.open()?;
+        return Ok(Box::new(DistinctScan::new(scan, self.fields.clone())));
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        self.sort_plan.get_schema()
+    }
+
+    fn blocks_accessed(&self) -> u32 {
+        self.sort_plan.blocks_accessed()
+    }
+
+    fn records_output(&self) -> u32 {
+        self.sort_plan.records_output()
+    }
+
+    fn get_distinct_value(&self, field_name: String) -> u32 {
+        self.sort_plan.get_distinct_value(field_name)
+    }
+
+    fn get_child_plans(&self) -> PlanTreeNodeForDebug {
+        let fields = self
+            .fields
+            .iter()
+            .map(|field| field.field_name.clone())
+            .collect::<Vec<String>>();
+        PlanTreeNodeForDebug {
+            current_node_type: format!("DistinctPlan({})", fields.join(", ")),
+            records_output: self.records_output(),
+            blocks_accessed: self.blocks_accessed(),
+            child_nodes: vec![self.sort_plan.get_child_plans()],
+        }
+    }
+}
+
+pub struct DistinctScan {
+    scan: Box<dyn ScanV2>,
+    fields: Vec<TableNameAndFieldName>,
+    // 最後に返した行の値
+    previous_values: Option<Vec<Option<ConstantValue>>>,
+}
+
+impl DistinctScan {
+    pub fn new(scan: Box<dyn ScanV2>, fields: Vec<TableNameAndFieldName>) -> Self {
+        DistinctScan {
+            scan,
+            fields,
+            previous_values: None,
+        }
+    }
+}
+
+impl ScanV2 for DistinctScan {
+    fn move_to_before_first(&mut self) -> Result<(), ValueNotFound> {
+        self.previous_values = None;
+        self.scan.move_to_before_first()
+    }
+
+    fn next(&mut self) -> Result<bool, ValueNotFound> {
+        while self.scan.next()? {
+            // NULL同士も同じ値として1行にまとめる
+            let values = self
+                .fields
+                .iter()
+                .map(|field| self.scan.get_value(field.clone()))
+                .collect::<Vec<Option<ConstantValue>>>();
+            if self.previous_values.as_ref() == Some(&values) {
+                continue;
+            }
+            self.previous_values = Some(values);
+            return Ok(true);
+        }
+        return Ok(false);
+    }
+
+    fn get_integer(&mut self, field_name: TableNameAndFieldName) -> Option<i32> {
+        self.scan.get_integer(field_name)
+    }
+
+    fn get_string(&mut self, field_name: TableNameAndFieldName) -> Option<String> {
+        self.scan.get_string(field_name)
+    }
+
+    fn get_value(&mut self, field_name: TableNameAndFieldName) -> Option<ConstantValue> {
+        self.scan.get_value(field_name)
+    }
+
+    fn close(&mut self) {
+        self.scan.close();
+    }
+
+    fn has_field(&self, field_name: TableNameAndFieldName) -> bool {
+        self.scan.has_field(field_name)
+    }
+
+    fn set_integer(&mut self, _field_name: String, _value: i32) {
+        panic!("set_integer not implemented for DistinctScan");
+    }
+
+    fn set_string(&mut self, _field_name: String, _value: String) {
+        panic!("set_string not implemented for DistinctScan");
+    }
+
+    fn set_value(&mut self, _field_name: String, _value: ConstantValue) {
+        panic!("set_value not implemented for DistinctScan");
+    }
+
+    fn insert(&mut self) {
+        panic!("insert not implemented for DistinctScan");
+    }
+
+    fn delete(&mut self) {
+        panic!("delete not implemented for DistinctScan");
+    }
+
+    fn get_record_id(&self) -> RecordID {
+        self.scan.get_record_id()
+    }
+
+    fn move_to_record_id(&mut self, record_id: RecordID) {
+        self.scan.move_to_record_id(record_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        database::Database,
+        error::DatabaseError,
+        metadata::metadata_manager::MetadataManager,
+        query::cursor::Cursor,
+        query::parser::{parse_sql, ParsedSQL},
+        query::plan_v2::create_query_plan,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_select_distinct() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table t (A integer, B varchar(10)); \
+             insert into t (A, B) values (1, 'x'); \
+             insert into t (A, B) values (2, 'y'); \
+             insert into t (A, B) values (3, 'x'); \
+             insert into t (A, B) values (1, 'x'); \
+             insert into t (A, B) values (4, null); \
+             insert into t (A, B) values (5, null)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let select = |sql: &str,
+                      field_names: &[&str]|
+         -> Result<Vec<Vec<ConstantValue>>, DatabaseError> {
+            let transaction = database.new_transaction(2);
+            let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+            let parsed_sql_list = parse_sql(sql.to_string()).unwrap();
+            let ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+                panic!("Expected a Query variant from parse_sql");
+            };
+            let plan = create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+            let mut cursor = Cursor::open(plan, transaction.clone())?;
+            let indexes = field_names
+                .iter()
+                .map(|field_name| {
+                    cursor
+                        .get_schema()
+                        .fields
+                        .iter()
+                        .position(|field| field == field_name)
+                        .unwrap()
+                })
+                .collect::<Vec<usize>>();
+            let rows = cursor.fetch(100)?;
+            cursor.close();
+            transaction.borrow_mut().commit()?;
+            return Ok(rows
+                .iter()
+                .map(|row| indexes.iter().map(|index| row[*index].clone()).collect())
+                .collect());
+        };
+        let string = |s: &str| ConstantValue::String(s.to_string());
+
+        // NULLも1行にまとめる
+        assert_eq!(
+            select("select distinct B from t order by B desc", &["B"])?,
+            vec![
+                vec![string("y")],
+                vec![string("x")],
+                vec![ConstantValue::Null]
+            ]
+        );
+        assert_eq!(
+            select("select distinct A, B from t where B = 'x'", &["A", "B"])?,
+            vec![
+                vec![ConstantValue::Number(1), string("x")],
+                vec![ConstantValue::Number(3), string("x")],
+            ]
+        );
+        assert_eq!(select("select distinct * from t", &["A"])?.len(), 5);
+        assert_eq!(
+            select("select distinct B from t where A > 100", &["B"])?,
+            Vec::<Vec<ConstantValue>>::new()
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_select_distinct_mixed_case() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table t (n varchar(10)); \
+             insert into t (n) values ('a'); \
+             insert into t (n) values ('A'); \
+             insert into t (n) values ('b'); \
+             insert into t (n) values ('a')",
+            transaction.clone(),
+        )?;
+
+        // 大文字小文字が違う値は別の値で、count(distinct)と同じ数になる
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let mut values = vec![];
+        for sql in [
+            "select distinct n from t",
+            "select count(distinct n) from t",
+        ] {
+            let parsed_sql_list = parse_sql(sql.to_string()).unwrap();
+            let ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+                panic!("Expected a Query variant from parse_sql");
+            };
+            let plan = create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+            let mut cursor = Cursor::open(plan, transaction.clone())?;
+            values.push(cursor.fetch(100)?);
+            cursor.close();
+        }
+        let string = |s: &str| ConstantValue::String(s.to_string());
+        assert_eq!(
+            values[0],
+            vec![vec![string("A")], vec![string("a")], vec![string("b")]]
+        );
+        assert_eq!(values[1], vec![vec![ConstantValue::Number(3)]]);
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+}
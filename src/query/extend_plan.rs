@@ -0,0 +1,308 @@
+use crate::{
+    error::ValueNotFound,
+    query::parser::SelectExpression,
+    query::plan_v2::{PlanTreeNodeForDebug, PlanV2},
+    query::predicate::{ConstantValue, ExpressionValue, TableNameAndFieldName},
+    query::predicate_v3::{ExpressionV2, ScalarFunction},
+    record::record_page::{TableFieldInfo, TableFieldType, TableSchema},
+    record::scan_v2::ScanV2,
+    record::table_scan_v2::RecordID,
+};
+
+// select 'active' as status のような別名つきの式を、元のplanのfieldに足して返すplan
+// 式は行ごとに元のscanに対して評価する
+pub struct ExtendPlan {
+    plan: Box<dyn PlanV2>,
+    schema: TableSchema,
+    select_expressions: Vec<SelectExpression>,
+}
+
+impl ExtendPlan {
+    pub fn new(plan: Box<dyn PlanV2>, select_expressions: Vec<SelectExpression>) -> Self {
+        let mut schema = plan.get_schema().clone();
+        for select_expression in select_expressions.iter() {
+            let field_info = expression_field_info(&select_expression.expression, &schema);
+            schema.add_field(
+                select_expression.alias.clone(),
+                field_info.field_type,
+                field_info.field_length,
+            );
+        }
+
+        ExtendPlan {
+            plan,
+            schema,
+            select_expressions,
+        }
+    }
+}
+
+// 式の結果の型。fieldの型がわからないときはINTEGERとして扱う
+fn expression_field_info(expression: &ExpressionV2, schema: &TableSchema) -> TableFieldInfo {
+    match &expression.value {
+        ExpressionValue::TableNameAndFieldName(field_name) => {
+            match schema.field_infos.get(&field_name.field_name) {
+                Some(field_info) => return field_info.clone(),
+                None => return TableFieldInfo::new(TableFieldType::INTEGER, 0),
+            }
+        }
+        ExpressionValue::Constant(constant) => match &constant.value {
+            ConstantValue::String(s) => {
+                return TableFieldInfo::new(TableFieldType::VARCHAR, s.len() as i32);
+            }
+            ConstantValue::Decimal { value: _, scale } => {
+                return TableFieldInfo::new(TableFieldType::DECIMAL(9, *scale as i32), 9);
+            }
+            ConstantValue::Float(_) => return TableFieldInfo::new(TableFieldType::FLOAT, 0),
+            ConstantValue::Date(_) => return TableFieldInfo::new(TableFieldType::DATE, 0),
+            _ => return TableFieldInfo::new(TableFieldType::INTEGER, 0),
+        },
+        ExpressionValue::Arithmetic { lhs, rhs, .. } => {
+            let lhs = expression_field_info(lhs, schema);
+            let rhs = expression_field_info(rhs, schema);
+            match (lhs.field_type, rhs.field_type) {
+                (TableFieldType::FLOAT, _) | (_, TableFieldType::FLOAT) => {
+                    return TableFieldInfo::new(TableFieldType::FLOAT, 0);
+                }
+                (TableFieldType::DECIMAL(_, scale), _) | (_, TableFieldType::DECIMAL(_, scale)) => {
+                    return TableFieldInfo::new(TableFieldType::DECIMAL(9, scale), 9);
+                }
+                _ => return TableFieldInfo::new(TableFieldType::INTEGER, 0),
+            }
+        }
+        ExpressionValue::Function { function, argument } => match function {
+            ScalarFunction::Length => return TableFieldInfo::new(TableFieldType::INTEGER, 0),
+            ScalarFunction::Upper | ScalarFunction::Lower => {
+                return expression_field_info(argument, schema);
+            }
+        },
+    }
+}
+
+impl PlanV2 for ExtendPlan {
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+        let scan = self.plan.open()?;
+        return Ok(Box::new(ExtendScan::new(
+            scan,
+            self.select_expressions.clone(),
+        )));
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    fn blocks_accessed(&self) -> u32 {
+        self.plan.blocks_accessed()
+    }
+
+    fn records_output(&self) -> u32 {
+        self.plan.records_output()
+    }
+
+    fn get_distinct_value(&self, field_name: String) -> u32 {
+        let is_alias = self
+            .select_expressions
+            .iter()
+            .any(|select_expression| select_expression.alias == field_name);
+        if is_alias {
+            return self.records_output();
+        }
+        return self.plan.get_distinct_value(field_name);
+    }
+
+    fn get_child_plans(&self) -> PlanTreeNodeForDebug {
+        let aliases = self
+            .select_expressions
+            .iter()
+            .map(|select_expression| select_expression.alias.clone())
+            .collect::<Vec<String>>();
+        PlanTreeNodeForDebug {
+            current_node_type: format!("ExtendPlan({})", aliases.join(", ")),
+            records_output: self.records_output(),
+            blocks_accessed: self.blocks_accessed(),
+            child_nodes: vec![self.plan.get_child_plans()],
+        }
+    }
+}
+
+pub struct ExtendScan {
+    scan: Box<dyn ScanV2>,
+    select_expressions: Vec<SelectExpression>,
+}
+
+impl ExtendScan {
+    pub fn new(scan: Box<dyn ScanV2>, select_expressions: Vec<SelectExpression>) -> Self {
+        ExtendScan {
+            scan,
+            select_expressions,
+        }
+    }
+
+    // 別名はtable名なしでだけ参照できる
+    fn find_expression(&self, field_name: &TableNameAndFieldName) -> Option<ExpressionV2> {
+        if field_name.table_name.is_some() {
+            return None;
+        }
+        return self
+            .select_expressions
+            .iter()
+            .find(|select_expression| select_expression.alias == field_name.field_name)
+            .map(|select_expression| select_expression.expression.clone());
+    }
+}
+
+impl ScanV2 for ExtendScan {
+    fn move_to_before_first(&mut self) -> Result<(), ValueNotFound> {
+        self.scan.move_to_before_first()
+    }
+
+    fn next(&mut self) -> Result<bool, ValueNotFound> {
+        self.scan.next()
+    }
+
+    fn get_integer(&mut self, field_name: TableNameAndFieldName) -> Option<i32> {
+        match self.get_value(field_name) {
+            Some(ConstantValue::Number(n)) => Some(n),
+            _ => None,
+        }
+    }
+
+    fn get_string(&mut self, field_name: TableNameAndFieldName) -> Option<String> {
+        match self.get_value(field_name) {
+            Some(ConstantValue::String(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn get_value(&mut self, field_name: TableNameAndFieldName) -> Option<ConstantValue> {
+        if let Some(expression) = self.find_expression(&field_name) {
+            return expression
+                .evaluate(self.scan.as_mut())
+                .map(|constant| constant.value);
+        }
+        return self.scan.get_value(field_name);
+    }
+
+    fn close(&mut self) {
+        self.scan.close();
+    }
+
+    fn has_field(&self, field_name: TableNameAndFieldName) -> bool {
+        self.find_expression(&field_name).is_some() || self.scan.has_field(field_name)
+    }
+
+    fn set_integer(&mut self, _field_name: String, _value: i32) {
+        panic!("set_integer not implemented for ExtendScan");
+    }
+
+    fn set_string(&mut self, _field_name: String, _value: String) {
+        panic!("set_string not implemented for ExtendScan");
+    }
+
+    fn set_value(&mut self, _field_name: String, _value: ConstantValue) {
+        panic!("set_value not implemented for ExtendScan");
+    }
+
+    fn insert(&mut self) {
+        panic!("insert not implemented for ExtendScan");
+    }
+
+    fn delete(&mut self) {
+        panic!("delete not implemented for ExtendScan");
+    }
+
+    fn get_record_id(&self) -> RecordID {
+        self.scan.get_record_id()
+    }
+
+    fn move_to_record_id(&mut self, record_id: RecordID) {
+        self.scan.move_to_record_id(record_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        database::Database,
+        error::DatabaseError,
+        metadata::metadata_manager::MetadataManager,
+        query::cursor::Cursor,
+        query::parser::{parse_sql, ParsedSQL},
+        query::plan_v2::create_query_plan,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_select_constant_columns() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table users (id integer, name varchar(10)); \
+             insert into users (id, name) values (1, 'alice'); \
+             insert into users (id, name) values (2, 'bob')",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let parsed_sql_list = parse_sql(
+            "select name, 'active' as status, 1 as one, id * 10 as score from users order by id"
+                .to_string(),
+        )
+        .unwrap();
+        let ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+        let plan = create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+        assert_eq!(
+            plan.get_schema().get_field_type("status".to_string()),
+            Some(TableFieldType::VARCHAR)
+        );
+
+        let mut scan =
+            create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?.open()?;
+        let mut rows = vec![];
+        while scan.next()? {
+            rows.push(
+                query_data
+                    .field_name_list
+                    .iter()
+                    .map(|field_name| scan.get_value(field_name.clone()).unwrap())
+                    .collect::<Vec<ConstantValue>>(),
+            );
+        }
+        scan.close();
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    ConstantValue::String("alice".to_string()),
+                    ConstantValue::String("active".to_string()),
+                    ConstantValue::Number(1),
+                    ConstantValue::Number(10),
+                ],
+                vec![
+                    ConstantValue::String("bob".to_string()),
+                    ConstantValue::String("active".to_string()),
+                    ConstantValue::Number(1),
+                    ConstantValue::Number(20),
+                ],
+            ]
+        );
+
+        // 別名もCursorのschemaに入る
+        let mut cursor = Cursor::open(plan, transaction.clone())?;
+        assert!(cursor.get_schema().has_field("one".to_string()));
+        cursor.close();
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+}
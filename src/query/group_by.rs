@@ -1,14 +1,19 @@
 use std::fmt;
 use std::str::FromStr;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 use crate::{
     error::ValueNotFound,
-    query::plan_v2::{PlanTreeNodeForDebug, PlanV2},
+    query::plan_v2::{format_field_names, PlanTreeNodeForDebug, PlanV2},
     query::predicate::{Constant, ConstantValue, TableNameAndFieldName},
-    record::record_page::{TableFieldType, TableSchema},
+    query::predicate_v3::ArithmeticOp,
+    record::record_page::{TableFieldInfo, TableFieldType, TableSchema},
     record::scan_v2::ScanV2,
-    query::sort_plan::SortPlan,
+    query::sort_plan::{SortDirection, SortPlan},
     tx::transaction_v2::TransactionV2,
 };
 
@@ -17,6 +22,7 @@ pub struct GroupByPlan {
     group_fields: Vec<TableNameAndFieldName>,
     aggregate_functions: Rc<RefCell<Vec<Box<dyn AggregateFunction>>>>,
     plan: Box<dyn PlanV2>,
+    schema: TableSchema,
 }
 
 impl GroupByPlan {
@@ -26,35 +32,56 @@ impl GroupByPlan {
         aggregate_functions: Rc<RefCell<Vec<Box<dyn AggregateFunction>>>>,
         plan: Box<dyn PlanV2>,
     ) -> Self {
-        let sort_plan = SortPlan::new(transaction.clone(), plan, group_fields.clone());
+        // group byのfieldが無いときはtable全体が1つのgroupなので、並べ替えなくてよい
+        let plan: Box<dyn PlanV2> = if group_fields.is_empty() {
+            plan
+        } else {
+            Box::new(SortPlan::new(
+                transaction.clone(),
+                plan,
+                group_fields
+                    .iter()
+                    .map(|field| (field.clone(), SortDirection::Ascending))
+                    .collect(),
+            ))
+        };
 
         let mut table_schema = TableSchema::new();
 
         for func in aggregate_functions.borrow_mut().iter() {
-            table_schema.add_field(func.get_field(), TableFieldType::INTEGER, 0);
+            let field_info = func.get_field_info(plan.get_schema());
+            table_schema.add_field(
+                func.get_field(),
+                field_info.field_type,
+                field_info.field_length,
+            );
         }
 
         for field in group_fields.iter() {
-            table_schema.add(field.field_name.clone(), sort_plan.get_schema().clone());
+            table_schema.add(field.field_name.clone(), plan.get_schema().clone());
         }
 
         GroupByPlan {
             transaction,
             group_fields,
             aggregate_functions,
-            plan: Box::new(sort_plan),
+            plan,
+            schema: table_schema,
         }
     }
 }
 
 impl PlanV2 for GroupByPlan {
-    fn open(&mut self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
         let source_scan = self.plan.open()?;
-        return Ok(Box::new(GroupByScan::new(
+        let mut group_by_scan = GroupByScan::new(
             source_scan,
             self.group_fields.clone(),
             self.aggregate_functions.clone(),
-        )));
+        );
+        // 最初のnextでsourceの1行目を読めるように位置を合わせておく
+        group_by_scan.move_to_before_first()?;
+        return Ok(Box::new(group_by_scan));
     }
 
     fn blocks_accessed(&self) -> u32 {
@@ -71,7 +98,11 @@ impl PlanV2 for GroupByPlan {
     }
 
     fn get_distinct_value(&self, field_name: String) -> u32 {
-        if (self.get_schema().has_field(field_name.clone())) {
+        let is_group_field = self
+            .group_fields
+            .iter()
+            .any(|field| field.field_name == field_name);
+        if is_group_field {
             return self.plan.get_distinct_value(field_name);
         } else {
             return self.records_output();
@@ -79,37 +110,49 @@ impl PlanV2 for GroupByPlan {
     }
 
     fn get_schema(&self) -> &TableSchema {
-        self.plan.get_schema()
+        &self.schema
     }
 
     fn get_child_plans(&self) -> PlanTreeNodeForDebug {
         PlanTreeNodeForDebug {
-            current_node_type: "GroupByPlan".to_string(),
+            current_node_type: format!("GroupByPlan({})", format_field_names(&self.group_fields)),
+            records_output: self.records_output(),
+            blocks_accessed: self.blocks_accessed(),
             child_nodes: vec![self.plan.get_child_plans()],
         }
     }
 }
 
 pub trait AggregateFunction {
+    // 1行も集約していない状態に戻す
+    fn reset(&mut self);
     fn process_first(&mut self, scan: &mut dyn ScanV2);
     fn process_next(&mut self, scan: &mut dyn ScanV2);
     fn get_field(&self) -> String;
     fn get_value(&self) -> Constant;
+    // 結果のfieldの型。schemaは集約する前のplanのもの
+    fn get_field_info(&self, schema: &TableSchema) -> TableFieldInfo;
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AggregateFunctionType {
     Max,
+    Min,
     Avg,
     Sum,
+    Count,
+    CountDistinct,
 }
 
 impl fmt::Display for AggregateFunctionType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let s = match self {
             Self::Max => "max",
+            Self::Min => "min",
             Self::Avg => "avg",
             Self::Sum => "sum",
+            Self::Count => "count",
+            Self::CountDistinct => "count_distinct",
         };
         write!(f, "{}", s)
     }
@@ -121,8 +164,10 @@ impl FromStr for AggregateFunctionType {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
             "max" => Ok(AggregateFunctionType::Max),
+            "min" => Ok(AggregateFunctionType::Min),
             "avg" => Ok(AggregateFunctionType::Avg),
             "sum" => Ok(AggregateFunctionType::Sum),
+            "count" => Ok(AggregateFunctionType::Count),
             _ => Err(()),
         }
     }
@@ -166,6 +211,8 @@ struct GroupByScan {
     aggregate_functions: Rc<RefCell<Vec<Box<dyn AggregateFunction>>>>,
     group_value: Option<GroupValue>,
     more_groups: bool,
+    // move_to_before_firstの後、まだ1つもgroupを返していない
+    is_first_group: bool,
 }
 
 impl GroupByScan {
@@ -180,6 +227,7 @@ impl GroupByScan {
             aggregate_functions,
             group_value: None,
             more_groups: true,
+            is_first_group: true,
         }
     }
 }
@@ -187,8 +235,18 @@ impl GroupByScan {
 impl ScanV2 for GroupByScan {
     fn next(&mut self) -> Result<bool, ValueNotFound> {
         if self.more_groups == false {
+            // group byが無いときは、行が無くても集約した結果を1行返す
+            if self.group_fields.is_empty() && self.is_first_group {
+                self.is_first_group = false;
+                for func in self.aggregate_functions.borrow_mut().iter_mut() {
+                    func.reset();
+                }
+                self.group_value = Some(GroupValue::new(self.source_scan.as_mut(), vec![]));
+                return Ok(true);
+            }
             return Ok(false);
         }
+        self.is_first_group = false;
 
         for func in self.aggregate_functions.borrow_mut().iter_mut() {
             func.process_first(self.source_scan.as_mut());
@@ -245,7 +303,7 @@ impl ScanV2 for GroupByScan {
             }
         }
 
-        panic!("Field not found: {}", field_name.field_name);
+        return None;
     }
 
     fn get_string(&mut self, field_name: TableNameAndFieldName) -> Option<String> {
@@ -283,8 +341,9 @@ impl ScanV2 for GroupByScan {
     }
 
     fn move_to_before_first(&mut self) -> Result<(), ValueNotFound> {
-        self.source_scan.move_to_before_first();
+        self.source_scan.move_to_before_first()?;
         self.more_groups = self.source_scan.next().unwrap();
+        self.is_first_group = true;
         return Ok(());
     }
 
@@ -317,40 +376,81 @@ impl ScanV2 for GroupByScan {
     }
 }
 
+// 集約関数はNULLの値を無視する。NULLしかなかったgroupはcount以外NULLを返す
+fn read_non_null(
+    scan: &mut dyn ScanV2,
+    field_name: &TableNameAndFieldName,
+) -> Option<ConstantValue> {
+    match scan.get_value(field_name.clone()) {
+        None | Some(ConstantValue::Null) => None,
+        Some(value) => Some(value),
+    }
+}
+
+// max/minは元のfieldと同じ型。元のschemaに無いfieldはINTEGERとして扱う
+fn source_field_info(schema: &TableSchema, field_name: &TableNameAndFieldName) -> TableFieldInfo {
+    match schema.field_infos.get(&field_name.field_name) {
+        Some(field_info) => return field_info.clone(),
+        None => return TableFieldInfo::new(TableFieldType::INTEGER, 0),
+    }
+}
+
+// sum/avgで足せる値。INTEGER, DECIMAL, FLOATのfieldの値
+fn is_numeric(value: &ConstantValue) -> bool {
+    matches!(
+        value,
+        ConstantValue::Number(_) | ConstantValue::Decimal { .. } | ConstantValue::Float(_)
+    )
+}
+
+// sum/avgの結果は元のfieldの型のまま。DECIMALならscaleもそのまま
+fn numeric_field_info(schema: &TableSchema, field_name: &TableNameAndFieldName) -> TableFieldInfo {
+    let field_info = source_field_info(schema, field_name);
+    match field_info.field_type {
+        TableFieldType::DECIMAL(_, scale) => {
+            return TableFieldInfo::new(TableFieldType::DECIMAL(9, scale), 0)
+        }
+        TableFieldType::FLOAT => return TableFieldInfo::new(TableFieldType::FLOAT, 0),
+        _ => return TableFieldInfo::new(TableFieldType::INTEGER, 0),
+    }
+}
+
 pub struct SumFunction {
     field_name: TableNameAndFieldName,
-    sum_value: i32,
+    // 足し算がoverflowしたらNULLになり、そのgroupの結果はNULLのまま
+    sum_value: Option<Constant>,
 }
 
 impl SumFunction {
     pub fn new(field_name: TableNameAndFieldName) -> Self {
         SumFunction {
             field_name,
-            sum_value: 0,
+            sum_value: None,
         }
     }
 }
 
 impl AggregateFunction for SumFunction {
-    fn process_first(&mut self, scan: &mut dyn ScanV2) {
-        let value = scan.get_value(self.field_name.clone()).unwrap();
+    fn reset(&mut self) {
+        self.sum_value = None;
+    }
 
-        match value {
-            ConstantValue::Number(n) => {
-                self.sum_value = n;
-            }
-            _ => panic!("SumFunction only supports numeric values"),
-        }
+    fn process_first(&mut self, scan: &mut dyn ScanV2) {
+        self.reset();
+        self.process_next(scan);
     }
 
     fn process_next(&mut self, scan: &mut dyn ScanV2) {
-        let new_value = scan.get_value(self.field_name.clone()).unwrap();
-
-        match new_value {
-            ConstantValue::Number(n) => {
-                self.sum_value += n;
-            }
-            _ => panic!("SumFunction only supports numeric values"),
+        let Some(value) = read_non_null(scan, &self.field_name) else {
+            return;
+        };
+        if !is_numeric(&value) {
+            panic!("SumFunction only supports numeric values");
+        }
+        let value = Constant::new(value);
+        self.sum_value = match &self.sum_value {
+            Some(sum_value) => Some(ArithmeticOp::Add.apply(sum_value, &value)),
+            None => Some(value),
         };
     }
 
@@ -364,66 +464,70 @@ impl AggregateFunction for SumFunction {
     }
 
     fn get_value(&self) -> Constant {
-        Constant::new(ConstantValue::Number(self.sum_value))
+        match &self.sum_value {
+            Some(sum_value) => sum_value.clone(),
+            None => Constant::new(ConstantValue::Null),
+        }
+    }
+
+    fn get_field_info(&self, schema: &TableSchema) -> TableFieldInfo {
+        numeric_field_info(schema, &self.field_name)
     }
 }
 
 pub struct AvgFunction {
-    field_name: TableNameAndFieldName,
-    sum_value: i32,
+    sum: SumFunction,
     number_of_values: u32,
 }
 
 impl AvgFunction {
     pub fn new(field_name: TableNameAndFieldName) -> Self {
         AvgFunction {
-            field_name,
-            sum_value: 0,
+            sum: SumFunction::new(field_name),
             number_of_values: 0,
         }
     }
 }
 
 impl AggregateFunction for AvgFunction {
-    fn process_first(&mut self, scan: &mut dyn ScanV2) {
-        let value = scan.get_value(self.field_name.clone()).unwrap();
+    fn reset(&mut self) {
+        self.sum.reset();
+        self.number_of_values = 0;
+    }
 
-        match value {
-            ConstantValue::Number(n) => {
-                self.sum_value = n;
-                self.number_of_values = 1;
-            }
-            _ => panic!("AvgFunction only supports numeric values"),
-        }
+    fn process_first(&mut self, scan: &mut dyn ScanV2) {
+        self.reset();
+        self.process_next(scan);
     }
 
     fn process_next(&mut self, scan: &mut dyn ScanV2) {
-        let new_value = scan.get_value(self.field_name.clone()).unwrap();
-
-        match new_value {
-            ConstantValue::Number(n) => {
-                self.sum_value += n;
-                self.number_of_values += 1;
-            }
-            _ => panic!("AvgFunction only supports numeric values"),
-        };
+        if read_non_null(scan, &self.sum.field_name).is_none() {
+            return;
+        }
+        self.sum.process_next(scan);
+        self.number_of_values += 1;
     }
 
     fn get_field(&self) -> String {
         let field_name = format!(
             "{}_{}",
             AggregateFunctionType::Avg,
-            self.field_name.clone().field_name
+            self.sum.field_name.clone().field_name
         );
         return field_name;
     }
 
+    // INTEGERは整数の割り算、DECIMALはscaleを保ったまま切り捨て、FLOATはそのまま割る
     fn get_value(&self) -> Constant {
         if self.number_of_values == 0 {
-            panic!("No values processed");
+            return Constant::new(ConstantValue::Null);
         }
-        let avg = self.sum_value / self.number_of_values as i32;
-        Constant::new(ConstantValue::Number(avg))
+        let count = Constant::new(ConstantValue::Number(self.number_of_values as i32));
+        return ArithmeticOp::Divide.apply(&self.sum.get_value(), &count);
+    }
+
+    fn get_field_info(&self, schema: &TableSchema) -> TableFieldInfo {
+        numeric_field_info(schema, &self.sum.field_name)
     }
 }
 
@@ -442,16 +546,27 @@ impl MaxFunction {
 }
 
 impl AggregateFunction for MaxFunction {
+    fn reset(&mut self) {
+        self.max_value = None;
+    }
+
     fn process_first(&mut self, scan: &mut dyn ScanV2) {
-        let value = scan.get_value(self.field_name.clone()).unwrap();
-        self.max_value = Some(Constant::new(value));
+        self.reset();
+        self.process_next(scan);
     }
 
     fn process_next(&mut self, scan: &mut dyn ScanV2) {
-        let new_value = scan.get_value(self.field_name.clone()).unwrap();
-        if (Constant::new(new_value.clone())).compare_to(self.max_value.clone().unwrap().value)
-            == std::cmp::Ordering::Greater
-        {
+        let Some(new_value) = read_non_null(scan, &self.field_name) else {
+            return;
+        };
+        let is_greater = match &self.max_value {
+            Some(max_value) => {
+                Constant::new(new_value.clone()).compare_to(max_value.value.clone())
+                    == std::cmp::Ordering::Greater
+            }
+            None => true,
+        };
+        if is_greater {
             self.max_value = Some(Constant::new(new_value));
         }
     }
@@ -466,7 +581,176 @@ impl AggregateFunction for MaxFunction {
     }
 
     fn get_value(&self) -> Constant {
-        self.max_value.clone().unwrap()
+        self.max_value
+            .clone()
+            .unwrap_or(Constant::new(ConstantValue::Null))
+    }
+
+    fn get_field_info(&self, schema: &TableSchema) -> TableFieldInfo {
+        source_field_info(schema, &self.field_name)
+    }
+}
+
+pub struct MinFunction {
+    field_name: TableNameAndFieldName,
+    min_value: Option<Constant>,
+}
+
+impl MinFunction {
+    pub fn new(field_name: TableNameAndFieldName) -> Self {
+        MinFunction {
+            field_name,
+            min_value: None,
+        }
+    }
+}
+
+impl AggregateFunction for MinFunction {
+    fn reset(&mut self) {
+        self.min_value = None;
+    }
+
+    fn process_first(&mut self, scan: &mut dyn ScanV2) {
+        self.reset();
+        self.process_next(scan);
+    }
+
+    fn process_next(&mut self, scan: &mut dyn ScanV2) {
+        let Some(new_value) = read_non_null(scan, &self.field_name) else {
+            return;
+        };
+        let is_less = match &self.min_value {
+            Some(min_value) => {
+                Constant::new(new_value.clone()).compare_to(min_value.value.clone())
+                    == std::cmp::Ordering::Less
+            }
+            None => true,
+        };
+        if is_less {
+            self.min_value = Some(Constant::new(new_value));
+        }
+    }
+
+    fn get_field(&self) -> String {
+        let field_name = format!(
+            "{}_{}",
+            AggregateFunctionType::Min,
+            self.field_name.clone().field_name
+        );
+        return field_name;
+    }
+
+    fn get_value(&self) -> Constant {
+        self.min_value
+            .clone()
+            .unwrap_or(Constant::new(ConstantValue::Null))
+    }
+
+    fn get_field_info(&self, schema: &TableSchema) -> TableFieldInfo {
+        source_field_info(schema, &self.field_name)
+    }
+}
+
+// count(*) はすべての行、count(field) はNULLでない行を数える
+pub struct CountFunction {
+    field_name: TableNameAndFieldName,
+    count: i32,
+}
+
+impl CountFunction {
+    pub const ALL_ROWS: &'static str = "*";
+
+    pub fn new(field_name: TableNameAndFieldName) -> Self {
+        CountFunction {
+            field_name,
+            count: 0,
+        }
+    }
+}
+
+impl AggregateFunction for CountFunction {
+    fn reset(&mut self) {
+        self.count = 0;
+    }
+
+    fn process_first(&mut self, scan: &mut dyn ScanV2) {
+        self.reset();
+        self.process_next(scan);
+    }
+
+    fn process_next(&mut self, scan: &mut dyn ScanV2) {
+        if self.field_name.field_name == Self::ALL_ROWS
+            || read_non_null(scan, &self.field_name).is_some()
+        {
+            self.count += 1;
+        }
+    }
+
+    fn get_field(&self) -> String {
+        let field_name = format!(
+            "{}_{}",
+            AggregateFunctionType::Count,
+            self.field_name.clone().field_name
+        );
+        return field_name;
+    }
+
+    fn get_value(&self) -> Constant {
+        Constant::new(ConstantValue::Number(self.count))
+    }
+
+    fn get_field_info(&self, _schema: &TableSchema) -> TableFieldInfo {
+        TableFieldInfo::new(TableFieldType::INTEGER, 0)
+    }
+}
+
+// count(distinct field)。groupの中はgroup byのfieldでしか並んでいないので、
+// 隣の行と比べるのではなく、見た値をすべて覚えておく
+pub struct CountDistinctFunction {
+    field_name: TableNameAndFieldName,
+    seen_values: HashSet<Constant>,
+}
+
+impl CountDistinctFunction {
+    pub fn new(field_name: TableNameAndFieldName) -> Self {
+        CountDistinctFunction {
+            field_name,
+            seen_values: HashSet::new(),
+        }
+    }
+}
+
+impl AggregateFunction for CountDistinctFunction {
+    fn reset(&mut self) {
+        self.seen_values.clear();
+    }
+
+    fn process_first(&mut self, scan: &mut dyn ScanV2) {
+        self.reset();
+        self.process_next(scan);
+    }
+
+    fn process_next(&mut self, scan: &mut dyn ScanV2) {
+        if let Some(value) = read_non_null(scan, &self.field_name) {
+            self.seen_values.insert(Constant::new(value));
+        }
+    }
+
+    fn get_field(&self) -> String {
+        let field_name = format!(
+            "{}_{}",
+            AggregateFunctionType::CountDistinct,
+            self.field_name.clone().field_name
+        );
+        return field_name;
+    }
+
+    fn get_value(&self) -> Constant {
+        Constant::new(ConstantValue::Number(self.seen_values.len() as i32))
+    }
+
+    fn get_field_info(&self, _schema: &TableSchema) -> TableFieldInfo {
+        TableFieldInfo::new(TableFieldType::INTEGER, 0)
     }
 }
 
@@ -485,7 +769,7 @@ mod tests {
 
     use super::*;
 
-    fn prepare_test_data(directory_path_name: String) -> Result<(), ValueNotFound> {
+    fn prepare_test_data(directory_path_name: String) -> Result<(), crate::error::DatabaseError> {
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -494,7 +778,7 @@ mod tests {
         let create_table_sql =
             "create table test_table_1 (A_1 integer, B_1 varchar(10))".to_string();
 
-        let parsed_sql_list = parse_sql(create_table_sql.clone());
+        let parsed_sql_list = parse_sql(create_table_sql.clone()).unwrap();
 
         let create_table_data = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::CreateTable(q) => q,
@@ -514,7 +798,7 @@ mod tests {
         let create_table_sql =
             "create table test_table_2 (A_2 integer, B_2 varchar(10))".to_string();
 
-        let parsed_sql_list = parse_sql(create_table_sql.clone());
+        let parsed_sql_list = parse_sql(create_table_sql.clone()).unwrap();
 
         let create_table_data = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::CreateTable(q) => q,
@@ -546,7 +830,7 @@ mod tests {
         ];
 
         for insert_sql in insert_sql_list.iter() {
-            let parsed_sql_list = parse_sql(insert_sql.clone());
+            let parsed_sql_list = parse_sql(insert_sql.clone()).unwrap();
 
             let insert_data = match &parsed_sql_list[0] {
                 crate::query::parser::ParsedSQL::Insert(q) => q,
@@ -557,18 +841,19 @@ mod tests {
                 transaction.clone(),
                 &mut metadata_manager,
                 insert_data.clone(),
-            );
+            )?;
         }
 
-        transaction.borrow_mut().commit();
+        transaction.borrow_mut().commit()?;
 
         return Ok(());
     }
 
     #[test]
-    fn test_group_by() -> Result<(), ValueNotFound> {
-        let directory_path_name = format!("test_data_{}", uuid::Uuid::new_v4());
-        prepare_test_data(directory_path_name.clone());
+    fn test_group_by() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        prepare_test_data(directory_path_name.clone())?;
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -581,7 +866,7 @@ mod tests {
         )
         .unwrap();
 
-        let mut group_by_plan = GroupByPlan::new(
+        let group_by_plan = GroupByPlan::new(
             transaction.clone(),
             vec![TableNameAndFieldName::new(None, "B_1".to_string())],
             Rc::new(RefCell::new(vec![Box::new(MaxFunction::new(
@@ -644,4 +929,567 @@ mod tests {
 
         return Ok(());
     }
+
+    // values (NULL), (3), ... の1列に集約関数をかける
+    fn aggregate(
+        function: &mut dyn AggregateFunction,
+        values: Vec<ConstantValue>,
+    ) -> ConstantValue {
+        let rows = values.into_iter().map(|value| vec![value]).collect();
+        let mut schema = TableSchema::new();
+        schema.add_integer_field("v".to_string());
+        let mut scan = crate::query::values_plan::ValuesScan::new("t".to_string(), schema, rows);
+
+        assert!(scan.next().unwrap());
+        function.process_first(&mut scan);
+        while scan.next().unwrap() {
+            function.process_next(&mut scan);
+        }
+        return function.get_value().value;
+    }
+
+    #[test]
+    fn test_aggregates_ignore_null() {
+        let field = TableNameAndFieldName::new(None, "v".to_string());
+        // 最初の値がNULLの場合も含める
+        let with_nulls = vec![
+            ConstantValue::Null,
+            ConstantValue::Number(3),
+            ConstantValue::Null,
+            ConstantValue::Number(1),
+        ];
+        let only_nulls = vec![ConstantValue::Null, ConstantValue::Null];
+
+        assert_eq!(
+            aggregate(&mut SumFunction::new(field.clone()), with_nulls.clone()),
+            ConstantValue::Number(4)
+        );
+        assert_eq!(
+            aggregate(&mut AvgFunction::new(field.clone()), with_nulls.clone()),
+            ConstantValue::Number(2)
+        );
+        assert_eq!(
+            aggregate(&mut MinFunction::new(field.clone()), with_nulls.clone()),
+            ConstantValue::Number(1)
+        );
+        assert_eq!(
+            aggregate(&mut MaxFunction::new(field.clone()), with_nulls.clone()),
+            ConstantValue::Number(3)
+        );
+        assert_eq!(
+            aggregate(&mut CountFunction::new(field.clone()), with_nulls.clone()),
+            ConstantValue::Number(2)
+        );
+        assert_eq!(
+            aggregate(
+                &mut CountFunction::new(TableNameAndFieldName::new(
+                    None,
+                    CountFunction::ALL_ROWS.to_string()
+                )),
+                with_nulls.clone()
+            ),
+            ConstantValue::Number(4)
+        );
+
+        // NULLしかないときはcount以外NULL
+        assert_eq!(
+            aggregate(&mut SumFunction::new(field.clone()), only_nulls.clone()),
+            ConstantValue::Null
+        );
+        assert_eq!(
+            aggregate(&mut AvgFunction::new(field.clone()), only_nulls.clone()),
+            ConstantValue::Null
+        );
+        assert_eq!(
+            aggregate(&mut MinFunction::new(field.clone()), only_nulls.clone()),
+            ConstantValue::Null
+        );
+        assert_eq!(
+            aggregate(&mut MaxFunction::new(field.clone()), only_nulls.clone()),
+            ConstantValue::Null
+        );
+        assert_eq!(
+            aggregate(&mut CountFunction::new(field.clone()), only_nulls.clone()),
+            ConstantValue::Number(0)
+        );
+    }
+
+    #[test]
+    fn test_sum_and_avg_of_decimal() {
+        let field = TableNameAndFieldName::new(None, "v".to_string());
+        let decimals = vec![
+            ConstantValue::Decimal {
+                value: 1050,
+                scale: 2,
+            },
+            ConstantValue::Null,
+            ConstantValue::Decimal {
+                value: 225,
+                scale: 2,
+            },
+        ];
+        assert_eq!(
+            aggregate(&mut SumFunction::new(field.clone()), decimals.clone()),
+            ConstantValue::Decimal {
+                value: 1275,
+                scale: 2
+            }
+        );
+        // 6.375は切り捨てて6.37
+        assert_eq!(
+            aggregate(&mut AvgFunction::new(field.clone()), decimals.clone()),
+            ConstantValue::Decimal {
+                value: 637,
+                scale: 2
+            }
+        );
+
+        // i32に収まらない合計はNULL
+        let large = vec![ConstantValue::Number(i32::MAX), ConstantValue::Number(1)];
+        assert_eq!(
+            aggregate(&mut SumFunction::new(field.clone()), large.clone()),
+            ConstantValue::Null
+        );
+        assert_eq!(
+            aggregate(&mut AvgFunction::new(field.clone()), large.clone()),
+            ConstantValue::Null
+        );
+    }
+
+    #[test]
+    fn test_sum_and_avg_of_float() {
+        let field = TableNameAndFieldName::new(None, "v".to_string());
+        let floats = vec![
+            ConstantValue::Float(1.5),
+            ConstantValue::Null,
+            ConstantValue::Float(0.25),
+        ];
+        assert_eq!(
+            aggregate(&mut SumFunction::new(field.clone()), floats.clone()),
+            ConstantValue::Float(1.75)
+        );
+        assert_eq!(
+            aggregate(&mut AvgFunction::new(field.clone()), floats.clone()),
+            ConstantValue::Float(0.875)
+        );
+
+        // 有限でなくなった合計はNULL
+        let large = vec![
+            ConstantValue::Float(f64::MAX),
+            ConstantValue::Float(f64::MAX),
+        ];
+        assert_eq!(
+            aggregate(&mut SumFunction::new(field.clone()), large),
+            ConstantValue::Null
+        );
+    }
+
+    #[test]
+    fn test_sum_of_decimal_from_sql() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table payments (name varchar(10), amount decimal(5, 2)); \
+             insert into payments (name, amount) values ('a', 12.50); \
+             insert into payments (name, amount) values ('b', 0.75)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let result = database.execute("select sum(amount) from payments", transaction.clone())?;
+        let crate::database::QueryResult::Rows { rows, .. } = result else {
+            panic!("Expected rows");
+        };
+        assert_eq!(
+            rows,
+            vec![vec![ConstantValue::Decimal {
+                value: 1325,
+                scale: 2
+            }]]
+        );
+
+        // 文字列は足せない
+        let result = database.execute("select sum(name) from payments", transaction.clone());
+        assert!(matches!(
+            result,
+            Err(crate::error::DatabaseError::TypeMismatch(_))
+        ));
+        let result = database.execute("select avg(name) from payments", transaction.clone());
+        assert!(matches!(
+            result,
+            Err(crate::error::DatabaseError::TypeMismatch(_))
+        ));
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    fn select_counts(
+        database: &Database,
+        sql: &str,
+    ) -> Result<Vec<ConstantValue>, crate::error::DatabaseError> {
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let parsed_sql_list = parse_sql(sql.to_string()).unwrap();
+        let crate::query::parser::ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+        let plan = crate::query::plan_v2::create_query_plan(
+            query_data,
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+        let mut scan = plan.open()?;
+        let mut counts = vec![];
+        while scan.next()? {
+            counts.push(
+                scan.get_value(TableNameAndFieldName::new(None, "count_*".to_string()))
+                    .unwrap(),
+            );
+        }
+        scan.close();
+        transaction.borrow_mut().commit()?;
+        return Ok(counts);
+    }
+
+    #[test]
+    fn test_having_without_group_by() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table orders (id integer, amount integer); \
+             insert into orders (id, amount) values (1, 10); \
+             insert into orders (id, amount) values (2, 20); \
+             insert into orders (id, amount) values (3, 30)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        // table全体が1つのgroupになる
+        assert_eq!(
+            select_counts(&database, "select count(*) from orders having count(*) > 0")?,
+            vec![ConstantValue::Number(3)]
+        );
+        assert_eq!(
+            select_counts(&database, "select count(*) from orders having count(*) > 3")?,
+            vec![]
+        );
+        // select listに無い集約関数も使える
+        assert_eq!(
+            select_counts(
+                &database,
+                "select count(*) from orders where id > 1 having max(amount) = 30"
+            )?,
+            vec![ConstantValue::Number(2)]
+        );
+        assert_eq!(
+            select_counts(
+                &database,
+                "select count(*) from orders having min(amount) > 10"
+            )?,
+            vec![]
+        );
+        // 行が無くてもcount(*)は0の1行を返す
+        assert_eq!(
+            select_counts(&database, "select count(*) from orders where id > 100")?,
+            vec![ConstantValue::Number(0)]
+        );
+        assert_eq!(
+            select_counts(
+                &database,
+                "select count(*) from orders where id > 100 having count(*) > 0"
+            )?,
+            vec![]
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_non_grouped_field_is_rejected() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table orders (customer varchar(10), amount integer); \
+             insert into orders (customer, amount) values ('bob', 10)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        // validate_queryを通らないREPLの経路でも、planを作るところでエラーになる
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let parsed_sql_list =
+            parse_sql("select customer, amount from orders group by customer".to_string())
+                .unwrap();
+        let crate::query::parser::ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+        let result = crate::query::plan_v2::create_query_plan(
+            query_data,
+            transaction.clone(),
+            &mut metadata_manager,
+        );
+        let Err(error) = result else {
+            panic!("expected ValueNotFound");
+        };
+        assert_eq!(error.to_string(), "Value not found: amount");
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_having_only_aggregate_is_not_output() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table orders (customer varchar(10), amount integer); \
+             insert into orders (customer, amount) values ('bob', 10); \
+             insert into orders (customer, amount) values ('alice', 5); \
+             insert into orders (customer, amount) values ('bob', 30)",
+            transaction.clone(),
+        )?;
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        metadata_manager.create_view(
+            "big_customers".to_string(),
+            "select customer from orders group by customer having max(amount) > 20".to_string(),
+            transaction.clone(),
+        );
+        transaction.borrow_mut().commit()?;
+
+        // havingのmax(amount)はviewのcolumnにならない
+        let transaction = database.new_transaction(2);
+        assert_eq!(
+            database.execute("select * from big_customers", transaction.clone())?,
+            crate::database::QueryResult::Rows {
+                columns: vec!["customer".to_string()],
+                rows: vec![vec![ConstantValue::String("bob".to_string())]],
+            }
+        );
+
+        // whereには集約関数を書けない
+        for sql in [
+            "select customer from orders where max(amount) > 20",
+            "delete from orders where count(*) > 0",
+        ] {
+            let Err(crate::error::DatabaseError::Parse(error)) =
+                database.execute(sql, transaction.clone())
+            else {
+                panic!("expected a parse error");
+            };
+            assert_eq!(error.get_line(), 1);
+        }
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_group_by_schema_field_types() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        prepare_test_data(directory_path_name.clone())?;
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let table_plan = TablePlanV2::new(
+            "test_table_1".to_string(),
+            transaction.clone(),
+            &mut metadata_manager,
+        )
+        .unwrap();
+
+        let b_1 = TableNameAndFieldName::new(None, "B_1".to_string());
+        let a_1 = TableNameAndFieldName::new(None, "A_1".to_string());
+        let group_by_plan = GroupByPlan::new(
+            transaction.clone(),
+            vec![a_1.clone()],
+            Rc::new(RefCell::new(vec![
+                Box::new(MaxFunction::new(b_1.clone())) as Box<dyn AggregateFunction>,
+                Box::new(CountFunction::new(b_1.clone())),
+                Box::new(SumFunction::new(a_1.clone())),
+            ])),
+            Box::new(table_plan),
+        );
+
+        let schema = group_by_plan.get_schema();
+        assert_eq!(
+            schema.fields,
+            vec![
+                "max_B_1".to_string(),
+                "count_B_1".to_string(),
+                "sum_A_1".to_string(),
+                "A_1".to_string(),
+            ]
+        );
+        assert_eq!(
+            schema.get_field_type("max_B_1".to_string()),
+            Some(TableFieldType::VARCHAR)
+        );
+        assert_eq!(schema.get_field_length("max_B_1".to_string()), Some(10));
+        assert_eq!(
+            schema.get_field_type("count_B_1".to_string()),
+            Some(TableFieldType::INTEGER)
+        );
+        assert_eq!(
+            schema.get_field_type("sum_A_1".to_string()),
+            Some(TableFieldType::INTEGER)
+        );
+        assert!(!schema.has_field("B_1".to_string()));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_group_by_from_sql() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table orders (customer varchar(10), amount integer); \
+             insert into orders (customer, amount) values ('bob', 10); \
+             insert into orders (customer, amount) values ('alice', 5); \
+             insert into orders (customer, amount) values ('bob', 30); \
+             insert into orders (customer, amount) values ('carol', 20); \
+             insert into orders (customer, amount) values ('alice', 7)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let parsed_sql_list = parse_sql(
+            "select customer, count(amount), max(amount) from orders \
+             group by customer having count(amount) > 0 order by max_amount"
+                .to_string(),
+        )
+        .unwrap();
+        let crate::query::parser::ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+        let plan = crate::query::plan_v2::create_query_plan(
+            query_data,
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+        let mut scan = plan.open()?;
+
+        // order byは集約した結果にかかるので、distinctなcustomerごとに1行になる
+        let mut rows = vec![];
+        while scan.next()? {
+            rows.push(
+                ["customer", "count_amount", "max_amount"]
+                    .iter()
+                    .map(|field| {
+                        scan.get_value(TableNameAndFieldName::new(None, field.to_string()))
+                            .unwrap()
+                    })
+                    .collect::<Vec<ConstantValue>>(),
+            );
+        }
+        scan.close();
+        transaction.borrow_mut().commit()?;
+
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    ConstantValue::String("alice".to_string()),
+                    ConstantValue::Number(2),
+                    ConstantValue::Number(7),
+                ],
+                vec![
+                    ConstantValue::String("carol".to_string()),
+                    ConstantValue::Number(1),
+                    ConstantValue::Number(20),
+                ],
+                vec![
+                    ConstantValue::String("bob".to_string()),
+                    ConstantValue::Number(2),
+                    ConstantValue::Number(30),
+                ],
+            ]
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_count_distinct() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        // groupの中でproductは並んでいない
+        database.execute(
+            "create table sales (region varchar(10), product integer); \
+             insert into sales (region, product) values ('east', 2); \
+             insert into sales (region, product) values ('west', 1); \
+             insert into sales (region, product) values ('east', 1); \
+             insert into sales (region, product) values ('east', 2); \
+             insert into sales (region, product) values ('west', 1); \
+             insert into sales (region, product) values ('east', 3); \
+             insert into sales (region, product) values ('east', 1)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let result = database.execute(
+            "select region, count(distinct product), count(product) from sales group by region",
+            transaction.clone(),
+        )?;
+        let crate::database::QueryResult::Rows { columns, rows } = result else {
+            panic!("Expected rows");
+        };
+        assert_eq!(
+            columns,
+            vec!["region", "count_distinct_product", "count_product"]
+        );
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    ConstantValue::String("east".to_string()),
+                    ConstantValue::Number(3),
+                    ConstantValue::Number(5),
+                ],
+                vec![
+                    ConstantValue::String("west".to_string()),
+                    ConstantValue::Number(1),
+                    ConstantValue::Number(2),
+                ],
+            ]
+        );
+
+        // NULLは数えない
+        database.execute(
+            "insert into sales (region, product) values ('west', null)",
+            transaction.clone(),
+        )?;
+        let result = database.execute(
+            "select count(distinct product) from sales",
+            transaction.clone(),
+        )?;
+        let crate::database::QueryResult::Rows { rows, .. } = result else {
+            panic!("Expected rows");
+        };
+        assert_eq!(rows, vec![vec![ConstantValue::Number(3)]]);
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
 }
@@ -0,0 +1,200 @@
+use crate::{
+    error::ValueNotFound,
+    metadata::index_manager::IndexInfo,
+    query::index_join_scan::IndexJoinScan,
+    query::plan_v2::{PlanTreeNodeForDebug, PlanV2, TablePlanV2},
+    query::predicate::TableNameAndFieldName,
+    record::record_page::TableSchema,
+    record::scan_v2::ScanV2,
+};
+
+// 左のplanの行ごとに、右のtableのindexで同じ値の行だけを探すjoin
+// join_fieldは左のplanのfieldで、右のtableのindexのfieldと等しいという条件に使う
+pub struct IndexJoinPlan {
+    left_plan: Box<dyn PlanV2>,
+    right_plan: TablePlanV2,
+    index_info: IndexInfo,
+    join_field: TableNameAndFieldName,
+    schema: TableSchema,
+}
+
+impl IndexJoinPlan {
+    pub fn new(
+        left_plan: Box<dyn PlanV2>,
+        right_plan: TablePlanV2,
+        index_info: IndexInfo,
+        join_field: TableNameAndFieldName,
+    ) -> Self {
+        let mut schema = TableSchema::new();
+        schema.add_all(left_plan.get_schema().clone());
+        schema.add_all(right_plan.get_schema().clone());
+
+        IndexJoinPlan {
+            left_plan,
+            right_plan,
+            index_info,
+            join_field,
+            schema,
+        }
+    }
+
+    // planを作る前に、plannerがjoinの仕方を比べるときにも使う
+    pub fn estimate_blocks_accessed(left_plan: &dyn PlanV2, index_info: &IndexInfo) -> u32 {
+        let blocks_per_left_record = index_info
+            .blocks_accessed()
+            .saturating_add(index_info.records_output());
+        left_plan.blocks_accessed().saturating_add(
+            left_plan
+                .records_output()
+                .saturating_mul(blocks_per_left_record),
+        )
+    }
+
+    pub fn estimate_records_output(left_plan: &dyn PlanV2, index_info: &IndexInfo) -> u32 {
+        left_plan
+            .records_output()
+            .saturating_mul(index_info.records_output())
+    }
+}
+
+impl PlanV2 for IndexJoinPlan {
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+        let left_scan = self.left_plan.open()?;
+        let index = self.index_info.clone().open();
+        let right_scan = self.right_plan.open_table_scan();
+        let mut scan = IndexJoinScan::new(left_scan, index, self.join_field.clone(), right_scan);
+        scan.move_to_before_first()?;
+        return Ok(Box::new(scan));
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    // 左の行ごとにindexを1回引く。見積もりなので桁あふれはしないようにする
+    fn blocks_accessed(&self) -> u32 {
+        Self::estimate_blocks_accessed(self.left_plan.as_ref(), &self.index_info)
+    }
+
+    fn records_output(&self) -> u32 {
+        Self::estimate_records_output(self.left_plan.as_ref(), &self.index_info)
+    }
+
+    fn get_distinct_value(&self, field_name: String) -> u32 {
+        if self.left_plan.get_schema().has_field(field_name.clone()) {
+            return self.left_plan.get_distinct_value(field_name);
+        }
+        return self.right_plan.get_distinct_value(field_name);
+    }
+
+    fn get_child_plans(&self) -> PlanTreeNodeForDebug {
+        PlanTreeNodeForDebug {
+            current_node_type: format!("IndexJoinPlan({})", self.join_field.field_name),
+            records_output: self.records_output(),
+            blocks_accessed: self.blocks_accessed(),
+            child_nodes: vec![
+                self.left_plan.get_child_plans(),
+                self.right_plan.get_child_plans(),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        database::Database,
+        error::DatabaseError,
+        metadata::metadata_manager::MetadataManager,
+        query::cursor::Cursor,
+        query::parser::{parse_sql, ParsedSQL},
+        query::plan_v2::create_query_plan,
+        query::predicate::ConstantValue,
+    };
+
+    #[test]
+    fn test_index_join_matches_product() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table users (id integer, name varchar(10)); \
+             create table orders (user_id integer, item varchar(10)); \
+             create table plain_users (id integer, name varchar(10)); \
+             create index users_id_index on users (id)",
+            transaction.clone(),
+        )?;
+        // usersが小さいとproductのほうが安く見積もられるので、joinしない行も入れておく
+        let users = [(1, "alice".to_string()), (2, "bob".to_string()), (3, "carol".to_string())]
+            .into_iter()
+            .chain((10..100).map(|id| (id, format!("user{}", id))));
+        for (id, name) in users {
+            for table_name in ["users", "plain_users"] {
+                database.execute(
+                    &format!(
+                        "insert into {} (id, name) values ({}, '{}')",
+                        table_name, id, name
+                    ),
+                    transaction.clone(),
+                )?;
+            }
+        }
+        database.execute(
+            "insert into orders (user_id, item) values (2, 'pen'); \
+             insert into orders (user_id, item) values (1, 'ink'); \
+             insert into orders (user_id, item) values (2, 'cup'); \
+             insert into orders (user_id, item) values (9, 'box'); \
+             insert into orders (user_id, item) values (null, 'tag')",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let select = |sql: &str| -> Result<(Vec<String>, Vec<Vec<ConstantValue>>), DatabaseError> {
+            let transaction = database.new_transaction(2);
+            let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+            let parsed_sql_list = parse_sql(sql.to_string()).unwrap();
+            let ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+                panic!("Expected a Query variant from parse_sql");
+            };
+            let plan = create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+            let plan_tree = plan.get_child_plans();
+            let mut node_types = vec![];
+            let mut nodes = vec![&plan_tree];
+            while let Some(node) = nodes.pop() {
+                node_types.push(node.current_node_type.clone());
+                nodes.extend(node.child_nodes.iter());
+            }
+            let mut cursor = Cursor::open(plan, transaction.clone())?;
+            let fields = cursor.get_schema().fields.clone();
+            let name_index = fields.iter().position(|f| f == "name").unwrap();
+            let item_index = fields.iter().position(|f| f == "item").unwrap();
+            let rows = cursor.fetch(100)?;
+            cursor.close();
+            transaction.borrow_mut().commit()?;
+            let mut rows = rows
+                .iter()
+                .map(|row| vec![row[name_index].clone(), row[item_index].clone()])
+                .collect::<Vec<Vec<ConstantValue>>>();
+            rows.sort_by_key(|row| format!("{:?}", row));
+            return Ok((node_types, rows));
+        };
+
+        let (node_types, index_join_rows) =
+            select("select name, item from orders, users where orders.user_id = users.id")?;
+        assert!(node_types.contains(&"IndexJoinPlan(user_id)".to_string()));
+        assert!(!node_types.contains(&"ProductPlanV2".to_string()));
+
+        // indexの無いtableではindex joinにならないが、結果は同じ
+        let (node_types, other_join_rows) = select(
+            "select name, item from orders, plain_users where orders.user_id = plain_users.id",
+        )?;
+        assert!(!node_types.iter().any(|node_type| node_type.starts_with("IndexJoinPlan")));
+        assert_eq!(index_join_rows, other_join_rows);
+        assert_eq!(index_join_rows.len(), 3);
+
+        return Ok(());
+    }
+}
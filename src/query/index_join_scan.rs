@@ -29,6 +29,8 @@ pub struct IndexJoinScan {
     pub index: BTreeIndex,
     pub join_field: TableNameAndFieldName,
     pub right_scan: TableScan,
+    // 左の行が無いか、join_fieldがNULLのときはindexを引かない
+    has_search_key: bool,
 }
 
 impl IndexJoinScan {
@@ -43,33 +45,41 @@ impl IndexJoinScan {
             index,
             join_field,
             right_scan,
+            has_search_key: false,
         }
     }
 
     fn reset_index(&mut self) {
-        let search_key = self.left_scan.get_value(self.join_field.clone());
-        let search_key_constant = Constant::new(search_key.unwrap());
-        self.index.before_first(search_key_constant);
+        match self.left_scan.get_value(self.join_field.clone()) {
+            Some(ConstantValue::Null) | None => self.has_search_key = false,
+            Some(search_key) => {
+                self.index.before_first(Constant::new(search_key));
+                self.has_search_key = true;
+            }
+        }
     }
 }
 
 impl ScanV2 for IndexJoinScan {
     fn move_to_before_first(&mut self) -> Result<(), crate::error::ValueNotFound> {
         self.left_scan.move_to_before_first()?;
-        self.left_scan.next()?;
-        self.reset_index();
+        if self.left_scan.next()? {
+            self.reset_index();
+        } else {
+            self.has_search_key = false;
+        }
         return Ok(());
     }
 
     fn next(&mut self) -> Result<bool, crate::error::ValueNotFound> {
         loop {
-            if self.index.next() {
+            if self.has_search_key && self.index.next() {
                 let new_record_id = self.index.get_data_record_id().unwrap();
                 self.right_scan.move_to_record_id(new_record_id);
                 return Ok(true);
             }
 
-            if !self.left_scan.next().unwrap() {
+            if !self.left_scan.next()? {
                 return Ok(false);
             }
 
@@ -143,7 +153,8 @@ impl ScanV2 for IndexJoinScan {
 
 #[test]
 fn test_view_mgr() {
-    let test_dir_name = format!("test_data_{}", uuid::Uuid::new_v4());
+    let test_directory = crate::database::TestDirectory::new();
+    let test_dir_name = test_directory.path_name();
     let test_dir = Path::new(&test_dir_name);
     let block_size = 400;
 
@@ -171,7 +182,11 @@ fn test_view_mgr() {
         log_manager.clone(),
     )));
 
-    let table_manager = TableManagerV2::new(transaction.clone(), true);
+    // catalogはMetadataManagerが作る
+    let metadata_manager = Rc::new(RefCell::new(
+        MetadataManager::new(transaction.clone()).unwrap(),
+    ));
+    let table_manager = TableManagerV2::new(transaction.clone(), false);
 
     let mut student_table_schema = TableSchema::new();
     student_table_schema.add_integer_field("student_id".to_string());
@@ -180,11 +195,13 @@ fn test_view_mgr() {
 
     let table_name = "student".to_string();
 
-    table_manager.create_table(
-        table_name.clone(),
-        &student_table_schema,
-        transaction.clone(),
-    );
+    table_manager
+        .create_table(
+            table_name.clone(),
+            &student_table_schema,
+            transaction.clone(),
+        )
+        .unwrap();
 
     let mut book_table_schema = TableSchema::new();
     book_table_schema.add_integer_field("student_id".to_string());
@@ -193,25 +210,25 @@ fn test_view_mgr() {
 
     let book_table_name = "book".to_string();
 
-    table_manager.create_table(
-        book_table_name.clone(),
-        &book_table_schema,
-        transaction.clone(),
-    );
+    table_manager
+        .create_table(
+            book_table_name.clone(),
+            &book_table_schema,
+            transaction.clone(),
+        )
+        .unwrap();
 
     let mut index_update_planner = IndexUpdatePlanner::new();
 
-    let metadata_manager = Rc::new(RefCell::new(
-        MetadataManager::new(transaction.clone()).unwrap(),
-    ));
-
-    index_update_planner.execute_create_index(
-        "student_id_index".to_string(),
-        table_name.clone(),
-        "student_id".to_string(),
-        transaction.clone(),
-        &mut metadata_manager.borrow_mut(),
-    );
+    index_update_planner
+        .execute_create_index(
+            "student_id_index".to_string(),
+            table_name.clone(),
+            "student_id".to_string(),
+            transaction.clone(),
+            &mut metadata_manager.borrow_mut(),
+        )
+        .unwrap();
 
     create_student_test_data(
         table_name.clone(),
@@ -257,7 +274,7 @@ fn test_view_mgr() {
         table_scan,
     );
 
-    index_join_scan.move_to_before_first();
+    index_join_scan.move_to_before_first().unwrap();
 
     struct ScanResult {
         student_id: i32,
@@ -354,11 +371,13 @@ fn create_book_test_data(
 
     let insert_data = InsertData::new(table_name.clone(), field_name_list.clone(), value_list);
 
-    index_update_planner.execute_insert(
-        insert_data,
-        transaction.clone(),
-        &mut metadata_manager.borrow_mut(),
-    );
+    index_update_planner
+        .execute_insert(
+            insert_data,
+            transaction.clone(),
+            &mut metadata_manager.borrow_mut(),
+        )
+        .unwrap();
 
     let value_list = vec![
         Constant::new(ConstantValue::Number(1)),
@@ -368,11 +387,13 @@ fn create_book_test_data(
 
     let insert_data = InsertData::new(table_name.clone(), field_name_list.clone(), value_list);
 
-    index_update_planner.execute_insert(
-        insert_data,
-        transaction.clone(),
-        &mut metadata_manager.borrow_mut(),
-    );
+    index_update_planner
+        .execute_insert(
+            insert_data,
+            transaction.clone(),
+            &mut metadata_manager.borrow_mut(),
+        )
+        .unwrap();
 
     let value_list = vec![
         Constant::new(ConstantValue::Number(2)),
@@ -382,11 +403,13 @@ fn create_book_test_data(
 
     let insert_data = InsertData::new(table_name.clone(), field_name_list.clone(), value_list);
 
-    index_update_planner.execute_insert(
-        insert_data,
-        transaction.clone(),
-        &mut metadata_manager.borrow_mut(),
-    );
+    index_update_planner
+        .execute_insert(
+            insert_data,
+            transaction.clone(),
+            &mut metadata_manager.borrow_mut(),
+        )
+        .unwrap();
 }
 
 fn create_student_test_data(
@@ -408,11 +431,13 @@ fn create_student_test_data(
 
     let insert_data = InsertData::new(table_name.clone(), field_name_list.clone(), value_list);
 
-    index_update_planner.execute_insert(
-        insert_data,
-        transaction.clone(),
-        &mut metadata_manager.borrow_mut(),
-    );
+    index_update_planner
+        .execute_insert(
+            insert_data,
+            transaction.clone(),
+            &mut metadata_manager.borrow_mut(),
+        )
+        .unwrap();
 
     let value_list = vec![
         Constant::new(ConstantValue::Number(5)),
@@ -422,11 +447,13 @@ fn create_student_test_data(
 
     let insert_data = InsertData::new(table_name.clone(), field_name_list.clone(), value_list);
 
-    index_update_planner.execute_insert(
-        insert_data,
-        transaction.clone(),
-        &mut metadata_manager.borrow_mut(),
-    );
+    index_update_planner
+        .execute_insert(
+            insert_data,
+            transaction.clone(),
+            &mut metadata_manager.borrow_mut(),
+        )
+        .unwrap();
 
     let value_list = vec![
         Constant::new(ConstantValue::Number(1)),
@@ -436,11 +463,13 @@ fn create_student_test_data(
 
     let insert_data = InsertData::new(table_name.clone(), field_name_list.clone(), value_list);
 
-    index_update_planner.execute_insert(
-        insert_data,
-        transaction.clone(),
-        &mut metadata_manager.borrow_mut(),
-    );
+    index_update_planner
+        .execute_insert(
+            insert_data,
+            transaction.clone(),
+            &mut metadata_manager.borrow_mut(),
+        )
+        .unwrap();
 
     let value_list = vec![
         Constant::new(ConstantValue::Number(2)),
@@ -450,11 +479,13 @@ fn create_student_test_data(
 
     let insert_data = InsertData::new(table_name.clone(), field_name_list.clone(), value_list);
 
-    index_update_planner.execute_insert(
-        insert_data,
-        transaction.clone(),
-        &mut metadata_manager.borrow_mut(),
-    );
+    index_update_planner
+        .execute_insert(
+            insert_data,
+            transaction.clone(),
+            &mut metadata_manager.borrow_mut(),
+        )
+        .unwrap();
 
     let value_list = vec![
         Constant::new(ConstantValue::Number(3)),
@@ -464,9 +495,11 @@ fn create_student_test_data(
 
     let insert_data = InsertData::new(table_name.clone(), field_name_list.clone(), value_list);
 
-    index_update_planner.execute_insert(
-        insert_data,
-        transaction.clone(),
-        &mut metadata_manager.borrow_mut(),
-    );
+    index_update_planner
+        .execute_insert(
+            insert_data,
+            transaction.clone(),
+            &mut metadata_manager.borrow_mut(),
+        )
+        .unwrap();
 }
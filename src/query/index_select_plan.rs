@@ -9,7 +9,8 @@ use crate::{
     error::ValueNotFound,
     metadata::index_manager::IndexInfo,
     query::plan_v2::{PlanTreeNodeForDebug, PlanV2},
-    query::predicate::{Constant, ConstantValue, TableNameAndFieldName},
+    query::predicate::{Constant, ConstantValue, ExpressionValue, TableNameAndFieldName},
+    query::predicate_v3::ExpressionV2,
     record::record_page::{Layout, TableSchema},
     record::scan_v2::ScanV2,
     record::table_scan_v2::{RecordID, TableScan},
@@ -33,7 +34,7 @@ impl IndexSelectPlan {
 }
 
 impl PlanV2 for IndexSelectPlan {
-    fn open(&mut self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
         let table_scan = self.plan.open().unwrap();
         let index = Rc::new(RefCell::new(self.index_info.open()));
         return Ok(Box::new(IndexSelectScan::new(
@@ -61,7 +62,13 @@ impl PlanV2 for IndexSelectPlan {
 
     fn get_child_plans(&self) -> PlanTreeNodeForDebug {
         PlanTreeNodeForDebug {
-            current_node_type: "IndexSelectPlan".to_string(),
+            current_node_type: format!(
+                "IndexSelectPlan({} = {})",
+                self.index_info.get_field_name(),
+                ExpressionV2::new(ExpressionValue::Constant(self.key.clone()))
+            ),
+            records_output: self.records_output(),
+            blocks_accessed: self.blocks_accessed(),
             child_nodes: vec![self.plan.get_child_plans()],
         }
     }
@@ -132,7 +139,7 @@ impl ScanV2 for IndexSelectScan {
     }
 
     fn get_record_id(&self) -> RecordID {
-        panic!("IndexSelectScan does not support get_record_id operation");
+        self.table_scan.get_record_id()
     }
 
     fn insert(&mut self) {
@@ -1,11 +1,24 @@
 use std::{cell::RefCell, f32::consts::E, rc::Rc};
 
+use std::collections::{HashMap, HashSet};
+
 use crate::{
-    error::{DatabaseError, UniqueConstraintError, ValueNotFound},
+    error::{
+        ColumnCountMismatch, DatabaseError, FileAccessError, TypeMismatch, UniqueConstraintError,
+        ValueNotFound,
+    },
+    metadata::index_manager::IndexInfo,
     metadata::metadata_manager::MetadataManager,
-    query::parser::{DeleteData, InsertData, UpdateData},
-    query::plan_v2::{PlanV2, SelectPlanV2, TablePlanV2},
-    query::predicate::{Constant, TableNameAndFieldName},
+    query::csv::parse_csv,
+    query::parser::{CopyData, DeleteData, IndexBuildMode, InsertData, UpdateData},
+    query::plan_v2::{
+        apply_field_defaults, collect_insert_rows, create_modify_plan, expand_insert_fields,
+        validate_not_null, validate_value_type, PlanV2, SelectPlanV2, TablePlanV2,
+    },
+    query::predicate::{Constant, ConstantValue, TableNameAndFieldName},
+    query::predicate_v3::PredicateV2,
+    record::record_page::TableFieldType,
+    record::table_scan_v2::RecordID,
     tx::transaction_v2::TransactionV2,
 };
 
@@ -16,83 +29,231 @@ impl IndexUpdatePlanner {
         IndexUpdatePlanner {}
     }
 
+    // 挿入した行数を返す
     pub fn execute_insert(
         &self,
         insert_data: InsertData,
         transaction: Rc<RefCell<TransactionV2>>,
         metadata_manager: &mut MetadataManager,
+    ) -> Result<usize, DatabaseError> {
+        let insert_data =
+            expand_insert_fields(&insert_data, transaction.clone(), metadata_manager)?;
+        let rows = collect_insert_rows(transaction.clone(), metadata_manager, &insert_data)?;
+        let layout =
+            metadata_manager.get_layout(insert_data.table_name.clone(), transaction.clone())?;
+        let (field_name_list, rows) = apply_field_defaults(
+            &insert_data.table_name,
+            &layout.schema,
+            &insert_data.field_name_list,
+            rows,
+        )?;
+        self.insert_rows(
+            insert_data.table_name.clone(),
+            &field_name_list,
+            &rows,
+            IndexBuildMode::PerRow,
+            transaction,
+            metadata_manager,
+        )?;
+        return Ok(rows.len());
+    }
+
+    // csvを読み込んでtableに挿入する。挿入した行数を返す
+    // commitは呼び出し側で最後に1回だけ行う
+    pub fn execute_copy(
+        &self,
+        copy_data: CopyData,
+        transaction: Rc<RefCell<TransactionV2>>,
+        metadata_manager: &mut MetadataManager,
+    ) -> Result<usize, DatabaseError> {
+        let table_name = copy_data.table_name.clone();
+        let layout = metadata_manager.get_layout(table_name.clone(), transaction.clone())?;
+
+        let field_name_list = if copy_data.field_name_list.is_empty() {
+            layout.schema.fields.clone()
+        } else {
+            copy_data.field_name_list.clone()
+        };
+
+        let content = std::fs::read_to_string(&copy_data.file_path).map_err(|e| {
+            DatabaseError::FileAccess(FileAccessError::new(
+                copy_data.file_path.clone(),
+                e.to_string(),
+            ))
+        })?;
+
+        let mut rows = Vec::new();
+        for record in parse_csv(&content).iter() {
+            let columns: Vec<Option<&str>> =
+                record.iter().map(|column| column.as_deref()).collect();
+            if columns.len() != field_name_list.len() {
+                return Err(DatabaseError::ColumnCountMismatch(
+                    ColumnCountMismatch::new(
+                        table_name.clone(),
+                        field_name_list.len(),
+                        columns.len(),
+                    ),
+                ));
+            }
+
+            let mut row = Vec::new();
+            for (field_name, column) in field_name_list.iter().zip(columns.iter()) {
+                let Some(field_type) = layout.schema.get_field_type(field_name.clone()) else {
+                    return Err(DatabaseError::ValueNotFound(ValueNotFound::new(
+                        field_name.clone(),
+                        Some(table_name.clone()),
+                    )));
+                };
+                // 囲まれていない空の項目はNULL
+                let parsed_value = match column {
+                    Some(column) => parse_csv_value(&field_type, column),
+                    None => Some(ConstantValue::Null),
+                };
+                let Some(value) = parsed_value else {
+                    return Err(DatabaseError::TypeMismatch(TypeMismatch::new(
+                        field_name.clone(),
+                        table_name.clone(),
+                    )));
+                };
+                let value = Constant::new(value);
+                validate_value_type(&table_name, &layout.schema, field_name, &value)?;
+                row.push(value);
+            }
+            rows.push(row);
+        }
+        let (field_name_list, rows) =
+            apply_field_defaults(&table_name, &layout.schema, &field_name_list, rows)?;
+
+        self.insert_rows(
+            table_name,
+            &field_name_list,
+            &rows,
+            copy_data.build_indexes,
+            transaction,
+            metadata_manager,
+        )?;
+
+        return Ok(rows.len());
+    }
+
+    fn insert_rows(
+        &self,
+        table_name: String,
+        field_name_list: &Vec<String>,
+        rows: &Vec<Vec<Constant>>,
+        build_indexes: IndexBuildMode,
+        transaction: Rc<RefCell<TransactionV2>>,
+        metadata_manager: &mut MetadataManager,
     ) -> Result<(), DatabaseError> {
-        let table_name = insert_data.table_name.clone();
         let mut indexes =
             metadata_manager.get_index_info(table_name.clone(), transaction.clone())?;
-        let mut plan = TablePlanV2::new(table_name.clone(), transaction.clone(), metadata_manager)?;
-
-        let mut update_scan = plan.open()?;
+        let plan = TablePlanV2::new(table_name.clone(), transaction.clone(), metadata_manager)?;
 
-        for (field, insert_value) in insert_data
-            .field_name_list
-            .iter()
-            .zip(insert_data.value_list.iter())
-        {
-            let index_info = indexes.get_mut(field);
+        let schema = plan.get_schema().clone();
 
-            if let Some(info) = index_info {
-                let mut index_scan = info.open();
-                index_scan.before_first(insert_value.clone());
+        // indexにまだ入っていない、このinsertで入れたunique fieldの値
+        let mut inserted_keys: HashMap<String, HashSet<Constant>> = HashMap::new();
+        // build_indexes = after のときは行ごとにindexを探さず、
+        // 今tableにあるunique fieldの値を1回のscanで読んでおく
+        let existing_keys = match build_indexes {
+            IndexBuildMode::PerRow => None,
+            IndexBuildMode::After => Some(Self::collect_unique_keys(&plan, field_name_list)?),
+        };
 
-                // if index_scan.next() {
-                //     return Err(DatabaseError::UniqueConstraintViolation(
-                //         UniqueConstraintError::new(field.clone(), table_name.clone()),
-                //     ));
-                // }
+        // 重複が見つかったら1行も書かないように、先に全部の行を確かめる
+        for row in rows.iter() {
+            for (field, insert_value) in field_name_list.iter().zip(row.iter()) {
+                // NULLはindexに入れないので、uniqueでも重複しない
+                if schema.get_key_constraint(field).is_none()
+                    || insert_value.value == ConstantValue::Null
+                {
+                    continue;
+                }
+
+                let is_new = inserted_keys
+                    .entry(field.clone())
+                    .or_default()
+                    .insert(insert_value.clone());
+                let exists = match (&existing_keys, indexes.get(field)) {
+                    (Some(existing_keys), _) => existing_keys[field].contains(insert_value),
+                    (None, Some(info)) => Self::index_contains(info, insert_value, None),
+                    (None, None) => false,
+                };
+                if !is_new || exists {
+                    return Err(
+                        UniqueConstraintError::new(field.clone(), table_name.clone()).into(),
+                    );
+                }
             }
         }
 
-        update_scan.insert();
-        let record_id = update_scan.get_record_id();
+        let mut update_scan = plan.open()?;
+
+        for row in rows.iter() {
+            update_scan.insert();
+            let record_id = update_scan.get_record_id();
 
-        for (field, value) in insert_data
-            .field_name_list
-            .iter()
-            .zip(insert_data.value_list.iter())
-        {
-            update_scan.set_value(field.clone(), value.value.clone());
+            for (field, value) in field_name_list.iter().zip(row.iter()) {
+                update_scan.set_value(field.clone(), value.value.clone());
 
-            let index_info = indexes.get_mut(field);
+                let index_info = indexes.get_mut(field);
 
-            if let Some(info) = index_info {
-                let mut index = info.open();
-                index.insert(value.clone(), record_id.clone());
-                index.close();
+                // NULLはindexに入れない
+                if value.value == ConstantValue::Null {
+                    continue;
+                }
+
+                // build_indexes = after のときは、loadの後でreindexと同じように作り直す
+                if let (Some(info), IndexBuildMode::PerRow) = (index_info, build_indexes) {
+                    let mut index = info.open();
+                    index.insert(value.clone(), record_id.clone());
+                    index.close();
+                }
             }
         }
+
         update_scan.close();
 
+        if build_indexes == IndexBuildMode::After {
+            self.execute_reindex(table_name.clone(), transaction.clone(), metadata_manager)?;
+        }
+
+        metadata_manager.record_modifications(table_name, rows.len() as u32, 0, transaction)?;
         return Ok(());
     }
 
+    // 消した行数を返す
     pub fn execute_delete(
         &mut self,
         delete_data: DeleteData,
         transaction: Rc<RefCell<TransactionV2>>,
         metadata_manager: &mut MetadataManager,
-    ) -> Result<(), ValueNotFound> {
+    ) -> Result<usize, ValueNotFound> {
         let table_name = delete_data.table_name.clone();
-        let plan = TablePlanV2::new(table_name.clone(), transaction.clone(), metadata_manager)?;
-
-        let mut select_plan = SelectPlanV2::new(Box::new(plan), delete_data.predicate);
+        let record_ids = Self::collect_target_record_ids(
+            &table_name,
+            &delete_data.predicate,
+            transaction.clone(),
+            metadata_manager,
+        )?;
 
-        let mut update_scan = select_plan.open()?;
+        let plan = TablePlanV2::new(table_name.clone(), transaction.clone(), metadata_manager)?;
+        let mut update_scan = plan.open()?;
 
-        let mut indexes = metadata_manager.get_index_info(table_name, transaction.clone())?;
+        let mut indexes =
+            metadata_manager.get_index_info(table_name.clone(), transaction.clone())?;
 
-        while update_scan.next()? {
-            let record_id = update_scan.get_record_id();
+        let count = record_ids.len();
+        for record_id in record_ids.into_iter() {
+            update_scan.move_to_record_id(record_id.clone());
 
             for (field_name, index_info) in indexes.iter_mut() {
                 let value =
                     update_scan.get_value(TableNameAndFieldName::new(None, field_name.clone()));
                 let constant = Constant::new(value.unwrap());
+                if constant.value == ConstantValue::Null {
+                    continue;
+                }
                 let mut index = index_info.open();
                 index.delete(constant, record_id.clone());
                 index.close();
@@ -101,26 +262,62 @@ impl IndexUpdatePlanner {
             update_scan.delete();
         }
         update_scan.close();
-        return Ok(());
+
+        metadata_manager.record_modifications(table_name, 0, count as u32, transaction)?;
+        return Ok(count);
     }
 
+    // 書き換えた行数を返す
     pub fn execute_modify(
         &mut self,
         update_data: UpdateData,
         transaction: Rc<RefCell<TransactionV2>>,
         metadata_manager: &mut MetadataManager,
-    ) -> Result<(), ValueNotFound> {
+    ) -> Result<usize, DatabaseError> {
         let table_name = update_data.table_name.clone();
         let field_name = update_data.field_name.clone();
 
+        let layout = metadata_manager.get_layout(table_name.clone(), transaction.clone())?;
+        validate_value_type(&table_name, &layout.schema, &field_name, &update_data.new_value)?;
+        validate_not_null(&table_name, &layout.schema, &field_name, &update_data.new_value)?;
+
+        let record_ids = Self::collect_target_record_ids(
+            &table_name,
+            &update_data.predicate,
+            transaction.clone(),
+            metadata_manager,
+        )?;
+        if record_ids.is_empty() {
+            return Ok(0);
+        }
+        let record_ids = metadata_manager.move_to_current_layout(
+            table_name.clone(),
+            &field_name,
+            record_ids,
+            transaction.clone(),
+        )?;
+
         let table_plan =
-            TablePlanV2::new(table_name.clone(), transaction.clone(), metadata_manager).unwrap();
+            TablePlanV2::new(table_name.clone(), transaction.clone(), metadata_manager)?;
 
-        let mut select_plan = SelectPlanV2::new(Box::new(table_plan), update_data.predicate);
+        let mut index_info_hash =
+            metadata_manager.get_index_info(table_name.clone(), transaction.clone())?;
 
-        let mut index_info_hash = metadata_manager
-            .get_index_info(table_name.clone(), transaction.clone())
-            .unwrap();
+        // 全部の行が同じ値になるので、uniqueなら書き換える行は1つまで
+        // 書き換える行そのものは、今の値が同じでも重複にならない
+        if layout.schema.get_key_constraint(&field_name).is_some()
+            && update_data.new_value.value != ConstantValue::Null
+        {
+            let exists_in_index = match index_info_hash.get(&field_name) {
+                Some(info) => {
+                    Self::index_contains(info, &update_data.new_value, record_ids.first())
+                }
+                None => false,
+            };
+            if record_ids.len() > 1 || exists_in_index {
+                return Err(UniqueConstraintError::new(field_name, table_name).into());
+            }
+        }
 
         let index_info = index_info_hash.get_mut(&field_name);
 
@@ -129,18 +326,25 @@ impl IndexUpdatePlanner {
             None => None,
         };
 
-        let mut update_scan = select_plan.open()?;
+        let mut update_scan = table_plan.open()?;
+
+        let count = record_ids.len();
+        for record_id in record_ids.into_iter() {
+            update_scan.move_to_record_id(record_id.clone());
 
-        while update_scan.next()? {
             let new_value = update_data.new_value.clone();
             let old_value =
                 update_scan.get_value(TableNameAndFieldName::new(None, field_name.clone()));
             update_scan.set_value(update_data.field_name.clone(), new_value.value.clone());
 
             if let Some(idx) = index.as_mut() {
-                let record_id = update_scan.get_record_id();
-                idx.delete(Constant::new(old_value.unwrap()), record_id.clone());
-                idx.insert(new_value, record_id.clone());
+                let old_value = Constant::new(old_value.unwrap());
+                if old_value.value != ConstantValue::Null {
+                    idx.delete(old_value, record_id.clone());
+                }
+                if new_value.value != ConstantValue::Null {
+                    idx.insert(new_value, record_id.clone());
+                }
             }
         }
 
@@ -149,7 +353,79 @@ impl IndexUpdatePlanner {
         }
 
         update_scan.close();
-        return Ok(());
+        return Ok(count);
+    }
+
+    // indexにvalueのentryがあるか。except_record_idの行のentryは数えない
+    // field_name_listのうちunique fieldについて、tableにある値を集める。NULLは入れない
+    fn collect_unique_keys(
+        plan: &TablePlanV2,
+        field_name_list: &Vec<String>,
+    ) -> Result<HashMap<String, HashSet<Constant>>, ValueNotFound> {
+        let schema = plan.get_schema();
+        let mut keys: HashMap<String, HashSet<Constant>> = field_name_list
+            .iter()
+            .filter(|field| schema.get_key_constraint(field).is_some())
+            .map(|field| (field.clone(), HashSet::new()))
+            .collect();
+        if keys.is_empty() {
+            return Ok(keys);
+        }
+
+        let mut scan = plan.open()?;
+        while scan.next()? {
+            for (field, values) in keys.iter_mut() {
+                let value = scan.get_value(TableNameAndFieldName::new(None, field.clone()));
+                match value {
+                    Some(ConstantValue::Null) | None => {}
+                    Some(value) => {
+                        values.insert(Constant::new(value));
+                    }
+                }
+            }
+        }
+        scan.close();
+        return Ok(keys);
+    }
+
+    fn index_contains(
+        index_info: &IndexInfo,
+        value: &Constant,
+        except_record_id: Option<&RecordID>,
+    ) -> bool {
+        let mut index = index_info.open();
+        index.before_first(value.clone());
+        let mut found = false;
+        while index.next() {
+            let record_id = index.get_data_record_id();
+            if record_id.as_ref() != except_record_id {
+                found = true;
+                break;
+            }
+        }
+        index.close();
+        return found;
+    }
+
+    // 対象の行を先に全部集めてから書き換える。
+    // indexで探している途中にそのindexを書き換えると、entryを読み飛ばすことがある
+    fn collect_target_record_ids(
+        table_name: &String,
+        predicate: &PredicateV2,
+        transaction: Rc<RefCell<TransactionV2>>,
+        metadata_manager: &mut MetadataManager,
+    ) -> Result<Vec<RecordID>, ValueNotFound> {
+        let plan =
+            create_modify_plan(table_name, predicate, transaction.clone(), metadata_manager)?;
+        let mut scan = plan.open()?;
+
+        let mut record_ids = Vec::new();
+        while scan.next()? {
+            record_ids.push(scan.get_record_id());
+        }
+        scan.close();
+
+        return Ok(record_ids);
     }
 
     pub fn execute_create_table(
@@ -158,9 +434,53 @@ impl IndexUpdatePlanner {
         schema: &crate::record::record_page::TableSchema,
         transaction: Rc<RefCell<TransactionV2>>,
         metadata_manager: &mut MetadataManager,
-    ) -> Result<(), ValueNotFound> {
-        metadata_manager.create_table(table_name, schema, transaction);
-        return Ok(());
+    ) -> Result<(), DatabaseError> {
+        return metadata_manager.create_table(table_name, schema, transaction);
+    }
+
+    // tableのindexをすべて空にして、tableの行を1回のscanで読んで作り直す
+    // 作り直したindexの数を返す
+    pub fn execute_reindex(
+        &self,
+        table_name: String,
+        transaction: Rc<RefCell<TransactionV2>>,
+        metadata_manager: &mut MetadataManager,
+    ) -> Result<usize, DatabaseError> {
+        let plan = TablePlanV2::new(table_name.clone(), transaction.clone(), metadata_manager)?;
+        let indexes = metadata_manager.get_index_info(table_name.clone(), transaction.clone())?;
+
+        let mut entries: HashMap<String, Vec<(Constant, RecordID)>> = HashMap::new();
+        let mut scan = plan.open()?;
+        while scan.next()? {
+            let record_id = scan.get_record_id();
+            for field_name in indexes.keys() {
+                let value = scan.get_value(TableNameAndFieldName::new(None, field_name.clone()));
+                // NULLはindexに入れない
+                let Some(value) = value.filter(|value| *value != ConstantValue::Null) else {
+                    continue;
+                };
+                entries
+                    .entry(field_name.clone())
+                    .or_default()
+                    .push((Constant::new(value), record_id.clone()));
+            }
+        }
+        scan.close();
+
+        // indexごとにkeyの順に並べて、1回のopenでまとめて挿入する
+        for (field_name, info) in indexes.iter() {
+            let mut entries = entries.remove(field_name).unwrap_or_default();
+            entries.sort_by(|(lhs, _), (rhs, _)| lhs.compare_to(rhs.value.clone()));
+
+            let mut index = info.open();
+            index.delete_all();
+            for (value, record_id) in entries.into_iter() {
+                index.insert(value, record_id);
+            }
+            index.close();
+        }
+
+        return Ok(indexes.len());
     }
 
     pub fn execute_create_index(
@@ -171,24 +491,79 @@ impl IndexUpdatePlanner {
         transaction: Rc<RefCell<TransactionV2>>,
         metadata_manager: &mut MetadataManager,
     ) -> Result<(), ValueNotFound> {
-        metadata_manager.create_index(index_name, table_name, field_name, transaction);
+        metadata_manager.create_index(index_name, table_name, field_name, transaction)?;
         return Ok(());
     }
 }
 
+// csvの1項目をfieldの型に合わせて変換する。変換できなければNone
+fn parse_csv_value(field_type: &TableFieldType, column: &str) -> Option<ConstantValue> {
+    let column = column.trim();
+    match field_type {
+        TableFieldType::INTEGER => {
+            return column.parse::<i32>().ok().map(ConstantValue::Number);
+        }
+        TableFieldType::DECIMAL(_, _) => {
+            let (sign, digits) = match column.strip_prefix('-') {
+                Some(digits) => (-1, digits),
+                None => (1, column),
+            };
+            let (integer_part, fraction_part) = digits.split_once('.').unwrap_or((digits, ""));
+            if integer_part.is_empty()
+                || !integer_part.chars().all(|c| c.is_ascii_digit())
+                || !fraction_part.chars().all(|c| c.is_ascii_digit())
+            {
+                return None;
+            }
+            let value = format!("{}{}", integer_part, fraction_part)
+                .parse::<i32>()
+                .ok()?;
+            return Some(ConstantValue::Decimal {
+                value: sign * value,
+                scale: fraction_part.len() as u32,
+            });
+        }
+        // "NaN" や "inf" もf64としては読めるが、比べられない値は入れない
+        TableFieldType::FLOAT => {
+            return column
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite())
+                .map(ConstantValue::Float);
+        }
+        TableFieldType::DATE => {
+            return Constant::parse_date(column).map(ConstantValue::Date);
+        }
+        TableFieldType::VARCHAR => {
+            // 'abc' や "abc" のように囲まれていれば外す
+            let unquoted = column
+                .strip_prefix('\'')
+                .and_then(|c| c.strip_suffix('\''))
+                .or_else(|| column.strip_prefix('"').and_then(|c| c.strip_suffix('"')))
+                .unwrap_or(column);
+            return Some(ConstantValue::String(unquoted.to_string()));
+        }
+    }
+}
+
 // test
 
 #[cfg(test)]
 mod tests {
     use std::path::Path;
 
-    use crate::{database::Database, query::parser::parse_sql, record::record_page::TableSchema};
+    use crate::{
+        database::Database,
+        query::parser::{parse_sql, ParsedSQL},
+        record::record_page::TableSchema,
+    };
 
     use super::*;
 
     #[test]
-    fn test_index_update_planner() -> Result<(), ValueNotFound> {
-        let directory_path_name = format!("test_data_{}", uuid::Uuid::new_v4());
+    fn test_index_update_planner() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
 
@@ -201,20 +576,21 @@ mod tests {
 
         let table_name = "test_table".to_string();
 
-        metadata_manager.create_table(table_name.clone(), &schema, transaction.clone());
+        metadata_manager.create_table(table_name.clone(), &schema, transaction.clone())?;
 
         metadata_manager.create_index(
             "my_index".to_string(),
             table_name.clone(),
             "A".to_string(),
             transaction.clone(),
-        );
+        )?;
 
         for i in 0..1000 {
             let parsed_sql = parse_sql(format!(
                 "insert into test_table (A, B) values ({}, 'Hello World yay!{}')",
                 i, i
-            ));
+            ))
+            .unwrap();
 
             let insert_data = match &parsed_sql[0] {
                 crate::query::parser::ParsedSQL::Insert(q) => q,
@@ -228,10 +604,10 @@ mod tests {
                 insert_data.clone(),
                 transaction.clone(),
                 &mut metadata_manager,
-            );
+            )?;
         }
 
-        transaction.borrow_mut().commit();
+        transaction.borrow_mut().commit()?;
 
         let index_update_planner = IndexUpdatePlanner::new();
 
@@ -255,7 +631,7 @@ mod tests {
 
             // assert_eq!(record_id.get_block_number(), 6);
 
-            let mut table_plan = TablePlanV2::new(
+            let table_plan = TablePlanV2::new(
                 table_name.clone(),
                 transaction.clone(),
                 &mut metadata_manager,
@@ -274,4 +650,280 @@ mod tests {
 
         return Ok(());
     }
+
+    // indexを使ってfieldの値がkeyのrecordを探し、B fieldの値を返す
+    fn lookup_by_index(
+        table_name: &str,
+        key: i32,
+        transaction: Rc<RefCell<TransactionV2>>,
+        metadata_manager: &mut MetadataManager,
+    ) -> Result<Vec<String>, ValueNotFound> {
+        let mut indexes =
+            metadata_manager.get_index_info(table_name.to_string(), transaction.clone())?;
+        let mut index = indexes.get_mut("A").unwrap().open();
+
+        let table_plan = TablePlanV2::new(
+            table_name.to_string(),
+            transaction.clone(),
+            metadata_manager,
+        )?;
+        let mut table_scan = table_plan.open()?;
+
+        let mut values = Vec::new();
+        index.before_first(Constant::new(ConstantValue::Number(key)));
+        while index.next() {
+            let record_id = index.get_data_record_id().unwrap();
+            table_scan.move_to_record_id(record_id);
+            let b_value = table_scan.get_string(TableNameAndFieldName::new(None, "B".to_string()));
+            values.push(b_value.unwrap());
+        }
+        index.close();
+        table_scan.close();
+
+        values.sort();
+        return Ok(values);
+    }
+
+    #[test]
+    fn test_copy_build_indexes_after() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let csv_path = directory_path.join("load.csv");
+        let csv = (0..200)
+            .map(|i| format!("{}, 'row {}'", (i * 7) % 50, i))
+            .collect::<Vec<String>>()
+            .join("\n");
+        std::fs::write(&csv_path, csv).unwrap();
+
+        let index_update_planner = IndexUpdatePlanner::new();
+
+        for (table_name, build_indexes) in [("per_row_table", "per_row"), ("after_table", "after")]
+        {
+            let mut schema = TableSchema::new();
+            schema.add_integer_field("A".to_string());
+            schema.add_string_field("B".to_string(), 9);
+            metadata_manager.create_table(table_name.to_string(), &schema, transaction.clone())?;
+            metadata_manager.create_index(
+                format!("{}_index", table_name),
+                table_name.to_string(),
+                "A".to_string(),
+                transaction.clone(),
+            )?;
+
+            // load前から入っている行のindexも残ること
+            let parsed_sql = parse_sql(format!(
+                "insert into {} (A, B) values (3, 'existing')",
+                table_name
+            ))
+            .unwrap();
+            let ParsedSQL::Insert(insert_data) = &parsed_sql[0] else {
+                panic!("Expected a Insert variant from parse_sql");
+            };
+            index_update_planner
+                .execute_insert(
+                    insert_data.clone(),
+                    transaction.clone(),
+                    &mut metadata_manager,
+                )
+                .unwrap();
+
+            let parsed_sql = parse_sql(format!(
+                "copy {} (A, B) from '{}' with (build_indexes = {})",
+                table_name,
+                csv_path.to_str().unwrap(),
+                build_indexes
+            ))
+            .unwrap();
+            let ParsedSQL::Copy(copy_data) = &parsed_sql[0] else {
+                panic!("Expected a Copy variant from parse_sql");
+            };
+            let count = index_update_planner
+                .execute_copy(
+                    copy_data.clone(),
+                    transaction.clone(),
+                    &mut metadata_manager,
+                )
+                .unwrap();
+            assert_eq!(count, 200);
+        }
+        transaction.borrow_mut().commit()?;
+
+        for key in 0..50 {
+            let per_row = lookup_by_index(
+                "per_row_table",
+                key,
+                transaction.clone(),
+                &mut metadata_manager,
+            )?;
+            let after = lookup_by_index(
+                "after_table",
+                key,
+                transaction.clone(),
+                &mut metadata_manager,
+            )?;
+            assert_eq!(per_row, after);
+
+            let mut expected: Vec<String> = (0..200)
+                .filter(|i| (i * 7) % 50 == key)
+                .map(|i| format!("row {}", i))
+                .collect();
+            if key == 3 {
+                expected.push("existing".to_string());
+            }
+            expected.sort();
+            assert_eq!(after, expected);
+        }
+
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_reindex_rebuilds_indexes_from_table() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+
+        // create indexは作る前からある行をindexに入れない
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table items (A integer, B varchar(9)); \
+             insert into items (A, B) values (1, 'a'); \
+             insert into items (A, B) values (2, 'b'); \
+             insert into items (A, B) values (1, 'c'); \
+             insert into items (A, B) values (null, 'd'); \
+             create index items_index on items (A)",
+            transaction.clone(),
+        )?;
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let values = lookup_by_index("items", 1, transaction.clone(), &mut metadata_manager)?;
+        assert_eq!(values, Vec::<String>::new());
+
+        // 2回作り直しても、entryは1行に1つだけ
+        for _ in 0..2 {
+            let result = database.execute("reindex items", transaction.clone())?;
+            assert_eq!(result, crate::database::QueryResult::RowsAffected(0));
+            let values = lookup_by_index("items", 1, transaction.clone(), &mut metadata_manager)?;
+            assert_eq!(values, vec!["a", "c"]);
+            let values = lookup_by_index("items", 2, transaction.clone(), &mut metadata_manager)?;
+            assert_eq!(values, vec!["b"]);
+        }
+
+        // 作り直した後もdeleteでindexのentryが消える
+        database.execute("delete from items where A = 2", transaction.clone())?;
+        let values = lookup_by_index("items", 2, transaction.clone(), &mut metadata_manager)?;
+        assert_eq!(values, Vec::<String>::new());
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let result = database.execute("reindex missing_table", transaction.clone());
+        assert!(matches!(result, Err(DatabaseError::ValueNotFound(_))));
+        transaction.borrow_mut().rollback();
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_copy_build_indexes_after_checks_unique() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table items (id integer unique, name varchar(10)); \
+             insert into items (id, name) values (1, 'existing')",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        // 既にある値とも、同じfileの中の値とも重ならないこと
+        let csv_path = directory_path.join("items.csv");
+        for csv in ["2, 'b'\n1, 'c'", "2, 'b'\n2, 'c'"] {
+            std::fs::write(&csv_path, csv).unwrap();
+            let transaction = database.new_transaction(2);
+            let result = database.execute(
+                &format!(
+                    "copy items (id, name) from '{}' with (build_indexes = after)",
+                    csv_path.to_str().unwrap()
+                ),
+                transaction.clone(),
+            );
+            assert!(matches!(
+                result,
+                Err(DatabaseError::UniqueConstraintViolation(_))
+            ));
+            transaction.borrow_mut().rollback();
+        }
+
+        std::fs::write(&csv_path, "2, 'b'\n3, 'c'").unwrap();
+        let transaction = database.new_transaction(3);
+        let result = database.execute(
+            &format!(
+                "copy items (id, name) from '{}' with (build_indexes = after)",
+                csv_path.to_str().unwrap()
+            ),
+            transaction.clone(),
+        )?;
+        assert_eq!(result, crate::database::QueryResult::RowsAffected(2));
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(4);
+        let result =
+            database.execute("select name from items where id = 3", transaction.clone())?;
+        let crate::database::QueryResult::Rows { rows, .. } = result else {
+            panic!("Expected rows");
+        };
+        assert_eq!(rows, vec![vec![ConstantValue::String("c".to_string())]]);
+        transaction.borrow_mut().commit()?;
+        return Ok(());
+    }
+
+    #[test]
+    fn test_copy_rejects_non_finite_float() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+
+        let transaction = database.new_transaction(1);
+        database.execute("create table readings (reading float)", transaction.clone())?;
+        transaction.borrow_mut().commit()?;
+
+        let csv_path = directory_path.join("readings.csv");
+        for column in ["NaN", "inf", "-infinity"] {
+            std::fs::write(&csv_path, format!("1.5\n{}", column)).unwrap();
+            let transaction = database.new_transaction(2);
+            let result = database.execute(
+                &format!(
+                    "copy readings (reading) from '{}'",
+                    csv_path.to_str().unwrap()
+                ),
+                transaction.clone(),
+            );
+            assert!(
+                matches!(result, Err(DatabaseError::TypeMismatch(_))),
+                "{} should be rejected",
+                column
+            );
+            transaction.borrow_mut().rollback();
+        }
+
+        let transaction = database.new_transaction(3);
+        let result = database.execute("select reading from readings", transaction.clone())?;
+        let crate::database::QueryResult::Rows { rows, .. } = result else {
+            panic!("Expected rows");
+        };
+        assert!(rows.is_empty());
+        transaction.borrow_mut().commit()?;
+        return Ok(());
+    }
 }
@@ -0,0 +1,94 @@
+use crate::query::predicate::{Constant, ConstantValue};
+
+// selectの結果をjsonで書き出す
+// 行ごとにcolumn名をkeyにしたobjectにし、全体を配列にする
+
+pub fn format_json_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    return escaped;
+}
+
+// jsonにはNaNや無限大が無いので、そのfloatはnullにする
+pub fn format_json_value(value: &ConstantValue) -> String {
+    match value {
+        ConstantValue::Null => return "null".to_string(),
+        ConstantValue::Parameter(index) => panic!("Parameter {} is not bound", index + 1),
+        ConstantValue::Number(i) => return i.to_string(),
+        ConstantValue::Decimal { value, scale } => {
+            return Constant::format_decimal(*value, *scale);
+        }
+        ConstantValue::Float(f) => {
+            if !f.is_finite() {
+                return "null".to_string();
+            }
+            return f.to_string();
+        }
+        ConstantValue::Date(days) => return format_json_string(&Constant::format_date(*days)),
+        ConstantValue::String(s) => return format_json_string(s),
+    }
+}
+
+pub fn format_json_rows(columns: &[String], rows: &[Vec<ConstantValue>]) -> String {
+    let objects = rows
+        .iter()
+        .map(|row| {
+            let members = columns
+                .iter()
+                .zip(row.iter())
+                .map(|(column, value)| {
+                    format!(
+                        "{}:{}",
+                        format_json_string(column),
+                        format_json_value(value)
+                    )
+                })
+                .collect::<Vec<String>>();
+            return format!("{{{}}}", members.join(","));
+        })
+        .collect::<Vec<String>>();
+    return format!("[{}]", objects.join(","));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_json_rows() {
+        let columns = vec!["id".to_string(), "name".to_string(), "price".to_string()];
+        let rows = vec![
+            vec![
+                ConstantValue::Number(1),
+                ConstantValue::String("say \"hi\" \\ bye\n".to_string()),
+                ConstantValue::Decimal {
+                    value: -150,
+                    scale: 2,
+                },
+            ],
+            vec![
+                ConstantValue::Number(-2),
+                ConstantValue::Null,
+                ConstantValue::Float(f64::NAN),
+            ],
+        ];
+        assert_eq!(
+            format_json_rows(&columns, &rows),
+            "[{\"id\":1,\"name\":\"say \\\"hi\\\" \\\\ bye\\n\",\"price\":-1.50},\
+             {\"id\":-2,\"name\":null,\"price\":null}]"
+        );
+        assert_eq!(format_json_rows(&columns, &[]), "[]");
+        assert_eq!(format_json_string("\u{1}"), "\"\\u0001\"");
+    }
+}
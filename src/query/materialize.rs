@@ -22,6 +22,10 @@ impl TempTable {
     pub fn new(transaction: Rc<RefCell<TransactionV2>>, schema: TableSchema) -> Self {
         let table_name = TempTable::next_table_name();
         let layout = Layout::new(schema.clone());
+        // fileはtransactionのcommit/rollbackで消える
+        transaction
+            .borrow_mut()
+            .register_temp_file(format!("{}.tbl", table_name));
         TempTable {
             transaction,
             table_name,
@@ -67,7 +71,7 @@ impl MaterializePlan {
 }
 
 impl PlanV2 for MaterializePlan {
-    fn open(&mut self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
         let schema = self.src_plan.get_schema().clone();
 
         let mut temp_table = TempTable::new(self.transaction.clone(), schema.clone());
@@ -92,13 +96,13 @@ impl PlanV2 for MaterializePlan {
         }
 
         src.close();
-        dest.move_to_before_first();
+        dest.move_to_before_first()?;
         Ok(dest)
     }
 
     fn blocks_accessed(&self) -> u32 {
         let layout = Layout::new(self.src_plan.get_schema().clone());
-        let rpb = self.transaction.borrow().get_block_size() as i32 / layout.get_slot_size();
+        let rpb = layout.records_per_block(self.transaction.borrow().get_block_size());
         return self.src_plan.records_output() as u32 / rpb as u32;
     }
 
@@ -117,6 +121,8 @@ impl PlanV2 for MaterializePlan {
     fn get_child_plans(&self) -> crate::query::plan_v2::PlanTreeNodeForDebug {
         crate::query::plan_v2::PlanTreeNodeForDebug {
             current_node_type: "MaterializePlan".to_string(),
+            records_output: self.records_output(),
+            blocks_accessed: self.blocks_accessed(),
             child_nodes: vec![self.src_plan.get_child_plans()],
         }
     }
@@ -137,7 +143,7 @@ mod tests {
 
     use super::*;
 
-    fn prepare_test_data(directory_path_name: String) -> Result<(), ValueNotFound> {
+    fn prepare_test_data(directory_path_name: String) -> Result<(), crate::error::DatabaseError> {
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -146,7 +152,7 @@ mod tests {
         let create_table_sql =
             "create table test_table_1 (A_1 integer, B_1 varchar(10))".to_string();
 
-        let parsed_sql_list = parse_sql(create_table_sql.clone());
+        let parsed_sql_list = parse_sql(create_table_sql.clone()).unwrap();
 
         let create_table_data = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::CreateTable(q) => q,
@@ -166,7 +172,7 @@ mod tests {
         let create_table_sql =
             "create table test_table_2 (A_2 integer, B_2 varchar(10))".to_string();
 
-        let parsed_sql_list = parse_sql(create_table_sql.clone());
+        let parsed_sql_list = parse_sql(create_table_sql.clone()).unwrap();
 
         let create_table_data = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::CreateTable(q) => q,
@@ -198,7 +204,7 @@ mod tests {
         ];
 
         for insert_sql in insert_sql_list.iter() {
-            let parsed_sql_list = parse_sql(insert_sql.clone());
+            let parsed_sql_list = parse_sql(insert_sql.clone()).unwrap();
 
             let insert_data = match &parsed_sql_list[0] {
                 crate::query::parser::ParsedSQL::Insert(q) => q,
@@ -209,18 +215,19 @@ mod tests {
                 transaction.clone(),
                 &mut metadata_manager,
                 insert_data.clone(),
-            );
+            )?;
         }
 
-        transaction.borrow_mut().commit();
+        transaction.borrow_mut().commit()?;
 
         return Ok(());
     }
 
     #[test]
-    fn test_materialize() -> Result<(), ValueNotFound> {
-        let directory_path_name = format!("test_data_{}", uuid::Uuid::new_v4());
-        prepare_test_data(directory_path_name.clone());
+    fn test_materialize() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        prepare_test_data(directory_path_name.clone())?;
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -233,7 +240,7 @@ mod tests {
         )
         .unwrap();
 
-        let mut materialize_plan = MaterializePlan::new(transaction.clone(), Box::new(table_plan));
+        let materialize_plan = MaterializePlan::new(transaction.clone(), Box::new(table_plan));
 
         let mut temp_table = materialize_plan.open()?;
 
@@ -0,0 +1,462 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    error::ValueNotFound,
+    query::plan_v2::{PlanTreeNodeForDebug, PlanV2},
+    query::predicate::{ConstantValue, TableNameAndFieldName},
+    query::sort_plan::{RecordComparator, SortDirection, SortPlan},
+    record::record_page::TableSchema,
+    record::scan_v2::ScanV2,
+    record::table_scan_v2::RecordID,
+    tx::transaction_v2::TransactionV2,
+};
+
+// A = B の条件で、両方のplanをjoinのfieldで並べ替えてから同時に進めるjoin
+// 並べ替えた後のtemp tableにはtable名が無いので、どちらの行かはtable名とschemaで決める
+pub struct MergeJoinPlan {
+    left_plan: SortPlan,
+    right_plan: SortPlan,
+    left_table_names: Vec<String>,
+    right_table_names: Vec<String>,
+    left_field: TableNameAndFieldName,
+    right_field: TableNameAndFieldName,
+    schema: TableSchema,
+}
+
+impl MergeJoinPlan {
+    pub fn new(
+        transaction: Rc<RefCell<TransactionV2>>,
+        left_plan: Box<dyn PlanV2>,
+        left_table_names: Vec<String>,
+        right_plan: Box<dyn PlanV2>,
+        right_table_names: Vec<String>,
+        left_field: TableNameAndFieldName,
+        right_field: TableNameAndFieldName,
+    ) -> Self {
+        let left_field = TableNameAndFieldName::new(None, left_field.field_name);
+        let right_field = TableNameAndFieldName::new(None, right_field.field_name);
+
+        let mut schema = TableSchema::new();
+        schema.add_all(left_plan.get_schema().clone());
+        schema.add_all(right_plan.get_schema().clone());
+
+        let left_plan = SortPlan::new(
+            transaction.clone(),
+            left_plan,
+            vec![(left_field.clone(), SortDirection::Ascending)],
+        );
+        let right_plan = SortPlan::new(
+            transaction,
+            right_plan,
+            vec![(right_field.clone(), SortDirection::Ascending)],
+        );
+
+        MergeJoinPlan {
+            left_plan,
+            right_plan,
+            left_table_names,
+            right_table_names,
+            left_field,
+            right_field,
+            schema,
+        }
+    }
+
+    // planを作る前に、plannerがjoinの仕方を比べるときにも使う
+    // 並べ替えた後はどちらも1回ずつ読むだけ
+    pub fn estimate_blocks_accessed(left_plan: &dyn PlanV2, right_plan: &dyn PlanV2) -> u32 {
+        SortPlan::estimate_blocks_accessed(left_plan)
+            .saturating_add(SortPlan::estimate_blocks_accessed(right_plan))
+    }
+
+    pub fn estimate_records_output(
+        left_plan: &dyn PlanV2,
+        left_field: &TableNameAndFieldName,
+        right_plan: &dyn PlanV2,
+        right_field: &TableNameAndFieldName,
+    ) -> u32 {
+        let distinct_values = left_plan
+            .get_distinct_value(left_field.field_name.clone())
+            .max(right_plan.get_distinct_value(right_field.field_name.clone()))
+            .max(1);
+        left_plan
+            .records_output()
+            .saturating_mul(right_plan.records_output())
+            / distinct_values
+    }
+}
+
+impl PlanV2 for MergeJoinPlan {
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+        let left_scan = self.left_plan.open()?;
+        let right_scan = self.right_plan.open()?;
+        let mut scan = MergeJoinScan::new(
+            MergeJoinSide {
+                scan: left_scan,
+                table_names: self.left_table_names.clone(),
+                schema: self.left_plan.get_schema().clone(),
+                join_field: self.left_field.clone(),
+            },
+            MergeJoinSide {
+                scan: right_scan,
+                table_names: self.right_table_names.clone(),
+                schema: self.right_plan.get_schema().clone(),
+                join_field: self.right_field.clone(),
+            },
+        );
+        scan.move_to_before_first()?;
+        return Ok(Box::new(scan));
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    fn blocks_accessed(&self) -> u32 {
+        self.left_plan
+            .blocks_accessed()
+            .saturating_add(self.right_plan.blocks_accessed())
+    }
+
+    fn records_output(&self) -> u32 {
+        Self::estimate_records_output(
+            &self.left_plan,
+            &self.left_field,
+            &self.right_plan,
+            &self.right_field,
+        )
+    }
+
+    fn get_distinct_value(&self, field_name: String) -> u32 {
+        if self.left_plan.get_schema().has_field(field_name.clone()) {
+            return self.left_plan.get_distinct_value(field_name);
+        }
+        return self.right_plan.get_distinct_value(field_name);
+    }
+
+    fn get_child_plans(&self) -> PlanTreeNodeForDebug {
+        PlanTreeNodeForDebug {
+            current_node_type: format!(
+                "MergeJoinPlan({} = {})",
+                self.left_field.field_name, self.right_field.field_name
+            ),
+            records_output: self.records_output(),
+            blocks_accessed: self.blocks_accessed(),
+            child_nodes: vec![
+                self.left_plan.get_child_plans(),
+                self.right_plan.get_child_plans(),
+            ],
+        }
+    }
+}
+
+// joinの片側のscanと、そのscanのfieldを見分けるための情報
+pub struct MergeJoinSide {
+    pub scan: Box<dyn ScanV2>,
+    pub table_names: Vec<String>,
+    pub schema: TableSchema,
+    pub join_field: TableNameAndFieldName,
+}
+
+impl MergeJoinSide {
+    fn has_field(&self, field_name: &TableNameAndFieldName) -> bool {
+        if let Some(table_name) = &field_name.table_name {
+            if !self.table_names.contains(table_name) {
+                return false;
+            }
+        }
+        return self.schema.has_field(field_name.field_name.clone());
+    }
+
+    fn join_key(&mut self) -> Result<ConstantValue, ValueNotFound> {
+        match self.scan.get_value(self.join_field.clone()) {
+            Some(value) => return Ok(value),
+            None => return Err(ValueNotFound::new(self.join_field.field_name.clone(), None)),
+        }
+    }
+
+    fn read_record(&mut self) -> Result<Vec<ConstantValue>, ValueNotFound> {
+        let mut values = vec![];
+        for field in self.schema.fields.iter() {
+            match self
+                .scan
+                .get_value(TableNameAndFieldName::new(None, field.clone()))
+            {
+                Some(value) => values.push(value),
+                None => return Err(ValueNotFound::new(field.clone(), None)),
+            }
+        }
+        return Ok(values);
+    }
+}
+
+pub struct MergeJoinScan {
+    left: MergeJoinSide,
+    right: MergeJoinSide,
+    // SortScanは位置を戻せないので、同じkeyの右の行はmemoryに持っておく
+    group_key: Option<ConstantValue>,
+    group_rows: Vec<Vec<ConstantValue>>,
+    // 今の左の行と組み合わせた行の数。0のときは右の行が無い
+    group_position: usize,
+    has_left_record: bool,
+    has_right_record: bool,
+}
+
+impl MergeJoinScan {
+    pub fn new(left: MergeJoinSide, right: MergeJoinSide) -> Self {
+        MergeJoinScan {
+            left,
+            right,
+            group_key: None,
+            group_rows: vec![],
+            group_position: 0,
+            has_left_record: false,
+            has_right_record: false,
+        }
+    }
+
+    // 右のscanを左のkey以上の行まで進め、同じkeyの行を集める
+    fn load_group(&mut self, left_key: ConstantValue) -> Result<(), ValueNotFound> {
+        self.group_rows.clear();
+        while self.has_right_record {
+            let right_key = self.right.join_key()?;
+            let ordering = RecordComparator::compare_values(right_key, left_key.clone());
+            if ordering == std::cmp::Ordering::Greater {
+                break;
+            }
+            if ordering == std::cmp::Ordering::Equal {
+                let values = self.right.read_record()?;
+                self.group_rows.push(values);
+            }
+            self.has_right_record = self.right.scan.next()?;
+        }
+        self.group_key = Some(left_key);
+        return Ok(());
+    }
+}
+
+impl ScanV2 for MergeJoinScan {
+    fn move_to_before_first(&mut self) -> Result<(), ValueNotFound> {
+        self.left.scan.move_to_before_first()?;
+        self.right.scan.move_to_before_first()?;
+        self.has_right_record = self.right.scan.next()?;
+        self.has_left_record = false;
+        self.group_key = None;
+        self.group_rows.clear();
+        self.group_position = 0;
+        return Ok(());
+    }
+
+    fn next(&mut self) -> Result<bool, ValueNotFound> {
+        loop {
+            if self.has_left_record && self.group_position < self.group_rows.len() {
+                self.group_position += 1;
+                return Ok(true);
+            }
+
+            self.has_left_record = self.left.scan.next()?;
+            if !self.has_left_record {
+                return Ok(false);
+            }
+            self.group_position = 0;
+
+            // NULLはどの値とも等しくならない
+            let left_key = self.left.join_key()?;
+            if left_key == ConstantValue::Null {
+                self.has_left_record = false;
+                continue;
+            }
+
+            // 左も並んでいるので、同じkeyが続く間は集めた右の行を使い回す
+            if let Some(group_key) = &self.group_key {
+                let ordering =
+                    RecordComparator::compare_values(group_key.clone(), left_key.clone());
+                if ordering == std::cmp::Ordering::Equal {
+                    continue;
+                }
+            }
+            self.load_group(left_key)?;
+        }
+    }
+
+    fn get_integer(&mut self, field_name: TableNameAndFieldName) -> Option<i32> {
+        match self.get_value(field_name) {
+            Some(ConstantValue::Number(n)) => Some(n),
+            _ => None,
+        }
+    }
+
+    fn get_string(&mut self, field_name: TableNameAndFieldName) -> Option<String> {
+        match self.get_value(field_name) {
+            Some(ConstantValue::String(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn get_value(&mut self, field_name: TableNameAndFieldName) -> Option<ConstantValue> {
+        if self.left.has_field(&field_name) {
+            return self
+                .left
+                .scan
+                .get_value(TableNameAndFieldName::new(None, field_name.field_name));
+        }
+        if !self.right.has_field(&field_name) || self.group_position == 0 {
+            return None;
+        }
+        let index = self
+            .right
+            .schema
+            .fields
+            .iter()
+            .position(|f| f == &field_name.field_name)?;
+        return self.group_rows[self.group_position - 1].get(index).cloned();
+    }
+
+    fn close(&mut self) {
+        self.left.scan.close();
+        self.right.scan.close();
+    }
+
+    fn has_field(&self, field_name: TableNameAndFieldName) -> bool {
+        self.left.has_field(&field_name) || self.right.has_field(&field_name)
+    }
+
+    fn set_integer(&mut self, _field_name: String, _value: i32) {
+        panic!("set_integer not implemented for MergeJoinScan");
+    }
+
+    fn set_string(&mut self, _field_name: String, _value: String) {
+        panic!("set_string not implemented for MergeJoinScan");
+    }
+
+    fn set_value(&mut self, _field_name: String, _value: ConstantValue) {
+        panic!("set_value not implemented for MergeJoinScan");
+    }
+
+    fn insert(&mut self) {
+        panic!("insert not implemented for MergeJoinScan");
+    }
+
+    fn delete(&mut self) {
+        panic!("delete not implemented for MergeJoinScan");
+    }
+
+    fn get_record_id(&self) -> RecordID {
+        panic!("get_record_id not implemented for MergeJoinScan");
+    }
+
+    fn move_to_record_id(&mut self, _record_id: RecordID) {
+        panic!("move_to_record_id not implemented for MergeJoinScan");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        database::Database,
+        error::DatabaseError,
+        metadata::metadata_manager::MetadataManager,
+        query::parser::{parse_sql, ParsedSQL},
+        query::plan_v2::{create_query_plan, ProductPlanV2, SelectPlanV2, TablePlanV2},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_merge_join_matches_product() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table users (id integer, name varchar(10)); \
+             create table orders (user_id integer, item varchar(10)); \
+             insert into users (id, name) values (3, 'carol'); \
+             insert into users (id, name) values (1, 'alice'); \
+             insert into users (id, name) values (2, 'bob'); \
+             insert into users (id, name) values (2, 'bea'); \
+             insert into users (id, name) values (null, 'nobody')",
+            transaction.clone(),
+        )?;
+        // 同じkeyの行が左右どちらにも複数ある
+        for i in 0..30 {
+            let user_id = if i % 7 == 0 {
+                "null".to_string()
+            } else {
+                (i % 5).to_string()
+            };
+            database.execute(
+                &format!(
+                    "insert into orders (user_id, item) values ({}, 'item{}')",
+                    user_id, i
+                ),
+                transaction.clone(),
+            )?;
+        }
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let parsed_sql_list = parse_sql(
+            "select name, item from orders, users where orders.user_id = users.id".to_string(),
+        )
+        .unwrap();
+        let ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+
+        let read_rows = |plan: &mut dyn PlanV2| -> Result<Vec<String>, ValueNotFound> {
+            let mut scan = plan.open()?;
+            let mut rows = vec![];
+            while scan.next()? {
+                let name = scan.get_value(TableNameAndFieldName::new(None, "name".to_string()));
+                let item = scan.get_value(TableNameAndFieldName::new(None, "item".to_string()));
+                rows.push(format!("{:?} {:?}", name, item));
+            }
+            scan.close();
+            rows.sort();
+            return Ok(rows);
+        };
+
+        // productではorders 30行 x usersのblockを読むので、merge joinが選ばれる
+        let mut plan = create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+        let plan_tree = plan.get_child_plans();
+        let mut node_types = vec![];
+        let mut nodes = vec![&plan_tree];
+        while let Some(node) = nodes.pop() {
+            node_types.push(node.current_node_type.clone());
+            nodes.extend(node.child_nodes.iter());
+        }
+        assert!(node_types.contains(&"MergeJoinPlan(user_id = id)".to_string()));
+        assert!(!node_types.contains(&"ProductPlanV2".to_string()));
+        let merge_join_rows = read_rows(plan.as_mut())?;
+
+        let orders_plan = TablePlanV2::new(
+            "orders".to_string(),
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+        let users_plan = TablePlanV2::new(
+            "users".to_string(),
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+        let mut product_plan = SelectPlanV2::new(
+            Box::new(ProductPlanV2::new(
+                Box::new(orders_plan),
+                Box::new(users_plan),
+            )),
+            query_data.predicate.clone(),
+        );
+        let product_rows = read_rows(&mut product_plan)?;
+
+        assert_eq!(merge_join_rows, product_rows);
+        // user_idが1, 2, 3の行はそれぞれ5行で、2はusersに2人いる。NULLと0と4は相手がいない
+        assert_eq!(merge_join_rows.len(), 5 + 5 * 2 + 5);
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+}
@@ -4,10 +4,14 @@ use pest::{iterators::Pair, Parser};
 use pest_derive::Parser;
 
 use crate::{
-    query::group_by::AggregateFunctionType,
+    error::ParseError,
+    query::group_by::{AggregateFunctionType, CountFunction},
+    query::sort_plan::SortDirection,
     query::predicate::{Constant, ConstantValue, ExpressionValue, TableNameAndFieldName},
-    query::predicate_v3::{ExpressionV2, PredicateV2, TermV2},
-    record::record_page::{TableFieldInfo, TableFieldType, TableSchema},
+    query::predicate_v3::{
+        ArithmeticOp, CompareOp, ExpressionV2, PredicateV2, ScalarFunction, TermV2,
+    },
+    record::record_page::{KeyConstraint, TableFieldInfo, TableFieldType, TableSchema},
 };
 
 // #[derive(Parser)]
@@ -23,6 +27,59 @@ pub struct InsertData {
     pub table_name: String,
     pub field_name_list: Vec<String>,
     pub value_list: Vec<Constant>,
+    // insert ... select の場合は、value_listの代わりにselectの結果を使う
+    pub select_query: Option<QueryData>,
+}
+
+// bulk load中のindexの更新方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexBuildMode {
+    // 1行ごとにindexにも挿入する
+    PerRow,
+    // tableへのloadが終わってからまとめてindexに挿入する
+    After,
+}
+
+// copy <table> (<fields>) from '<csv file>' with (build_indexes = after)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopyData {
+    pub table_name: String,
+    // 空のときはtableのschemaの順
+    pub field_name_list: Vec<String>,
+    pub file_path: String,
+    pub build_indexes: IndexBuildMode,
+}
+
+impl CopyData {
+    pub fn new(
+        table_name: String,
+        field_name_list: Vec<String>,
+        file_path: String,
+        build_indexes: IndexBuildMode,
+    ) -> Self {
+        CopyData {
+            table_name,
+            field_name_list,
+            file_path,
+            build_indexes,
+        }
+    }
+}
+
+// copy (<select>) to '<csv file>'
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopyToData {
+    pub query_data: QueryData,
+    pub file_path: String,
+}
+
+impl CopyToData {
+    pub fn new(query_data: QueryData, file_path: String) -> Self {
+        CopyToData {
+            query_data,
+            file_path,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -74,6 +131,20 @@ impl InsertData {
             table_name,
             field_name_list,
             value_list,
+            select_query: None,
+        }
+    }
+
+    pub fn new_with_select(
+        table_name: String,
+        field_name_list: Vec<String>,
+        select_query: QueryData,
+    ) -> Self {
+        InsertData {
+            table_name,
+            field_name_list,
+            value_list: vec![],
+            select_query: Some(select_query),
         }
     }
 
@@ -102,12 +173,73 @@ pub enum ParsedSQL {
     CreateView(ViewData),
     Delete(DeleteData),
     Update(UpdateData),
+    Copy(CopyData),
+    CopyTo(CopyToData),
     ShowTables,
-    DescribeTable { table_name: String },
-    Explain(QueryData),
+    ShowIndexes,
+    // 明示的なtransactionの開始と終了
+    Begin,
+    Commit,
+    Rollback,
+    DescribeTable {
+        table_name: String,
+    },
+    DropTable {
+        table_name: String,
+    },
+    // deleteで空いたslotを詰める
+    Vacuum {
+        table_name: String,
+    },
+    // tableのindexを空にして、tableの行から作り直す
+    Reindex {
+        table_name: String,
+    },
+    AlterTable(AlterTableData),
+    Explain {
+        query_data: QueryData,
+        format: ExplainFormat,
+    },
+    // explain delete / explain update。statementはDeleteかUpdate
+    ExplainDml {
+        statement: Box<ParsedSQL>,
+        format: ExplainFormat,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExplainFormat {
+    Text,
+    Json,
 }
 
 impl ParsedSQL {
+    // 文の中の定数をすべて渡す。prepareした文の ? を置き換えるのに使う
+    // create viewなど、ここで辿らない文の定数には ? を書けない
+    pub fn for_each_constant_mut<F>(&mut self, f: &mut F)
+    where
+        F: FnMut(&mut Constant),
+    {
+        match self {
+            ParsedSQL::Query(query_data) | ParsedSQL::Explain { query_data, .. } => {
+                query_data.for_each_constant_mut(f);
+            }
+            ParsedSQL::Insert(insert_data) => {
+                insert_data.value_list.iter_mut().for_each(&mut *f);
+                if let Some(select_query) = insert_data.select_query.as_mut() {
+                    select_query.for_each_constant_mut(f);
+                }
+            }
+            ParsedSQL::Update(update_data) => {
+                f(&mut update_data.new_value);
+                update_data.predicate.for_each_constant_mut(f);
+            }
+            ParsedSQL::Delete(delete_data) => delete_data.predicate.for_each_constant_mut(f),
+            ParsedSQL::ExplainDml { statement, .. } => statement.for_each_constant_mut(f),
+            _ => {}
+        }
+    }
+
     pub fn debug_print(&self) {
         match self {
             ParsedSQL::Query(query_data) => {
@@ -146,12 +278,56 @@ impl ParsedSQL {
                     update_data.predicate.to_string()
                 );
             }
+            ParsedSQL::Copy(copy_data) => {
+                println!(
+                    "Parsed Copy Data: \nTable: {}\nFields: {:?}\nFile: {}\nBuild Indexes: {:?}",
+                    copy_data.table_name,
+                    copy_data.field_name_list,
+                    copy_data.file_path,
+                    copy_data.build_indexes
+                );
+            }
+            ParsedSQL::CopyTo(copy_to_data) => {
+                println!(
+                    "Parsed Copy To Data: \nTables: {:?}\nFile: {}",
+                    copy_to_data.query_data.table_name_list, copy_to_data.file_path
+                );
+            }
             ParsedSQL::ShowTables => {
                 println!("Parsed Show Tables Command");
             }
+            ParsedSQL::Begin => {
+                println!("Parsed Begin Command");
+            }
+            ParsedSQL::Commit => {
+                println!("Parsed Commit Command");
+            }
+            ParsedSQL::Rollback => {
+                println!("Parsed Rollback Command");
+            }
+            ParsedSQL::ShowIndexes => {
+                println!("Parsed Show Indexes Command");
+            }
             ParsedSQL::DescribeTable { table_name } => {
                 println!("Parsed Describe Table Command for table: {}", table_name);
             }
+            ParsedSQL::DropTable { table_name } => {
+                println!("Parsed Drop Table Command for table: {}", table_name);
+            }
+            ParsedSQL::Vacuum { table_name } => {
+                println!("Parsed Vacuum Command for table: {}", table_name);
+            }
+            ParsedSQL::Reindex { table_name } => {
+                println!("Parsed Reindex Command for table: {}", table_name);
+            }
+            ParsedSQL::AlterTable(alter_table_data) => {
+                println!(
+                    "Parsed Alter Table Data: \nTable: {}\nAdd Column: {} {:?}",
+                    alter_table_data.table_name,
+                    alter_table_data.field_name,
+                    alter_table_data.field_info
+                );
+            }
             ParsedSQL::CreateIndex(create_index_data) => {
                 println!(
                     "Parsed Create Index Data: \nIndex Name: {}\nTable Name: {}\nField Name: {}",
@@ -160,12 +336,17 @@ impl ParsedSQL {
                     create_index_data.field_name
                 );
             }
-            ParsedSQL::Explain(query_data) => {
+            ParsedSQL::Explain { query_data, format } => {
                 println!(
-                    "Parsed Explain Command for query: \n{}",
+                    "Parsed Explain Command ({:?}) for query: \n{}",
+                    format,
                     query_data.to_string()
                 );
             }
+            ParsedSQL::ExplainDml { statement, format } => {
+                println!("Parsed Explain Command ({:?}) for statement:", format);
+                statement.debug_print();
+            }
         }
     }
 }
@@ -174,6 +355,16 @@ impl ParsedSQL {
 pub struct CreateTableData {
     pub table_name: String,
     pub schema: TableSchema,
+    // 指定されなければdatabaseのblock sizeを使う
+    pub block_size: Option<usize>,
+}
+
+// alter table t add column c integer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlterTableData {
+    pub table_name: String,
+    pub field_name: String,
+    pub field_info: TableFieldInfo,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -181,6 +372,8 @@ pub struct CreateIndexData {
     pub index_name: String,
     pub table_name: String,
     pub field_name: String,
+    // with (buckets = N) で指定したhash indexのbucketの数
+    pub number_of_buckets: Option<usize>,
 }
 
 use std::fmt;
@@ -197,6 +390,35 @@ pub struct AggregateFunctionInfo {
     pub field: TableNameAndFieldName,
 }
 
+impl AggregateFunctionInfo {
+    // GroupByScanが集約関数の結果を返すときのfield名 (count_*, max_A など)
+    pub fn get_field_name(&self) -> String {
+        return format!("{}_{}", self.function_type, self.field.field_name);
+    }
+}
+
+// select 'active' as status, price * 2 as doubled のような、別名つきの式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectExpression {
+    pub alias: String,
+    pub expression: ExpressionV2,
+}
+
+// having句。predicateの中の集約関数はget_field_nameのfieldとして参照する
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HavingData {
+    pub predicate: PredicateV2,
+    // select listに無いものも含めて、predicateが使う集約関数
+    pub aggregate_functions: Vec<AggregateFunctionInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderByItem {
+    Field(TableNameAndFieldName),
+    // select listの位置(1始まり)。create_query_planでフィールドに解決する
+    Position(usize),
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ViewData {
     pub view_name: String,
@@ -220,33 +442,107 @@ impl ViewData {
     }
 }
 
+// from (values (1, 'a'), (2, 'b')) as t(id, name)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValuesData {
+    pub alias: String,
+    pub column_names: Vec<String>,
+    pub rows: Vec<Vec<Constant>>,
+}
+
+impl ValuesData {
+    pub fn new(alias: String, column_names: Vec<String>, rows: Vec<Vec<Constant>>) -> Self {
+        ValuesData {
+            alias,
+            column_names,
+            rows,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct QueryData {
+    // select distinct
+    pub distinct: bool,
     pub table_name_list: Vec<String>,
+    // from句のvaluesのtable
+    pub values_list: Vec<ValuesData>,
+    // select * のときはtrueで、field_name_listは空のまま。planのschemaのfieldをすべて返す
+    pub all_columns: bool,
+    // select_expressionsの別名もselect listの位置に入る
+    // t.* はfield名がALL_FIELDSになり、planを作るときにtのfieldに展開する
     pub field_name_list: Vec<TableNameAndFieldName>,
+    pub select_expressions: Vec<SelectExpression>,
     pub predicate: PredicateV2,
-    pub order_by_list: Vec<TableNameAndFieldName>,
+    pub order_by_list: Vec<(OrderByItem, SortDirection)>,
     pub group_by_list: Vec<TableNameAndFieldName>,
     pub aggregate_functions: Vec<AggregateFunctionInfo>,
+    // aggregate_functionsと同じ順番の別名 (max(B) as maxb)。別名が無いものはNone
+    pub aggregate_aliases: Vec<Option<String>>,
+    pub having: Option<HavingData>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
 }
 
 impl QueryData {
+    pub const ALL_FIELDS: &'static str = "*";
+
+    pub fn for_each_constant_mut<F>(&mut self, f: &mut F)
+    where
+        F: FnMut(&mut Constant),
+    {
+        for values_data in self.values_list.iter_mut() {
+            values_data.rows.iter_mut().flatten().for_each(&mut *f);
+        }
+        for select_expression in self.select_expressions.iter_mut() {
+            select_expression.expression.for_each_constant_mut(f);
+        }
+        self.predicate.for_each_constant_mut(f);
+        if let Some(having) = self.having.as_mut() {
+            having.predicate.for_each_constant_mut(f);
+        }
+    }
+
     pub fn new(
+        distinct: bool,
         table_name_list: Vec<String>,
+        values_list: Vec<ValuesData>,
+        all_columns: bool,
         field_name_list: Vec<TableNameAndFieldName>,
+        select_expressions: Vec<SelectExpression>,
         predicate: PredicateV2,
-        order_by_list: Vec<TableNameAndFieldName>,
+        order_by_list: Vec<(OrderByItem, SortDirection)>,
         group_by_list: Vec<TableNameAndFieldName>,
         aggregate_functions: Vec<AggregateFunctionInfo>,
+        aggregate_aliases: Vec<Option<String>>,
+        having: Option<HavingData>,
+        limit: Option<usize>,
+        offset: Option<usize>,
     ) -> Self {
         QueryData {
+            distinct,
             table_name_list,
+            values_list,
+            all_columns,
             field_name_list,
+            select_expressions,
             predicate,
             order_by_list,
             group_by_list,
             aggregate_functions,
+            aggregate_aliases,
+            having,
+            limit,
+            offset,
+        }
+    }
+
+    // 集約関数の結果の列名。別名があれば別名、無ければGroupByScanのfield名
+    pub fn aggregate_output_name(&self, index: usize) -> String {
+        if let Some(Some(alias)) = self.aggregate_aliases.get(index) {
+            return alias.clone();
         }
+        return self.aggregate_functions[index].get_field_name();
     }
 
     pub fn to_string(&self) -> String {
@@ -266,12 +562,18 @@ impl QueryData {
         result.push_str("\nPredicate: ");
         result.push_str(&self.predicate.to_string());
         result.push_str("\nOrder By: ");
-        for order_by in &self.order_by_list {
-            result.push_str(&format!(
-                "{} {} ",
-                order_by.table_name.clone().unwrap_or("".to_string()),
-                order_by.field_name
-            ));
+        for (order_by, direction) in &self.order_by_list {
+            match order_by {
+                OrderByItem::Field(field) => result.push_str(&format!(
+                    "{} {} ",
+                    field.table_name.clone().unwrap_or("".to_string()),
+                    field.field_name
+                )),
+                OrderByItem::Position(position) => result.push_str(&format!("{} ", position)),
+            }
+            if *direction == SortDirection::Descending {
+                result.push_str("desc ");
+            }
         }
         result.push_str("\nGroup By: ");
         for group_by in &self.group_by_list {
@@ -290,112 +592,269 @@ impl QueryData {
                 agg.field.field_name
             ));
         }
+        if let Some(having) = &self.having {
+            result.push_str("\nHaving: ");
+            result.push_str(&having.predicate.to_string());
+        }
         result
     }
 }
 
+// "12.34" -> Decimal { value: 1234, scale: 2 }
+fn parse_decimal_token(token: &str) -> ConstantValue {
+    let (integer_part, fraction_part) = token.split_once('.').unwrap();
+    // i32に収まらない桁数のdecimalはfloatとして扱う
+    let Ok(value) = format!("{}{}", integer_part, fraction_part).parse::<i32>() else {
+        return ConstantValue::Float(token.parse::<f64>().unwrap());
+    };
+    return ConstantValue::Decimal {
+        value,
+        scale: fraction_part.len() as u32,
+    };
+}
+
+// "1.5e3" -> Float(1500.0)
+fn parse_float_token(token: &str) -> ConstantValue {
+    return ConstantValue::Float(token.parse::<f64>().unwrap());
+}
+
+// "date '2024-01-01'" -> Date(19723)。月と日の範囲はgrammarで確認している
+fn parse_date_token(token: &str) -> ConstantValue {
+    let date = token.split('\'').nth(1).unwrap();
+    let days =
+        Constant::parse_date(date).unwrap_or_else(|| panic!("Invalid date literal '{}'", date));
+    return ConstantValue::Date(days);
+}
+
+// ? にはsqlの中の位置を入れておき、Database::prepareで0からの番号に振り直す
+fn parse_parameter_token(token: &Pair<Rule>) -> Constant {
+    return Constant::new(ConstantValue::Parameter(token.as_span().start()));
+}
+
+fn parse_expression(inner_value: Pair<'_, Rule>) -> Option<ExpressionV2> {
+    match inner_value.as_rule() {
+        // 左結合で畳み込む (a - b - c は (a - b) - c)
+        Rule::expression | Rule::mul_expression => {
+            let mut inner_iter = inner_value.into_inner();
+            let mut expression = parse_expression(inner_iter.next()?)?;
+
+            while let Some(op) = inner_iter.next() {
+                let op = match op.as_str() {
+                    "+" => ArithmeticOp::Add,
+                    "-" => ArithmeticOp::Subtract,
+                    "*" => ArithmeticOp::Multiply,
+                    _ => ArithmeticOp::Divide,
+                };
+                let rhs = parse_expression(inner_iter.next()?)?;
+                expression = ExpressionV2::new_arithmetic(op, expression, rhs);
+            }
+
+            return Some(expression);
+        }
+        Rule::primary => return parse_expression(inner_value.into_inner().next()?),
+        Rule::aggregate_function => {
+            let aggregate_function = parse_aggregate_function(inner_value);
+            return Some(ExpressionV2::new(ExpressionValue::TableNameAndFieldName(
+                TableNameAndFieldName::new(None, aggregate_function.get_field_name()),
+            )));
+        }
+        Rule::scalar_function => {
+            let mut inner_iter = inner_value.into_inner();
+            let function = match inner_iter.next()?.as_str().to_lowercase().as_str() {
+                "length" => ScalarFunction::Length,
+                "upper" => ScalarFunction::Upper,
+                _ => ScalarFunction::Lower,
+            };
+            let argument = parse_expression(inner_iter.next()?)?;
+            return Some(ExpressionV2::new_function(function, argument));
+        }
+        Rule::field => match inner_value.into_inner().next() {
+            Some(inner_value) => match inner_value.as_rule() {
+                Rule::qualified_field => {
+                    let mut inner_iter = inner_value.into_inner();
+                    let table_name = inner_iter.next().unwrap().as_str();
+                    let field_name = inner_iter.next().unwrap().as_str();
+
+                    return Some(ExpressionV2::new(ExpressionValue::TableNameAndFieldName(
+                        TableNameAndFieldName::new(
+                            Some(table_name.to_string()),
+                            field_name.to_string(),
+                        ),
+                    )));
+                }
+                Rule::id_token => {
+                    return Some(ExpressionV2::new(ExpressionValue::TableNameAndFieldName(
+                        TableNameAndFieldName::new(None, inner_value.as_str().to_string()),
+                    )));
+                }
+                _ => return None,
+            },
+            None => return None,
+        },
+        Rule::constant => match inner_value.into_inner().next() {
+            Some(inner_value) => match inner_value.as_rule() {
+                Rule::date_token => {
+                    let constant = Constant::new(parse_date_token(inner_value.as_str()));
+
+                    return Some(ExpressionV2::new(ExpressionValue::Constant(constant)));
+                }
+                Rule::float_token => {
+                    let constant = Constant::new(parse_float_token(inner_value.as_str()));
+
+                    return Some(ExpressionV2::new(ExpressionValue::Constant(constant)));
+                }
+                Rule::decimal_token => {
+                    let constant = Constant::new(parse_decimal_token(inner_value.as_str()));
+
+                    return Some(ExpressionV2::new(ExpressionValue::Constant(constant)));
+                }
+                Rule::int_token => {
+                    let value = inner_value.as_str().parse::<i32>().unwrap();
+                    let int_constant_value = ConstantValue::Number(value);
+
+                    let constant = Constant::new(int_constant_value);
+
+                    return Some(ExpressionV2::new(ExpressionValue::Constant(constant)));
+                }
+                Rule::string_token => {
+                    let value = inner_value
+                        .into_inner()
+                        .find(|p| p.as_rule() == Rule::string_content)
+                        .map(|p| p.as_str().to_string())
+                        .unwrap_or_default();
+                    let string_constant_value = ConstantValue::String(value.clone());
+                    let constant = Constant::new(string_constant_value);
+
+                    return Some(ExpressionV2::new(ExpressionValue::Constant(constant)));
+                }
+                Rule::null_token => {
+                    let constant = Constant::new(ConstantValue::Null);
+
+                    return Some(ExpressionV2::new(ExpressionValue::Constant(constant)));
+                }
+                Rule::parameter_token => {
+                    let constant = parse_parameter_token(&inner_value);
+
+                    return Some(ExpressionV2::new(ExpressionValue::Constant(constant)));
+                }
+                _ => return None,
+            },
+            None => return None,
+        },
+        _ => return None,
+    }
+}
+
 pub fn parse_predicate(inner_value: Pair<'_, Rule>) -> Option<PredicateV2> {
-    let mut terms: Vec<TermV2> = Vec::new();
+    let mut disjuncts: Vec<PredicateV2> = Vec::new();
+    for conjunction in inner_value.into_inner() {
+        if conjunction.as_rule() == Rule::conjunction {
+            disjuncts.push(parse_conjunction(conjunction));
+        }
+    }
+
+    // orが無ければ今までどおりtermのandになる
+    if disjuncts.len() == 1 {
+        return disjuncts.pop();
+    }
+    return Some(PredicateV2::new(vec![TermV2::new_or(disjuncts)]));
+}
+
+fn parse_conjunction(inner_value: Pair<'_, Rule>) -> PredicateV2 {
+    let mut predicate = PredicateV2::new(vec![]);
     inner_value
         .into_inner()
         .for_each(|inner_value| match inner_value.as_rule() {
             Rule::term => {
                 let mut lhs: Option<ExpressionV2> = None;
                 let mut rhs: Option<ExpressionV2> = None;
+                let mut op = CompareOp::Equal;
+                let mut subquery: Option<QueryData> = None;
+                let mut in_values: Option<Vec<Constant>> = None;
+                let mut between: Option<(ExpressionV2, ExpressionV2)> = None;
+                let mut nested: Option<PredicateV2> = None;
+                let mut is_null: Option<bool> = None;
 
                 inner_value
                     .into_inner()
                     .for_each(|inner_value| match inner_value.as_rule() {
+                        Rule::compare_op => match inner_value.into_inner().next() {
+                            Some(inner_value) => match inner_value.as_rule() {
+                                Rule::not_distinct_from_op => op = CompareOp::NotDistinctFrom,
+                                Rule::distinct_from_op => op = CompareOp::DistinctFrom,
+                                Rule::not_equal_op => op = CompareOp::NotEqual,
+                                Rule::less_op => op = CompareOp::LessThan,
+                                Rule::less_equal_op => op = CompareOp::LessThanOrEqual,
+                                Rule::greater_op => op = CompareOp::GreaterThan,
+                                Rule::greater_equal_op => op = CompareOp::GreaterThanOrEqual,
+                                _ => op = CompareOp::Equal,
+                            },
+                            None => {}
+                        },
                         Rule::expression => {
+                            let expression = parse_expression(inner_value);
+                            if lhs.is_none() {
+                                lhs = expression;
+                            } else {
+                                rhs = expression;
+                            }
+                        }
+                        // 括弧で囲まれたpredicate
+                        Rule::predicate => nested = parse_predicate(inner_value),
+                        Rule::in_subquery => {
                             inner_value.into_inner().for_each(|inner_value| {
                                 match inner_value.as_rule() {
-                                    Rule::field => match inner_value.into_inner().next() {
-                                        Some(inner_value) => match inner_value.as_rule() {
-                                            Rule::qualified_field => {
-                                                let mut inner_iter = inner_value.into_inner();
-                                                let table_name =
-                                                    inner_iter.next().unwrap().as_str();
-                                                let field_name =
-                                                    inner_iter.next().unwrap().as_str();
-
-                                                let expression = ExpressionV2::new(
-                                                    ExpressionValue::TableNameAndFieldName(
-                                                        TableNameAndFieldName::new(
-                                                            Some(table_name.to_string()),
-                                                            field_name.to_string(),
-                                                        ),
-                                                    ),
-                                                );
-
-                                                if lhs.is_none() {
-                                                    lhs = Some(expression);
-                                                } else {
-                                                    rhs = Some(expression);
-                                                }
-                                            }
-                                            Rule::id_token => {
-                                                let expression = ExpressionV2::new(
-                                                    ExpressionValue::TableNameAndFieldName(
-                                                        TableNameAndFieldName::new(
-                                                            None,
-                                                            inner_value.as_str().to_string(),
-                                                        ),
-                                                    ),
-                                                );
-
-                                                if lhs.is_none() {
-                                                    lhs = Some(expression);
-                                                } else {
-                                                    rhs = Some(expression);
-                                                }
-                                            }
-                                            _ => {}
-                                        },
-                                        None => {}
-                                    },
-                                    Rule::constant => match inner_value.into_inner().next() {
-                                        Some(inner_value) => match inner_value.as_rule() {
-                                            Rule::int_token => {
-                                                let value =
-                                                    inner_value.as_str().parse::<i32>().unwrap();
-                                                let int_constant_value =
-                                                    ConstantValue::Number(value);
-
-                                                let constant = Constant::new(int_constant_value);
-
-                                                let expression = ExpressionV2::new(
-                                                    ExpressionValue::Constant(constant.clone()),
-                                                );
-
-                                                if lhs.is_none() {
-                                                    lhs = Some(expression);
-                                                } else {
-                                                    rhs = Some(expression);
-                                                }
-                                            }
-                                            Rule::string_token => {
-                                                let value = inner_value
-                                                    .into_inner()
-                                                    .find(|p| p.as_rule() == Rule::string_content)
-                                                    .map(|p| p.as_str().to_string())
-                                                    .unwrap_or_default();
-                                                let string_constant_value =
-                                                    ConstantValue::String(value.clone());
-                                                let constant = Constant::new(string_constant_value);
-                                                let expression = ExpressionV2::new(
-                                                    ExpressionValue::Constant(constant.clone()),
-                                                );
-
-                                                if lhs.is_none() {
-                                                    lhs = Some(expression);
-                                                } else {
-                                                    rhs = Some(expression);
-                                                }
-                                            }
-                                            _ => {}
-                                        },
-                                        None => {}
-                                    },
+                                    Rule::expression => lhs = parse_expression(inner_value),
+                                    Rule::select_sql => {
+                                        subquery = Some(parse_select_sql(inner_value))
+                                    }
+                                    _ => {}
+                                }
+                            });
+                        }
+                        // A between low and high は A >= low and A <= high にする
+                        Rule::between_term => {
+                            let mut expressions = inner_value
+                                .into_inner()
+                                .filter(|inner_value| inner_value.as_rule() == Rule::expression)
+                                .map(|inner_value| parse_expression(inner_value).unwrap());
+                            lhs = expressions.next();
+                            between =
+                                Some((expressions.next().unwrap(), expressions.next().unwrap()));
+                        }
+                        Rule::in_list => {
+                            in_values = Some(parse_constant_list(inner_value.clone()));
+                            if let Some(expression) = inner_value.into_inner().next() {
+                                lhs = parse_expression(expression);
+                            }
+                        }
+                        // 右辺は文字列のpatternだけ
+                        Rule::like_term => {
+                            op = CompareOp::Like;
+                            inner_value.into_inner().for_each(|inner_value| {
+                                match inner_value.as_rule() {
+                                    Rule::expression => lhs = parse_expression(inner_value),
+                                    Rule::string_token => {
+                                        let pattern = inner_value
+                                            .into_inner()
+                                            .find(|p| p.as_rule() == Rule::string_content)
+                                            .map(|p| p.as_str().to_string())
+                                            .unwrap_or_default();
+                                        rhs = Some(ExpressionV2::new(ExpressionValue::Constant(
+                                            Constant::new(ConstantValue::String(pattern)),
+                                        )));
+                                    }
+                                    _ => {}
+                                }
+                            });
+                        }
+                        // trueなら is not null
+                        Rule::is_null_term => {
+                            inner_value.into_inner().for_each(|inner_value| {
+                                match inner_value.as_rule() {
+                                    Rule::expression => lhs = parse_expression(inner_value),
+                                    Rule::is_not_null_op => is_null = Some(true),
+                                    Rule::is_null_op => is_null = Some(false),
                                     _ => {}
                                 }
                             });
@@ -403,27 +862,132 @@ pub fn parse_predicate(inner_value: Pair<'_, Rule>) -> Option<PredicateV2> {
                         _ => {}
                     });
 
-                let term = TermV2::new(lhs.unwrap(), rhs.unwrap());
-                terms.push(term);
+                if let Some(nested) = nested {
+                    predicate.conjunction_with(nested);
+                    return;
+                }
+                if let Some((low, high)) = between {
+                    let lhs = lhs.unwrap();
+                    predicate.conjunction_with(PredicateV2::new(vec![
+                        TermV2::new_with_op(lhs.clone(), CompareOp::GreaterThanOrEqual, low),
+                        TermV2::new_with_op(lhs, CompareOp::LessThanOrEqual, high),
+                    ]));
+                    return;
+                }
+                let term = match (subquery, in_values, is_null) {
+                    (Some(query), _, _) => TermV2::new_in_subquery(lhs.unwrap(), query),
+                    (None, Some(values), _) => TermV2::new_in_list(lhs.unwrap(), values),
+                    (None, None, Some(negated)) => TermV2::new_is_null(lhs.unwrap(), negated),
+                    (None, None, None) => TermV2::new_with_op(lhs.unwrap(), op, rhs.unwrap()),
+                };
+                predicate.conjunction_with(PredicateV2::new(vec![term]));
+            }
+            _ => {}
+        });
+
+    return predicate;
+}
+
+fn parse_values_source(record: Pair<Rule>) -> ValuesData {
+    let mut alias: Option<String> = None;
+    let mut column_names: Vec<String> = Vec::new();
+    let mut rows: Vec<Vec<Constant>> = Vec::new();
+
+    record
+        .into_inner()
+        .for_each(|inner_value| match inner_value.as_rule() {
+            Rule::constant_list => {
+                rows.push(parse_constant_list(inner_value));
+            }
+            // 最初のid_tokenがtableの別名、残りがcolumn名
+            Rule::id_token => {
+                if alias.is_none() {
+                    alias = Some(inner_value.as_str().to_string());
+                } else {
+                    column_names.push(inner_value.as_str().to_string());
+                }
             }
             _ => {}
         });
 
-    return Some(PredicateV2::new(terms));
+    return ValuesData::new(alias.unwrap(), column_names, rows);
+}
+
+fn parse_aggregate_function(record: Pair<Rule>) -> AggregateFunctionInfo {
+    let mut inner_iter = record.into_inner();
+    let function_pair = inner_iter.next().unwrap();
+
+    // count(distinct field)
+    if function_pair.as_rule() == Rule::count_distinct {
+        let field_pair = function_pair
+            .into_inner()
+            .find(|pair| pair.as_rule() == Rule::field)
+            .unwrap();
+        return AggregateFunctionInfo {
+            function_type: AggregateFunctionType::CountDistinct,
+            field: parse_aggregate_field(field_pair),
+        };
+    }
+
+    let function_name = function_pair.as_str().to_string();
+    let function_type = AggregateFunctionType::from_str(&function_name).unwrap();
+
+    let field_pair = inner_iter.next().unwrap();
+    let field = match field_pair.as_rule() {
+        // count(*)
+        Rule::all_rows => TableNameAndFieldName::new(None, CountFunction::ALL_ROWS.to_string()),
+        _ => parse_aggregate_field(field_pair),
+    };
+
+    return AggregateFunctionInfo {
+        function_type,
+        field,
+    };
+}
+
+fn parse_aggregate_field(field_pair: Pair<Rule>) -> TableNameAndFieldName {
+    match field_pair.into_inner().next() {
+        Some(field_inner) => match field_inner.as_rule() {
+            Rule::qualified_field => {
+                let mut inner_iter = field_inner.into_inner();
+                let table_name = inner_iter.next().unwrap().as_str();
+                let field_name = inner_iter.next().unwrap().as_str();
+
+                TableNameAndFieldName::new(Some(table_name.to_string()), field_name.to_string())
+            }
+            Rule::id_token => TableNameAndFieldName::new(None, field_inner.as_str().to_string()),
+            _ => {
+                panic!("Unexpected rule in aggregate function field")
+            }
+        },
+        None => {
+            panic!("No inner value in aggregate function field")
+        }
+    }
 }
 
 fn parse_select_sql(record: Pair<Rule>) -> QueryData {
+    let mut distinct = false;
     let mut table_name_list: Vec<String> = Vec::new();
+    let mut values_list: Vec<ValuesData> = Vec::new();
+    let mut all_columns = false;
     let mut field_name_list: Vec<TableNameAndFieldName> = Vec::new();
-    let mut order_by_list: Vec<TableNameAndFieldName> = Vec::new();
+    let mut select_expressions: Vec<SelectExpression> = Vec::new();
+    let mut order_by_list: Vec<(OrderByItem, SortDirection)> = Vec::new();
     let mut group_by_list: Vec<TableNameAndFieldName> = Vec::new();
     let mut aggregate_functions: Vec<AggregateFunctionInfo> = Vec::new();
+    let mut aggregate_aliases: Vec<Option<String>> = Vec::new();
+    let mut having: Option<HavingData> = None;
+    let mut limit: Option<usize> = None;
+    let mut offset: Option<usize> = None;
 
     let mut predicate: Option<PredicateV2> = None;
+    let mut join_predicates: Vec<PredicateV2> = Vec::new();
 
     record
         .into_inner()
         .for_each(|inner_value| match inner_value.as_rule() {
+            Rule::distinct => distinct = true,
             Rule::table_list => {
                 inner_value
                     .into_inner()
@@ -432,50 +996,62 @@ fn parse_select_sql(record: Pair<Rule>) -> QueryData {
                             let table_name = inner_value.as_str().to_string();
                             table_name_list.push(table_name.clone());
                         }
+                        Rule::values_source => {
+                            values_list.push(parse_values_source(inner_value));
+                        }
+                        // join ... on の条件はwhereの条件にまとめて、productとselectで処理する
+                        Rule::join_clause => {
+                            inner_value.into_inner().for_each(|inner_value| {
+                                match inner_value.as_rule() {
+                                    Rule::id_token => {
+                                        table_name_list.push(inner_value.as_str().to_string());
+                                    }
+                                    Rule::values_source => {
+                                        values_list.push(parse_values_source(inner_value));
+                                    }
+                                    Rule::predicate => {
+                                        if let Some(join_predicate) = parse_predicate(inner_value) {
+                                            join_predicates.push(join_predicate);
+                                        }
+                                    }
+                                    _ => {}
+                                }
+                            });
+                        }
                         _ => {}
                     });
             }
+            Rule::select_list if inner_value.as_str() == "*" => all_columns = true,
             Rule::select_list => inner_value
                 .into_inner()
                 .for_each(|inner_value| match inner_value.as_rule() {
                     Rule::select_field => inner_value.into_inner().for_each(|inner_value| {
                         match inner_value.as_rule() {
                             Rule::aggregate_function => {
+                                aggregate_functions.push(parse_aggregate_function(inner_value));
+                                aggregate_aliases.push(None);
+                            }
+                            // 直前の集約関数の別名
+                            Rule::id_token => {
+                                if let Some(alias) = aggregate_aliases.last_mut() {
+                                    *alias = Some(inner_value.as_str().to_string());
+                                }
+                            }
+                            Rule::table_star => {
+                                let table_name = inner_value.into_inner().next().unwrap();
+                                field_name_list.push(TableNameAndFieldName::new(
+                                    Some(table_name.as_str().to_string()),
+                                    QueryData::ALL_FIELDS.to_string(),
+                                ));
+                            }
+                            Rule::select_expression => {
                                 let mut inner_iter = inner_value.into_inner();
-                                let function_name = inner_iter.next().unwrap().as_str().to_string();
-                                let function_type =
-                                    AggregateFunctionType::from_str(&function_name).unwrap();
-
-                                let field_pair = inner_iter.next().unwrap();
-                                let field = match field_pair.into_inner().next() {
-                                    Some(field_inner) => match field_inner.as_rule() {
-                                        Rule::qualified_field => {
-                                            let mut inner_iter = field_inner.into_inner();
-                                            let table_name = inner_iter.next().unwrap().as_str();
-                                            let field_name = inner_iter.next().unwrap().as_str();
-
-                                            TableNameAndFieldName::new(
-                                                Some(table_name.to_string()),
-                                                field_name.to_string(),
-                                            )
-                                        }
-                                        Rule::id_token => TableNameAndFieldName::new(
-                                            None,
-                                            field_inner.as_str().to_string(),
-                                        ),
-                                        _ => {
-                                            panic!("Unexpected rule in aggregate function field")
-                                        }
-                                    },
-                                    None => {
-                                        panic!("No inner value in aggregate function field")
-                                    }
-                                };
-
-                                aggregate_functions.push(AggregateFunctionInfo {
-                                    function_type,
-                                    field,
-                                });
+                                let expression =
+                                    parse_expression(inner_iter.next().unwrap()).unwrap();
+                                let alias = inner_iter.next().unwrap().as_str().to_string();
+                                field_name_list
+                                    .push(TableNameAndFieldName::new(None, alias.clone()));
+                                select_expressions.push(SelectExpression { alias, expression });
                             }
 
                             Rule::field => {
@@ -511,8 +1087,26 @@ fn parse_select_sql(record: Pair<Rule>) -> QueryData {
             Rule::predicate => {
                 predicate = parse_predicate(inner_value);
             }
-            Rule::order_by_list => {
-                inner_value
+            Rule::having_predicate => {
+                let mut having_aggregate_functions: Vec<AggregateFunctionInfo> = Vec::new();
+                for pair in inner_value.clone().into_inner().flatten() {
+                    if pair.as_rule() == Rule::aggregate_function {
+                        let aggregate_function = parse_aggregate_function(pair);
+                        if !having_aggregate_functions.contains(&aggregate_function) {
+                            having_aggregate_functions.push(aggregate_function);
+                        }
+                    }
+                }
+                having = Some(HavingData {
+                    predicate: parse_predicate(inner_value.into_inner().next().unwrap())
+                        .unwrap_or(PredicateV2::new(vec![])),
+                    aggregate_functions: having_aggregate_functions,
+                });
+            }
+            Rule::order_by_list => inner_value.into_inner().for_each(|order_by_item| {
+                let mut item: Option<OrderByItem> = None;
+                let mut direction = SortDirection::Ascending;
+                order_by_item
                     .into_inner()
                     .for_each(|inner_value| match inner_value.as_rule() {
                         Rule::field => {
@@ -520,9 +1114,8 @@ fn parse_select_sql(record: Pair<Rule>) -> QueryData {
                                 match inner_value.as_rule() {
                                     Rule::id_token => {
                                         let field_name = inner_value.as_str().to_string();
-                                        order_by_list.push(TableNameAndFieldName::new(
-                                            None,
-                                            field_name.to_string(),
+                                        item = Some(OrderByItem::Field(
+                                            TableNameAndFieldName::new(None, field_name),
                                         ));
                                     }
                                     Rule::qualified_field => {
@@ -530,18 +1123,41 @@ fn parse_select_sql(record: Pair<Rule>) -> QueryData {
                                         let table_name = inner_iter.next().unwrap().as_str();
                                         let field_name = inner_iter.next().unwrap().as_str();
 
-                                        order_by_list.push(TableNameAndFieldName::new(
-                                            Some(table_name.to_string()),
-                                            field_name.to_string(),
-                                        ));
+                                        item =
+                                            Some(OrderByItem::Field(TableNameAndFieldName::new(
+                                                Some(table_name.to_string()),
+                                                field_name.to_string(),
+                                            )));
                                     }
                                     _ => {}
                                 }
                             });
                         }
+                        Rule::int_token => {
+                            let position = inner_value.as_str().parse::<usize>().unwrap();
+                            item = Some(OrderByItem::Position(position));
+                        }
+                        Rule::sort_direction => {
+                            if inner_value.as_str().eq_ignore_ascii_case("desc") {
+                                direction = SortDirection::Descending;
+                            }
+                        }
                         _ => {}
-                    })
-            }
+                    });
+                if let Some(item) = item {
+                    order_by_list.push((item, direction));
+                }
+            }),
+            Rule::limit_clause => {
+                inner_value.into_inner().for_each(|inner_value| {
+                    let count = inner_value.as_str().parse::<usize>().unwrap();
+                    match inner_value.as_rule() {
+                        Rule::limit_count => limit = Some(count),
+                        Rule::offset_count => offset = Some(count),
+                        _ => {}
+                    }
+                });
+            }
             Rule::group_by_list => {
                 inner_value
                     .into_inner()
@@ -575,23 +1191,86 @@ fn parse_select_sql(record: Pair<Rule>) -> QueryData {
             }
             _ => {}
         });
+    let mut predicate = predicate.unwrap_or(PredicateV2::new(vec![]));
+    for join_predicate in join_predicates {
+        predicate.conjunction_with(join_predicate);
+    }
     let query_data = QueryData::new(
+        distinct,
         table_name_list,
+        values_list,
+        all_columns,
         field_name_list,
-        predicate.unwrap_or(PredicateV2::new(vec![])),
+        select_expressions,
+        predicate,
         order_by_list,
         group_by_list,
         aggregate_functions,
+        aggregate_aliases,
+        having,
+        limit,
+        offset,
     );
 
     return query_data;
 }
 
+fn parse_constant_list(record: Pair<Rule>) -> Vec<Constant> {
+    let mut constant_list: Vec<Constant> = Vec::new();
+    record
+        .into_inner()
+        .for_each(|inner_value| match inner_value.as_rule() {
+            Rule::constant => match inner_value.into_inner().next() {
+                Some(inner_value) => match inner_value.as_rule() {
+                    Rule::date_token => {
+                        let constant = Constant::new(parse_date_token(inner_value.as_str()));
+                        constant_list.push(constant);
+                    }
+                    Rule::float_token => {
+                        let constant = Constant::new(parse_float_token(inner_value.as_str()));
+                        constant_list.push(constant);
+                    }
+                    Rule::decimal_token => {
+                        let constant = Constant::new(parse_decimal_token(inner_value.as_str()));
+                        constant_list.push(constant);
+                    }
+                    Rule::int_token => {
+                        let value = inner_value.as_str().parse::<i32>().unwrap();
+                        let int_constant_value = ConstantValue::Number(value);
+                        let constant = Constant::new(int_constant_value);
+                        constant_list.push(constant);
+                    }
+                    Rule::string_token => {
+                        let value = inner_value
+                            .into_inner()
+                            .find(|p| p.as_rule() == Rule::string_content)
+                            .map(|p| p.as_str().to_string())
+                            .unwrap_or_default();
+                        let string_constant_value = ConstantValue::String(value.clone());
+                        let constant = Constant::new(string_constant_value);
+                        constant_list.push(constant);
+                    }
+                    Rule::null_token => {
+                        constant_list.push(Constant::new(ConstantValue::Null));
+                    }
+                    Rule::parameter_token => {
+                        constant_list.push(parse_parameter_token(&inner_value));
+                    }
+                    _ => {}
+                },
+                None => {}
+            },
+            _ => {}
+        });
+    return constant_list;
+}
+
 fn parse_insert_sql(record: Pair<Rule>) -> InsertData {
     // Handle INSERT SQL
     let mut table_name: Option<String> = None;
     let mut field_name_vec: Vec<String> = Vec::new();
     let mut constant_list: Vec<Constant> = Vec::new();
+    let mut select_query: Option<QueryData> = None;
     record
         .into_inner()
         .for_each(|inner_value| match inner_value.as_rule() {
@@ -609,41 +1288,94 @@ fn parse_insert_sql(record: Pair<Rule>) -> InsertData {
                     });
             }
             Rule::constant_list => {
+                constant_list = parse_constant_list(inner_value);
+            }
+            Rule::select_sql => {
+                select_query = Some(parse_select_sql(inner_value));
+            }
+            _ => {}
+        });
+
+    if let Some(select_query) = select_query {
+        return InsertData::new_with_select(table_name.unwrap(), field_name_vec, select_query);
+    }
+
+    let insert_data = InsertData::new(table_name.unwrap(), field_name_vec, constant_list);
+
+    return insert_data;
+}
+
+fn parse_copy_sql(record: Pair<Rule>) -> CopyData {
+    let mut table_name: Option<String> = None;
+    let mut field_name_vec: Vec<String> = vec![];
+    let mut file_path = String::new();
+    let mut build_indexes = IndexBuildMode::PerRow;
+
+    record
+        .into_inner()
+        .for_each(|inner_value| match inner_value.as_rule() {
+            Rule::id_token => {
+                table_name = Some(inner_value.as_str().to_string());
+            }
+            Rule::field_list => {
                 inner_value
                     .into_inner()
                     .for_each(|inner_value| match inner_value.as_rule() {
-                        Rule::constant => match inner_value.into_inner().next() {
-                            Some(inner_value) => match inner_value.as_rule() {
-                                Rule::int_token => {
-                                    let value = inner_value.as_str().parse::<i32>().unwrap();
-                                    let int_constant_value = ConstantValue::Number(value);
-                                    let constant = Constant::new(int_constant_value);
-                                    constant_list.push(constant);
-                                }
-                                Rule::string_token => {
-                                    let value = inner_value
-                                        .into_inner()
-                                        .find(|p| p.as_rule() == Rule::string_content)
-                                        .map(|p| p.as_str().to_string())
-                                        .unwrap_or_default();
-                                    let string_constant_value =
-                                        ConstantValue::String(value.clone());
-                                    let constant = Constant::new(string_constant_value);
-                                    constant_list.push(constant);
-                                }
-                                _ => {}
-                            },
-                            None => {}
-                        },
+                        Rule::field => {
+                            field_name_vec.push(inner_value.as_str().to_string());
+                        }
                         _ => {}
                     });
             }
+            Rule::string_token => {
+                file_path = inner_value
+                    .into_inner()
+                    .find(|p| p.as_rule() == Rule::string_content)
+                    .map(|p| p.as_str().to_string())
+                    .unwrap_or_default();
+            }
+            Rule::copy_options => {
+                inner_value.into_inner().for_each(|copy_option| {
+                    let mode = copy_option.into_inner().next().unwrap();
+                    if mode.as_str().eq_ignore_ascii_case("after") {
+                        build_indexes = IndexBuildMode::After;
+                    } else {
+                        build_indexes = IndexBuildMode::PerRow;
+                    }
+                });
+            }
             _ => {}
         });
 
-    let insert_data = InsertData::new(table_name.unwrap(), field_name_vec, constant_list);
+    return CopyData::new(
+        table_name.unwrap(),
+        field_name_vec,
+        file_path,
+        build_indexes,
+    );
+}
 
-    return insert_data;
+fn parse_copy_to_sql(record: Pair<Rule>) -> CopyToData {
+    let mut query_data: Option<QueryData> = None;
+    let mut file_path = String::new();
+
+    record
+        .into_inner()
+        .for_each(|inner_value| match inner_value.as_rule() {
+            Rule::select_sql => {
+                query_data = Some(parse_select_sql(inner_value));
+            }
+            Rule::string_token => {
+                file_path = inner_value
+                    .into_inner()
+                    .find(|p| p.as_rule() == Rule::string_content)
+                    .map(|p| p.as_str().to_string())
+                    .unwrap_or_default();
+            }
+            _ => {}
+        });
+
+    return CopyToData::new(query_data.unwrap(), file_path);
 }
 
 fn parse_delete_sql(record: Pair<Rule>) -> DeleteData {
@@ -689,6 +1421,21 @@ fn parse_update_sql(record: Pair<Rule>) -> UpdateData {
                     inner_value
                         .into_inner()
                         .for_each(|inner_value| match inner_value.as_rule() {
+                            Rule::date_token => {
+                                let constant =
+                                    Constant::new(parse_date_token(inner_value.as_str()));
+                                new_value = Some(constant);
+                            }
+                            Rule::float_token => {
+                                let constant =
+                                    Constant::new(parse_float_token(inner_value.as_str()));
+                                new_value = Some(constant);
+                            }
+                            Rule::decimal_token => {
+                                let constant =
+                                    Constant::new(parse_decimal_token(inner_value.as_str()));
+                                new_value = Some(constant);
+                            }
                             Rule::int_token => {
                                 let value = inner_value.as_str().parse::<i32>().unwrap();
                                 let int_constant_value = ConstantValue::Number(value);
@@ -705,6 +1452,12 @@ fn parse_update_sql(record: Pair<Rule>) -> UpdateData {
                                 let constant = Constant::new(string_constant_value);
                                 new_value = Some(constant);
                             }
+                            Rule::null_token => {
+                                new_value = Some(Constant::new(ConstantValue::Null));
+                            }
+                            Rule::parameter_token => {
+                                new_value = Some(parse_parameter_token(&inner_value));
+                            }
                             _ => {}
                         });
                 }
@@ -752,6 +1505,7 @@ fn parse_create_index_sql(record: Pair<Rule>) -> CreateIndexData {
     let mut index_name: Option<String> = None;
     let mut table_name: Option<String> = None;
     let mut field_name: Option<String> = None;
+    let mut number_of_buckets: Option<usize> = None;
 
     record
         .into_inner()
@@ -768,6 +1522,12 @@ fn parse_create_index_sql(record: Pair<Rule>) -> CreateIndexData {
                     field_name = Some(inner_value.as_str().to_string());
                 }
             }
+            Rule::index_options => {
+                inner_value.into_inner().for_each(|index_option| {
+                    let value = index_option.into_inner().next().unwrap();
+                    number_of_buckets = Some(value.as_str().parse::<usize>().unwrap());
+                });
+            }
             Rule::constant => {}
             _ => {}
         });
@@ -776,6 +1536,7 @@ fn parse_create_index_sql(record: Pair<Rule>) -> CreateIndexData {
         index_name: index_name.unwrap(),
         table_name: table_name.unwrap(),
         field_name: field_name.unwrap(),
+        number_of_buckets,
     };
 
     return create_index_data;
@@ -783,6 +1544,7 @@ fn parse_create_index_sql(record: Pair<Rule>) -> CreateIndexData {
 
 fn parse_create_table_sql(record: Pair<Rule>) -> CreateTableData {
     let mut table_name: Option<String> = None;
+    let mut block_size: Option<usize> = None;
     let mut schema = TableSchema {
         fields: Vec::new(),
         field_infos: HashMap::new(),
@@ -795,56 +1557,155 @@ fn parse_create_table_sql(record: Pair<Rule>) -> CreateTableData {
                 table_name = Some(inner_value.as_str().to_string());
             }
             Rule::field_definitions => {
-                let mut table_field_infos: Vec<TableFieldInfo> = vec![];
                 inner_value
                     .into_inner()
                     .for_each(|inner_value| match inner_value.as_rule() {
                         Rule::field_definition => {
-                            let mut field_name = String::new();
-                            let mut field_type = TableFieldType::INTEGER;
-                            let mut field_length: Option<i32> = None;
-
-                            inner_value.into_inner().for_each(|inner_value| {
-                                match inner_value.as_rule() {
-                                    Rule::id_token => {
-                                        field_name = inner_value.as_str().to_string();
-                                    }
-                                    Rule::text => {
-                                        field_type = TableFieldType::VARCHAR;
-                                    }
-                                    Rule::integer => {
-                                        field_type = TableFieldType::INTEGER;
-                                    }
-                                    Rule::int_token => {
-                                        field_length =
-                                            Some(inner_value.as_str().parse::<i32>().unwrap());
-                                    }
-
-                                    _ => {}
-                                }
-                            });
-
-                            schema.add_field(field_name, field_type, field_length.unwrap_or(0));
+                            parse_field_definition(inner_value, &mut schema);
                         }
                         _ => {}
                     });
             }
+            Rule::table_options => {
+                inner_value.into_inner().for_each(|table_option| {
+                    let value = table_option.into_inner().next().unwrap();
+                    block_size = Some(value.as_str().parse::<usize>().unwrap());
+                });
+            }
             _ => {}
         });
 
     let create_table_data = CreateTableData {
         table_name: table_name.unwrap(),
         schema,
+        block_size,
     };
 
     return create_table_data;
 }
 
-pub fn parse_sql(sql: String) -> Vec<ParsedSQL> {
+// field_definitionを1つ読んでschemaに足す
+fn parse_field_definition(record: Pair<Rule>, schema: &mut TableSchema) {
+    let mut field_name = String::new();
+    let mut field_type = TableFieldType::INTEGER;
+    let mut field_length: Option<i32> = None;
+    let mut field_scale: Option<i32> = None;
+    let mut nullable = true;
+    let mut default: Option<Constant> = None;
+    let mut key: Option<KeyConstraint> = None;
+
+    record
+        .into_inner()
+        .for_each(|inner_value| match inner_value.as_rule() {
+            Rule::id_token => {
+                field_name = inner_value.as_str().to_string();
+            }
+            Rule::not_null_constraint => {
+                nullable = false;
+            }
+            // primary keyはnot nullも兼ねる
+            Rule::primary_key_constraint => {
+                nullable = false;
+                key = Some(KeyConstraint::PrimaryKey);
+            }
+            Rule::unique_constraint => {
+                if key.is_none() {
+                    key = Some(KeyConstraint::Unique);
+                }
+            }
+            // default null は default を書かないのと同じ
+            Rule::default_constraint => {
+                default = parse_constant_list(inner_value)
+                    .into_iter()
+                    .next()
+                    .filter(|constant| constant.value != ConstantValue::Null);
+            }
+            Rule::text => {
+                field_type = TableFieldType::VARCHAR;
+            }
+            Rule::integer => {
+                field_type = TableFieldType::INTEGER;
+            }
+            Rule::decimal => {
+                field_type = TableFieldType::DECIMAL(0, 0);
+            }
+            Rule::float => {
+                field_type = TableFieldType::FLOAT;
+            }
+            Rule::date => {
+                field_type = TableFieldType::DATE;
+            }
+            // DECIMAL(precision, scale) は2つ目がscale
+            Rule::int_token => {
+                let value = inner_value.as_str().parse::<i32>().unwrap();
+                if field_length.is_none() {
+                    field_length = Some(value);
+                } else {
+                    field_scale = Some(value);
+                }
+            }
+
+            _ => {}
+        });
+
+    if let TableFieldType::DECIMAL(_, _) = field_type {
+        schema.add_decimal_field(
+            field_name.clone(),
+            field_length.unwrap_or(0),
+            field_scale.unwrap_or(0),
+        );
+    } else {
+        schema.add_field(field_name.clone(), field_type, field_length.unwrap_or(0));
+    }
+    schema.set_field_constraints(&field_name, nullable, default);
+    schema.set_key_constraint(&field_name, key);
+}
+
+fn parse_alter_table_sql(record: Pair<Rule>) -> AlterTableData {
+    let mut table_name: Option<String> = None;
+    let mut schema = TableSchema::new();
+
+    record
+        .into_inner()
+        .for_each(|inner_value| match inner_value.as_rule() {
+            Rule::id_token => {
+                table_name = Some(inner_value.as_str().to_string());
+            }
+            Rule::field_definition => {
+                parse_field_definition(inner_value, &mut schema);
+            }
+            _ => {}
+        });
+
+    let field_name = schema.fields[0].clone();
+    let field_info = schema.field_infos.get(&field_name).unwrap().clone();
+    return AlterTableData {
+        table_name: table_name.unwrap(),
+        field_name,
+        field_info,
+    };
+}
+
+// ? に値を結びつけられるのはprepareした文だけなので、それ以外の経路では弾く
+pub fn parse_sql(sql: String) -> Result<Vec<ParsedSQL>, ParseError> {
+    if let Some((_, line, column)) = parameter_positions(&sql)?.first() {
+        return Err(ParseError::new(
+            *line,
+            *column,
+            "parameter is not bound".to_string(),
+        ));
+    }
+    return parse_sql_with_parameters(sql);
+}
+
+pub fn parse_sql_with_parameters(sql: String) -> Result<Vec<ParsedSQL>, ParseError> {
     let file = SQLParser::parse(Rule::sql, &sql)
-        .expect("unsuccessful parse") // unwrap the parse result
+        .map_err(to_parse_error)?
         .next()
         .unwrap(); // get and unwrap the `file` rule; never fails
+    check_aggregate_positions(file.clone(), false)?;
+    check_usize_tokens(file.clone())?;
+    check_decimal_types(file.clone())?;
 
     for record in file.into_inner() {
         let mut result: Vec<ParsedSQL> = Vec::new();
@@ -871,6 +1732,16 @@ pub fn parse_sql(sql: String) -> Vec<ParsedSQL> {
                                         result.push(ParsedSQL::Delete(delete_data));
                                     }
 
+                                    Rule::copy_sql => {
+                                        let copy_data = parse_copy_sql(inner_value);
+                                        result.push(ParsedSQL::Copy(copy_data));
+                                    }
+
+                                    Rule::copy_to_sql => {
+                                        let copy_to_data = parse_copy_to_sql(inner_value);
+                                        result.push(ParsedSQL::CopyTo(copy_to_data));
+                                    }
+
                                     Rule::update_sql => {
                                         let update_data = parse_update_sql(inner_value);
                                         result.push(ParsedSQL::Update(update_data));
@@ -895,14 +1766,64 @@ pub fn parse_sql(sql: String) -> Vec<ParsedSQL> {
                                         result.push(ParsedSQL::ShowTables);
                                     }
 
+                                    Rule::begin_sql => {
+                                        result.push(ParsedSQL::Begin);
+                                    }
+
+                                    Rule::commit_sql => {
+                                        result.push(ParsedSQL::Commit);
+                                    }
+
+                                    Rule::rollback_sql => {
+                                        result.push(ParsedSQL::Rollback);
+                                    }
+
+                                    Rule::show_indexes_sql => {
+                                        result.push(ParsedSQL::ShowIndexes);
+                                    }
+
                                     Rule::explain_sql => {
-                                        let select_query = parse_select_sql(
-                                            inner_value
-                                                .into_inner()
-                                                .find(|p| p.as_rule() == Rule::select_sql)
-                                                .unwrap(),
-                                        );
-                                        result.push(ParsedSQL::Explain(select_query));
+                                        let mut format = ExplainFormat::Text;
+                                        let mut select_query: Option<QueryData> = None;
+                                        let mut dml_statement: Option<ParsedSQL> = None;
+
+                                        inner_value.into_inner().for_each(|inner_value| {
+                                            match inner_value.as_rule() {
+                                                Rule::explain_format => {
+                                                    format = ExplainFormat::Json;
+                                                }
+                                                Rule::select_sql => {
+                                                    select_query =
+                                                        Some(parse_select_sql(inner_value));
+                                                }
+                                                Rule::delete_sql => {
+                                                    dml_statement = Some(ParsedSQL::Delete(
+                                                        parse_delete_sql(inner_value),
+                                                    ));
+                                                }
+                                                Rule::update_sql => {
+                                                    dml_statement = Some(ParsedSQL::Update(
+                                                        parse_update_sql(inner_value),
+                                                    ));
+                                                }
+                                                _ => {}
+                                            }
+                                        });
+
+                                        match dml_statement {
+                                            Some(statement) => {
+                                                result.push(ParsedSQL::ExplainDml {
+                                                    statement: Box::new(statement),
+                                                    format,
+                                                });
+                                            }
+                                            None => {
+                                                result.push(ParsedSQL::Explain {
+                                                    query_data: select_query.unwrap(),
+                                                    format,
+                                                });
+                                            }
+                                        }
                                     }
 
                                     Rule::describe_table_sql => {
@@ -923,6 +1844,51 @@ pub fn parse_sql(sql: String) -> Vec<ParsedSQL> {
                                         });
                                     }
 
+                                    Rule::drop_table_sql => {
+                                        let table_name = inner_value
+                                            .into_inner()
+                                            .find(|inner_value| {
+                                                inner_value.as_rule() == Rule::id_token
+                                            })
+                                            .unwrap()
+                                            .as_str()
+                                            .to_string();
+
+                                        result.push(ParsedSQL::DropTable { table_name });
+                                    }
+
+                                    Rule::vacuum_sql => {
+                                        let table_name = inner_value
+                                            .into_inner()
+                                            .find(|inner_value| {
+                                                inner_value.as_rule() == Rule::id_token
+                                            })
+                                            .unwrap()
+                                            .as_str()
+                                            .to_string();
+
+                                        result.push(ParsedSQL::Vacuum { table_name });
+                                    }
+
+                                    Rule::reindex_sql => {
+                                        let table_name = inner_value
+                                            .into_inner()
+                                            .find(|inner_value| {
+                                                inner_value.as_rule() == Rule::id_token
+                                            })
+                                            .unwrap()
+                                            .as_str()
+                                            .to_string();
+
+                                        result.push(ParsedSQL::Reindex { table_name });
+                                    }
+
+                                    Rule::alter_table_sql => {
+                                        result.push(ParsedSQL::AlterTable(parse_alter_table_sql(
+                                            inner_value,
+                                        )));
+                                    }
+
                                     _ => {}
                                 }
                             });
@@ -932,14 +1898,131 @@ pub fn parse_sql(sql: String) -> Vec<ParsedSQL> {
             }
 
             _ => {
-                return vec![];
+                return Ok(vec![]);
             }
         }
 
-        return result;
+        return Ok(result);
+    }
+
+    return Ok(vec![]);
+}
+
+// pestのerrorから、最初に失敗した位置とmessageを取り出す
+// sqlに書かれた ? の位置と、その行と列
+pub fn parameter_positions(sql: &str) -> Result<Vec<(usize, usize, usize)>, ParseError> {
+    let file = SQLParser::parse(Rule::sql, sql).map_err(to_parse_error)?;
+    return Ok(file
+        .flatten()
+        .filter(|pair| pair.as_rule() == Rule::parameter_token)
+        .map(|pair| {
+            let (line, column) = pair.as_span().start_pos().line_col();
+            (pair.as_span().start(), line, column)
+        })
+        .collect());
+}
+
+// 集約関数はselect listとhavingにだけ書ける
+// whereやupdateの式に書くと、groupを作らずに集約関数のfieldを読もうとしてしまう
+fn check_aggregate_positions(pair: Pair<Rule>, in_having: bool) -> Result<(), ParseError> {
+    let is_select_field = pair.as_rule() == Rule::select_field;
+    for inner_pair in pair.into_inner() {
+        if inner_pair.as_rule() == Rule::aggregate_function && !is_select_field && !in_having {
+            let (line, column) = inner_pair.as_span().start_pos().line_col();
+            return Err(ParseError::new(
+                line,
+                column,
+                "aggregate function is not allowed here".to_string(),
+            ));
+        }
+        // subqueryのwhereはhavingの中にあっても集約関数を書けない
+        let in_having = match inner_pair.as_rule() {
+            Rule::having_predicate => true,
+            Rule::select_sql => false,
+            _ => in_having,
+        };
+        check_aggregate_positions(inner_pair, in_having)?;
+    }
+    return Ok(());
+}
+
+// order byの列番号やlimitのようにusizeで持つ数は、usizeに収まらなければparse errorにする
+fn check_usize_tokens(pair: Pair<Rule>) -> Result<(), ParseError> {
+    let holds_usize = matches!(
+        pair.as_rule(),
+        Rule::order_by_item
+            | Rule::limit_count
+            | Rule::offset_count
+            | Rule::table_option
+            | Rule::index_option
+    );
+    for inner_pair in pair.into_inner() {
+        if holds_usize
+            && inner_pair.as_rule() == Rule::int_token
+            && inner_pair.as_str().parse::<usize>().is_err()
+        {
+            let (line, column) = inner_pair.as_span().start_pos().line_col();
+            return Err(ParseError::new(
+                line,
+                column,
+                "number is too large".to_string(),
+            ));
+        }
+        check_usize_tokens(inner_pair)?;
+    }
+    return Ok(());
+}
+
+// DECIMAL(precision, scale) のprecisionは1から9まで、scaleはprecision以下
+// create tableで通すと、insertのときにscaleの桁をかける計算があふれる
+fn check_decimal_types(pair: Pair<Rule>) -> Result<(), ParseError> {
+    if pair.as_rule() == Rule::field_definition
+        && pair
+            .clone()
+            .into_inner()
+            .any(|p| p.as_rule() == Rule::decimal)
+    {
+        let tokens = pair
+            .clone()
+            .into_inner()
+            .filter(|p| p.as_rule() == Rule::int_token)
+            .collect::<Vec<Pair<Rule>>>();
+        let max_precision = TableSchema::MAX_DECIMAL_PRECISION;
+        let precision = tokens[0].as_str().parse::<i32>().unwrap_or(0);
+        let error = if precision < 1 || precision > max_precision {
+            Some((
+                &tokens[0],
+                format!("decimal precision must be between 1 and {}", max_precision),
+            ))
+        } else if !tokens[1]
+            .as_str()
+            .parse::<i32>()
+            .is_ok_and(|scale| scale <= precision)
+        {
+            Some((
+                &tokens[1],
+                "decimal scale must not be larger than the precision".to_string(),
+            ))
+        } else {
+            None
+        };
+        if let Some((token, message)) = error {
+            let (line, column) = token.as_span().start_pos().line_col();
+            return Err(ParseError::new(line, column, message));
+        }
+    }
+    for inner_pair in pair.into_inner() {
+        check_decimal_types(inner_pair)?;
     }
+    return Ok(());
+}
 
-    return vec![];
+fn to_parse_error(error: pest::error::Error<Rule>) -> ParseError {
+    let (line, column) = match error.line_col {
+        pest::error::LineColLocation::Pos(position) => position,
+        pest::error::LineColLocation::Span(start, _) => start,
+    };
+    return ParseError::new(line, column, error.variant.message().to_string());
 }
 
 #[cfg(test)]
@@ -952,21 +2035,31 @@ mod tests {
     fn test_plan() {
         let unparsed_file =
             fs::read_to_string("./sql/sample_create_table.sql").expect("cannot read file");
-        let parsed_sql = parse_sql(unparsed_file);
+        let parsed_sql = parse_sql(unparsed_file).unwrap();
         parsed_sql[0].debug_print();
     }
 
+    #[test]
+    fn test_parse_error() {
+        let error = parse_sql("selct A from t".to_string()).unwrap_err();
+        assert_eq!((error.get_line(), error.get_column()), (1, 1));
+
+        let error = parse_sql("select A from t;\nselect from t".to_string()).unwrap_err();
+        assert_eq!(error.get_line(), 2);
+        assert!(error.to_string().starts_with("Parse error at line 2"));
+    }
+
     #[test]
     fn test_delete_sql() {
         let sql = "delete from test_table where A = 44".to_string();
-        let parsed_sql = parse_sql(sql);
+        let parsed_sql = parse_sql(sql).unwrap();
         parsed_sql[0].debug_print();
     }
 
     #[test]
     fn test_select_join_query() {
         let sql = "select A, B from test_table, test_table2 where C = 'content'".to_string();
-        let parsed_sql = parse_sql(sql);
+        let parsed_sql = parse_sql(sql).unwrap();
         parsed_sql[0].debug_print();
     }
 
@@ -974,14 +2067,27 @@ mod tests {
     fn test_select_table_name() {
         let sql =
             "select test_table.A, B from test_table, test_table2 where C = 'content'".to_string();
-        let parsed_sql = parse_sql(sql);
+        let parsed_sql = parse_sql(sql).unwrap();
         parsed_sql[0].debug_print();
     }
 
+    #[test]
+    fn test_select_star() {
+        let sql = "select * from test_table where A = 1".to_string();
+        let parsed_sql_vec = parse_sql(sql).unwrap();
+
+        let ParsedSQL::Query(query_data) = &parsed_sql_vec[0] else {
+            panic!("Expected Query variant");
+        };
+        assert_eq!(query_data.table_name_list, vec!["test_table".to_string()]);
+        // 空のfield listは select * を表す
+        assert!(query_data.all_columns);
+    }
+
     #[test]
     fn test_create_table() {
         let sql = "create table posts (title varchar(10), content varchar(10))".to_string();
-        let parsed_sql_vec = parse_sql(sql);
+        let parsed_sql_vec = parse_sql(sql).unwrap();
 
         let parsed_sql = &parsed_sql_vec[0];
 
@@ -1020,7 +2126,7 @@ mod tests {
     #[test]
     fn test_create_table_2() {
         let sql = "create table posts (age integer)".to_string();
-        let parsed_sql_vec = parse_sql(sql);
+        let parsed_sql_vec = parse_sql(sql).unwrap();
         let parsed_sql = &parsed_sql_vec[0];
 
         match parsed_sql {
@@ -1044,57 +2150,495 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_create_table_with_block_size() {
+        let sql = "create table posts (age integer) with (block_size = 1024)".to_string();
+        let parsed_sql_vec = parse_sql(sql).unwrap();
+
+        match &parsed_sql_vec[0] {
+            ParsedSQL::CreateTable(create_table_data) => {
+                assert_eq!(create_table_data.table_name, "posts".to_string());
+                assert_eq!(create_table_data.block_size, Some(1024));
+            }
+            _ => {
+                panic!("Expected CreateTable variant");
+            }
+        }
+
+        let sql = "create table posts (age integer)".to_string();
+        let parsed_sql_vec = parse_sql(sql).unwrap();
+
+        match &parsed_sql_vec[0] {
+            ParsedSQL::CreateTable(create_table_data) => {
+                assert_eq!(create_table_data.block_size, None);
+            }
+            _ => {
+                panic!("Expected CreateTable variant");
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_count_all_rows() {
+        let sql = "select count(*), min(A) from test_table group by B".to_string();
+        let parsed_sql_vec = parse_sql(sql).unwrap();
+
+        match &parsed_sql_vec[0] {
+            ParsedSQL::Query(query_data) => {
+                assert_eq!(
+                    query_data.aggregate_functions,
+                    vec![
+                        AggregateFunctionInfo {
+                            function_type: AggregateFunctionType::Count,
+                            field: TableNameAndFieldName::new(None, "*".to_string()),
+                        },
+                        AggregateFunctionInfo {
+                            function_type: AggregateFunctionType::Min,
+                            field: TableNameAndFieldName::new(None, "A".to_string()),
+                        },
+                    ]
+                );
+            }
+            _ => {
+                panic!("Expected Query variant");
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_count_distinct() {
+        let sql = "select count(distinct A), count(A) from test_table group by B".to_string();
+        let parsed_sql_vec = parse_sql(sql).unwrap();
+
+        match &parsed_sql_vec[0] {
+            ParsedSQL::Query(query_data) => {
+                assert_eq!(
+                    query_data.aggregate_functions,
+                    vec![
+                        AggregateFunctionInfo {
+                            function_type: AggregateFunctionType::CountDistinct,
+                            field: TableNameAndFieldName::new(None, "A".to_string()),
+                        },
+                        AggregateFunctionInfo {
+                            function_type: AggregateFunctionType::Count,
+                            field: TableNameAndFieldName::new(None, "A".to_string()),
+                        },
+                    ]
+                );
+                assert_eq!(
+                    query_data.aggregate_functions[0].get_field_name(),
+                    "count_distinct_A"
+                );
+            }
+            _ => {
+                panic!("Expected Query variant");
+            }
+        }
+
+        // distinctはcountにしか付けられない
+        assert!(parse_sql("select sum(distinct A) from test_table".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_parse_field_constraints() {
+        let sql = "create table t (a integer not null, b varchar(10) default 'x', \
+                   c integer default 0 not null, d float default null)"
+            .to_string();
+        let parsed_sql_vec = parse_sql(sql).unwrap();
+
+        let ParsedSQL::CreateTable(create_table_data) = &parsed_sql_vec[0] else {
+            panic!("Expected CreateTable variant");
+        };
+        let schema = &create_table_data.schema;
+        assert_eq!(schema.fields, vec!["a", "b", "c", "d"]);
+        assert!(!schema.is_nullable("a"));
+        assert_eq!(schema.get_default("a"), None);
+        assert!(schema.is_nullable("b"));
+        assert_eq!(
+            schema.get_default("b"),
+            Some(Constant::new(ConstantValue::String("x".to_string())))
+        );
+        assert!(!schema.is_nullable("c"));
+        assert_eq!(
+            schema.get_default("c"),
+            Some(Constant::new(ConstantValue::Number(0)))
+        );
+        assert!(schema.is_nullable("d"));
+        assert_eq!(schema.get_default("d"), None);
+
+        let sql = "alter table t add column e integer not null default 1".to_string();
+        let ParsedSQL::AlterTable(alter_table_data) = &parse_sql(sql).unwrap()[0] else {
+            panic!("Expected AlterTable variant");
+        };
+        assert!(!alter_table_data.field_info.nullable);
+        assert_eq!(
+            alter_table_data.field_info.default,
+            Some(Constant::new(ConstantValue::Number(1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_key_constraints() {
+        let sql = "create table t (id integer primary key, email varchar(20) unique not null, \
+                   name varchar(10))"
+            .to_string();
+        let ParsedSQL::CreateTable(create_table_data) = &parse_sql(sql).unwrap()[0] else {
+            panic!("Expected CreateTable variant");
+        };
+        let schema = &create_table_data.schema;
+        // primary keyはnot nullでもある
+        assert!(!schema.is_nullable("id"));
+        assert_eq!(
+            schema.get_key_constraint("id"),
+            Some(KeyConstraint::PrimaryKey)
+        );
+        assert!(!schema.is_nullable("email"));
+        assert_eq!(
+            schema.get_key_constraint("email"),
+            Some(KeyConstraint::Unique)
+        );
+        assert!(schema.is_nullable("name"));
+        assert_eq!(schema.get_key_constraint("name"), None);
+    }
+
+    #[test]
+    fn test_parse_table_star() {
+        let sql = "select t1.*, t2.id from t1, t2".to_string();
+        let parsed_sql_vec = parse_sql(sql).unwrap();
+
+        match &parsed_sql_vec[0] {
+            ParsedSQL::Query(query_data) => {
+                assert_eq!(
+                    query_data.field_name_list,
+                    vec![
+                        TableNameAndFieldName::new(
+                            Some("t1".to_string()),
+                            QueryData::ALL_FIELDS.to_string()
+                        ),
+                        TableNameAndFieldName::new(Some("t2".to_string()), "id".to_string()),
+                    ]
+                );
+            }
+            _ => {
+                panic!("Expected Query variant");
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_values_source() {
+        let sql = "select * from users, (values (1, 'a'), (2, 'b')) as t(id, name)".to_string();
+        let parsed_sql_vec = parse_sql(sql).unwrap();
+
+        match &parsed_sql_vec[0] {
+            ParsedSQL::Query(query_data) => {
+                assert_eq!(query_data.table_name_list, vec!["users".to_string()]);
+                assert!(query_data.all_columns);
+                assert_eq!(
+                    query_data.values_list,
+                    vec![ValuesData::new(
+                        "t".to_string(),
+                        vec!["id".to_string(), "name".to_string()],
+                        vec![
+                            vec![
+                                Constant::new(ConstantValue::Number(1)),
+                                Constant::new(ConstantValue::String("a".to_string())),
+                            ],
+                            vec![
+                                Constant::new(ConstantValue::Number(2)),
+                                Constant::new(ConstantValue::String("b".to_string())),
+                            ],
+                        ],
+                    )]
+                );
+            }
+            _ => {
+                panic!("Expected Query variant");
+            }
+        }
+    }
+
+    #[test]
+    fn test_create_table_with_decimal() {
+        let sql = "create table items (price decimal(8, 2))".to_string();
+        let parsed_sql_vec = parse_sql(sql).unwrap();
+
+        match &parsed_sql_vec[0] {
+            ParsedSQL::CreateTable(create_table_data) => {
+                assert_eq!(
+                    create_table_data.schema.get_field_type("price".to_string()),
+                    Some(TableFieldType::DECIMAL(8, 2))
+                );
+            }
+            _ => {
+                panic!("Expected CreateTable variant");
+            }
+        }
+
+        let sql = "insert into items (price) values (12.34)".to_string();
+        let parsed_sql_vec = parse_sql(sql).unwrap();
+
+        match &parsed_sql_vec[0] {
+            ParsedSQL::Insert(insert_data) => {
+                assert_eq!(
+                    insert_data.value_list[0].value,
+                    ConstantValue::Decimal {
+                        value: 1234,
+                        scale: 2
+                    }
+                );
+            }
+            _ => {
+                panic!("Expected Insert variant");
+            }
+        }
+
+        // precisionは1から9まで、scaleはprecision以下
+        for (sql, token) in [
+            ("create table items (price decimal(5, 45))", "45"),
+            ("create table items (price decimal(10, 2))", "10"),
+            ("create table items (price decimal(0, 0))", "0, 0"),
+            ("alter table items add column tax decimal(99999999999, 2)", "99999999999"),
+        ] {
+            let error = parse_sql(sql.to_string()).unwrap_err();
+            assert_eq!(
+                (error.get_line(), error.get_column()),
+                (1, sql.find(token).unwrap() + 1)
+            );
+        }
+        assert!(parse_sql("create table items (price decimal(9, 9))".to_string()).is_ok());
+
+        // i32に収まらないdecimalはpanicせずにfloatになる
+        let sql = "insert into items (price) values (12345678901.5)".to_string();
+        let parsed_sql_vec = parse_sql(sql).unwrap();
+
+        match &parsed_sql_vec[0] {
+            ParsedSQL::Insert(insert_data) => {
+                assert_eq!(
+                    insert_data.value_list[0].value,
+                    ConstantValue::Float(12345678901.5)
+                );
+            }
+            _ => {
+                panic!("Expected Insert variant");
+            }
+        }
+    }
+
     #[test]
     fn test_insert_sql() {
         let sql = "insert into test_table (A, B) values (44, 'Hello World')".to_string();
-        let parsed_sql = parse_sql(sql);
+        let parsed_sql = parse_sql(sql).unwrap();
         parsed_sql[0].debug_print();
     }
 
+    #[test]
+    fn test_insert_select_sql() {
+        let sql = "insert into dst (A, B) select C, D from src where C = 1".to_string();
+        let parsed_sql = parse_sql(sql).unwrap();
+
+        match &parsed_sql[0] {
+            ParsedSQL::Insert(insert_data) => {
+                assert_eq!(insert_data.table_name, "dst".to_string());
+                assert_eq!(insert_data.field_name_list, vec!["A", "B"]);
+                assert!(insert_data.value_list.is_empty());
+
+                let select_query = insert_data.select_query.as_ref().unwrap();
+                assert_eq!(select_query.table_name_list, vec!["src"]);
+                assert_eq!(
+                    select_query.field_name_list,
+                    vec![
+                        TableNameAndFieldName::new(None, "C".to_string()),
+                        TableNameAndFieldName::new(None, "D".to_string()),
+                    ]
+                );
+            }
+            _ => {
+                panic!("Expected Insert variant");
+            }
+        }
+    }
+
     #[test]
     fn test_update_sql() {
         let sql = "update test_table set B = 'Updated Value' where A = 44".to_string();
-        let parsed_sql = parse_sql(sql);
+        let parsed_sql = parse_sql(sql).unwrap();
         parsed_sql[0].debug_print();
     }
 
     #[test]
     fn test_create_index_sql() {
         let sql = "create index idx_test on test_table (A)".to_string();
-        let parsed_sql = parse_sql(sql);
+        let parsed_sql = parse_sql(sql).unwrap();
         parsed_sql[0].debug_print();
     }
 
     #[test]
     fn test_select_order_by() {
         let sql = "select A, B from test_table order by A".to_string();
-        let parsed_sql = parse_sql(sql);
+        let parsed_sql = parse_sql(sql).unwrap();
         parsed_sql[0].debug_print();
     }
 
+    #[test]
+    fn test_too_large_number_is_parse_error() {
+        for (sql, number) in [
+            (
+                "select A, B from test_table order by 99999999999999999999999",
+                "99999999999999999999999",
+            ),
+            (
+                "select A from test_table limit 99999999999999999999999",
+                "99999999999999999999999",
+            ),
+            (
+                "select A from test_table limit 1 offset 99999999999999999999999",
+                "99999999999999999999999",
+            ),
+            (
+                "create table t (A integer) with (block_size = 99999999999999999999999)",
+                "99999999999999999999999",
+            ),
+            (
+                "create index t_a on t (A) with (buckets = 99999999999999999999999)",
+                "99999999999999999999999",
+            ),
+        ] {
+            let error = parse_sql(sql.to_string()).unwrap_err();
+            assert_eq!(
+                (error.get_line(), error.get_column()),
+                (1, sql.find(number).unwrap() + 1)
+            );
+        }
+    }
+
     #[test]
     fn test_select_group_by() {
         let sql = "select A, max(B) from test_table group by A".to_string();
-        let parsed_sql = parse_sql(sql);
+        let parsed_sql = parse_sql(sql).unwrap();
         parsed_sql[0].debug_print();
     }
 
+    #[test]
+    fn test_select_not_distinct_from() {
+        let field_a = ExpressionV2::new(ExpressionValue::TableNameAndFieldName(
+            TableNameAndFieldName::new(None, "A".to_string()),
+        ));
+        let field_b = ExpressionV2::new(ExpressionValue::TableNameAndFieldName(
+            TableNameAndFieldName::new(None, "B".to_string()),
+        ));
+
+        for (sql, op) in [
+            ("select A from t where A <=> B", CompareOp::NotDistinctFrom),
+            (
+                "select A from t where A is not distinct from B",
+                CompareOp::NotDistinctFrom,
+            ),
+            (
+                "select A from t where A is distinct from B",
+                CompareOp::DistinctFrom,
+            ),
+        ] {
+            let parsed_sql = parse_sql(sql.to_string()).unwrap();
+            match &parsed_sql[0] {
+                ParsedSQL::Query(query_data) => {
+                    assert_eq!(
+                        query_data.predicate,
+                        PredicateV2::new(vec![TermV2::new_with_op(
+                            field_a.clone(),
+                            op,
+                            field_b.clone()
+                        )])
+                    );
+                }
+                _ => panic!("Expected Query variant"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_select_arithmetic_expression() {
+        let field = |name: &str| {
+            ExpressionV2::new(ExpressionValue::TableNameAndFieldName(
+                TableNameAndFieldName::new(None, name.to_string()),
+            ))
+        };
+        let number = |n: i32| {
+            ExpressionV2::new(ExpressionValue::Constant(Constant::new(
+                ConstantValue::Number(n),
+            )))
+        };
+
+        let parsed_sql = parse_sql("select A from t where price * qty > 100".to_string()).unwrap();
+        match &parsed_sql[0] {
+            ParsedSQL::Query(query_data) => {
+                assert_eq!(
+                    query_data.predicate,
+                    PredicateV2::new(vec![TermV2::new_with_op(
+                        ExpressionV2::new_arithmetic(
+                            ArithmeticOp::Multiply,
+                            field("price"),
+                            field("qty")
+                        ),
+                        CompareOp::GreaterThan,
+                        number(100)
+                    )])
+                );
+            }
+            _ => panic!("Expected Query variant"),
+        }
+
+        // 優先順位と左結合を定数の式で確認する
+        for (sql, is_always_false) in [
+            ("select A from t where 1 + 2 * 3 = 7", false),
+            ("select A from t where (1 + 2) * 3 = 7", true),
+            ("select A from t where 10 - 4 - 3 = 3", false),
+            ("select A from t where 10 - 4 - 3 = 9", true),
+            ("select A from t where length('hello') >= 5", false),
+            ("select A from t where 1 / 0 = 1", true),
+            ("select A from t where 'b' between 'a' and 'c'", false),
+            ("select A from t where 'd' between 'a' and 'c'", true),
+            ("select A from t where 5 between 10 and 1", true),
+        ] {
+            let parsed_sql = parse_sql(sql.to_string()).unwrap();
+            match &parsed_sql[0] {
+                ParsedSQL::Query(query_data) => {
+                    assert_eq!(
+                        query_data.predicate.is_always_false(),
+                        is_always_false,
+                        "{}",
+                        sql
+                    );
+                }
+                _ => panic!("Expected Query variant"),
+            }
+        }
+    }
+
     #[test]
     fn test_create_view_sql() {
         let sql = "create view my_view as select A from test_table".to_string();
-        let parsed_sql = parse_sql(sql);
+        let parsed_sql = parse_sql(sql).unwrap();
 
         assert_eq!(
             parsed_sql[0],
             ParsedSQL::CreateView(ViewData::new(
                 "my_view".to_string(),
                 QueryData {
+                    distinct: false,
                     table_name_list: vec!["test_table".to_string()],
+                    values_list: vec![],
+                    all_columns: false,
+                    select_expressions: vec![],
                     field_name_list: vec![TableNameAndFieldName::new(None, "A".to_string())],
                     predicate: PredicateV2::new(vec![]),
                     order_by_list: vec![],
                     group_by_list: vec![],
                     aggregate_functions: vec![],
+                    aggregate_aliases: vec![],
+                    having: None,
+                    limit: None,
+                    offset: None,
                 }
             ))
         );
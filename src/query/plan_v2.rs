@@ -1,46 +1,107 @@
 use std::{cell::RefCell, cmp::min, collections::HashMap, rc::Rc};
 
 use crate::{
-    error::{TableAlreadyExists, ValueNotFound},
-    query::group_by::{AggregateFunction, AggregateFunctionType, AvgFunction, GroupByPlan, MaxFunction},
+    error::{
+        ColumnCountMismatch, DatabaseError, NotNullViolation, TypeMismatch, ValueNotFound,
+        ValueTooLong,
+    },
+    query::group_by::{AggregateFunction, AggregateFunctionType, AvgFunction, CountFunction, GroupByPlan, MaxFunction},
     metadata::index_manager::IndexInfo,
+    query::index_join_plan::IndexJoinPlan,
+    query::json::format_json_string,
+    query::merge_join_plan::MergeJoinPlan,
     query::index_select_plan::IndexSelectPlan,
     metadata::metadata_manager::MetadataManager,
-    query::parser::{parse_sql, CreateTableData, DeleteData, InsertData, QueryData, UpdateData},
-    query::predicate::TableNameAndFieldName,
-    query::predicate_v3::PredicateV2,
-    record::record_page::{Layout, TableSchema},
-    record::scan_v2::{ProductScanV2, ProjectScanV2, ScanV2, SelectScanV2},
-    query::sort_plan::SortPlan,
-    metadata::stat_manager_v2::StatInfoV2,
+    query::parser::{
+        parse_sql, CreateTableData, DeleteData, InsertData, OrderByItem, QueryData,
+        SelectExpression, UpdateData,
+    },
+    query::predicate::{Constant, ConstantValue, ExpressionValue, TableNameAndFieldName},
+    query::predicate_v3::{ExpressionV2, PredicateV2},
+    record::record_page::{Layout, TableFieldType, TableSchema},
+    record::scan_v2::{
+        EmptyScanV2, LimitScanV2, ProductScanV2, ProjectScanV2, ScanV2, SelectScanV2,
+    },
+    query::sort_plan::{SortDirection, SortPlan},
+    query::distinct_plan::DistinctPlan,
+    query::extend_plan::ExtendPlan,
+    query::values_plan::{validate_values, ValuesPlan},
+    metadata::stat_manager_v2::{Histogram, StatInfoV2},
     record::table_scan_v2::TableScan,
+    storage::page::Page,
     tx::transaction_v2::TransactionV2,
 };
 
 pub struct PlanTreeNodeForDebug {
     pub current_node_type: String,
+    pub records_output: u32,
+    pub blocks_accessed: u32,
     pub child_nodes: Vec<PlanTreeNodeForDebug>,
 }
 
 impl PlanTreeNodeForDebug {
     pub fn print_tree(&self) {
-        self.print_tree_inner("", true);
+        print!("{}", self.to_text());
     }
 
-    fn print_tree_inner(&self, prefix: &str, is_last: bool) {
+    // 1行に1つのnodeを、見積もりのrows/blocksと一緒に書く
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        self.to_text_inner("", true, &mut text);
+        return text;
+    }
+
+    fn to_text_inner(&self, prefix: &str, is_last: bool, text: &mut String) {
         let connector = if is_last { "└─ " } else { "├─ " };
-        println!("{}{}{}", prefix, connector, self.current_node_type);
+        text.push_str(&format!(
+            "{}{}{} (rows={}, blocks={})\n",
+            prefix, connector, self.current_node_type, self.records_output, self.blocks_accessed
+        ));
 
         let child_prefix = format!("{}{}", prefix, if is_last { "   " } else { "│  " });
         let len = self.child_nodes.len();
         for (i, child) in self.child_nodes.iter().enumerate() {
-            child.print_tree_inner(&child_prefix, i == len - 1);
+            child.to_text_inner(&child_prefix, i == len - 1, text);
         }
     }
+
+    // explain (format json) 用
+    pub fn to_json(&self) -> String {
+        let child_nodes = self
+            .child_nodes
+            .iter()
+            .map(|child| child.to_json())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        // 条件の文字列が入るので、jsonの文字列としてescapeする
+        format!(
+            "{{\"node_type\":{},\"rows\":{},\"blocks\":{},\"children\":[{}]}}",
+            format_json_string(&self.current_node_type),
+            self.records_output,
+            self.blocks_accessed,
+            child_nodes
+        )
+    }
+}
+
+// explainで表示するfieldの一覧
+pub fn format_field_names(fields: &[TableNameAndFieldName]) -> String {
+    return fields
+        .iter()
+        .map(|field| match &field.table_name {
+            Some(table_name) => format!("{}.{}", table_name, field.field_name),
+            None => field.field_name.clone(),
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
 }
 
 pub trait PlanV2 {
-    fn open(&mut self) -> Result<Box<dyn ScanV2>, ValueNotFound>;
+    // openするたびに最初の行の前に位置する新しいscanを返す。planは変更しないので、
+    // 同じplanを何度openしてもよく、前のscanを閉じる前に次のscanを開いてもよい。
+    // scanを使い終わったら呼び出し側がcloseする
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound>;
     fn get_schema(&self) -> &TableSchema;
 
     fn blocks_accessed(&self) -> u32;
@@ -49,7 +110,17 @@ pub trait PlanV2 {
 
     fn get_distinct_value(&self, field_name: String) -> u32;
 
+    // 選択率の見積もりに使う。行の割合が変わらないplanだけが下のplanのものを返す
+    fn get_histogram(&self, _field_name: String) -> Option<Histogram> {
+        return None;
+    }
+
     fn get_child_plans(&self) -> PlanTreeNodeForDebug;
+
+    // explainの出力。get_child_plansの木をnodeごとに1行で書く
+    fn explain(&self) -> String {
+        return self.get_child_plans().to_text();
+    }
 }
 
 pub struct TablePlanV2 {
@@ -80,15 +151,19 @@ impl TablePlanV2 {
             transaction: transaction.clone(),
         })
     }
-}
 
-impl PlanV2 for TablePlanV2 {
-    fn open(&mut self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
-        return Ok(Box::new(TableScan::new(
+    pub fn open_table_scan(&self) -> TableScan {
+        return TableScan::new(
             self.table_name.clone(),
             self.transaction.clone(),
             self.layout.clone(),
-        )));
+        );
+    }
+}
+
+impl PlanV2 for TablePlanV2 {
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+        return Ok(Box::new(self.open_table_scan()));
     }
 
     fn get_schema(&self) -> &TableSchema {
@@ -107,9 +182,15 @@ impl PlanV2 for TablePlanV2 {
         self.stat_info.distinct_value(field_name)
     }
 
+    fn get_histogram(&self, field_name: String) -> Option<Histogram> {
+        self.stat_info.get_histogram(&field_name).cloned()
+    }
+
     fn get_child_plans(&self) -> PlanTreeNodeForDebug {
         PlanTreeNodeForDebug {
-            current_node_type: "TablePlanV2".to_string(),
+            current_node_type: format!("TablePlanV2({})", self.table_name),
+            records_output: self.records_output(),
+            blocks_accessed: self.blocks_accessed(),
             child_nodes: vec![],
         }
     }
@@ -119,6 +200,8 @@ pub struct SelectPlanV2 {
     // Fields for the plan
     table_plan: Box<dyn PlanV2>,
     predicate: PredicateV2,
+    // predicateの in (select ...) のplanと、値を読むfield。openするまで実行しない
+    subquery_plans: Vec<(TableNameAndFieldName, Box<dyn PlanV2>)>,
 }
 
 impl SelectPlanV2 {
@@ -126,14 +209,69 @@ impl SelectPlanV2 {
         SelectPlanV2 {
             table_plan,
             predicate,
+            subquery_plans: vec![],
+        }
+    }
+
+    // in (select ...) があれば、サブクエリのplanも作っておく
+    // explainやvalidateはplanを作るだけなので、サブクエリは実行されない
+    pub fn new_with_subqueries(
+        table_plan: Box<dyn PlanV2>,
+        predicate: PredicateV2,
+        transaction: Rc<RefCell<TransactionV2>>,
+        metadata_manager: &mut MetadataManager,
+    ) -> Result<Self, ValueNotFound> {
+        let mut subquery_plans = Vec::new();
+        for query_data in predicate.subqueries().iter() {
+            let field = match query_data.field_name_list.first() {
+                Some(field) => field.clone(),
+                None => return Err(ValueNotFound::new("subquery field".to_string(), None)),
+            };
+            let plan = create_query_plan(query_data, transaction.clone(), metadata_manager)?;
+            subquery_plans.push((field, plan));
+        }
+
+        return Ok(SelectPlanV2 {
+            table_plan,
+            predicate,
+            subquery_plans,
+        });
+    }
+
+    // サブクエリを実行して、in (select ...) を値のリストに置き換える
+    fn resolve_subqueries(&self) -> Result<PredicateV2, ValueNotFound> {
+        let mut values_list = Vec::new();
+        for (field, plan) in self.subquery_plans.iter() {
+            let mut scan = plan.open()?;
+            let mut values = Vec::new();
+            while scan.next()? {
+                match scan.get_value(field.clone()) {
+                    Some(value) => values.push(Constant::new(value)),
+                    None => {
+                        scan.close();
+                        return Err(ValueNotFound::new(
+                            field.field_name.clone(),
+                            field.table_name.clone(),
+                        ));
+                    }
+                }
+            }
+            scan.close();
+            values_list.push(values);
         }
+
+        let mut values_list = values_list.into_iter();
+        return self
+            .predicate
+            .resolve_subqueries(&mut |_| Ok(values_list.next().unwrap_or_default()));
     }
 }
 
 impl PlanV2 for SelectPlanV2 {
-    fn open(&mut self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+        let predicate = self.resolve_subqueries()?;
         let scan = self.table_plan.open()?;
-        return Ok(Box::new(SelectScanV2::new(scan, self.predicate.clone())));
+        return Ok(Box::new(SelectScanV2::new(scan, predicate)));
     }
 
     fn get_schema(&self) -> &TableSchema {
@@ -170,10 +308,118 @@ impl PlanV2 for SelectPlanV2 {
         self.table_plan.records_output() / self.predicate.reduction_factor(self.table_plan.as_ref())
     }
 
+    fn get_child_plans(&self) -> PlanTreeNodeForDebug {
+        // サブクエリのplanも子として表示する
+        let mut child_nodes = vec![self.table_plan.get_child_plans()];
+        for (_, plan) in self.subquery_plans.iter() {
+            child_nodes.push(plan.get_child_plans());
+        }
+        PlanTreeNodeForDebug {
+            current_node_type: format!("SelectPlanV2({})", self.predicate),
+            records_output: self.records_output(),
+            blocks_accessed: self.blocks_accessed(),
+            child_nodes,
+        }
+    }
+}
+
+// 述語が常に偽のときに使う。下位のplanは開かない
+pub struct EmptyPlanV2 {
+    schema: TableSchema,
+}
+
+impl EmptyPlanV2 {
+    pub fn new(schema: TableSchema) -> Self {
+        EmptyPlanV2 { schema }
+    }
+}
+
+impl PlanV2 for EmptyPlanV2 {
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+        return Ok(Box::new(EmptyScanV2::new(self.schema.clone())));
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    fn blocks_accessed(&self) -> u32 {
+        0
+    }
+
+    fn records_output(&self) -> u32 {
+        0
+    }
+
+    fn get_distinct_value(&self, _field_name: String) -> u32 {
+        0
+    }
+
+    fn get_child_plans(&self) -> PlanTreeNodeForDebug {
+        PlanTreeNodeForDebug {
+            current_node_type: "EmptyPlanV2".to_string(),
+            records_output: self.records_output(),
+            blocks_accessed: self.blocks_accessed(),
+            child_nodes: vec![],
+        }
+    }
+}
+
+// limit/offsetはsortやgroup byの結果に対してかける
+pub struct LimitPlanV2 {
+    plan: Box<dyn PlanV2>,
+    limit: Option<usize>,
+    offset: usize,
+}
+
+impl LimitPlanV2 {
+    pub fn new(plan: Box<dyn PlanV2>, limit: Option<usize>, offset: usize) -> Self {
+        LimitPlanV2 {
+            plan,
+            limit,
+            offset,
+        }
+    }
+}
+
+impl PlanV2 for LimitPlanV2 {
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+        let scan = self.plan.open()?;
+        return Ok(Box::new(LimitScanV2::new(scan, self.limit, self.offset)));
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        self.plan.get_schema()
+    }
+
+    fn blocks_accessed(&self) -> u32 {
+        self.plan.blocks_accessed()
+    }
+
+    fn records_output(&self) -> u32 {
+        let records = (self.plan.records_output() as usize).saturating_sub(self.offset);
+        match self.limit {
+            Some(limit) => return min(records, limit) as u32,
+            None => return records as u32,
+        }
+    }
+
+    fn get_distinct_value(&self, field_name: String) -> u32 {
+        min(
+            self.plan.get_distinct_value(field_name),
+            self.records_output(),
+        )
+    }
+
     fn get_child_plans(&self) -> PlanTreeNodeForDebug {
         PlanTreeNodeForDebug {
-            current_node_type: "SelectPlanV2".to_string(),
-            child_nodes: vec![self.table_plan.get_child_plans()],
+            current_node_type: match self.limit {
+                Some(limit) => format!("LimitPlanV2(limit {}, offset {})", limit, self.offset),
+                None => format!("LimitPlanV2(offset {})", self.offset),
+            },
+            records_output: self.records_output(),
+            blocks_accessed: self.blocks_accessed(),
+            child_nodes: vec![self.plan.get_child_plans()],
         }
     }
 }
@@ -202,7 +448,7 @@ impl ProjectPlanV2 {
 }
 
 impl PlanV2 for ProjectPlanV2 {
-    fn open(&mut self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
         let scan = self.plan.open()?;
         return Ok(Box::new(ProjectScanV2::new(scan, self.fields.clone())));
     }
@@ -225,13 +471,15 @@ impl PlanV2 for ProjectPlanV2 {
 
     fn get_child_plans(&self) -> PlanTreeNodeForDebug {
         PlanTreeNodeForDebug {
-            current_node_type: "ProjectPlanV2".to_string(),
+            current_node_type: format!("ProjectPlanV2({})", format_field_names(&self.fields)),
+            records_output: self.records_output(),
+            blocks_accessed: self.blocks_accessed(),
             child_nodes: vec![self.plan.get_child_plans()],
         }
     }
 }
 
-struct ProductPlanV2 {
+pub struct ProductPlanV2 {
     // Fields for the plan
     left_plan: Box<dyn PlanV2>,
     right_plan: Box<dyn PlanV2>,
@@ -251,23 +499,24 @@ impl ProductPlanV2 {
         }
     }
 
-    fn block_accessed(left_plan: &Box<dyn PlanV2>, right_plan: &Box<dyn PlanV2>) -> u32 {
-        println!(
-            "Calculating block accessed for ProductPlanV2: left_plan blocks_accessed = {}, left_plan records_output = {}, right_plan blocks_accessed = {}",
-            left_plan.blocks_accessed(),
-            left_plan.records_output(),
-            right_plan.blocks_accessed()
-        );
-
-        left_plan.blocks_accessed() + left_plan.records_output() * right_plan.blocks_accessed()
+    // 左の行ごとに右のplanを読み直す。planを作る前に、plannerがjoinの仕方を比べるときにも使う
+    fn block_accessed(left_plan: &dyn PlanV2, right_plan: &dyn PlanV2) -> u32 {
+        left_plan.blocks_accessed().saturating_add(
+            left_plan
+                .records_output()
+                .saturating_mul(right_plan.blocks_accessed()),
+        )
     }
 }
 
 impl PlanV2 for ProductPlanV2 {
-    fn open(&mut self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
         let scan1 = self.left_plan.open()?;
         let scan2 = self.right_plan.open()?;
-        return Ok(Box::new(ProductScanV2::new(scan1, scan2)));
+        // left_scanを最初の行に進めておかないと、右の行と組み合わせられない
+        let mut scan = ProductScanV2::new(scan1, scan2);
+        scan.move_to_before_first()?;
+        return Ok(Box::new(scan));
     }
 
     fn get_schema(&self) -> &TableSchema {
@@ -275,12 +524,13 @@ impl PlanV2 for ProductPlanV2 {
     }
 
     fn blocks_accessed(&self) -> u32 {
-        self.left_plan.blocks_accessed()
-            + self.left_plan.records_output() * self.right_plan.blocks_accessed()
+        Self::block_accessed(self.left_plan.as_ref(), self.right_plan.as_ref())
     }
 
     fn records_output(&self) -> u32 {
-        self.left_plan.records_output() * self.right_plan.records_output()
+        self.left_plan
+            .records_output()
+            .saturating_mul(self.right_plan.records_output())
     }
 
     fn get_distinct_value(&self, field_name: String) -> u32 {
@@ -291,9 +541,18 @@ impl PlanV2 for ProductPlanV2 {
         }
     }
 
+    fn get_histogram(&self, field_name: String) -> Option<Histogram> {
+        if self.left_plan.get_schema().has_field(field_name.clone()) {
+            return self.left_plan.get_histogram(field_name);
+        }
+        return self.right_plan.get_histogram(field_name);
+    }
+
     fn get_child_plans(&self) -> PlanTreeNodeForDebug {
         PlanTreeNodeForDebug {
             current_node_type: "ProductPlanV2".to_string(),
+            records_output: self.records_output(),
+            blocks_accessed: self.blocks_accessed(),
             child_nodes: vec![
                 self.left_plan.get_child_plans(),
                 self.right_plan.get_child_plans(),
@@ -316,13 +575,270 @@ fn create_index_select(
     return table_plan;
 }
 
+// 2つのplanのjoinの仕方
+enum JoinMethod {
+    // 右のtableのindexを、左の行ごとにleft_fieldの値で引く
+    Index {
+        table_name: String,
+        index_field: String,
+        left_field: TableNameAndFieldName,
+    },
+    Merge {
+        left_field: TableNameAndFieldName,
+        right_field: TableNameAndFieldName,
+    },
+    // swappedのときは右のplanを外側のloopにする
+    Product { swapped: bool },
+}
+
+struct JoinEstimate {
+    method: JoinMethod,
+    blocks_accessed: u32,
+    records_output: u32,
+}
+
+// joinしたplanのfieldと、まだjoinしていないplanのfieldが等しいという条件の組
+fn join_fields(
+    left: (&[String], &dyn PlanV2),
+    right: (&[String], &dyn PlanV2),
+    predicate: &PredicateV2,
+) -> Vec<(TableNameAndFieldName, TableNameAndFieldName)> {
+    let mut fields = vec![];
+    for (lhs, rhs) in predicate.equated_fields() {
+        for (left_field, right_field) in [(lhs.clone(), rhs.clone()), (rhs.clone(), lhs.clone())] {
+            if plan_has_field(left.0, left.1, &left_field)
+                && plan_has_field(right.0, right.1, &right_field)
+            {
+                fields.push((left_field, right_field));
+            }
+        }
+    }
+    return fields;
+}
+
+// productと、使えるならindex joinとmerge joinを比べて、一番読むblockの少ないものを選ぶ
+fn estimate_join(
+    left: (&[String], &dyn PlanV2),
+    right: (&[String], &dyn PlanV2),
+    index_infos: &HashMap<String, HashMap<String, IndexInfo>>,
+    predicate: &PredicateV2,
+) -> JoinEstimate {
+    let join_fields = join_fields(left, right, predicate);
+    // productの行はjoinの条件で後から絞られるので、他のjoinと同じ数で比べる
+    let mut product_records = left.1.records_output().saturating_mul(right.1.records_output());
+    for (left_field, right_field) in join_fields.iter() {
+        let distinct_values = left
+            .1
+            .get_distinct_value(left_field.field_name.clone())
+            .max(right.1.get_distinct_value(right_field.field_name.clone()))
+            .max(1);
+        product_records /= distinct_values;
+    }
+    let mut best = JoinEstimate {
+        method: JoinMethod::Product { swapped: false },
+        blocks_accessed: ProductPlanV2::block_accessed(left.1, right.1),
+        records_output: product_records,
+    };
+    let mut candidates = vec![JoinEstimate {
+        method: JoinMethod::Product { swapped: true },
+        blocks_accessed: ProductPlanV2::block_accessed(right.1, left.1),
+        records_output: product_records,
+    }];
+
+    for (left_field, right_field) in join_fields {
+        // index joinの右側は、index selectを使っていない1つのtableだけ
+        let index_info = match right.0 {
+            [table_name] => index_infos
+                .get(table_name)
+                .and_then(|infos| infos.get(&right_field.field_name)),
+            _ => None,
+        };
+        if let Some(index_info) = index_info {
+            candidates.push(JoinEstimate {
+                method: JoinMethod::Index {
+                    table_name: right.0[0].clone(),
+                    index_field: right_field.field_name.clone(),
+                    left_field: left_field.clone(),
+                },
+                blocks_accessed: IndexJoinPlan::estimate_blocks_accessed(left.1, index_info),
+                records_output: IndexJoinPlan::estimate_records_output(left.1, index_info),
+            });
+        }
+        candidates.push(JoinEstimate {
+            blocks_accessed: MergeJoinPlan::estimate_blocks_accessed(left.1, right.1),
+            records_output: MergeJoinPlan::estimate_records_output(
+                left.1,
+                &left_field,
+                right.1,
+                &right_field,
+            ),
+            method: JoinMethod::Merge {
+                left_field,
+                right_field,
+            },
+        });
+    }
+
+    for candidate in candidates {
+        if candidate.blocks_accessed < best.blocks_accessed {
+            best = candidate;
+        }
+    }
+    return best;
+}
+
+// left-deepにjoinの順番を決める
+// 最初の2つはすべての組から、その後は残りのplanから1つずつ、joinした結果の行が
+// 一番少なくなるもの (同じならblockを読む数が少ないもの) を選んで足していく
+fn create_join_plan(
+    mut plans: Vec<(Vec<String>, Box<dyn PlanV2>)>,
+    mut index_infos: HashMap<String, HashMap<String, IndexInfo>>,
+    predicate: &PredicateV2,
+    transaction: Rc<RefCell<TransactionV2>>,
+    metadata_manager: &mut MetadataManager,
+) -> Result<Box<dyn PlanV2>, ValueNotFound> {
+    if plans.len() == 1 {
+        return Ok(plans.pop().unwrap().1);
+    }
+
+    let mut first_pair: Option<(usize, usize, JoinEstimate)> = None;
+    for (left_position, (left_table_names, left_plan)) in plans.iter().enumerate() {
+        for (right_position, (right_table_names, right_plan)) in plans.iter().enumerate() {
+            if left_position == right_position {
+                continue;
+            }
+            let estimate = estimate_join(
+                (left_table_names, left_plan.as_ref()),
+                (right_table_names, right_plan.as_ref()),
+                &index_infos,
+                predicate,
+            );
+            let is_cheaper = first_pair.as_ref().map_or(true, |(_, _, best)| {
+                (estimate.records_output, estimate.blocks_accessed)
+                    < (best.records_output, best.blocks_accessed)
+            });
+            if is_cheaper {
+                first_pair = Some((left_position, right_position, estimate));
+            }
+        }
+    }
+    let (left_position, right_position, estimate) = first_pair.unwrap();
+    let (mut table_names, left_plan) = plans.remove(left_position);
+    let right_position = if right_position > left_position {
+        right_position - 1
+    } else {
+        right_position
+    };
+    let (right_table_names, right_plan) = plans.remove(right_position);
+    let mut plan = create_join(
+        (&table_names, left_plan),
+        (&right_table_names, right_plan),
+        estimate.method,
+        &mut index_infos,
+        transaction.clone(),
+        metadata_manager,
+    )?;
+    table_names.extend(right_table_names);
+
+    while !plans.is_empty() {
+        let (position, estimate) = plans
+            .iter()
+            .enumerate()
+            .map(|(position, (right_table_names, right_plan))| {
+                let estimate = estimate_join(
+                    (&table_names, plan.as_ref()),
+                    (right_table_names, right_plan.as_ref()),
+                    &index_infos,
+                    predicate,
+                );
+                (position, estimate)
+            })
+            .min_by_key(|(_, estimate)| (estimate.records_output, estimate.blocks_accessed))
+            .unwrap();
+
+        let (right_table_names, right_plan) = plans.remove(position);
+        plan = create_join(
+            (&table_names, plan),
+            (&right_table_names, right_plan),
+            estimate.method,
+            &mut index_infos,
+            transaction.clone(),
+            metadata_manager,
+        )?;
+        table_names.extend(right_table_names);
+    }
+    return Ok(plan);
+}
+
+fn create_join(
+    left: (&[String], Box<dyn PlanV2>),
+    right: (&[String], Box<dyn PlanV2>),
+    method: JoinMethod,
+    index_infos: &mut HashMap<String, HashMap<String, IndexInfo>>,
+    transaction: Rc<RefCell<TransactionV2>>,
+    metadata_manager: &mut MetadataManager,
+) -> Result<Box<dyn PlanV2>, ValueNotFound> {
+    let (left_table_names, left_plan) = left;
+    let (right_table_names, right_plan) = right;
+    match method {
+        JoinMethod::Index {
+            table_name,
+            index_field,
+            left_field,
+        } => {
+            let index_info = index_infos
+                .get_mut(&table_name)
+                .and_then(|infos| infos.remove(&index_field))
+                .unwrap();
+            // 右のtableの元のplanはIndexJoinPlanの中で読み直すので使わない
+            let right_plan = TablePlanV2::new(table_name, transaction, metadata_manager)?;
+            return Ok(Box::new(IndexJoinPlan::new(
+                left_plan, right_plan, index_info, left_field,
+            )));
+        }
+        JoinMethod::Merge {
+            left_field,
+            right_field,
+        } => {
+            return Ok(Box::new(MergeJoinPlan::new(
+                transaction,
+                left_plan,
+                left_table_names.to_vec(),
+                right_plan,
+                right_table_names.to_vec(),
+                left_field,
+                right_field,
+            )));
+        }
+        JoinMethod::Product { swapped: false } => {
+            return Ok(Box::new(ProductPlanV2::new(left_plan, right_plan)));
+        }
+        JoinMethod::Product { swapped: true } => {
+            return Ok(Box::new(ProductPlanV2::new(right_plan, left_plan)));
+        }
+    }
+}
+
+fn plan_has_field(
+    table_names: &[String],
+    plan: &dyn PlanV2,
+    field_name: &TableNameAndFieldName,
+) -> bool {
+    if let Some(table_name) = &field_name.table_name {
+        if !table_names.contains(table_name) {
+            return false;
+        }
+    }
+    return plan.get_schema().has_field(field_name.field_name.clone());
+}
+
 pub fn get_optimized_product_plan(plans: &mut Vec<Box<dyn PlanV2>>) -> Box<dyn PlanV2> {
     let mut plan: Box<dyn PlanV2> = plans.pop().unwrap();
 
     //TODO: productの順番を最適化する
     for next_plan in plans.drain(..) {
-        let block_access_1 = ProductPlanV2::block_accessed(&plan, &next_plan);
-        let block_access_2 = ProductPlanV2::block_accessed(&next_plan, &plan);
+        let block_access_1 = ProductPlanV2::block_accessed(plan.as_ref(), next_plan.as_ref());
+        let block_access_2 = ProductPlanV2::block_accessed(next_plan.as_ref(), plan.as_ref());
 
         if block_access_1 < block_access_2 {
             let product_plan = ProductPlanV2::new(plan, next_plan);
@@ -335,25 +851,119 @@ pub fn get_optimized_product_plan(plans: &mut Vec<Box<dyn PlanV2>>) -> Box<dyn P
     return plan;
 }
 
+// order by 1 のような位置指定を select list のフィールドに置き換える
+fn resolve_order_by_list(
+    query_data: &QueryData,
+) -> Result<Vec<(TableNameAndFieldName, SortDirection)>, ValueNotFound> {
+    let mut sort_fields = Vec::new();
+
+    for (order_by, direction) in query_data.order_by_list.iter() {
+        match order_by {
+            OrderByItem::Field(field) => sort_fields.push((field.clone(), *direction)),
+            OrderByItem::Position(position) => {
+                if *position == 0 || *position > query_data.field_name_list.len() {
+                    return Err(ValueNotFound::new(
+                        format!("order by position {}", position),
+                        None,
+                    ));
+                }
+                sort_fields.push((query_data.field_name_list[position - 1].clone(), *direction));
+            }
+        }
+    }
+
+    return Ok(sort_fields);
+}
+
 pub fn create_query_plan(
     query_data: &QueryData,
     transaction: Rc<RefCell<TransactionV2>>,
     metadata_manager: &mut MetadataManager,
 ) -> Result<Box<dyn PlanV2>, ValueNotFound> {
-    let mut plans: Vec<Box<dyn PlanV2>> = Vec::new();
+    let query_data = &expand_table_stars(query_data, transaction.clone(), metadata_manager)?;
+    let plan = create_unlimited_query_plan(query_data, transaction, metadata_manager)?;
+
+    if query_data.limit.is_none() && query_data.offset.is_none() {
+        return Ok(plan);
+    }
+    return Ok(Box::new(LimitPlanV2::new(
+        plan,
+        query_data.limit,
+        query_data.offset.unwrap_or(0),
+    )));
+}
+
+// select list の t.* を、tのfieldの一覧に置き換える
+// tはfrom句のtableかvaluesの別名
+pub fn expand_table_stars(
+    query_data: &QueryData,
+    transaction: Rc<RefCell<TransactionV2>>,
+    metadata_manager: &mut MetadataManager,
+) -> Result<QueryData, ValueNotFound> {
+    let mut field_name_list = Vec::new();
+    for field_name in query_data.field_name_list.iter() {
+        let Some(table_name) = &field_name.table_name else {
+            field_name_list.push(field_name.clone());
+            continue;
+        };
+        if field_name.field_name != QueryData::ALL_FIELDS {
+            field_name_list.push(field_name.clone());
+            continue;
+        }
+
+        let fields = if let Some(values_data) = query_data
+            .values_list
+            .iter()
+            .find(|values_data| &values_data.alias == table_name)
+        {
+            values_data.column_names.clone()
+        } else if query_data.table_name_list.contains(table_name) {
+            metadata_manager
+                .get_layout(table_name.clone(), transaction.clone())?
+                .schema
+                .fields
+        } else {
+            return Err(ValueNotFound::new(
+                field_name.field_name.clone(),
+                Some(table_name.clone()),
+            ));
+        };
+        for field in fields {
+            field_name_list.push(TableNameAndFieldName::new(Some(table_name.clone()), field));
+        }
+    }
+
+    let mut expanded_query_data = query_data.clone();
+    expanded_query_data.field_name_list = field_name_list;
+    return Ok(expanded_query_data);
+}
+
+fn create_unlimited_query_plan(
+    query_data: &QueryData,
+    transaction: Rc<RefCell<TransactionV2>>,
+    metadata_manager: &mut MetadataManager,
+) -> Result<Box<dyn PlanV2>, ValueNotFound> {
+    // planと、そのplanが読むtable名
+    let mut plans: Vec<(Vec<String>, Box<dyn PlanV2>)> = Vec::new();
+    // index selectに使わなかったindex。joinの右側に使えることがある
+    let mut join_index_infos: HashMap<String, HashMap<String, IndexInfo>> = HashMap::new();
+
+    let predicate = query_data.predicate.clone();
 
     for table_name in query_data.table_name_list.iter() {
         let view_definition =
             metadata_manager.get_view_definition(table_name.clone(), transaction.clone());
 
         if let Some(view_def) = view_definition {
-            let parsed_sql_list = parse_sql(view_def.clone());
+            // view定義はcreate viewのときに一度parseできている
+            let parsed_sql_list =
+                parse_sql(view_def.clone()).expect("view definition should be valid sql");
             let parsed_sql = &parsed_sql_list[0];
 
             match parsed_sql {
                 crate::query::parser::ParsedSQL::Query(q) => {
                     let view_plan = create_query_plan(q, transaction.clone(), metadata_manager)?;
-                    plans.push(view_plan);
+                    plans.push((vec![table_name.clone()], view_plan));
                     continue;
                 }
                 _ => panic!("Expected a Query variant from parse_sql for view definition"),
@@ -369,51 +979,80 @@ pub fn create_query_plan(
 
         match index_info_list {
             Err(_) => {
-                plans.push(plan);
+                plans.push((vec![table_name.clone()], plan));
                 continue;
             }
             Ok(info) => {
-                let index_select_plan =
-                    create_index_select(info.clone(), query_data.predicate.clone(), plan);
+                let uses_index_select = info.keys().any(|field_name| {
+                    predicate
+                        .equates_with_constant(field_name.clone())
+                        .is_some()
+                });
+                if !uses_index_select {
+                    join_index_infos.insert(table_name.clone(), info.clone());
+                }
+                let index_select_plan = create_index_select(info.clone(), predicate.clone(), plan);
 
-                plans.push(index_select_plan);
+                plans.push((vec![table_name.clone()], index_select_plan));
             }
         };
     }
 
-    // //TODO: productの順番を最適化する
-    // for next_plan in plans.into_iter() {
-    //     let block_access_1 = ProductPlanV2::block_accessed(&plan, &next_plan);
-    //     let block_access_2 = ProductPlanV2::block_accessed(&next_plan, &plan);
-
-    //     if block_access_1 < block_access_2 {
-    //         let product_plan = ProductPlanV2::new(plan, next_plan);
-    //         plan = Box::new(product_plan);
-    //     } else {
-    //         let product_plan_2 = ProductPlanV2::new(next_plan, plan);
-    //         plan = Box::new(product_plan_2);
-    //     }
-    // }
+    for values_data in query_data.values_list.iter() {
+        plans.push((
+            vec![values_data.alias.clone()],
+            Box::new(ValuesPlan::new(values_data)?),
+        ));
+    }
 
-    let optimized_plan = get_optimized_product_plan(&mut plans);
+    let optimized_plan = create_join_plan(
+        plans,
+        join_index_infos,
+        &predicate,
+        transaction.clone(),
+        metadata_manager,
+    )?;
 
-    let select_plan = SelectPlanV2::new(optimized_plan, query_data.predicate.clone());
+    let select_plan: Box<dyn PlanV2> = if predicate.is_always_false() {
+        Box::new(EmptyPlanV2::new(optimized_plan.get_schema().clone()))
+    } else {
+        Box::new(SelectPlanV2::new_with_subqueries(
+            optimized_plan,
+            predicate,
+            transaction.clone(),
+            metadata_manager,
+        )?)
+    };
 
     // let project_plan =
     //     ProjectPlanV2::new(Box::new(select_plan), query_data.field_name_list.clone());
 
-    if query_data.order_by_list.len() > 0 {
-        let sort_plan = SortPlan::new(
-            transaction.clone(),
-            Box::new(select_plan),
-            query_data.order_by_list.clone(),
-        );
-        return Ok(Box::new(sort_plan));
-    }
+    // order byで別名を使えるように、sortの前に式のfieldを足しておく
+    let select_plan: Box<dyn PlanV2> = if query_data.select_expressions.is_empty() {
+        select_plan
+    } else {
+        Box::new(ExtendPlan::new(
+            select_plan,
+            query_data.select_expressions.clone(),
+        ))
+    };
 
-    if query_data.group_by_list.len() > 0 {
-        let max_aggregate_functions = query_data
-            .aggregate_functions
+    // group byが無くても、集約関数かhavingがあればtable全体を1つのgroupにする
+    let mut plan = select_plan;
+    if query_data.group_by_list.len() > 0
+        || query_data.aggregate_functions.len() > 0
+        || query_data.having.is_some()
+    {
+        let mut aggregate_function_infos = query_data.aggregate_functions.clone();
+        if let Some(having) = &query_data.having {
+            for aggregate_function in having.aggregate_functions.iter() {
+                if !aggregate_function_infos.contains(aggregate_function) {
+                    aggregate_function_infos.push(aggregate_function.clone());
+                }
+            }
+        }
+
+        let max_aggregate_functions = aggregate_function_infos
             .iter()
             // TODO: 現状はmax関数のみ対応しているが、将来的には他の集約関数も対応する必要がある
             .map(|f| match f.function_type {
@@ -427,6 +1066,17 @@ pub fn create_query_plan(
                     Box::new(crate::query::group_by::SumFunction::new(f.field.clone()))
                         as Box<dyn AggregateFunction>
                 }
+                AggregateFunctionType::Min => {
+                    Box::new(crate::query::group_by::MinFunction::new(f.field.clone()))
+                        as Box<dyn AggregateFunction>
+                }
+                AggregateFunctionType::Count => {
+                    Box::new(crate::query::group_by::CountFunction::new(f.field.clone()))
+                        as Box<dyn AggregateFunction>
+                }
+                AggregateFunctionType::CountDistinct => Box::new(
+                    crate::query::group_by::CountDistinctFunction::new(f.field.clone()),
+                ) as Box<dyn AggregateFunction>,
             })
             .collect::<Vec<Box<dyn AggregateFunction>>>();
 
@@ -434,41 +1084,241 @@ pub fn create_query_plan(
             transaction.clone(),
             query_data.group_by_list.clone(),
             Rc::new(RefCell::new(max_aggregate_functions)),
-            Box::new(select_plan),
+            plan,
         );
-        return Ok(Box::new(group_by_plan));
-    }
 
-    return Ok(Box::new(select_plan));
-}
+        plan = match &query_data.having {
+            Some(having) => Box::new(SelectPlanV2::new(
+                Box::new(group_by_plan),
+                having.predicate.clone(),
+            )),
+            None => Box::new(group_by_plan),
+        };
 
-pub fn execute_insert(
-    transaction: Rc<RefCell<TransactionV2>>,
-    metadata_manager: &mut MetadataManager,
-    insert_data: InsertData,
-) -> Result<(), ValueNotFound> {
-    let mut plan = TablePlanV2::new(
-        insert_data.table_name.clone(),
-        transaction,
-        metadata_manager,
-    )?;
+        // 集約した後のschemaには別名のfieldが無いので、ここで足し直す
+        // select A as id, max(B) as maxb ... group by A のidとmaxbになる
+        let mut aliased_expressions = query_data
+            .select_expressions
+            .iter()
+            .filter(|select_expression| {
+                !plan.get_schema().has_field(select_expression.alias.clone())
+                    && select_expression
+                        .expression
+                        .can_apply_to(plan.get_schema().clone())
+            })
+            .cloned()
+            .collect::<Vec<SelectExpression>>();
+        for (index, aggregate_function) in query_data.aggregate_functions.iter().enumerate() {
+            let Some(Some(alias)) = query_data.aggregate_aliases.get(index) else {
+                continue;
+            };
+            aliased_expressions.push(SelectExpression {
+                alias: alias.clone(),
+                expression: ExpressionV2::new(ExpressionValue::TableNameAndFieldName(
+                    TableNameAndFieldName::new(None, aggregate_function.get_field_name()),
+                )),
+            });
+        }
+        if !aliased_expressions.is_empty() {
+            plan = Box::new(ExtendPlan::new(plan, aliased_expressions));
+        }
 
-    let mut scan = plan.open()?;
+        // group byに無いfieldはgroupの中のどの行の値か決まらないので、scanを開く前に弾く
+        for field_name in query_data.field_name_list.iter() {
+            if !plan.get_schema().has_field(field_name.field_name.clone()) {
+                return Err(ValueNotFound::new(
+                    field_name.field_name.clone(),
+                    field_name.table_name.clone(),
+                ));
+            }
+        }
 
-    scan.insert();
+        // havingでだけ使った集約関数はselect listに無いので、出力のfieldから外す
+        let select_field_names = query_data
+            .aggregate_functions
+            .iter()
+            .map(|aggregate_function| aggregate_function.get_field_name())
+            .collect::<Vec<String>>();
+        let having_only_field_names = aggregate_function_infos
+            [query_data.aggregate_functions.len()..]
+            .iter()
+            .map(|aggregate_function| aggregate_function.get_field_name())
+            .filter(|field_name| !select_field_names.contains(field_name))
+            .collect::<Vec<String>>();
+        if !having_only_field_names.is_empty() {
+            let fields = plan
+                .get_schema()
+                .fields
+                .iter()
+                .filter(|field_name| !having_only_field_names.contains(field_name))
+                .map(|field_name| TableNameAndFieldName::new(None, field_name.clone()))
+                .collect::<Vec<TableNameAndFieldName>>();
+            plan = Box::new(ProjectPlanV2::new(plan, fields));
+        }
+    }
 
-    let mut val_inter = insert_data.value_list.iter();
+    // 重複はselect listのfieldの値で取り除く。select * ならすべてのfield
+    if query_data.distinct {
+        let fields = if query_data.all_columns {
+            plan.get_schema().fields.clone()
+        } else {
+            let mut fields = query_data
+                .field_name_list
+                .iter()
+                .map(|field_name| field_name.field_name.clone())
+                .collect::<Vec<String>>();
+            for index in 0..query_data.aggregate_functions.len() {
+                fields.push(query_data.aggregate_output_name(index));
+            }
+            fields
+        };
+        plan = Box::new(DistinctPlan::new(transaction.clone(), plan, fields));
+    }
 
-    for field in insert_data.field_name_list.iter() {
-        let value = val_inter.next().unwrap();
-        scan.set_value(field.clone(), value.value.clone());
+    // order byは集約した後の行を並べ替える
+    if query_data.order_by_list.len() > 0 {
+        let sort_fields = resolve_order_by_list(query_data)?;
+        let sort_plan = SortPlan::new(transaction.clone(), plan, sort_fields);
+        plan = Box::new(sort_plan);
     }
 
-    scan.close();
+    return Ok(plan);
+}
+
+// insert先に書き込む行の一覧。insert ... select の場合はselectの結果を位置で対応させる
+// insert into t values (...) のようにcolumnを省略したときは、tableのfieldを定義した順に使う
+pub fn expand_insert_fields(
+    insert_data: &InsertData,
+    transaction: Rc<RefCell<TransactionV2>>,
+    metadata_manager: &mut MetadataManager,
+) -> Result<InsertData, DatabaseError> {
+    let mut insert_data = insert_data.clone();
+    if insert_data.field_name_list.is_empty() {
+        let layout = metadata_manager.get_layout(insert_data.table_name.clone(), transaction)?;
+        insert_data.field_name_list = layout.schema.fields.clone();
+    }
+    return Ok(insert_data);
+}
+
+pub fn collect_insert_rows(
+    transaction: Rc<RefCell<TransactionV2>>,
+    metadata_manager: &mut MetadataManager,
+    insert_data: &InsertData,
+) -> Result<Vec<Vec<Constant>>, DatabaseError> {
+    let layout =
+        metadata_manager.get_layout(insert_data.table_name.clone(), transaction.clone())?;
+
+    let value_count = match &insert_data.select_query {
+        Some(query_data) => query_data.field_name_list.len(),
+        None => insert_data.value_list.len(),
+    };
+    if value_count != insert_data.field_name_list.len() {
+        return Err(DatabaseError::ColumnCountMismatch(
+            ColumnCountMismatch::new(
+                insert_data.table_name.clone(),
+                insert_data.field_name_list.len(),
+                value_count,
+            ),
+        ));
+    }
+
+    let rows = match &insert_data.select_query {
+        None => vec![insert_data.value_list.clone()],
+        Some(query_data) => {
+            // 同じtableからselectする場合に備えて、書き込む前にすべての行を読み切る
+            let plan = create_query_plan(query_data, transaction.clone(), metadata_manager)?;
+            let mut scan = plan.open()?;
+
+            let mut rows = Vec::new();
+            while scan.next()? {
+                let mut row = Vec::new();
+                for field in query_data.field_name_list.iter() {
+                    match scan.get_value(field.clone()) {
+                        Some(value) => row.push(Constant::new(value)),
+                        None => {
+                            return Err(DatabaseError::ValueNotFound(ValueNotFound::new(
+                                field.field_name.clone(),
+                                field.table_name.clone(),
+                            )))
+                        }
+                    }
+                }
+                rows.push(row);
+            }
+            scan.close();
+
+            rows
+        }
+    };
+
+    for row in rows.iter() {
+        for (field_name, value) in insert_data.field_name_list.iter().zip(row.iter()) {
+            validate_value_type(&insert_data.table_name, &layout.schema, field_name, value)?;
+        }
+    }
+
+    return Ok(rows);
+}
+
+pub fn execute_insert(
+    transaction: Rc<RefCell<TransactionV2>>,
+    metadata_manager: &mut MetadataManager,
+    insert_data: InsertData,
+) -> Result<(), DatabaseError> {
+    let insert_data = expand_insert_fields(&insert_data, transaction.clone(), metadata_manager)?;
+    let rows = collect_insert_rows(transaction.clone(), metadata_manager, &insert_data)?;
+
+    let plan = TablePlanV2::new(
+        insert_data.table_name.clone(),
+        transaction,
+        metadata_manager,
+    )?;
+    let (field_name_list, rows) = apply_field_defaults(
+        &insert_data.table_name,
+        plan.get_schema(),
+        &insert_data.field_name_list,
+        rows,
+    )?;
+
+    let mut scan = plan.open()?;
+
+    for row in rows.iter() {
+        scan.insert();
+
+        for (field, value) in field_name_list.iter().zip(row.iter()) {
+            scan.set_value(field.clone(), value.value.clone());
+        }
+    }
+
+    scan.close();
 
     return Ok(());
 }
 
+// delete / updateの対象の行を探すplan。indexが使えるときはIndexSelectPlanになる
+// IndexUpdatePlannerとexplainで同じplanを使う
+pub fn create_modify_plan(
+    table_name: &String,
+    predicate: &PredicateV2,
+    transaction: Rc<RefCell<TransactionV2>>,
+    metadata_manager: &mut MetadataManager,
+) -> Result<Box<dyn PlanV2>, ValueNotFound> {
+    let predicate = predicate.clone();
+    let table_plan = TablePlanV2::new(table_name.clone(), transaction.clone(), metadata_manager)?;
+    let index_info = metadata_manager.get_index_info(table_name.clone(), transaction.clone())?;
+    let plan = create_index_select(index_info, predicate.clone(), Box::new(table_plan));
+
+    if predicate.is_always_false() {
+        return Ok(Box::new(EmptyPlanV2::new(plan.get_schema().clone())));
+    }
+    return Ok(Box::new(SelectPlanV2::new_with_subqueries(
+        plan,
+        predicate,
+        transaction,
+        metadata_manager,
+    )?));
+}
+
 pub fn execute_delete(
     transaction: Rc<RefCell<TransactionV2>>,
     metadata_manager: &mut MetadataManager,
@@ -479,7 +1329,12 @@ pub fn execute_delete(
         transaction.clone(),
         metadata_manager,
     )?;
-    let mut select_plan = SelectPlanV2::new(Box::new(plan), delete_data.predicate.clone());
+    let select_plan = SelectPlanV2::new_with_subqueries(
+        Box::new(plan),
+        delete_data.predicate.clone(),
+        transaction.clone(),
+        metadata_manager,
+    )?;
     let mut scan = select_plan.open()?;
 
     let mut count = 0;
@@ -503,7 +1358,12 @@ pub fn execute_update(
         transaction.clone(),
         metadata_manager,
     )?;
-    let mut select_plan = SelectPlanV2::new(Box::new(plan), update_data.predicate.clone());
+    let select_plan = SelectPlanV2::new_with_subqueries(
+        Box::new(plan),
+        update_data.predicate.clone(),
+        transaction.clone(),
+        metadata_manager,
+    )?;
     let mut scan = select_plan.open()?;
 
     let mut count = 0;
@@ -512,6 +1372,7 @@ pub fn execute_update(
         let field = update_data.field_name.clone();
         let value = update_data.new_value.clone();
         scan.set_value(field.clone(), value.value);
+        count += 1;
     }
 
     scan.close();
@@ -522,12 +1383,387 @@ pub fn execute_create_table(
     transaction: Rc<RefCell<TransactionV2>>,
     metadata_manager: &mut MetadataManager,
     create_table_data: CreateTableData,
-) -> Result<(), TableAlreadyExists> {
-    metadata_manager.create_table(
+) -> Result<(), DatabaseError> {
+    let Some(block_size) = create_table_data.block_size else {
+        metadata_manager.create_table(
+            create_table_data.table_name.clone(),
+            &create_table_data.schema,
+            transaction,
+        )?;
+        return Ok(());
+    };
+
+    metadata_manager.create_table_with_block_size(
         create_table_data.table_name.clone(),
         &create_table_data.schema,
+        block_size,
         transaction,
-    )
+    )?;
+    return Ok(());
+}
+
+// 実行せずに検証だけする。planは作るがscanはopenしないので、書き込みも起きない
+pub fn validate_query(
+    query_data: &QueryData,
+    transaction: Rc<RefCell<TransactionV2>>,
+    metadata_manager: &mut MetadataManager,
+) -> Result<TableSchema, DatabaseError> {
+    for values_data in query_data.values_list.iter() {
+        validate_values(values_data)?;
+    }
+    let query_data = &expand_table_stars(query_data, transaction.clone(), metadata_manager)?;
+
+    // サブクエリも検証する。create_query_planはサブクエリのplanを作るだけで実行しない
+    for subquery in query_data.predicate.subqueries().iter() {
+        validate_query(subquery, transaction.clone(), metadata_manager)?;
+    }
+
+    let plan = create_query_plan(query_data, transaction.clone(), metadata_manager)?;
+    let schema = plan.get_schema().clone();
+
+    // group byのplanのschemaには集約した結果のfieldしか無いので、
+    // where句や集約関数の引数は集約する前のschemaで確認する
+    let is_aggregated = !query_data.group_by_list.is_empty()
+        || !query_data.aggregate_functions.is_empty()
+        || query_data.having.is_some();
+    let source_schema = if is_aggregated {
+        let mut source_query_data = query_data.clone();
+        source_query_data.group_by_list = vec![];
+        source_query_data.aggregate_functions = vec![];
+        source_query_data.having = None;
+        source_query_data.order_by_list = vec![];
+        create_query_plan(&source_query_data, transaction, metadata_manager)?
+            .get_schema()
+            .clone()
+    } else {
+        schema.clone()
+    };
+    // selectやorder byのfieldは集約した後のschemaに無ければいけない
+    // group byに無いfieldを選ぶと、groupの中のどの行の値か決まらない
+    let mut field_names = query_data.field_name_list.clone();
+    field_names.extend(
+        resolve_order_by_list(query_data)?
+            .into_iter()
+            .map(|(field_name, _)| field_name),
+    );
+    for select_expression in query_data.select_expressions.iter() {
+        field_names.extend(select_expression.expression.field_names());
+    }
+    for field_name in field_names {
+        if !schema.has_field(field_name.field_name.clone()) {
+            return Err(ValueNotFound::new(field_name.field_name, field_name.table_name).into());
+        }
+    }
+
+    let mut source_field_names = query_data.predicate.field_names();
+    source_field_names.extend(query_data.group_by_list.clone());
+    source_field_names.extend(
+        query_data
+            .aggregate_functions
+            .iter()
+            .filter(|aggregate_function| {
+                aggregate_function.field.field_name != CountFunction::ALL_ROWS
+            })
+            .map(|aggregate_function| aggregate_function.field.clone()),
+    );
+    for field_name in source_field_names {
+        if !source_schema.has_field(field_name.field_name.clone()) {
+            return Err(ValueNotFound::new(field_name.field_name, field_name.table_name).into());
+        }
+    }
+
+    // sum/avgは数値のfieldしか集約できない
+    for aggregate_function in query_data.aggregate_functions.iter() {
+        if !matches!(
+            aggregate_function.function_type,
+            AggregateFunctionType::Sum | AggregateFunctionType::Avg
+        ) {
+            continue;
+        }
+        let field_name = &aggregate_function.field;
+        let field_type = source_schema.get_field_type(field_name.field_name.clone());
+        if matches!(
+            field_type,
+            Some(TableFieldType::VARCHAR) | Some(TableFieldType::DATE)
+        ) {
+            return Err(TypeMismatch::new(
+                field_name.field_name.clone(),
+                field_name.table_name.clone().unwrap_or_default(),
+            )
+            .into());
+        }
+    }
+
+    let mut output_schema = TableSchema::new();
+    if query_data.all_columns {
+        // select *
+        output_schema = schema.clone();
+    }
+    for field_name in query_data.field_name_list.iter() {
+        output_schema.add(field_name.field_name.clone(), schema.clone());
+    }
+    for index in 0..query_data.aggregate_functions.len() {
+        output_schema.add(query_data.aggregate_output_name(index), schema.clone());
+    }
+
+    return Ok(output_schema);
+}
+
+// insertで省略されたfieldをschemaの順に足して、defaultかNULLで埋める
+// 埋めたあとの行にnot nullのfieldのNULLがあればNotNullViolation
+pub fn apply_field_defaults(
+    table_name: &String,
+    schema: &TableSchema,
+    field_name_list: &Vec<String>,
+    rows: Vec<Vec<Constant>>,
+) -> Result<(Vec<String>, Vec<Vec<Constant>>), DatabaseError> {
+    let mut completed_field_name_list = field_name_list.clone();
+    let mut defaults = Vec::new();
+    for field_name in schema.fields.iter() {
+        if field_name_list.contains(field_name) {
+            continue;
+        }
+        let default = schema
+            .get_default(field_name)
+            .unwrap_or(Constant::new(ConstantValue::Null));
+        // 行が無くても、埋められないfieldがあることはわかる
+        validate_not_null(table_name, schema, field_name, &default)?;
+        completed_field_name_list.push(field_name.clone());
+        defaults.push(default);
+    }
+
+    let mut completed_rows = Vec::new();
+    for mut row in rows.into_iter() {
+        for (field_name, value) in field_name_list.iter().zip(row.iter()) {
+            validate_not_null(table_name, schema, field_name, value)?;
+        }
+        row.extend(defaults.iter().cloned());
+        completed_rows.push(row);
+    }
+
+    return Ok((completed_field_name_list, completed_rows));
+}
+
+pub fn validate_not_null(
+    table_name: &String,
+    schema: &TableSchema,
+    field_name: &String,
+    value: &Constant,
+) -> Result<(), DatabaseError> {
+    if value.value == ConstantValue::Null && !schema.is_nullable(field_name) {
+        return Err(NotNullViolation::new(field_name.clone(), table_name.clone()).into());
+    }
+    return Ok(());
+}
+
+pub fn validate_value_type(
+    table_name: &String,
+    schema: &TableSchema,
+    field_name: &String,
+    value: &Constant,
+) -> Result<(), DatabaseError> {
+    let Some(field_type) = schema.get_field_type(field_name.clone()) else {
+        return Err(DatabaseError::ValueNotFound(ValueNotFound::new(
+            field_name.clone(),
+            Some(table_name.clone()),
+        )));
+    };
+
+    let is_valid = match (&value.value, field_type) {
+        (ConstantValue::Null, _) => true,
+        (ConstantValue::Number(_), TableFieldType::INTEGER) => true,
+        // scaleに揃えたときにprecisionの桁数に収まるか
+        (ConstantValue::Number(n), TableFieldType::DECIMAL(precision, scale)) => {
+            fits_decimal(*n, 0, precision, scale)
+        }
+        (
+            ConstantValue::Decimal {
+                value: n,
+                scale: from,
+            },
+            TableFieldType::DECIMAL(precision, scale),
+        ) => fits_decimal(*n, *from, precision, scale),
+        (ConstantValue::String(_), TableFieldType::VARCHAR) => true,
+        (
+            ConstantValue::Number(_) | ConstantValue::Decimal { .. } | ConstantValue::Float(_),
+            TableFieldType::FLOAT,
+        ) => true,
+        (ConstantValue::Date(_), TableFieldType::DATE) => true,
+        _ => false,
+    };
+
+    if !is_valid {
+        return Err(DatabaseError::TypeMismatch(TypeMismatch::new(
+            field_name.clone(),
+            table_name.clone(),
+        )));
+    }
+
+    // VARCHAR(n)に確保したbyte数に入らない文字列は、切り詰めずにエラーにする
+    if let ConstantValue::String(string_value) = &value.value {
+        let field_length = schema
+            .get_field_length(field_name.clone())
+            .unwrap_or(0)
+            .max(0) as u32;
+        let max_bytes = Page::get_max_length(field_length) - Page::get_integer_byte_size();
+        if string_value.len() > max_bytes {
+            return Err(
+                ValueTooLong::new(field_name.clone(), table_name.clone(), max_bytes).into(),
+            );
+        }
+    }
+
+    return Ok(());
+}
+
+fn fits_decimal(value: i32, from_scale: u32, precision: i32, scale: i32) -> bool {
+    let Some(scaled) = Constant::rescale_decimal(value, from_scale, scale as u32) else {
+        return false;
+    };
+    if precision <= 0 {
+        return true;
+    }
+    match 10_i64.checked_pow(precision as u32) {
+        Some(limit) => return (scaled as i64).abs() < limit,
+        None => return true,
+    }
+}
+
+fn validate_predicate_fields(
+    table_name: &String,
+    schema: &TableSchema,
+    predicate: &PredicateV2,
+) -> Result<(), ValueNotFound> {
+    for field_name in predicate.field_names() {
+        if !schema.has_field(field_name.field_name.clone()) {
+            return Err(ValueNotFound::new(
+                field_name.field_name,
+                Some(table_name.clone()),
+            ));
+        }
+    }
+    return Ok(());
+}
+
+pub fn validate_insert(
+    transaction: Rc<RefCell<TransactionV2>>,
+    metadata_manager: &mut MetadataManager,
+    insert_data: &InsertData,
+) -> Result<(), DatabaseError> {
+    let insert_data = &expand_insert_fields(insert_data, transaction.clone(), metadata_manager)?;
+    let layout =
+        metadata_manager.get_layout(insert_data.table_name.clone(), transaction.clone())?;
+
+    let Some(query_data) = &insert_data.select_query else {
+        if insert_data.value_list.len() != insert_data.field_name_list.len() {
+            return Err(DatabaseError::ColumnCountMismatch(
+                ColumnCountMismatch::new(
+                    insert_data.table_name.clone(),
+                    insert_data.field_name_list.len(),
+                    insert_data.value_list.len(),
+                ),
+            ));
+        }
+
+        for (field_name, value) in insert_data
+            .field_name_list
+            .iter()
+            .zip(insert_data.value_list.iter())
+        {
+            validate_value_type(&insert_data.table_name, &layout.schema, field_name, value)?;
+        }
+        apply_field_defaults(
+            &insert_data.table_name,
+            &layout.schema,
+            &insert_data.field_name_list,
+            vec![insert_data.value_list.clone()],
+        )?;
+
+        return Ok(());
+    };
+
+    // insert ... select はselectの出力schemaと型を突き合わせる
+    let output_schema = validate_query(query_data, transaction, metadata_manager)?;
+    if output_schema.fields.len() != insert_data.field_name_list.len() {
+        return Err(DatabaseError::ColumnCountMismatch(
+            ColumnCountMismatch::new(
+                insert_data.table_name.clone(),
+                insert_data.field_name_list.len(),
+                output_schema.fields.len(),
+            ),
+        ));
+    }
+
+    for (field_name, source_field_name) in insert_data
+        .field_name_list
+        .iter()
+        .zip(output_schema.fields.iter())
+    {
+        let Some(field_type) = layout.schema.get_field_type(field_name.clone()) else {
+            return Err(DatabaseError::ValueNotFound(ValueNotFound::new(
+                field_name.clone(),
+                Some(insert_data.table_name.clone()),
+            )));
+        };
+        if output_schema.get_field_type(source_field_name.clone()) != Some(field_type) {
+            return Err(DatabaseError::TypeMismatch(TypeMismatch::new(
+                field_name.clone(),
+                insert_data.table_name.clone(),
+            )));
+        }
+    }
+    apply_field_defaults(
+        &insert_data.table_name,
+        &layout.schema,
+        &insert_data.field_name_list,
+        vec![],
+    )?;
+
+    return Ok(());
+}
+
+pub fn validate_update(
+    transaction: Rc<RefCell<TransactionV2>>,
+    metadata_manager: &mut MetadataManager,
+    update_data: &UpdateData,
+) -> Result<(), DatabaseError> {
+    let layout = metadata_manager.get_layout(update_data.table_name.clone(), transaction)?;
+
+    validate_value_type(
+        &update_data.table_name,
+        &layout.schema,
+        &update_data.field_name,
+        &update_data.new_value,
+    )?;
+    validate_not_null(
+        &update_data.table_name,
+        &layout.schema,
+        &update_data.field_name,
+        &update_data.new_value,
+    )?;
+    validate_predicate_fields(
+        &update_data.table_name,
+        &layout.schema,
+        &update_data.predicate,
+    )?;
+
+    return Ok(());
+}
+
+pub fn validate_delete(
+    transaction: Rc<RefCell<TransactionV2>>,
+    metadata_manager: &mut MetadataManager,
+    delete_data: &DeleteData,
+) -> Result<(), DatabaseError> {
+    let layout = metadata_manager.get_layout(delete_data.table_name.clone(), transaction)?;
+
+    validate_predicate_fields(
+        &delete_data.table_name,
+        &layout.schema,
+        &delete_data.predicate,
+    )?;
+
+    return Ok(());
 }
 
 #[cfg(test)]
@@ -536,14 +1772,15 @@ mod tests {
     use super::*;
     use crate::{
         database::Database, metadata::metadata_manager::MetadataManager, query::parser::parse_sql,
-        query::predicate::ConstantValue,
+        query::parser::ExplainFormat, query::predicate::ConstantValue,
     };
     use std::path::Path;
 
     #[test]
 
-    fn test_optimized_product_plan() -> Result<(), ValueNotFound> {
-        let directory_path_name = format!("test_data_{}", uuid::Uuid::new_v4());
+    fn test_optimized_product_plan() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -552,7 +1789,7 @@ mod tests {
         let create_table_sql =
             "create table test_table_1 (A_1 integer, B_1 varchar(10))".to_string();
 
-        let parsed_sql_list = parse_sql(create_table_sql.clone());
+        let parsed_sql_list = parse_sql(create_table_sql.clone()).unwrap();
 
         let create_table_data = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::CreateTable(q) => q,
@@ -572,7 +1809,7 @@ mod tests {
         let create_table_sql =
             "create table test_table_2 (A_2 integer, B_2 varchar(10))".to_string();
 
-        let parsed_sql_list = parse_sql(create_table_sql.clone());
+        let parsed_sql_list = parse_sql(create_table_sql.clone()).unwrap();
 
         let create_table_data = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::CreateTable(q) => q,
@@ -626,7 +1863,7 @@ mod tests {
         ];
 
         for insert_sql in insert_sql_list.iter() {
-            let parsed_sql_list = parse_sql(insert_sql.clone());
+            let parsed_sql_list = parse_sql(insert_sql.clone()).unwrap();
 
             let insert_data = match &parsed_sql_list[0] {
                 crate::query::parser::ParsedSQL::Insert(q) => q,
@@ -637,10 +1874,10 @@ mod tests {
                 transaction.clone(),
                 &mut metadata_manager,
                 insert_data.clone(),
-            );
+            )?;
         }
 
-        transaction.borrow_mut().commit();
+        transaction.borrow_mut().commit()?;
 
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -663,7 +1900,7 @@ mod tests {
             Box::new(table_plan_2) as Box<dyn PlanV2>,
         ];
 
-        let mut optimized_plan = get_optimized_product_plan(&mut plans);
+        let optimized_plan = get_optimized_product_plan(&mut plans);
 
         let mut scan = optimized_plan.open()?;
 
@@ -695,8 +1932,9 @@ mod tests {
         return Ok(());
     }
 
-    fn test_insert_data() -> Result<(), ValueNotFound> {
-        let directory_path_name = format!("test_data_{}", uuid::Uuid::new_v4());
+    fn test_insert_data() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -705,7 +1943,7 @@ mod tests {
         let create_table_sql =
             "create table test_table_11 (A_1 integer, B_1 varchar(10))".to_string();
 
-        let parsed_sql_list = parse_sql(create_table_sql.clone());
+        let parsed_sql_list = parse_sql(create_table_sql.clone()).unwrap();
 
         let create_table_data = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::CreateTable(q) => q,
@@ -725,7 +1963,7 @@ mod tests {
         let insert_sql =
             "insert into test_table_11 (A_1, B_1) values (42, 'Hello World!')".to_string();
 
-        let parsed_sql_list = parse_sql(insert_sql.clone());
+        let parsed_sql_list = parse_sql(insert_sql.clone()).unwrap();
 
         let insert_data = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::Insert(q) => q,
@@ -736,12 +1974,12 @@ mod tests {
             transaction.clone(),
             &mut metadata_manager,
             insert_data.clone(),
-        );
+        )?;
 
         let insert_sql_2 =
             "insert into test_table_11 (A_1, B_1) values (42, 'Hello World!')".to_string();
 
-        let parsed_sql_list = parse_sql(insert_sql_2.clone());
+        let parsed_sql_list = parse_sql(insert_sql_2.clone()).unwrap();
 
         let insert_data_2 = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::Insert(q) => q,
@@ -752,8 +1990,8 @@ mod tests {
             transaction.clone(),
             &mut metadata_manager,
             insert_data_2.clone(),
-        );
-        transaction.borrow_mut().commit();
+        )?;
+        transaction.borrow_mut().commit()?;
 
         // ここでテーブルにデータが挿入されたことを確認するために、selectクエリを実行してみる
 
@@ -763,17 +2001,17 @@ mod tests {
 
         let create_table_sql = "select A_1, B_1 from test_table_11".to_string();
 
-        let parsed_sql_list = parse_sql(create_table_sql.clone());
+        let parsed_sql_list = parse_sql(create_table_sql.clone()).unwrap();
 
         let select_query = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::Query(q) => q,
             _ => panic!("Expected a Query  variant from parse_sql"),
         };
-        let mut plan =
+        let plan =
             create_query_plan(&select_query, transaction.clone(), &mut metadata_manager)?;
 
         let mut scan = plan.open()?;
-        scan.move_to_before_first();
+        scan.move_to_before_first()?;
 
         let mut count = 0;
 
@@ -798,7 +2036,7 @@ mod tests {
         return Ok(());
     }
 
-    fn prepare_test_data_1(directory_path_name: &Path) -> Result<(), ValueNotFound> {
+    fn prepare_test_data_1(directory_path_name: &Path) -> Result<(), DatabaseError> {
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -807,7 +2045,7 @@ mod tests {
         let create_table_sql =
             "create table test_table_1 (A_1 integer, B_1 varchar(10))".to_string();
 
-        let parsed_sql_list = parse_sql(create_table_sql.clone());
+        let parsed_sql_list = parse_sql(create_table_sql.clone()).unwrap();
 
         let create_table_data = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::CreateTable(q) => q,
@@ -842,7 +2080,7 @@ mod tests {
             &mut metadata_manager,
         );
 
-        transaction.borrow_mut().commit();
+        transaction.borrow_mut().commit()?;
         Ok(())
     }
 
@@ -851,19 +2089,19 @@ mod tests {
         transaction: Rc<RefCell<TransactionV2>>,
         metadata_manager: &mut MetadataManager,
     ) {
-        let parsed_sql_list = parse_sql(insert_sql.clone());
+        let parsed_sql_list = parse_sql(insert_sql.clone()).unwrap();
 
         let insert_data = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::Insert(q) => q,
             _ => panic!("Expected a Insert variant from parse_sql"),
         };
 
-        execute_insert(transaction.clone(), metadata_manager, insert_data.clone());
+        execute_insert(transaction.clone(), metadata_manager, insert_data.clone()).unwrap();
 
-        transaction.borrow_mut().commit();
+        transaction.borrow_mut().commit().unwrap();
     }
 
-    fn prepare_test_data_2(directory_path_name: &Path) -> Result<(), ValueNotFound> {
+    fn prepare_test_data_2(directory_path_name: &Path) -> Result<(), DatabaseError> {
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -872,7 +2110,7 @@ mod tests {
         let create_table_sql =
             "create table test_table_2 (A_2 integer, B_2 varchar(10))".to_string();
 
-        let parsed_sql_list = parse_sql(create_table_sql.clone());
+        let parsed_sql_list = parse_sql(create_table_sql.clone()).unwrap();
 
         let create_table_data = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::CreateTable(q) => q,
@@ -909,12 +2147,13 @@ mod tests {
     }
 
     #[test]
-    fn test_join_query() -> Result<(), ValueNotFound> {
-        let directory_path_name = format!("test_data_{}", uuid::Uuid::new_v4());
+    fn test_join_query() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
         let directory_path = Path::new(&directory_path_name);
 
-        prepare_test_data_1(directory_path);
-        prepare_test_data_2(directory_path);
+        prepare_test_data_1(directory_path)?;
+        prepare_test_data_2(directory_path)?;
 
         let database = Database::new(directory_path);
 
@@ -922,7 +2161,8 @@ mod tests {
         let mut metadata_manager = MetadataManager::new(transaction.clone())?;
 
         let parsed_sql =
-            &parse_sql("select A_1, B_1, A_2, B_2 from test_table_1, test_table_2".to_string())[0];
+            &parse_sql("select A_1, B_1, A_2, B_2 from test_table_1, test_table_2".to_string())
+                .unwrap()[0];
 
         let query_data = match parsed_sql {
             crate::query::parser::ParsedSQL::Query(q) => q,
@@ -963,10 +2203,10 @@ mod tests {
         let mut test_value_list: Vec<TestValue> =
             vec![test_value_1, test_value_2, test_value_3, test_value_4];
 
-        let mut plan = create_query_plan(&query_data, transaction.clone(), &mut metadata_manager)?;
+        let plan = create_query_plan(&query_data, transaction.clone(), &mut metadata_manager)?;
 
         let mut scan = plan.open()?;
-        scan.move_to_before_first();
+        scan.move_to_before_first()?;
 
         let mut count = 0;
 
@@ -1004,7 +2244,7 @@ mod tests {
         return Ok(());
     }
 
-    fn prepare_test_data_3(directory_path_name: &Path) -> Result<(), ValueNotFound> {
+    fn prepare_test_data_3(directory_path_name: &Path) -> Result<(), DatabaseError> {
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -1013,7 +2253,7 @@ mod tests {
         let create_table_sql =
             "create table test_table_1 (A_1 integer, B_1 varchar(10))".to_string();
 
-        let parsed_sql_list = parse_sql(create_table_sql.clone());
+        let parsed_sql_list = parse_sql(create_table_sql.clone()).unwrap();
 
         let create_table_data = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::CreateTable(q) => q,
@@ -1075,20 +2315,22 @@ mod tests {
             &mut metadata_manager,
         );
 
-        transaction.borrow_mut().commit();
+        transaction.borrow_mut().commit()?;
 
         metadata_manager.create_view(
             "test_view".to_string(),
             "select A_1, B_1 from test_table_1".to_string(),
             transaction.clone(),
         );
+        transaction.borrow_mut().commit()?;
 
         Ok(())
     }
 
     #[test]
-    fn test_view_query() -> Result<(), ValueNotFound> {
-        let directory_path_name = format!("test_data_{}", uuid::Uuid::new_v4());
+    fn test_view_query() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
         let directory_path = Path::new(&directory_path_name);
 
         prepare_test_data_3(directory_path)?;
@@ -1098,16 +2340,17 @@ mod tests {
         let transaction = database.new_transaction(1);
         let mut metadata_manager = MetadataManager::new(transaction.clone())?;
 
-        let parsed_sql = &parse_sql("select A_1, B_1 from test_view where A_1 = 1".to_string())[0];
+        let parsed_sql =
+            &parse_sql("select A_1, B_1 from test_view where A_1 = 1".to_string()).unwrap()[0];
 
         let query_data = match parsed_sql {
             crate::query::parser::ParsedSQL::Query(q) => q,
             _ => panic!("Expected a Query variant from parse_sql"),
         };
 
-        let mut plan = create_query_plan(&query_data, transaction.clone(), &mut metadata_manager)?;
+        let plan = create_query_plan(&query_data, transaction.clone(), &mut metadata_manager)?;
         let mut scan = plan.open()?;
-        scan.move_to_before_first();
+        scan.move_to_before_first()?;
         let mut count = 0;
 
         scan.next()?;
@@ -1142,4 +2385,1478 @@ mod tests {
 
         Ok(())
     }
+
+    fn collect_rows_for_test(
+        sql: String,
+        transaction: Rc<RefCell<TransactionV2>>,
+        metadata_manager: &mut MetadataManager,
+    ) -> Result<Vec<(ConstantValue, ConstantValue)>, ValueNotFound> {
+        let parsed_sql = &parse_sql(sql).unwrap()[0];
+
+        let query_data = match parsed_sql {
+            crate::query::parser::ParsedSQL::Query(q) => q,
+            _ => panic!("Expected a Query variant from parse_sql"),
+        };
+
+        let plan = create_query_plan(&query_data, transaction.clone(), metadata_manager)?;
+        let mut scan = plan.open()?;
+
+        let mut rows = Vec::new();
+
+        while scan.next()? {
+            let field1_value = scan
+                .get_value(TableNameAndFieldName::new(None, "A_1".to_string()))
+                .unwrap();
+            let field2_value = scan
+                .get_value(TableNameAndFieldName::new(None, "B_1".to_string()))
+                .unwrap();
+            rows.push((field1_value, field2_value));
+        }
+
+        scan.close();
+        return Ok(rows);
+    }
+
+    #[test]
+    fn test_order_by_position() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+
+        prepare_test_data_3(directory_path)?;
+
+        let database = Database::new(directory_path);
+
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let rows_by_name = collect_rows_for_test(
+            "select A_1, B_1 from test_table_1 order by B_1".to_string(),
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+
+        let rows_by_position = collect_rows_for_test(
+            "select A_1, B_1 from test_table_1 order by 2".to_string(),
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+
+        assert_eq!(rows_by_position.len(), 5);
+        assert_eq!(rows_by_position, rows_by_name);
+        assert_eq!(
+            rows_by_position[1],
+            (
+                ConstantValue::Number(1),
+                ConstantValue::String("Hello World1111".to_string())
+            )
+        );
+
+        for sql in [
+            "select A_1, B_1 from test_table_1 order by 3",
+            "select A_1, B_1 from test_table_1 order by 0",
+        ] {
+            let result =
+                collect_rows_for_test(sql.to_string(), transaction.clone(), &mut metadata_manager);
+            assert!(result.is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_limit_offset() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+
+        prepare_test_data_3(directory_path)?;
+
+        let database = Database::new(directory_path);
+
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let mut select_a_1 = |sql: &str| -> Result<Vec<ConstantValue>, ValueNotFound> {
+            let rows =
+                collect_rows_for_test(sql.to_string(), transaction.clone(), &mut metadata_manager)?;
+            return Ok(rows.into_iter().map(|(a_1, _)| a_1).collect());
+        };
+
+        assert_eq!(
+            select_a_1("select A_1, B_1 from test_table_1 order by A_1, B_1 limit 2")?,
+            vec![ConstantValue::Number(1), ConstantValue::Number(1)]
+        );
+        assert_eq!(
+            select_a_1("select A_1, B_1 from test_table_1 order by A_1, B_1 limit 2 offset 1")?,
+            vec![ConstantValue::Number(1), ConstantValue::Number(2)]
+        );
+        assert_eq!(
+            select_a_1("select A_1, B_1 from test_table_1 where A_1 > 1 limit 10")?.len(),
+            3
+        );
+        assert!(select_a_1("select A_1, B_1 from test_table_1 limit 0")?.is_empty());
+        assert!(select_a_1("select A_1, B_1 from test_table_1 limit 2 offset 5")?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_plan_twice() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+
+        prepare_test_data_3(directory_path)?;
+
+        let database = Database::new(directory_path);
+
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let left_plan = TablePlanV2::new(
+            "test_table_1".to_string(),
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+        let right_plan = SortPlan::new(
+            transaction.clone(),
+            Box::new(TablePlanV2::new(
+                "test_table_1".to_string(),
+                transaction.clone(),
+                &mut metadata_manager,
+            )?),
+            vec![(
+                TableNameAndFieldName::new(None, "A_1".to_string()),
+                SortDirection::Ascending,
+            )],
+        );
+        let plan = ProductPlanV2::new(Box::new(left_plan), Box::new(right_plan));
+
+        let count_rows = |scan: &mut Box<dyn ScanV2>| -> Result<usize, ValueNotFound> {
+            let mut count = 0;
+            while scan.next()? {
+                count += 1;
+            }
+            return Ok(count);
+        };
+
+        // 1つ目のscanを読んでいる途中で、同じplanから2つ目のscanを開く
+        let mut first_scan = plan.open()?;
+        assert!(first_scan.next()?);
+        let mut second_scan = plan.open()?;
+        assert_eq!(count_rows(&mut second_scan)?, 25);
+        second_scan.close();
+        assert_eq!(count_rows(&mut first_scan)?, 24);
+        first_scan.close();
+
+        // 閉じたあとにもう一度openしても最初から読める
+        let mut third_scan = plan.open()?;
+        assert_eq!(count_rows(&mut third_scan)?, 25);
+        third_scan.close();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_constant_predicate() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+
+        prepare_test_data_3(directory_path)?;
+
+        let database = Database::new(directory_path);
+
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let parsed_sql =
+            &parse_sql("select A_1, B_1 from test_table_1 where 1 = 0".to_string()).unwrap()[0];
+
+        let query_data = match parsed_sql {
+            crate::query::parser::ParsedSQL::Query(q) => q,
+            _ => panic!("Expected a Query variant from parse_sql"),
+        };
+
+        // 常に偽の述語ではテーブルをscanしない
+        let plan = create_query_plan(&query_data, transaction.clone(), &mut metadata_manager)?;
+        let plan_tree = plan.get_child_plans();
+        assert_eq!(plan_tree.current_node_type, "EmptyPlanV2");
+        assert_eq!(plan_tree.child_nodes.len(), 0);
+
+        let rows = collect_rows_for_test(
+            "select A_1, B_1 from test_table_1 where 1 = 0".to_string(),
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+        assert_eq!(rows.len(), 0);
+
+        let rows = collect_rows_for_test(
+            "select A_1, B_1 from test_table_1 where 1 = 1".to_string(),
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+        assert_eq!(rows.len(), 5);
+
+        Ok(())
+    }
+
+    fn prepare_test_data_subquery(directory_path_name: &Path) -> Result<(), DatabaseError> {
+        let database = Database::new(directory_path_name);
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        for create_table_sql in [
+            "create table target (id integer, flag integer)",
+            "create table staging (id integer)",
+        ] {
+            let parsed_sql_list = parse_sql(create_table_sql.to_string()).unwrap();
+
+            let create_table_data = match &parsed_sql_list[0] {
+                crate::query::parser::ParsedSQL::CreateTable(q) => q,
+                _ => panic!("Expected a CreateTable variant from parse_sql"),
+            };
+
+            execute_create_table(
+                transaction.clone(),
+                &mut metadata_manager,
+                create_table_data.clone(),
+            )
+            .unwrap();
+        }
+
+        for insert_sql in [
+            "insert into target (id, flag) values (1, 0)",
+            "insert into target (id, flag) values (2, 0)",
+            "insert into target (id, flag) values (3, 0)",
+            "insert into target (id, flag) values (4, 0)",
+            "insert into staging (id) values (1)",
+            "insert into staging (id) values (3)",
+        ] {
+            insert_data_for_test(
+                insert_sql.to_string(),
+                transaction.clone(),
+                &mut metadata_manager,
+            );
+        }
+
+        Ok(())
+    }
+
+    fn collect_target_rows_for_test(
+        transaction: Rc<RefCell<TransactionV2>>,
+        metadata_manager: &mut MetadataManager,
+    ) -> Result<Vec<(ConstantValue, ConstantValue)>, ValueNotFound> {
+        let plan = TablePlanV2::new("target".to_string(), transaction, metadata_manager)?;
+        let mut scan = plan.open()?;
+
+        let mut rows = Vec::new();
+        while scan.next()? {
+            let id = scan
+                .get_value(TableNameAndFieldName::new(None, "id".to_string()))
+                .unwrap();
+            let flag = scan
+                .get_value(TableNameAndFieldName::new(None, "flag".to_string()))
+                .unwrap();
+            rows.push((id, flag));
+        }
+        scan.close();
+
+        return Ok(rows);
+    }
+
+    #[test]
+    fn test_update_and_delete_with_subquery() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+
+        prepare_test_data_subquery(directory_path)?;
+
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let parsed_sql = &parse_sql(
+            "update target set flag = 1 where target.id in (select id from staging)".to_string(),
+        )
+        .unwrap()[0];
+
+        let update_data = match parsed_sql {
+            crate::query::parser::ParsedSQL::Update(q) => q,
+            _ => panic!("Expected a Update variant from parse_sql"),
+        };
+
+        let count = execute_update(
+            transaction.clone(),
+            &mut metadata_manager,
+            update_data.clone(),
+        )?;
+        assert_eq!(count, 2);
+
+        let rows = collect_target_rows_for_test(transaction.clone(), &mut metadata_manager)?;
+        assert_eq!(
+            rows,
+            vec![
+                (ConstantValue::Number(1), ConstantValue::Number(1)),
+                (ConstantValue::Number(2), ConstantValue::Number(0)),
+                (ConstantValue::Number(3), ConstantValue::Number(1)),
+                (ConstantValue::Number(4), ConstantValue::Number(0)),
+            ]
+        );
+
+        let parsed_sql =
+            &parse_sql("delete from target where id in (select id from staging)".to_string())
+                .unwrap()[0];
+
+        let delete_data = match parsed_sql {
+            crate::query::parser::ParsedSQL::Delete(q) => q,
+            _ => panic!("Expected a Delete variant from parse_sql"),
+        };
+
+        let count = execute_delete(
+            transaction.clone(),
+            &mut metadata_manager,
+            delete_data.clone(),
+        )?;
+        assert_eq!(count, 2);
+
+        let rows = collect_target_rows_for_test(transaction.clone(), &mut metadata_manager)?;
+        assert_eq!(
+            rows,
+            vec![
+                (ConstantValue::Number(2), ConstantValue::Number(0)),
+                (ConstantValue::Number(4), ConstantValue::Number(0)),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_json() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+
+        prepare_test_data_1(directory_path)?;
+        prepare_test_data_2(directory_path)?;
+
+        let database = Database::new(directory_path);
+
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let parsed_sql = &parse_sql(
+            "explain (format json) select A_1, B_2 from test_table_1, test_table_2 where A_1 = 1"
+                .to_string(),
+        )
+        .unwrap()[0];
+
+        let query_data = match parsed_sql {
+            crate::query::parser::ParsedSQL::Explain {
+                query_data,
+                format: ExplainFormat::Json,
+            } => query_data,
+            _ => panic!("Expected a json Explain variant from parse_sql"),
+        };
+
+        let plan = create_query_plan(&query_data, transaction.clone(), &mut metadata_manager)?;
+        let json = plan.get_child_plans().to_json();
+
+        // A_1は1と2なので、histogramから半分の行になると見積もる
+        assert!(json.starts_with(r#"{"node_type":"SelectPlanV2(A_1 = 1)","rows":2,"blocks":3,"#));
+        assert!(json.contains(r#""children":[{"node_type":"ProductPlanV2","#));
+        for table_name in ["test_table_1", "test_table_2"] {
+            assert!(json.contains(&format!(
+                r#"{{"node_type":"TablePlanV2({})","rows":2,"blocks":1,"children":[]}}"#,
+                table_name
+            )));
+        }
+
+        // textでは1行に1つのnodeを、条件と見積もりと一緒に書く
+        let lines = plan
+            .explain()
+            .lines()
+            .map(|line| line.to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "└─ SelectPlanV2(A_1 = 1) (rows=2, blocks=3)");
+        assert_eq!(lines[1], "   └─ ProductPlanV2 (rows=4, blocks=3)");
+        assert!(lines[2].ends_with("TablePlanV2(test_table_1) (rows=2, blocks=1)"));
+
+        // 条件の文字列に改行や制御文字があってもjsonとして読める
+        let node = PlanTreeNodeForDebug {
+            current_node_type: "SelectPlanV2(B_1 = 'a\n\t\u{1}\"')".to_string(),
+            records_output: 1,
+            blocks_accessed: 1,
+            child_nodes: vec![],
+        };
+        assert_eq!(
+            node.to_json(),
+            r#"{"node_type":"SelectPlanV2(B_1 = 'a\n\t\u0001\"')","rows":1,"blocks":1,"children":[]}"#
+        );
+
+        Ok(())
+    }
+
+    fn prepare_test_data_orders(directory_path_name: &Path) -> Result<(), DatabaseError> {
+        let database = Database::new(directory_path_name);
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let parsed_sql_list = parse_sql(
+            "create table orders (price integer, qty integer, name varchar(10))".to_string(),
+        )
+        .unwrap();
+
+        let create_table_data = match &parsed_sql_list[0] {
+            crate::query::parser::ParsedSQL::CreateTable(q) => q,
+            _ => panic!("Expected a CreateTable variant from parse_sql"),
+        };
+
+        execute_create_table(
+            transaction.clone(),
+            &mut metadata_manager,
+            create_table_data.clone(),
+        )
+        .unwrap();
+
+        for insert_sql in [
+            "insert into orders (price, qty, name) values (10, 5, 'pen')",
+            "insert into orders (price, qty, name) values (30, 4, 'notebook')",
+            "insert into orders (price, qty, name) values (200, 1, 'stapler')",
+            "insert into orders (price, qty, name) values (7, 3, 'eraser')",
+        ] {
+            insert_data_for_test(
+                insert_sql.to_string(),
+                transaction.clone(),
+                &mut metadata_manager,
+            );
+        }
+
+        Ok(())
+    }
+
+    fn collect_order_names_for_test(
+        sql: &str,
+        transaction: Rc<RefCell<TransactionV2>>,
+        metadata_manager: &mut MetadataManager,
+    ) -> Result<Vec<ConstantValue>, ValueNotFound> {
+        let parsed_sql = &parse_sql(sql.to_string()).unwrap()[0];
+
+        let query_data = match parsed_sql {
+            crate::query::parser::ParsedSQL::Query(q) => q,
+            _ => panic!("Expected a Query variant from parse_sql"),
+        };
+
+        let plan = create_query_plan(&query_data, transaction.clone(), metadata_manager)?;
+        let mut scan = plan.open()?;
+
+        let mut names = Vec::new();
+        while scan.next()? {
+            names.push(
+                scan.get_value(TableNameAndFieldName::new(None, "name".to_string()))
+                    .unwrap(),
+            );
+        }
+
+        scan.close();
+        return Ok(names);
+    }
+
+    #[test]
+    fn test_computed_expression_predicate() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+
+        prepare_test_data_orders(directory_path)?;
+
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let names = collect_order_names_for_test(
+            "select name from orders where price * qty > 100",
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+        assert_eq!(
+            names,
+            vec![
+                ConstantValue::String("notebook".to_string()),
+                ConstantValue::String("stapler".to_string()),
+            ]
+        );
+
+        let names = collect_order_names_for_test(
+            "select name from orders where length(name) > 5",
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+        assert_eq!(
+            names,
+            vec![
+                ConstantValue::String("notebook".to_string()),
+                ConstantValue::String("stapler".to_string()),
+                ConstantValue::String("eraser".to_string()),
+            ]
+        );
+
+        // 掛け算が足し算より先に評価される
+        let names = collect_order_names_for_test(
+            "select name from orders where price + qty * 10 = 60",
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+        assert_eq!(names, vec![ConstantValue::String("pen".to_string())]);
+
+        let names = collect_order_names_for_test(
+            "select name from orders where (price + qty) * 10 <= 100 and upper(name) <> 'PEN'",
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+        assert_eq!(names, vec![ConstantValue::String("eraser".to_string())]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_table_with_block_size() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+
+        {
+            let database = Database::new(directory_path);
+            let transaction = database.new_transaction(1);
+            let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+            let parsed_sql_list = parse_sql(
+                "create table wide_table (id integer, name varchar(10)) with (block_size = 1024)"
+                    .to_string(),
+            )
+            .unwrap();
+            let create_table_data = match &parsed_sql_list[0] {
+                crate::query::parser::ParsedSQL::CreateTable(q) => q,
+                _ => panic!("Expected a CreateTable variant from parse_sql"),
+            };
+            execute_create_table(
+                transaction.clone(),
+                &mut metadata_manager,
+                create_table_data.clone(),
+            )
+            .unwrap();
+            transaction.borrow_mut().commit()?;
+
+            for id in 0..100 {
+                insert_data_for_test(
+                    format!(
+                        "insert into wide_table (id, name) values ({}, 'name {}')",
+                        id, id
+                    ),
+                    transaction.clone(),
+                    &mut metadata_manager,
+                );
+            }
+
+            // 1つのrecordも入らないblock sizeはエラー
+            let parsed_sql_list = parse_sql(
+                "create table tiny_table (id integer, name varchar(10)) with (block_size = 8)"
+                    .to_string(),
+            )
+            .unwrap();
+            let create_table_data = match &parsed_sql_list[0] {
+                crate::query::parser::ParsedSQL::CreateTable(q) => q,
+                _ => panic!("Expected a CreateTable variant from parse_sql"),
+            };
+            let result = execute_create_table(
+                transaction.clone(),
+                &mut metadata_manager,
+                create_table_data.clone(),
+            );
+            assert!(matches!(result, Err(DatabaseError::InvalidBlockSize(_))));
+            transaction.borrow_mut().commit()?;
+        }
+
+        // tableのfileだけが指定したblock sizeで書かれている
+        let table_file_length = std::fs::metadata(directory_path.join("wide_table.tbl"))
+            .unwrap()
+            .len();
+        assert!(table_file_length > 1024);
+        assert_eq!(table_file_length % 1024, 0);
+        let catalog_file_length = std::fs::metadata(directory_path.join("table_catalog.tbl"))
+            .unwrap()
+            .len();
+        assert_eq!(catalog_file_length % 400, 0);
+
+        // 開き直しても、catalogからblock sizeを読んでrecordを読める
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let parsed_sql = &parse_sql("select id, name from wide_table".to_string()).unwrap()[0];
+        let query_data = match parsed_sql {
+            crate::query::parser::ParsedSQL::Query(q) => q,
+            _ => panic!("Expected a Query variant from parse_sql"),
+        };
+
+        let plan = create_query_plan(&query_data, transaction.clone(), &mut metadata_manager)?;
+        let mut scan = plan.open()?;
+
+        let mut ids = Vec::new();
+        while scan.next()? {
+            let id = scan
+                .get_value(TableNameAndFieldName::new(None, "id".to_string()))
+                .unwrap();
+            let name = scan
+                .get_value(TableNameAndFieldName::new(None, "name".to_string()))
+                .unwrap();
+            let ConstantValue::Number(id) = id else {
+                panic!("Expected a Number value");
+            };
+            assert_eq!(name, ConstantValue::String(format!("name {}", id)));
+            ids.push(id);
+        }
+        scan.close();
+
+        assert_eq!(ids, (0..100).collect::<Vec<i32>>());
+
+        transaction.borrow_mut().commit()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_select() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+
+        prepare_test_data_orders(directory_path)?;
+
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let parsed_sql_list = parse_sql(
+            "create table expensive_orders (title varchar(10), total integer)".to_string(),
+        )
+        .unwrap();
+        let create_table_data = match &parsed_sql_list[0] {
+            crate::query::parser::ParsedSQL::CreateTable(q) => q,
+            _ => panic!("Expected a CreateTable variant from parse_sql"),
+        };
+        execute_create_table(
+            transaction.clone(),
+            &mut metadata_manager,
+            create_table_data.clone(),
+        )
+        .unwrap();
+
+        let insert_select = |sql: &str, metadata_manager: &mut MetadataManager| {
+            let parsed_sql_list = parse_sql(sql.to_string()).unwrap();
+            let insert_data = match &parsed_sql_list[0] {
+                crate::query::parser::ParsedSQL::Insert(q) => q,
+                _ => panic!("Expected a Insert variant from parse_sql"),
+            };
+            execute_insert(transaction.clone(), metadata_manager, insert_data.clone())
+        };
+
+        insert_select(
+            "insert into expensive_orders (title, total) select name, price from orders where price >= 30",
+            &mut metadata_manager,
+        )
+        .unwrap();
+
+        // 列数や型が合わなければ何も書き込まない
+        let result = insert_select(
+            "insert into expensive_orders (title, total) select name from orders",
+            &mut metadata_manager,
+        );
+        assert!(matches!(result, Err(DatabaseError::ColumnCountMismatch(_))));
+
+        let result = insert_select(
+            "insert into expensive_orders (title, total) select price, name from orders",
+            &mut metadata_manager,
+        );
+        assert!(matches!(result, Err(DatabaseError::TypeMismatch(_))));
+
+        transaction.borrow_mut().commit()?;
+
+        let parsed_sql =
+            &parse_sql("select title, total from expensive_orders".to_string()).unwrap()[0];
+        let query_data = match parsed_sql {
+            crate::query::parser::ParsedSQL::Query(q) => q,
+            _ => panic!("Expected a Query variant from parse_sql"),
+        };
+
+        let plan = create_query_plan(&query_data, transaction.clone(), &mut metadata_manager)?;
+        let mut scan = plan.open()?;
+
+        let mut rows = Vec::new();
+        while scan.next()? {
+            rows.push((
+                scan.get_value(TableNameAndFieldName::new(None, "title".to_string()))
+                    .unwrap(),
+                scan.get_value(TableNameAndFieldName::new(None, "total".to_string()))
+                    .unwrap(),
+            ));
+        }
+        scan.close();
+
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    ConstantValue::String("notebook".to_string()),
+                    ConstantValue::Number(30)
+                ),
+                (
+                    ConstantValue::String("stapler".to_string()),
+                    ConstantValue::Number(200)
+                ),
+            ]
+        );
+
+        transaction.borrow_mut().commit()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decimal_round_trip() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+
+        {
+            let database = Database::new(directory_path);
+            let transaction = database.new_transaction(1);
+            let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+            let parsed_sql_list =
+                parse_sql("create table prices (id integer, price decimal(6, 2))".to_string())
+                    .unwrap();
+            let create_table_data = match &parsed_sql_list[0] {
+                crate::query::parser::ParsedSQL::CreateTable(q) => q,
+                _ => panic!("Expected a CreateTable variant from parse_sql"),
+            };
+            execute_create_table(
+                transaction.clone(),
+                &mut metadata_manager,
+                create_table_data.clone(),
+            )
+            .unwrap();
+            transaction.borrow_mut().commit()?;
+
+            // scaleが違う値もfieldのscaleに揃えて保存する
+            for insert_sql in [
+                "insert into prices (id, price) values (1, 12.34)",
+                "insert into prices (id, price) values (2, 1.5)",
+                "insert into prices (id, price) values (3, 7)",
+                "insert into prices (id, price) values (4, 0.125)",
+            ] {
+                insert_data_for_test(
+                    insert_sql.to_string(),
+                    transaction.clone(),
+                    &mut metadata_manager,
+                );
+            }
+
+            // precisionの桁数を超える値は入れられない
+            let parsed_sql_list =
+                parse_sql("insert into prices (id, price) values (5, 12345.6)".to_string())
+                    .unwrap();
+            let insert_data = match &parsed_sql_list[0] {
+                crate::query::parser::ParsedSQL::Insert(q) => q,
+                _ => panic!("Expected a Insert variant from parse_sql"),
+            };
+            let result = execute_insert(
+                transaction.clone(),
+                &mut metadata_manager,
+                insert_data.clone(),
+            );
+            assert!(matches!(result, Err(DatabaseError::TypeMismatch(_))));
+            transaction.borrow_mut().commit()?;
+        }
+
+        // 開き直しても、catalogからprecisionとscaleを読める
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        let layout = metadata_manager.get_layout("prices".to_string(), transaction.clone())?;
+        assert_eq!(
+            layout.schema.get_field_type("price".to_string()),
+            Some(TableFieldType::DECIMAL(6, 2))
+        );
+
+        let parsed_sql =
+            &parse_sql("select id, price from prices where price >= 1.50".to_string()).unwrap()[0];
+        let query_data = match parsed_sql {
+            crate::query::parser::ParsedSQL::Query(q) => q,
+            _ => panic!("Expected a Query variant from parse_sql"),
+        };
+
+        let plan = create_query_plan(&query_data, transaction.clone(), &mut metadata_manager)?;
+        let mut scan = plan.open()?;
+
+        let mut rows = Vec::new();
+        while scan.next()? {
+            let id = scan
+                .get_value(TableNameAndFieldName::new(None, "id".to_string()))
+                .unwrap();
+            let price = scan
+                .get_value(TableNameAndFieldName::new(None, "price".to_string()))
+                .unwrap();
+            rows.push((id, price));
+        }
+        scan.close();
+
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    ConstantValue::Number(1),
+                    ConstantValue::Decimal {
+                        value: 1234,
+                        scale: 2
+                    }
+                ),
+                (
+                    ConstantValue::Number(2),
+                    ConstantValue::Decimal {
+                        value: 150,
+                        scale: 2
+                    }
+                ),
+                (
+                    ConstantValue::Number(3),
+                    ConstantValue::Decimal {
+                        value: 700,
+                        scale: 2
+                    }
+                ),
+            ]
+        );
+
+        // intのliteralとDECIMALのfieldも値で比べる
+        let parsed_sql =
+            &parse_sql("select id from prices where price > 7".to_string()).unwrap()[0];
+        let query_data = match parsed_sql {
+            crate::query::parser::ParsedSQL::Query(q) => q,
+            _ => panic!("Expected a Query variant from parse_sql"),
+        };
+
+        let plan = create_query_plan(&query_data, transaction.clone(), &mut metadata_manager)?;
+        let mut scan = plan.open()?;
+
+        let mut ids = Vec::new();
+        while scan.next()? {
+            ids.push(
+                scan.get_value(TableNameAndFieldName::new(None, "id".to_string()))
+                    .unwrap(),
+            );
+        }
+        scan.close();
+        assert_eq!(ids, vec![ConstantValue::Number(1)]);
+
+        transaction.borrow_mut().commit()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_date_round_trip() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        {
+            let database = Database::new(directory_path);
+            let transaction = database.new_transaction(1);
+            database.execute(
+                "create table events (id integer, happened date); \
+                 create index events_index on events (happened); \
+                 insert into events (id, happened) values (1, date '2024-01-01'); \
+                 insert into events (id, happened) values (2, date '2022-12-31'); \
+                 insert into events (id, happened) values (3, date '2023-06-15'); \
+                 insert into events (id, happened) values (4, date '1969-07-20'); \
+                 insert into events (id, happened) values (5, null)",
+                transaction.clone(),
+            )?;
+            transaction.borrow_mut().commit()?;
+        }
+
+        // 開き直しても、catalogからDATEの型を読める
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let layout = metadata_manager.get_layout("events".to_string(), transaction.clone())?;
+        assert_eq!(
+            layout.schema.get_field_type("happened".to_string()),
+            Some(TableFieldType::DATE)
+        );
+        assert!(format!("{:?}", layout.schema).contains("DATE"));
+
+        let mut fetch =
+            |sql: &str, field_name: &str| -> Result<Vec<ConstantValue>, ValueNotFound> {
+                let parsed_sql = &parse_sql(sql.to_string()).unwrap()[0];
+                let crate::query::parser::ParsedSQL::Query(query_data) = parsed_sql else {
+                    panic!("Expected a Query variant from parse_sql");
+                };
+                let plan =
+                    create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+                let mut scan = plan.open()?;
+                let mut values = Vec::new();
+                while scan.next()? {
+                    values.push(
+                        scan.get_value(TableNameAndFieldName::new(None, field_name.to_string()))
+                            .unwrap(),
+                    );
+                }
+                scan.close();
+                return Ok(values);
+            };
+
+        assert_eq!(
+            fetch(
+                "select id from events where happened > date '2023-01-01' order by happened",
+                "id"
+            )?,
+            vec![ConstantValue::Number(3), ConstantValue::Number(1)]
+        );
+        assert_eq!(
+            fetch("select happened from events order by happened", "happened")?,
+            vec![
+                ConstantValue::Null,
+                ConstantValue::Date(-165),
+                ConstantValue::Date(19357),
+                ConstantValue::Date(19523),
+                ConstantValue::Date(19723),
+            ]
+        );
+        // indexで同じ日付を探す
+        assert_eq!(
+            fetch(
+                "select id from events where happened = date '2022-12-31'",
+                "id"
+            )?,
+            vec![ConstantValue::Number(2)]
+        );
+
+        transaction.borrow_mut().commit()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_float_round_trip() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        {
+            let database = Database::new(directory_path);
+            let transaction = database.new_transaction(1);
+            database.execute(
+                "create table readings (id integer, reading float); \
+                 create index readings_index on readings (reading); \
+                 insert into readings (id, reading) values (1, 1.5e0); \
+                 insert into readings (id, reading) values (2, 2); \
+                 insert into readings (id, reading) values (3, 0.25); \
+                 insert into readings (id, reading) values (4, 3E-1)",
+                transaction.clone(),
+            )?;
+            transaction.borrow_mut().commit()?;
+        }
+
+        // 開き直しても、catalogからFLOATの型を読める
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let layout = metadata_manager.get_layout("readings".to_string(), transaction.clone())?;
+        assert_eq!(
+            layout.schema.get_field_type("reading".to_string()),
+            Some(TableFieldType::FLOAT)
+        );
+
+        let mut fetch =
+            |sql: &str, field_name: &str| -> Result<Vec<ConstantValue>, ValueNotFound> {
+                let parsed_sql = &parse_sql(sql.to_string()).unwrap()[0];
+                let crate::query::parser::ParsedSQL::Query(query_data) = parsed_sql else {
+                    panic!("Expected a Query variant from parse_sql");
+                };
+                let plan =
+                    create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+                let mut scan = plan.open()?;
+                let mut values = Vec::new();
+                while scan.next()? {
+                    values.push(
+                        scan.get_value(TableNameAndFieldName::new(None, field_name.to_string()))
+                            .unwrap(),
+                    );
+                }
+                scan.close();
+                return Ok(values);
+            };
+
+        // intやdecimalのliteralとも値で比べて並べる
+        assert_eq!(
+            fetch(
+                "select reading from readings where reading > 0.25 order by reading",
+                "reading"
+            )?,
+            vec![
+                ConstantValue::Float(0.3),
+                ConstantValue::Float(1.5),
+                ConstantValue::Float(2.0),
+            ]
+        );
+        // indexは2と2.0を同じkeyとして探す
+        assert_eq!(
+            fetch("select id from readings where reading = 2", "id")?,
+            vec![ConstantValue::Number(2)]
+        );
+        assert_eq!(
+            fetch(
+                "select reading * 2 as doubled from readings where id = 3",
+                "doubled"
+            )?,
+            vec![ConstantValue::Float(0.5)]
+        );
+
+        transaction.borrow_mut().commit()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_null_constant_is_not_an_index_key() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table accounts (id integer, name varchar(10)); \
+             create index accounts_id on accounts (id); \
+             insert into accounts (id, name) values (1, 'alice'); \
+             insert into accounts (id, name) values (null, 'bob')",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        // id = null はindexを使わずに読み、どの行にも当てはまらない
+        let transaction = database.new_transaction(2);
+        assert_eq!(
+            database.execute(
+                "select name from accounts where id = null",
+                transaction.clone()
+            )?,
+            crate::database::QueryResult::Rows {
+                columns: vec!["name".to_string()],
+                rows: vec![],
+            }
+        );
+        // <=> null はNULLの行に当てはまる
+        assert_eq!(
+            database.execute(
+                "select name from accounts where id <=> null",
+                transaction.clone()
+            )?,
+            crate::database::QueryResult::Rows {
+                columns: vec!["name".to_string()],
+                rows: vec![vec![ConstantValue::String("bob".to_string())]],
+            }
+        );
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_explain_delete_uses_index() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table accounts (id integer, name varchar(10)); \
+             create index accounts_id on accounts (id)",
+            transaction.clone(),
+        )?;
+        for id in 0..20 {
+            database.execute(
+                &format!(
+                    "insert into accounts (id, name) values ({}, 'name {}')",
+                    id % 5,
+                    id
+                ),
+                transaction.clone(),
+            )?;
+        }
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+
+        // 括弧の中の条件やfieldは除いて、planの種類だけを比べる
+        let node_types = |plan: &PlanTreeNodeForDebug| -> Vec<String> {
+            let mut node_types = vec![];
+            let mut nodes = vec![plan];
+            while let Some(node) = nodes.pop() {
+                let node_type = node.current_node_type.split('(').next().unwrap();
+                node_types.push(node_type.to_string());
+                nodes.extend(node.child_nodes.iter());
+            }
+            return node_types;
+        };
+
+        let parsed_sql =
+            &parse_sql("explain delete from accounts where id = 3".to_string()).unwrap()[0];
+        let crate::query::parser::ParsedSQL::ExplainDml { statement, .. } = parsed_sql else {
+            panic!("Expected an ExplainDml variant from parse_sql");
+        };
+        let crate::query::parser::ParsedSQL::Delete(delete_data) = statement.as_ref() else {
+            panic!("Expected a Delete statement in explain");
+        };
+        let plan = create_modify_plan(
+            &delete_data.table_name,
+            &delete_data.predicate,
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+        assert_eq!(
+            node_types(&plan.get_child_plans()),
+            vec!["SelectPlanV2", "IndexSelectPlan", "TablePlanV2"]
+        );
+
+        // indexのないfieldの条件ではtableを全部読む
+        let parsed_sql = &parse_sql(
+            "explain (format json) update accounts set id = 9 where name = 'name 3'".to_string(),
+        )
+        .unwrap()[0];
+        let crate::query::parser::ParsedSQL::ExplainDml { statement, format } = parsed_sql else {
+            panic!("Expected an ExplainDml variant from parse_sql");
+        };
+        assert_eq!(*format, ExplainFormat::Json);
+        let crate::query::parser::ParsedSQL::Update(update_data) = statement.as_ref() else {
+            panic!("Expected an Update statement in explain");
+        };
+        let plan = create_modify_plan(
+            &update_data.table_name,
+            &update_data.predicate,
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+        assert_eq!(
+            node_types(&plan.get_child_plans()),
+            vec!["SelectPlanV2", "TablePlanV2"]
+        );
+
+        // 同じplanで実際に消すと、indexで探した行だけが消える
+        database.execute(
+            "delete from accounts where id = 3; update accounts set id = 3 where id = 4",
+            transaction.clone(),
+        )?;
+        let parsed_sql =
+            &parse_sql("select name from accounts where id = 3".to_string()).unwrap()[0];
+        let crate::query::parser::ParsedSQL::Query(query_data) = parsed_sql else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+        let plan = create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+        // selectでもindexで探す
+        assert!(node_types(&plan.get_child_plans()).contains(&"IndexSelectPlan".to_string()));
+        let mut scan = plan.open()?;
+        let mut names = vec![];
+        while scan.next()? {
+            names.push(
+                scan.get_value(TableNameAndFieldName::new(None, "name".to_string()))
+                    .unwrap(),
+            );
+        }
+        scan.close();
+
+        assert_eq!(
+            names,
+            ["name 4", "name 9", "name 14", "name 19"]
+                .iter()
+                .map(|name| ConstantValue::String(name.to_string()))
+                .collect::<Vec<_>>()
+        );
+
+        let parsed_sql =
+            &parse_sql("select id from accounts where name = 'name 3'".to_string()).unwrap()[0];
+        let crate::query::parser::ParsedSQL::Query(query_data) = parsed_sql else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+        let plan = create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+        assert!(!node_types(&plan.get_child_plans()).contains(&"IndexSelectPlan".to_string()));
+        transaction.borrow_mut().commit()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_star_join() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table users (id integer, name varchar(10)); \
+             create table orders (user_id integer, item varchar(10)); \
+             insert into users (id, name) values (1, 'alice'); \
+             insert into users (id, name) values (2, 'bob'); \
+             insert into orders (user_id, item) values (2, 'pen')",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let parsed_sql =
+            &parse_sql("select * from users, orders where id = user_id".to_string()).unwrap()[0];
+        let crate::query::parser::ParsedSQL::Query(query_data) = parsed_sql else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+        // select * は両方のtableのfieldを返す
+        let plan = create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+        let mut fields = plan.get_schema().fields.clone();
+        fields.sort();
+        assert_eq!(fields, vec!["id", "item", "name", "user_id"]);
+
+        let mut cursor = crate::query::cursor::Cursor::open(plan, transaction.clone())?;
+        let schema_fields = cursor.get_schema().fields.clone();
+        let rows = cursor.fetch(10)?;
+        cursor.close();
+        transaction.borrow_mut().commit()?;
+
+        assert_eq!(rows.len(), 1);
+        let value_of = |field_name: &str| {
+            let index = schema_fields.iter().position(|f| f == field_name).unwrap();
+            return rows[0][index].clone();
+        };
+        assert_eq!(value_of("name"), ConstantValue::String("bob".to_string()));
+        assert_eq!(value_of("item"), ConstantValue::String("pen".to_string()));
+        assert_eq!(value_of("user_id"), ConstantValue::Number(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_on() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table users (id integer, name varchar(10)); \
+             create table orders (user_id integer, item_id integer); \
+             create table items (code integer, title varchar(10)); \
+             insert into users (id, name) values (1, 'alice'); \
+             insert into users (id, name) values (2, 'bob'); \
+             insert into users (id, name) values (3, 'carol'); \
+             insert into orders (user_id, item_id) values (2, 10); \
+             insert into orders (user_id, item_id) values (1, 20); \
+             insert into orders (user_id, item_id) values (3, 10); \
+             insert into items (code, title) values (10, 'pen'); \
+             insert into items (code, title) values (20, 'ink')",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let select_names =
+            |sql: &str| -> Result<Vec<(ConstantValue, ConstantValue)>, DatabaseError> {
+                let transaction = database.new_transaction(2);
+                let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+                let parsed_sql = &parse_sql(sql.to_string()).unwrap()[0];
+                let crate::query::parser::ParsedSQL::Query(query_data) = parsed_sql else {
+                    panic!("Expected a Query variant from parse_sql");
+                };
+                let plan =
+                    create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+                let mut cursor = crate::query::cursor::Cursor::open(plan, transaction.clone())?;
+                let schema_fields = cursor.get_schema().fields.clone();
+                let name_index = schema_fields.iter().position(|f| f == "name").unwrap();
+                let title_index = schema_fields.iter().position(|f| f == "title").unwrap();
+                let rows = cursor.fetch(10)?;
+                cursor.close();
+                transaction.borrow_mut().commit()?;
+                return Ok(rows
+                    .iter()
+                    .map(|row| (row[name_index].clone(), row[title_index].clone()))
+                    .collect());
+            };
+
+        // onの条件はwhereの条件と合わせて使われる
+        let joined = select_names(
+            "select name, title from users \
+             join orders on users.id = orders.user_id \
+             inner join items on orders.item_id = items.code \
+             where name <> 'carol' order by name",
+        )?;
+        assert_eq!(
+            joined,
+            vec![
+                (
+                    ConstantValue::String("alice".to_string()),
+                    ConstantValue::String("ink".to_string())
+                ),
+                (
+                    ConstantValue::String("bob".to_string()),
+                    ConstantValue::String("pen".to_string())
+                ),
+            ]
+        );
+        assert_eq!(
+            select_names(
+                "select name, title from users, orders, items \
+                 where users.id = orders.user_id and orders.item_id = items.code \
+                 and name <> 'carol' order by name",
+            )?,
+            joined
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_star() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table users (id integer, name varchar(10)); \
+             create table orders (user_id integer, item varchar(10)); \
+             insert into users (id, name) values (2, 'bob'); \
+             insert into orders (user_id, item) values (2, 'pen')",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let parse_query = |sql: &str| -> QueryData {
+            let crate::query::parser::ParsedSQL::Query(query_data) =
+                &parse_sql(sql.to_string()).unwrap()[0]
+            else {
+                panic!("Expected a Query variant from parse_sql");
+            };
+            return query_data.clone();
+        };
+
+        // users.* はusersのfieldだけに展開する
+        let query_data =
+            parse_query("select users.*, orders.item from users, orders where id = user_id");
+        let expanded = expand_table_stars(&query_data, transaction.clone(), &mut metadata_manager)?;
+        assert_eq!(
+            expanded.field_name_list,
+            vec![
+                TableNameAndFieldName::new(Some("users".to_string()), "id".to_string()),
+                TableNameAndFieldName::new(Some("users".to_string()), "name".to_string()),
+                TableNameAndFieldName::new(Some("orders".to_string()), "item".to_string()),
+            ]
+        );
+        let schema = validate_query(&query_data, transaction.clone(), &mut metadata_manager)?;
+        assert_eq!(schema.fields, vec!["id", "name", "item"]);
+
+        // valuesの別名も展開できる
+        let query_data = parse_query("select t.* from (values (1, 'a')) as t(x, y)");
+        let schema = validate_query(&query_data, transaction.clone(), &mut metadata_manager)?;
+        assert_eq!(schema.fields, vec!["x", "y"]);
+
+        // from句に無いtable
+        let query_data = parse_query("select other.* from users");
+        assert!(validate_query(&query_data, transaction.clone(), &mut metadata_manager).is_err());
+
+        transaction.borrow_mut().commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_order_is_chosen_by_cost() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table big (bx integer, bpad varchar(20)); \
+             create table mid (mx integer, my integer); \
+             create table small (sy integer)",
+            transaction.clone(),
+        )?;
+        for i in 0..300 {
+            database.execute(
+                &format!("insert into big (bx, bpad) values ({}, 'pad')", i % 60),
+                transaction.clone(),
+            )?;
+        }
+        for i in 0..30 {
+            database.execute(
+                &format!("insert into mid (mx, my) values ({}, {})", i, i % 3),
+                transaction.clone(),
+            )?;
+        }
+        for i in 0..3 {
+            database.execute(
+                &format!("insert into small (sy) values ({})", i),
+                transaction.clone(),
+            )?;
+        }
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let parsed_sql_list = parse_sql(
+            "select bx, mx, sy from big, mid, small where bx = mx and my = sy".to_string(),
+        )
+        .unwrap();
+        let crate::query::parser::ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+
+        // from句の順に左から並べたproduct
+        let mut listed_order_plan: Box<dyn PlanV2> = Box::new(TablePlanV2::new(
+            "big".to_string(),
+            transaction.clone(),
+            &mut metadata_manager,
+        )?);
+        for table_name in ["mid", "small"] {
+            let table_plan = TablePlanV2::new(
+                table_name.to_string(),
+                transaction.clone(),
+                &mut metadata_manager,
+            )?;
+            listed_order_plan =
+                Box::new(ProductPlanV2::new(listed_order_plan, Box::new(table_plan)));
+        }
+        let listed_order_plan = SelectPlanV2::new(listed_order_plan, query_data.predicate.clone());
+
+        let plan = create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+        assert!(
+            plan.blocks_accessed() < listed_order_plan.blocks_accessed(),
+            "{}",
+            plan.explain()
+        );
+        // 小さいsmallとmidを先にproductにし、bigはmerge joinで足す
+        assert!(plan.explain().contains("MergeJoinPlan(mx = bx)"));
+
+        let mut scan = plan.open()?;
+        let mut rows = 0;
+        while scan.next()? {
+            let bx = scan.get_value(TableNameAndFieldName::new(None, "bx".to_string()));
+            let mx = scan.get_value(TableNameAndFieldName::new(None, "mx".to_string()));
+            assert_eq!(bx, mx);
+            rows += 1;
+        }
+        scan.close();
+        // bxが0から29の行が150行あり、myはどれもsmallにある
+        assert_eq!(rows, 150);
+        transaction.borrow_mut().commit()?;
+
+        Ok(())
+    }
 }
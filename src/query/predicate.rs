@@ -1,11 +1,46 @@
-#[derive(Debug, Clone, PartialEq, Eq)]
+use crate::query::predicate_v3::{ArithmeticOp, ExpressionV2, ScalarFunction};
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ConstantValue {
     String(String),
     Number(i32),
+    // scaleをかけた整数として持つ。12.34 (scale 2) は value 1234
+    Decimal { value: i32, scale: u32 },
+    Float(f64),
+    // 1970-01-01からの日数
+    Date(i64),
+    // prepareした文の ? の位置。0から数える。実行の前に値に置き換える
+    Parameter(usize),
     Null,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+// NaNはliteralにもCOPYにも書けず、有限にならない計算やbindした値はNULLになるので、
+// Floatも含めてEqとして扱う
+impl Eq for ConstantValue {}
+
+// PartialEqと同じく型ごとに比べる。0.0と-0.0は等しいので同じhashにする
+impl std::hash::Hash for ConstantValue {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            ConstantValue::String(s) => s.hash(state),
+            ConstantValue::Number(n) => n.hash(state),
+            ConstantValue::Decimal { value, scale } => {
+                value.hash(state);
+                scale.hash(state);
+            }
+            ConstantValue::Float(f) => {
+                let f = if *f == 0.0 { 0.0 } else { *f };
+                f.to_bits().hash(state);
+            }
+            ConstantValue::Date(days) => days.hash(state),
+            ConstantValue::Parameter(index) => index.hash(state),
+            ConstantValue::Null => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Constant {
     pub value: ConstantValue,
 }
@@ -26,56 +61,192 @@ impl Constant {
                     }
                 }
                 ConstantValue::Number(_n) => return false,
+                ConstantValue::Decimal { .. } => return false,
+                ConstantValue::Float(_) => return false,
+                ConstantValue::Date(_) => return false,
+                ConstantValue::Parameter(_) => return false,
                 ConstantValue::Null => return false,
             },
-            ConstantValue::Number(n) => match self.value {
-                ConstantValue::String(ref _str) => return false,
-                ConstantValue::Number(m) => {
-                    if n == m {
-                        return true;
-                    } else {
-                        return false;
+            ConstantValue::Date(days) => return self.value == ConstantValue::Date(days),
+            ConstantValue::Number(_) | ConstantValue::Decimal { .. } | ConstantValue::Float(_) => {
+                if let (Some(lhs), Some(rhs)) = (
+                    Constant::float_value(&self.value),
+                    Constant::float_value(&value),
+                ) {
+                    if Constant::is_float(&self.value) || Constant::is_float(&value) {
+                        return lhs == rhs;
                     }
                 }
-                ConstantValue::Null => return false,
-            },
-            ConstantValue::Null => return false,
+                match (
+                    Constant::numeric_value(&self.value),
+                    Constant::numeric_value(&value),
+                ) {
+                    (Some((m, self_scale)), Some((n, scale))) => {
+                        return Constant::compare_decimal(m, self_scale, n, scale)
+                            == std::cmp::Ordering::Equal;
+                    }
+                    _ => return false,
+                }
+            }
+            ConstantValue::Parameter(_) | ConstantValue::Null => return false,
         }
     }
 
+    // 数値の型は Number (int) -> Decimal -> Float の順に広がる
+    // Numberはscale 0のDecimalとして扱うので、どの組み合わせも値で比べられる
+    fn numeric_value(value: &ConstantValue) -> Option<(i32, u32)> {
+        match value {
+            ConstantValue::Number(n) => return Some((*n, 0)),
+            ConstantValue::Decimal { value, scale } => return Some((*value, *scale)),
+            _ => return None,
+        }
+    }
+
+    fn is_float(value: &ConstantValue) -> bool {
+        return matches!(value, ConstantValue::Float(_));
+    }
+
+    // 片方がFloatのときは、もう片方もf64に広げて比べる
+    pub fn float_value(value: &ConstantValue) -> Option<f64> {
+        match value {
+            ConstantValue::Number(n) => return Some(*n as f64),
+            ConstantValue::Decimal { value, scale } => {
+                return Some(*value as f64 / 10_f64.powi(*scale as i32));
+            }
+            ConstantValue::Float(f) => return Some(*f),
+            _ => return None,
+        }
+    }
+
+    // scaleが違っても値として比べる (1.5 と 1.50 は等しい)
+    fn compare_decimal(lhs: i32, lhs_scale: u32, rhs: i32, rhs_scale: u32) -> std::cmp::Ordering {
+        let scale = lhs_scale.max(rhs_scale);
+        let lhs = lhs as i128 * 10_i128.pow(scale - lhs_scale);
+        let rhs = rhs as i128 * 10_i128.pow(scale - rhs_scale);
+        return lhs.cmp(&rhs);
+    }
+
+    // scaleを変える。桁を落とすときは四捨五入 (0から遠い方へ)。i32に収まらなければNone
+    pub fn rescale_decimal(value: i32, from_scale: u32, to_scale: u32) -> Option<i32> {
+        let value = value as i128;
+        let rescaled = if to_scale >= from_scale {
+            value * 10_i128.pow(to_scale - from_scale)
+        } else {
+            let divisor = 10_i128.pow(from_scale - to_scale);
+            let quotient = value / divisor;
+            let remainder = value % divisor;
+            if remainder.abs() * 2 >= divisor {
+                quotient + value.signum()
+            } else {
+                quotient
+            }
+        };
+        return i32::try_from(rescaled).ok();
+    }
+
+    pub fn format_decimal(value: i32, scale: u32) -> String {
+        if scale == 0 {
+            return value.to_string();
+        }
+        let divisor = 10_i64.pow(scale);
+        let abs = (value as i64).abs();
+        let sign = if value < 0 { "-" } else { "" };
+        return format!(
+            "{}{}.{:0width$}",
+            sign,
+            abs / divisor,
+            abs % divisor,
+            width = scale as usize
+        );
+    }
+
+    // 1970-01-01からの日数とyear, month, dayの変換。グレゴリオ暦を過去にもそのまま延ばす
+    pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let month_from_march = (month as i64 + 9) % 12;
+        let day_of_year = (153 * month_from_march + 2) / 5 + day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        return era * 146097 + day_of_era - 719468;
+    }
+
+    pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+        let days = days + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_from_march = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+        let month = if month_from_march < 10 {
+            month_from_march + 3
+        } else {
+            month_from_march - 9
+        } as u32;
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        return (year, month, day);
+    }
+
+    // "2024-01-01" -> 19723。存在しない日付 (2023-02-30など) はNone
+    pub fn parse_date(date: &str) -> Option<i64> {
+        let mut parts = date.splitn(3, '-');
+        let year = parts.next()?.parse::<i64>().ok()?;
+        let month = parts.next()?.parse::<u32>().ok()?;
+        let day = parts.next()?.parse::<u32>().ok()?;
+        let days = Constant::days_from_civil(year, month, day);
+        if Constant::civil_from_days(days) != (year, month, day) {
+            return None;
+        }
+        return Some(days);
+    }
+
+    pub fn format_date(days: i64) -> String {
+        let (year, month, day) = Constant::civil_from_days(days);
+        return format!("{:04}-{:02}-{:02}", year, month, day);
+    }
+
+    // 型が違っても順序が決まるように、NULL < 数値 < 日付 < 文字列 の順に並べる
+    // 数値同士はINTEGER / DECIMAL / FLOATの違いに関係なく値で、文字列は大文字小文字を区別せずに比べる
     pub fn compare_to(&self, value: ConstantValue) -> std::cmp::Ordering {
+        if let (ConstantValue::Date(lhs), ConstantValue::Date(rhs)) = (&self.value, &value) {
+            return lhs.cmp(rhs);
+        }
+
+        if Constant::is_float(&self.value) || Constant::is_float(&value) {
+            if let (Some(lhs), Some(rhs)) = (
+                Constant::float_value(&self.value),
+                Constant::float_value(&value),
+            ) {
+                return lhs.partial_cmp(&rhs).unwrap_or_else(|| lhs.total_cmp(&rhs));
+            }
+        }
+
+        if let (Some((m, self_scale)), Some((n, scale))) = (
+            Constant::numeric_value(&self.value),
+            Constant::numeric_value(&value),
+        ) {
+            return Constant::compare_decimal(m, self_scale, n, scale);
+        }
+
+        if let (ConstantValue::String(lhs), ConstantValue::String(rhs)) = (&self.value, &value) {
+            return lhs.to_lowercase().cmp(&rhs.to_lowercase());
+        }
+
+        return Constant::type_rank(&self.value).cmp(&Constant::type_rank(&value));
+    }
+
+    // compare_toで型が違う値を比べるときの順番
+    fn type_rank(value: &ConstantValue) -> u8 {
         match value {
-            ConstantValue::String(s) => match self.value.clone() {
-                ConstantValue::String(str) => {
-                    if str.to_lowercase() == s.to_lowercase() {
-                        return std::cmp::Ordering::Equal;
-                    } else if str.to_lowercase() < s.to_lowercase() {
-                        return std::cmp::Ordering::Less;
-                    } else {
-                        return std::cmp::Ordering::Greater;
-                    }
-                }
-                _ => {
-                    panic!("Cannot compare String with non-String value")
-                }
-            },
-            ConstantValue::Number(n) => match self.value {
-                ConstantValue::Number(m) => {
-                    if n == m {
-                        return std::cmp::Ordering::Equal;
-                    } else if m < n {
-                        return std::cmp::Ordering::Less;
-                    } else {
-                        return std::cmp::Ordering::Greater;
-                    }
-                }
-                _ => {
-                    panic!("Cannot compare Number with non-Number value")
-                }
-            },
-            _ => {
-                panic!("Cannot compare Null value")
+            ConstantValue::Null => return 0,
+            ConstantValue::Number(_) | ConstantValue::Decimal { .. } | ConstantValue::Float(_) => {
+                return 1;
             }
+            ConstantValue::Date(_) => return 2,
+            ConstantValue::String(_) => return 3,
+            ConstantValue::Parameter(index) => panic!("Parameter {} is not bound", index + 1),
         }
     }
 }
@@ -84,6 +255,17 @@ impl Constant {
 pub enum ExpressionValue {
     TableNameAndFieldName(TableNameAndFieldName),
     Constant(Constant),
+    // price * qty など
+    Arithmetic {
+        op: ArithmeticOp,
+        lhs: Box<ExpressionV2>,
+        rhs: Box<ExpressionV2>,
+    },
+    // length(name) など
+    Function {
+        function: ScalarFunction,
+        argument: Box<ExpressionV2>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -100,3 +282,174 @@ impl TableNameAndFieldName {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(value: i32, scale: u32) -> ConstantValue {
+        ConstantValue::Decimal { value, scale }
+    }
+
+    #[test]
+    fn test_compare_decimal_with_different_scales() {
+        let one_and_half = Constant::new(decimal(15, 1));
+
+        assert!(one_and_half.equals(decimal(150, 2)));
+        assert_eq!(
+            one_and_half.compare_to(decimal(150, 2)),
+            std::cmp::Ordering::Equal
+        );
+        assert_eq!(
+            one_and_half.compare_to(decimal(1234, 2)),
+            std::cmp::Ordering::Less
+        );
+        assert_eq!(
+            one_and_half.compare_to(decimal(-2, 0)),
+            std::cmp::Ordering::Greater
+        );
+        assert!(!one_and_half.equals(ConstantValue::Number(15)));
+    }
+
+    #[test]
+    fn test_compare_numeric_promotion() {
+        let int_two = Constant::new(ConstantValue::Number(2));
+        let decimal_two = Constant::new(decimal(200, 2));
+
+        // int と int
+        assert_eq!(
+            int_two.compare_to(ConstantValue::Number(10)),
+            std::cmp::Ordering::Less
+        );
+        // int と decimal
+        assert!(int_two.equals(decimal(20, 1)));
+        assert_eq!(
+            int_two.compare_to(decimal(199, 2)),
+            std::cmp::Ordering::Greater
+        );
+        // decimal と int
+        assert!(decimal_two.equals(ConstantValue::Number(2)));
+        assert_eq!(
+            decimal_two.compare_to(ConstantValue::Number(10)),
+            std::cmp::Ordering::Less
+        );
+        assert_eq!(
+            Constant::new(decimal(-150, 2)).compare_to(ConstantValue::Number(-2)),
+            std::cmp::Ordering::Greater
+        );
+        // decimal と decimal
+        assert_eq!(
+            decimal_two.compare_to(decimal(2, 0)),
+            std::cmp::Ordering::Equal
+        );
+
+        // 文字列と数値は等しくならない
+        assert!(!int_two.equals(ConstantValue::String("2".to_string())));
+    }
+
+    #[test]
+    fn test_compare_mixed_types() {
+        let null = Constant::new(ConstantValue::Null);
+        let number = Constant::new(ConstantValue::Number(2));
+        let string = Constant::new(ConstantValue::String("abc".to_string()));
+
+        // NULLが一番小さく、NULL同士は等しい
+        assert_eq!(
+            null.compare_to(ConstantValue::Number(-100)),
+            std::cmp::Ordering::Less
+        );
+        assert_eq!(
+            number.compare_to(ConstantValue::Null),
+            std::cmp::Ordering::Greater
+        );
+        assert_eq!(
+            null.compare_to(ConstantValue::Null),
+            std::cmp::Ordering::Equal
+        );
+
+        // 型が違うときは値ではなく型の順番で決まる
+        assert_eq!(
+            number.compare_to(ConstantValue::String("1".to_string())),
+            std::cmp::Ordering::Less
+        );
+        assert_eq!(
+            string.compare_to(ConstantValue::Float(1e10)),
+            std::cmp::Ordering::Greater
+        );
+        assert_eq!(
+            string.compare_to(ConstantValue::Date(0)),
+            std::cmp::Ordering::Greater
+        );
+
+        assert_eq!(
+            number.compare_to(ConstantValue::Number(2)),
+            std::cmp::Ordering::Equal
+        );
+        assert_eq!(
+            string.compare_to(ConstantValue::String("abc".to_string())),
+            std::cmp::Ordering::Equal
+        );
+        assert_eq!(
+            string.compare_to(ConstantValue::String("abd".to_string())),
+            std::cmp::Ordering::Less
+        );
+    }
+
+    #[test]
+    fn test_date_conversion_and_compare() {
+        assert_eq!(Constant::parse_date("1970-01-01"), Some(0));
+        assert_eq!(Constant::parse_date("2024-01-01"), Some(19723));
+        assert_eq!(Constant::parse_date("1969-12-31"), Some(-1));
+        assert_eq!(Constant::parse_date("2024-02-29"), Some(19782));
+        assert_eq!(Constant::parse_date("2023-02-29"), None);
+        assert_eq!(Constant::parse_date("2023-13-01"), None);
+        for days in [-719468, -1, 0, 19723, 2932896] {
+            let formatted = Constant::format_date(days);
+            assert_eq!(Constant::parse_date(&formatted), Some(days));
+        }
+        assert_eq!(Constant::format_date(19782), "2024-02-29");
+
+        let new_year = Constant::new(ConstantValue::Date(19723));
+        assert!(new_year.equals(ConstantValue::Date(19723)));
+        assert!(!new_year.equals(ConstantValue::Number(19723)));
+        assert_eq!(
+            new_year.compare_to(ConstantValue::Date(19358)),
+            std::cmp::Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn test_compare_float() {
+        let float = Constant::new(ConstantValue::Float(1.5));
+
+        assert!(float.equals(decimal(150, 2)));
+        assert!(Constant::new(ConstantValue::Float(2.0)).equals(ConstantValue::Number(2)));
+        assert_eq!(
+            float.compare_to(ConstantValue::Number(2)),
+            std::cmp::Ordering::Less
+        );
+        assert_eq!(
+            Constant::new(ConstantValue::Number(1)).compare_to(ConstantValue::Float(0.5)),
+            std::cmp::Ordering::Greater
+        );
+        assert_eq!(
+            float.compare_to(ConstantValue::Float(-1e10)),
+            std::cmp::Ordering::Greater
+        );
+        assert!(!float.equals(ConstantValue::String("1.5".to_string())));
+    }
+
+    #[test]
+    fn test_rescale_and_format_decimal() {
+        assert_eq!(Constant::rescale_decimal(1234, 2, 4), Some(123400));
+        // 落とす桁は四捨五入
+        assert_eq!(Constant::rescale_decimal(125, 3, 2), Some(13));
+        assert_eq!(Constant::rescale_decimal(-125, 3, 2), Some(-13));
+        assert_eq!(Constant::rescale_decimal(124, 3, 2), Some(12));
+        assert_eq!(Constant::rescale_decimal(i32::MAX, 0, 1), None);
+
+        assert_eq!(Constant::format_decimal(1234, 2), "12.34");
+        assert_eq!(Constant::format_decimal(-5, 2), "-0.05");
+        assert_eq!(Constant::format_decimal(7, 0), "7");
+    }
+}
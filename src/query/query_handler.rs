@@ -1,12 +1,15 @@
 use std::{cell::RefCell, rc::Rc, vec};
 
-use comfy_table::Table;
+use comfy_table::{CellAlignment, Table};
 
 use crate::{
+    error::{DatabaseError, FileAccessError, ValueNotFound},
     metadata::metadata_manager::MetadataManager,
-    query::parser::QueryData,
-    query::plan_v2::{create_query_plan, PlanV2},
-    query::predicate::{ConstantValue, TableNameAndFieldName},
+    query::csv::{format_csv_record, format_csv_value},
+    query::parser::{CopyToData, QueryData},
+    query::plan_v2::{create_query_plan, expand_table_stars, validate_query, PlanV2},
+    query::predicate::{Constant, ConstantValue, TableNameAndFieldName},
+    record::record_page::TableSchema,
     tx::transaction_v2::TransactionV2,
 };
 
@@ -22,43 +25,26 @@ pub fn handle_select_query(
         return;
     }
 
-    let mut plan = create_query_plan(&select_query, transaction.clone(), metadata_manager).unwrap();
+    // order byの列番号が範囲外のときなどは、planを作る前にエラーを表示する
+    if let Err(error) = validate_query(&select_query, transaction.clone(), metadata_manager) {
+        println!("Error executing select: {:?}", error);
+        return;
+    }
+
+    let plan = create_query_plan(&select_query, transaction.clone(), metadata_manager).unwrap();
+    let select_query =
+        expand_table_stars(&select_query, transaction.clone(), metadata_manager).unwrap();
 
     let plan_tree = plan.get_child_plans();
     println!("Query Plan:");
     plan_tree.print_tree();
 
     let mut scan = plan.open().unwrap();
-    scan.move_to_before_first();
+    scan.move_to_before_first().unwrap();
 
     let mut result_vec = vec![];
-    let mut headers = select_query
-        .field_name_list
-        .iter()
-        .map(|field_name| {
-            if let Some(table_name) = &field_name.table_name {
-                return format!("{}.{}", table_name, field_name.field_name);
-            } else {
-                return field_name.field_name.clone();
-            }
-        })
-        .collect::<Vec<String>>();
-
-    let aggregate_function_headers = select_query
-        .aggregate_functions
-        .iter()
-        .map(|aggregate_function_info| {
-            let field_name = aggregate_function_info.field.clone();
-            let function_name = aggregate_function_info.function_type.clone();
-            if let Some(table_name) = &field_name.table_name {
-                return format!("{}_{}.{}", function_name, table_name, field_name.field_name);
-            } else {
-                return format!("{}_{}", function_name, field_name.field_name);
-            }
-        })
-        .collect::<Vec<String>>();
-
-    headers.extend(aggregate_function_headers);
+    let fields = output_fields(&select_query, plan.get_schema());
+    let headers = result_headers(&select_query, &fields);
 
     loop {
         match scan.next() {
@@ -66,22 +52,14 @@ pub fn handle_select_query(
                 if !has_next {
                     break;
                 }
-                let results = select_query
-                    .field_name_list
+                let results = fields
                     .iter()
                     .map(|field_name| {
                         let value = scan.get_value(field_name.clone());
                         return value;
                     })
-                    .map(|value| match value {
-                        Some(value) => match value {
-                            ConstantValue::String(s) => s,
-                            ConstantValue::Number(i) => i.to_string(),
-                            ConstantValue::Null => "".to_string(),
-                        },
-                        None => "".to_string(),
-                    })
-                    .collect::<Vec<String>>();
+                    .map(|value| value.unwrap_or(ConstantValue::Null))
+                    .collect::<Vec<ConstantValue>>();
 
                 result_vec.push(results);
 
@@ -97,15 +75,8 @@ pub fn handle_select_query(
                         ));
                         return value;
                     })
-                    .map(|value| match value {
-                        Some(value) => match value {
-                            ConstantValue::String(s) => s,
-                            ConstantValue::Number(i) => i.to_string(),
-                            ConstantValue::Null => "".to_string(),
-                        },
-                        None => "".to_string(),
-                    })
-                    .collect::<Vec<String>>();
+                    .map(|value| value.unwrap_or(ConstantValue::Null))
+                    .collect::<Vec<ConstantValue>>();
 
                 result_vec
                     .last_mut()
@@ -119,14 +90,166 @@ pub fn handle_select_query(
         }
     }
 
+    println!("{}", format_result_table(&headers, &result_vec));
+}
+
+// REPLに表示するときの値。文字列は引用符をつけず、nullはNULLと書く
+pub fn format_display_value(value: &ConstantValue) -> String {
+    match value {
+        ConstantValue::String(s) => return s.clone(),
+        ConstantValue::Number(i) => return i.to_string(),
+        ConstantValue::Decimal { value, scale } => return Constant::format_decimal(*value, *scale),
+        ConstantValue::Float(f) => return f.to_string(),
+        ConstantValue::Date(days) => return Constant::format_date(*days),
+        ConstantValue::Parameter(_) => return "?".to_string(),
+        ConstantValue::Null => return "NULL".to_string(),
+    }
+}
+
+// headerと区切り線のついた表にする。数値のcolumnは右に揃える
+pub fn format_result_table(headers: &[String], rows: &[Vec<ConstantValue>]) -> String {
     let mut table = Table::new();
     table.set_header(headers);
 
-    for result in result_vec {
-        table.add_row(result);
+    for row in rows.iter() {
+        table.add_row(
+            row.iter()
+                .map(format_display_value)
+                .collect::<Vec<String>>(),
+        );
+    }
+
+    for (index, column) in table.column_iter_mut().enumerate() {
+        let is_numeric = rows.iter().all(|row| match row.get(index) {
+            Some(ConstantValue::Number(_))
+            | Some(ConstantValue::Decimal { .. })
+            | Some(ConstantValue::Float(_))
+            | Some(ConstantValue::Null)
+            | None => true,
+            _ => false,
+        });
+        if is_numeric && !rows.is_empty() {
+            column.set_cell_alignment(CellAlignment::Right);
+        }
     }
 
-    println!("{table}");
+    return table.to_string();
+}
+
+// select * のときはplanのschemaのfieldをすべて返す
+fn output_fields(select_query: &QueryData, schema: &TableSchema) -> Vec<TableNameAndFieldName> {
+    if select_query.all_columns {
+        return schema
+            .fields
+            .iter()
+            .map(|field| TableNameAndFieldName::new(None, field.clone()))
+            .collect();
+    }
+    return select_query.field_name_list.clone();
+}
+
+// selectの結果のcolumn名。projectionのfieldの後に集約関数が続く
+fn result_headers(select_query: &QueryData, fields: &[TableNameAndFieldName]) -> Vec<String> {
+    let mut headers = fields
+        .iter()
+        .map(|field_name| {
+            if let Some(table_name) = &field_name.table_name {
+                return format!("{}.{}", table_name, field_name.field_name);
+            } else {
+                return field_name.field_name.clone();
+            }
+        })
+        .collect::<Vec<String>>();
+
+    let aggregate_function_headers = select_query
+        .aggregate_functions
+        .iter()
+        .enumerate()
+        .map(|(index, aggregate_function_info)| {
+            if let Some(Some(alias)) = select_query.aggregate_aliases.get(index) {
+                return alias.clone();
+            }
+            let field_name = aggregate_function_info.field.clone();
+            let function_name = aggregate_function_info.function_type.clone();
+            if let Some(table_name) = &field_name.table_name {
+                return format!("{}_{}.{}", function_name, table_name, field_name.field_name);
+            } else {
+                return format!("{}_{}", function_name, field_name.field_name);
+            }
+        })
+        .collect::<Vec<String>>();
+
+    headers.extend(aggregate_function_headers);
+    return headers;
+}
+
+// selectを最後まで実行して、column名と行の値を返す
+// columnの並びはREPLの表示やcsvのheaderと同じで、projectionのfieldの後に集約関数が続く
+pub fn select_rows(
+    query_data: &QueryData,
+    metadata_manager: &mut MetadataManager,
+    transaction: Rc<RefCell<TransactionV2>>,
+) -> Result<(Vec<String>, Vec<Vec<ConstantValue>>), DatabaseError> {
+    // scanの途中でpanicしないように、実行する前に検証する
+    validate_query(query_data, transaction.clone(), metadata_manager)?;
+    let select_query = &expand_table_stars(query_data, transaction.clone(), metadata_manager)?;
+    let plan = create_query_plan(select_query, transaction.clone(), metadata_manager)?;
+    let mut scan = plan.open()?;
+
+    let fields = output_fields(select_query, plan.get_schema());
+    let mut columns = fields.clone();
+    for aggregate_function_info in select_query.aggregate_functions.iter() {
+        columns.push(TableNameAndFieldName::new(
+            None,
+            format!(
+                "{}_{}",
+                aggregate_function_info.function_type, aggregate_function_info.field.field_name
+            ),
+        ));
+    }
+
+    let mut rows = vec![];
+    while scan.next()? {
+        let mut row = vec![];
+        for column in columns.iter() {
+            let Some(value) = scan.get_value(column.clone()) else {
+                scan.close();
+                return Err(DatabaseError::ValueNotFound(ValueNotFound::new(
+                    column.field_name.clone(),
+                    column.table_name.clone(),
+                )));
+            };
+            row.push(value);
+        }
+        rows.push(row);
+    }
+    scan.close();
+
+    return Ok((result_headers(select_query, &fields), rows));
+}
+
+// selectの結果をheader付きのcsvに書き出し、書いた行数を返す
+pub fn export_csv(
+    copy_to_data: &CopyToData,
+    metadata_manager: &mut MetadataManager,
+    transaction: Rc<RefCell<TransactionV2>>,
+) -> Result<usize, DatabaseError> {
+    let (headers, rows) = select_rows(&copy_to_data.query_data, metadata_manager, transaction)?;
+
+    let mut content = format_csv_record(&headers);
+    for row in rows.iter() {
+        let fields = row.iter().map(format_csv_value).collect::<Vec<String>>();
+        content.push_str(&format_csv_record(&fields));
+    }
+
+    std::fs::write(&copy_to_data.file_path, content).map_err(|e| {
+        DatabaseError::FileAccess(FileAccessError::new(
+            copy_to_data.file_path.clone(),
+            e.to_string(),
+        ))
+    })?;
+
+    return Ok(rows.len());
 }
 
 #[cfg(test)]
@@ -135,17 +258,208 @@ mod tests {
 
     use crate::{
         database::Database,
+        query::csv::parse_csv,
         query::group_by::AggregateFunctionType,
-        query::parser::AggregateFunctionInfo,
+        query::parser::{parse_sql, AggregateFunctionInfo, OrderByItem, ParsedSQL},
+        query::sort_plan::SortDirection,
         query::predicate::{Constant, ConstantValue, ExpressionValue, TableNameAndFieldName},
         query::predicate_v3::{ExpressionV2, PredicateV2, TermV2},
     };
 
     use super::*;
 
+    #[test]
+    fn test_export_filtered_projection_to_csv() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(1);
+
+        database.execute(
+            "create table users (id integer, name varchar(20), active integer); \
+             insert into users (id, name, active) values (1, 'Smith, John', 1); \
+             insert into users (id, name, active) values (2, 'hidden', 0); \
+             insert into users (id, name, active) values (3, 'say \"hi\"', 1); \
+             insert into users (id, name, active) values (4, 'two\nlines', 1)",
+            transaction.clone(),
+        )?;
+
+        let csv_path = directory_path.join("out.csv");
+        database.execute(
+            &format!(
+                "copy (select id, name from users where active = 1) to '{}'",
+                csv_path.to_str().unwrap()
+            ),
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let content = std::fs::read_to_string(&csv_path).unwrap();
+        assert!(content.contains("\"Smith, John\""));
+        assert!(content.contains("\"say \"\"hi\"\"\""));
+
+        let records = parse_csv(&content);
+        let expected = vec![
+            vec!["id", "name"],
+            vec!["1", "Smith, John"],
+            vec!["3", "say \"hi\""],
+            vec!["4", "two\nlines"],
+        ];
+        assert_eq!(
+            records,
+            expected
+                .iter()
+                .map(|record| record.iter().map(|f| Some(f.to_string())).collect())
+                .collect::<Vec<Vec<Option<String>>>>()
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_export_aliased_aggregates_to_csv() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(1);
+
+        database.execute(
+            "create table users (id integer, active integer); \
+             insert into users (id, active) values (1, 1); \
+             insert into users (id, active) values (2, 0); \
+             insert into users (id, active) values (4, 1)",
+            transaction.clone(),
+        )?;
+
+        // 集約関数の別名はorder byでも使える
+        let csv_path = directory_path.join("out.csv");
+        database.execute(
+            &format!(
+                "copy (select active as flag, max(id) as maxid from users \
+                 group by active order by maxid desc) to '{}'",
+                csv_path.to_str().unwrap()
+            ),
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let content = std::fs::read_to_string(&csv_path).unwrap();
+        let expected = vec![vec!["flag", "maxid"], vec!["1", "4"], vec!["0", "2"]];
+        assert_eq!(
+            parse_csv(&content),
+            expected
+                .iter()
+                .map(|record| record.iter().map(|f| Some(f.to_string())).collect())
+                .collect::<Vec<Vec<Option<String>>>>()
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_export_whole_table_with_nulls_to_csv() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(1);
+
+        database.execute(
+            "create table users (id integer, name varchar(20)); \
+             insert into users (id, name) values (1, 'a,b'); \
+             insert into users (id, name) values (2, null); \
+             insert into users (id, name) values (3, '')",
+            transaction.clone(),
+        )?;
+
+        let csv_path = directory_path.join("out.csv");
+        database.execute(
+            &format!(
+                "copy (select * from users) to '{}'",
+                csv_path.to_str().unwrap()
+            ),
+            transaction.clone(),
+        )?;
+
+        // csvを読み戻すと、NULLは空の項目、空文字列は "" のまま区別される
+        let parsed_sql_list = parse_sql("select * from users".to_string()).unwrap();
+        let ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+        let (headers, rows) = select_rows(
+            query_data,
+            &mut MetadataManager::new(transaction.clone())?,
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let mut expected = vec![headers
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<Option<String>>>()];
+        for row in rows.iter() {
+            expected.push(
+                row.iter()
+                    .map(|value| match value {
+                        ConstantValue::Null => None,
+                        ConstantValue::String(s) => Some(s.clone()),
+                        value => Some(format_csv_value(value)),
+                    })
+                    .collect(),
+            );
+        }
+        let content = std::fs::read_to_string(&csv_path).unwrap();
+        assert_eq!(parse_csv(&content), expected);
+        assert_eq!(expected.len(), 4);
+        assert_eq!(expected[2], vec![Some("2".to_string()), None]);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_export_select_star_to_csv() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(1);
+
+        database.execute(
+            "create table users (id integer, name varchar(20)); \
+             insert into users (id, name) values (1, 'alice'); \
+             insert into users (id, name) values (2, 'bob')",
+            transaction.clone(),
+        )?;
+
+        // select * はtableのfieldを順番どおりにすべて返す
+        let csv_path = directory_path.join("out.csv");
+        database.execute(
+            &format!(
+                "copy (select * from users where id = 2) to '{}'",
+                csv_path.to_str().unwrap()
+            ),
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let content = std::fs::read_to_string(&csv_path).unwrap();
+        assert_eq!(
+            parse_csv(&content),
+            vec![
+                vec![Some("id".to_string()), Some("name".to_string())],
+                vec![Some("2".to_string()), Some("bob".to_string())],
+            ]
+        );
+
+        return Ok(());
+    }
+
     #[test]
     fn test_handle_select_query() {
-        let directory_path_name = format!("test_data_{}", uuid::Uuid::new_v4());
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
 
@@ -165,19 +479,28 @@ mod tests {
         );
 
         let select_query = QueryData {
+            distinct: false,
             field_name_list: vec![TableNameAndFieldName::new(None, "table_name".to_string())],
             table_name_list: vec!["table_catalog".to_string()],
+            values_list: vec![],
+            all_columns: false,
+            select_expressions: vec![],
             predicate: PredicateV2::new(vec![term]),
             order_by_list: vec![],
             group_by_list: vec![],
             aggregate_functions: vec![],
+            aggregate_aliases: vec![],
+            having: None,
+            limit: None,
+            offset: None,
         };
 
         handle_select_query(select_query, &mut metadata_manager, transaction);
     }
     #[test]
     fn test_handle_select_query_2() {
-        let directory_path_name = format!("test_data_{}", uuid::Uuid::new_v4());
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -196,20 +519,54 @@ mod tests {
         );
 
         let select_query = QueryData {
+            distinct: false,
             field_name_list: vec![TableNameAndFieldName::new(None, "table_name".to_string())],
             table_name_list: vec!["field_catalog".to_string(), "table_catalog".to_string()],
+            values_list: vec![],
+            all_columns: false,
+            select_expressions: vec![],
             predicate: PredicateV2::new(vec![term]),
             order_by_list: vec![],
             group_by_list: vec![],
             aggregate_functions: vec![],
+            aggregate_aliases: vec![],
+            having: None,
+            limit: None,
+            offset: None,
         };
 
         handle_select_query(select_query, &mut metadata_manager, transaction);
     }
 
+    #[test]
+    fn test_handle_select_query_out_of_range_order_by() {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let directory_path = Path::new(&directory_path_name);
+        let database = Database::new(directory_path);
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone()).unwrap();
+
+        // select listに2つしか無いので、order by 3はpanicせずにエラーを表示する
+        let parsed_sql_list = crate::query::parser::parse_sql(
+            "select table_name, block_size from table_catalog order by 3".to_string(),
+        )
+        .unwrap();
+        let crate::query::parser::ParsedSQL::Query(select_query) = &parsed_sql_list[0] else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+        handle_select_query(
+            select_query.clone(),
+            &mut metadata_manager,
+            transaction.clone(),
+        );
+        transaction.borrow_mut().commit().unwrap();
+    }
+
     #[test]
     fn test_handle_select_query_3() {
-        let directory_path_name = format!("test_data_{}", uuid::Uuid::new_v4());
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -228,15 +585,23 @@ mod tests {
         );
 
         let select_query = QueryData {
+            distinct: false,
             field_name_list: vec![TableNameAndFieldName::new(
                 Some("table_catalog".to_string()),
                 "table_name".to_string(),
             )],
             table_name_list: vec!["field_catalog".to_string(), "table_catalog".to_string()],
+            values_list: vec![],
+            all_columns: false,
+            select_expressions: vec![],
             predicate: PredicateV2::new(vec![]),
             order_by_list: vec![],
             group_by_list: vec![],
             aggregate_functions: vec![],
+            aggregate_aliases: vec![],
+            having: None,
+            limit: None,
+            offset: None,
         };
 
         handle_select_query(select_query, &mut metadata_manager, transaction);
@@ -244,7 +609,8 @@ mod tests {
 
     #[test]
     fn test_handle_select_query_4() {
-        let directory_path_name = format!("test_data_{}", uuid::Uuid::new_v4());
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -262,12 +628,20 @@ mod tests {
         );
 
         let select_query = QueryData {
+            distinct: false,
             field_name_list: vec![TableNameAndFieldName::new(None, "content".to_string())],
             table_name_list: vec!["posts".to_string()],
+            values_list: vec![],
+            all_columns: false,
+            select_expressions: vec![],
             predicate: PredicateV2::new(vec![term]),
             order_by_list: vec![],
             group_by_list: vec![],
             aggregate_functions: vec![],
+            aggregate_aliases: vec![],
+            having: None,
+            limit: None,
+            offset: None,
         };
 
         handle_select_query(
@@ -297,7 +671,8 @@ mod tests {
     #[test]
     fn test_handle_select_query_5() {
         delete_temp_files();
-        let directory_path_name = format!("test_data_{}", uuid::Uuid::new_v4());
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
 
@@ -316,12 +691,23 @@ mod tests {
         );
 
         let select_query = QueryData {
+            distinct: false,
             field_name_list: vec![TableNameAndFieldName::new(None, "content".to_string())],
             table_name_list: vec!["posts".to_string()],
+            values_list: vec![],
+            all_columns: false,
+            select_expressions: vec![],
             predicate: PredicateV2::new(vec![]),
-            order_by_list: vec![TableNameAndFieldName::new(None, "content".to_string())],
+            order_by_list: vec![(
+                OrderByItem::Field(TableNameAndFieldName::new(None, "content".to_string())),
+                SortDirection::Ascending,
+            )],
             group_by_list: vec![],
             aggregate_functions: vec![],
+            aggregate_aliases: vec![],
+            having: None,
+            limit: None,
+            offset: None,
         };
 
         handle_select_query(
@@ -337,7 +723,8 @@ mod tests {
     fn test_handle_select_query_group_by() {
         delete_temp_files();
 
-        let directory_path_name = format!("test_data_{}", uuid::Uuid::new_v4());
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -355,11 +742,15 @@ mod tests {
         );
 
         let select_query = QueryData {
+            distinct: false,
             field_name_list: vec![
                 TableNameAndFieldName::new(None, "content".to_string()),
                 TableNameAndFieldName::new(None, "title".to_string()),
             ],
             table_name_list: vec!["posts".to_string()],
+            values_list: vec![],
+            all_columns: false,
+            select_expressions: vec![],
             predicate: PredicateV2::new(vec![]),
             order_by_list: vec![],
             group_by_list: vec![TableNameAndFieldName::new(None, "content".to_string())],
@@ -367,6 +758,10 @@ mod tests {
                 function_type: AggregateFunctionType::Max,
                 field: TableNameAndFieldName::new(None, "title".to_string()),
             }],
+            aggregate_aliases: vec![None],
+            having: None,
+            limit: None,
+            offset: None,
         };
 
         handle_select_query(
@@ -377,4 +772,27 @@ mod tests {
 
         delete_temp_files();
     }
+
+    #[test]
+    fn test_format_result_table() {
+        let headers = vec!["id".to_string(), "name".to_string()];
+        let rows = vec![
+            vec![
+                ConstantValue::Number(1),
+                ConstantValue::String("alice".to_string()),
+            ],
+            vec![ConstantValue::Number(100), ConstantValue::Null],
+        ];
+        let lines = format_result_table(&headers, &rows)
+            .lines()
+            .map(|line| line.to_string())
+            .collect::<Vec<String>>();
+
+        // 文字列は引用符なし、nullはNULL、数値は右揃え
+        assert!(lines.iter().any(|line| line.contains("|   1 | alice |")));
+        assert!(lines.iter().any(|line| line.contains("| 100 | NULL  |")));
+        assert!(lines
+            .iter()
+            .all(|line| line.chars().count() == lines[0].chars().count()));
+    }
 }
@@ -3,14 +3,20 @@ use std::{cell::RefCell, rc::Rc};
 use crate::{
     error::ValueNotFound,
     query::materialize::TempTable,
-    query::plan_v2::{PlanTreeNodeForDebug, PlanV2},
-    query::predicate::{Constant, TableNameAndFieldName},
+    query::plan_v2::{format_field_names, PlanTreeNodeForDebug, PlanV2},
+    query::predicate::{Constant, ConstantValue, TableNameAndFieldName},
     record::record_page::TableSchema,
     record::scan_v2::ScanV2,
     record::table_scan_v2::RecordID,
     tx::transaction_v2::TransactionV2,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
 pub struct SortPlan {
     transaction: Rc<RefCell<TransactionV2>>,
     plan: Box<dyn PlanV2>,
@@ -22,7 +28,7 @@ impl SortPlan {
     pub fn new(
         transaction: Rc<RefCell<TransactionV2>>,
         plan: Box<dyn PlanV2>,
-        sort_fields: Vec<TableNameAndFieldName>,
+        sort_fields: Vec<(TableNameAndFieldName, SortDirection)>,
     ) -> Self {
         let table_schema = plan.get_schema().clone();
         let comparator = RecordComparator::new(sort_fields);
@@ -35,6 +41,11 @@ impl SortPlan {
         }
     }
 
+    // TODO: runの数からmergeの回数を見積もる。今は並べ替える前のplanによらず一定
+    pub fn estimate_blocks_accessed(_plan: &dyn PlanV2) -> u32 {
+        return 10;
+    }
+
     fn copy(
         &self,
         src_scan: &mut dyn ScanV2,
@@ -55,7 +66,7 @@ impl SortPlan {
     }
 
     fn do_merge_iteration(
-        &mut self,
+        &self,
         runs: &mut Vec<TempTable>,
     ) -> Result<Vec<TempTable>, ValueNotFound> {
         let mut new_runs: Vec<TempTable> = Vec::new();
@@ -75,11 +86,11 @@ impl SortPlan {
     }
 
     fn split_into_runs(
-        &mut self,
+        &self,
         src_scan: &mut dyn ScanV2,
     ) -> Result<Vec<TempTable>, ValueNotFound> {
         let mut temp_tables: Vec<TempTable> = Vec::new();
-        src_scan.move_to_before_first();
+        src_scan.move_to_before_first()?;
 
         if !src_scan.next()? {
             return Ok(temp_tables); // No records to sort
@@ -108,7 +119,7 @@ impl SortPlan {
     }
 
     fn merge_two_runs(
-        &mut self,
+        &self,
         temp_table_1: &mut TempTable,
         temp_table_2: &mut TempTable,
     ) -> Result<TempTable, ValueNotFound> {
@@ -124,8 +135,9 @@ impl SortPlan {
         let mut has_more_data_1 = scan1.next()?;
         let mut has_more_data_2 = scan2.next()?;
 
+        // 同じ値のときは前のrunを先に出して、入力の順を保つ
         while has_more_data_1 && has_more_data_2 {
-            if self.comparator.compare(&mut *scan1, &mut *scan2)? == std::cmp::Ordering::Less {
+            if self.comparator.compare(&mut *scan1, &mut *scan2)? != std::cmp::Ordering::Greater {
                 has_more_data_1 = self.copy(&mut *scan1, &mut *destination_result)?;
             } else {
                 has_more_data_2 = self.copy(&mut *scan2, &mut *destination_result)?;
@@ -151,7 +163,7 @@ impl SortPlan {
 }
 
 impl PlanV2 for SortPlan {
-    fn open(&mut self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
         let mut src_scan = self.plan.open()?;
         let runs = self.split_into_runs(&mut *src_scan);
         src_scan.close();
@@ -162,6 +174,14 @@ impl PlanV2 for SortPlan {
             inner_runs = self.do_merge_iteration(&mut inner_runs)?;
         }
 
+        // 行が無いときは空のrunを1つ読む
+        if inner_runs.is_empty() {
+            inner_runs.push(TempTable::new(
+                self.transaction.clone(),
+                self.table_schema.clone(),
+            ));
+        }
+
         let sort_scan = SortScan::new(&mut inner_runs, self.comparator.clone())?;
 
         return Ok(Box::new(sort_scan));
@@ -180,45 +200,78 @@ impl PlanV2 for SortPlan {
     }
 
     fn blocks_accessed(&self) -> u32 {
-        // TODO: 所有権の問題で実装できていない
-        return 10;
+        Self::estimate_blocks_accessed(self.plan.as_ref())
     }
 
     fn get_child_plans(&self) -> PlanTreeNodeForDebug {
         PlanTreeNodeForDebug {
-            current_node_type: "SortPlan".to_string(),
+            current_node_type: format!("SortPlan({})", self.comparator.describe()),
+            records_output: self.records_output(),
+            blocks_accessed: self.blocks_accessed(),
             child_nodes: vec![self.plan.get_child_plans()],
         }
     }
 }
 
 #[derive(Clone)]
-struct RecordComparator {
-    field_name_list: Vec<TableNameAndFieldName>,
+pub(crate) struct RecordComparator {
+    field_name_list: Vec<(TableNameAndFieldName, SortDirection)>,
 }
 
 impl RecordComparator {
-    pub fn new(field_name_list: Vec<TableNameAndFieldName>) -> Self {
+    pub fn new(field_name_list: Vec<(TableNameAndFieldName, SortDirection)>) -> Self {
         RecordComparator { field_name_list }
     }
 
+    // explain用に "A, B desc" のように並べる
+    fn describe(&self) -> String {
+        return self
+            .field_name_list
+            .iter()
+            .map(|(field_name, direction)| {
+                let field_name = format_field_names(std::slice::from_ref(field_name));
+                match direction {
+                    SortDirection::Ascending => field_name,
+                    SortDirection::Descending => format!("{} desc", field_name),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+    }
+
+    // NULLは一番小さい値として並べる
+    // 大文字小文字だけが違う文字列も区別するので、Equalになるのはまったく同じ値だけ
+    pub fn compare_values(value1: ConstantValue, value2: ConstantValue) -> std::cmp::Ordering {
+        match (&value1, &value2) {
+            (ConstantValue::Null, ConstantValue::Null) => std::cmp::Ordering::Equal,
+            (ConstantValue::Null, _) => std::cmp::Ordering::Less,
+            (_, ConstantValue::Null) => std::cmp::Ordering::Greater,
+            (ConstantValue::String(lhs), ConstantValue::String(rhs)) => {
+                Constant::new(value1.clone())
+                    .compare_to(value2.clone())
+                    .then_with(|| lhs.cmp(rhs))
+            }
+            _ => Constant::new(value1).compare_to(value2),
+        }
+    }
+
     pub fn compare(
         &self,
         scan1: &mut dyn ScanV2,
         scan2: &mut dyn ScanV2,
     ) -> Result<std::cmp::Ordering, ValueNotFound> {
-        for field_name in &self.field_name_list {
+        for (field_name, direction) in &self.field_name_list {
             let value1 = scan1.get_value(field_name.clone());
             let value2 = scan2.get_value(field_name.clone());
 
             if let (Some(inner_value1), Some(inner_value2)) = (value1, value2) {
-                let val1 = Constant::new(inner_value1);
-                let val2 = Constant::new(inner_value2);
-
-                match val1.compare_to(val2.value) {
-                    std::cmp::Ordering::Equal => continue,
-                    std::cmp::Ordering::Less => return Ok(std::cmp::Ordering::Less),
-                    std::cmp::Ordering::Greater => return Ok(std::cmp::Ordering::Greater),
+                let ordering = RecordComparator::compare_values(inner_value1, inner_value2);
+                let ordering = match direction {
+                    SortDirection::Ascending => ordering,
+                    SortDirection::Descending => ordering.reverse(),
+                };
+                if ordering != std::cmp::Ordering::Equal {
+                    return Ok(ordering);
                 }
             } else {
                 return Err(ValueNotFound::new(
@@ -238,7 +291,7 @@ enum CurrentScan {
     None,
 }
 
-struct SortScan {
+pub struct SortScan {
     scan1: Box<dyn ScanV2>,
     scan2: Option<Box<dyn ScanV2>>,
     current_scan: CurrentScan,
@@ -277,7 +330,9 @@ impl SortScan {
 
 impl ScanV2 for SortScan {
     fn move_to_before_first(&mut self) -> Result<(), ValueNotFound> {
-        self.scan1.move_to_before_first();
+        // 読み直した先頭の行を次のnextで飛ばさないようにする
+        self.current_scan = CurrentScan::None;
+        self.scan1.move_to_before_first()?;
         let has_more_data_1 = self.scan1.next();
 
         match has_more_data_1 {
@@ -288,7 +343,7 @@ impl ScanV2 for SortScan {
         }
 
         if let Some(scan2) = &mut self.scan2 {
-            scan2.move_to_before_first();
+            scan2.move_to_before_first()?;
             let has_more_data_2 = scan2.next();
             match has_more_data_2 {
                 Err(e) => return Err(e),
@@ -317,7 +372,8 @@ impl ScanV2 for SortScan {
         } else if self.has_more_data_1 && self.has_more_data_2 {
             if let Some(scan2) = &mut self.scan2 {
                 let compare_value = self.comparator.compare(&mut *self.scan1, &mut **scan2)?;
-                if compare_value == std::cmp::Ordering::Less {
+                // merge_two_runsと同じく、同じ値ならscan1 (前のrun) を先に出す
+                if compare_value != std::cmp::Ordering::Greater {
                     self.current_scan = CurrentScan::Scan1;
                 } else {
                     self.current_scan = CurrentScan::Scan2;
@@ -441,7 +497,7 @@ mod tests {
 
     use super::*;
 
-    fn prepare_test_data(directory_path_name: String) -> Result<(), ValueNotFound> {
+    fn prepare_test_data(directory_path_name: String) -> Result<(), crate::error::DatabaseError> {
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -450,7 +506,7 @@ mod tests {
         let create_table_sql =
             "create table test_table_1 (A_1 integer, B_1 varchar(10))".to_string();
 
-        let parsed_sql_list = parse_sql(create_table_sql.clone());
+        let parsed_sql_list = parse_sql(create_table_sql.clone()).unwrap();
 
         let create_table_data = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::CreateTable(q) => q,
@@ -470,7 +526,7 @@ mod tests {
         let create_table_sql =
             "create table test_table_2 (A_2 integer, B_2 varchar(10))".to_string();
 
-        let parsed_sql_list = parse_sql(create_table_sql.clone());
+        let parsed_sql_list = parse_sql(create_table_sql.clone()).unwrap();
 
         let create_table_data = match &parsed_sql_list[0] {
             crate::query::parser::ParsedSQL::CreateTable(q) => q,
@@ -502,7 +558,7 @@ mod tests {
         ];
 
         for insert_sql in insert_sql_list.iter() {
-            let parsed_sql_list = parse_sql(insert_sql.clone());
+            let parsed_sql_list = parse_sql(insert_sql.clone()).unwrap();
 
             let insert_data = match &parsed_sql_list[0] {
                 crate::query::parser::ParsedSQL::Insert(q) => q,
@@ -513,18 +569,19 @@ mod tests {
                 transaction.clone(),
                 &mut metadata_manager,
                 insert_data.clone(),
-            );
+            )?;
         }
 
-        transaction.borrow_mut().commit();
+        transaction.borrow_mut().commit()?;
 
         return Ok(());
     }
 
     #[test]
-    fn test_sort() -> Result<(), ValueNotFound> {
-        let directory_path_name = format!("test_data_{}", uuid::Uuid::new_v4());
-        prepare_test_data(directory_path_name.clone());
+    fn test_sort() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        prepare_test_data(directory_path_name.clone())?;
         let directory_path = Path::new(&directory_path_name);
         let database = Database::new(directory_path);
         let transaction = database.new_transaction(1);
@@ -537,7 +594,10 @@ mod tests {
         )
         .unwrap();
 
-        let sort_fields = vec![TableNameAndFieldName::new(None, "A_1".to_string())];
+        let sort_fields = vec![(
+            TableNameAndFieldName::new(None, "A_1".to_string()),
+            SortDirection::Ascending,
+        )];
 
         struct TestValue {
             a1: ConstantValue,
@@ -591,7 +651,7 @@ mod tests {
             },
         ];
 
-        let mut sort_plan = SortPlan::new(transaction.clone(), Box::new(table_plan), sort_fields);
+        let sort_plan = SortPlan::new(transaction.clone(), Box::new(table_plan), sort_fields);
 
         let mut sort_scan = sort_plan.open()?;
 
@@ -617,4 +677,233 @@ mod tests {
 
         return Ok(());
     }
+
+    #[test]
+    fn test_sort_by_multiple_keys() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+
+        // aが同じ行はbで並び、aもbも同じ行は入力の順 (seq) のまま
+        let rows = [
+            (2, 1, 0),
+            (1, 3, 1),
+            (2, 0, 2),
+            (1, 1, 3),
+            (3, 2, 4),
+            (1, 2, 5),
+            (2, 1, 6),
+            (1, 1, 7),
+            (3, 0, 8),
+            (2, 2, 9),
+        ];
+        database.execute(
+            "create table multi_key (a integer, b integer, seq integer)",
+            transaction.clone(),
+        )?;
+        for (a, b, seq) in rows.iter() {
+            database.execute(
+                &format!(
+                    "insert into multi_key (a, b, seq) values ({}, {}, {})",
+                    a, b, seq
+                ),
+                transaction.clone(),
+            )?;
+        }
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let parsed_sql_list =
+            parse_sql("select a, b, seq from multi_key order by a, b".to_string()).unwrap();
+        let crate::query::parser::ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+        let plan = crate::query::plan_v2::create_query_plan(
+            query_data,
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+        let mut scan = plan.open()?;
+
+        let mut sorted = vec![];
+        while scan.next()? {
+            let mut row = vec![];
+            for field_name in ["a", "b", "seq"] {
+                match scan.get_value(TableNameAndFieldName::new(None, field_name.to_string())) {
+                    Some(ConstantValue::Number(n)) => row.push(n),
+                    _ => panic!("Expected a number for {}", field_name),
+                }
+            }
+            sorted.push((row[0], row[1], row[2]));
+        }
+        scan.close();
+        transaction.borrow_mut().commit()?;
+
+        assert_eq!(
+            sorted,
+            vec![
+                (1, 1, 3),
+                (1, 1, 7),
+                (1, 2, 5),
+                (1, 3, 1),
+                (2, 0, 2),
+                (2, 1, 0),
+                (2, 1, 6),
+                (2, 2, 9),
+                (3, 0, 8),
+                (3, 2, 4),
+            ]
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_temp_tables_are_deleted_on_commit() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table sort_source (a integer); \
+             insert into sort_source (a) values (3); \
+             insert into sort_source (a) values (1); \
+             insert into sort_source (a) values (2)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let temp_files = || -> Vec<String> {
+            std::fs::read_dir(&directory_path_name)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .filter(|name| name.starts_with("temp_") && name.ends_with(".tbl"))
+                .collect()
+        };
+
+        let transaction = database.new_transaction(2);
+        database.execute("select a from sort_source order by a", transaction.clone())?;
+        // sortのrunはcommitまで残っている
+        assert!(!temp_files().is_empty());
+        transaction.borrow_mut().commit()?;
+        assert_eq!(temp_files(), Vec::<String>::new());
+
+        let transaction = database.new_transaction(3);
+        database.execute("select a from sort_source order by a", transaction.clone())?;
+        transaction.borrow_mut().rollback();
+        assert_eq!(temp_files(), Vec::<String>::new());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_order_by_after_move_to_before_first() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table unsorted (a integer); \
+             insert into unsorted (a) values (5); \
+             insert into unsorted (a) values (2); \
+             insert into unsorted (a) values (4); \
+             insert into unsorted (a) values (1); \
+             insert into unsorted (a) values (3)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let parsed_sql_list = parse_sql("select a from unsorted order by a".to_string()).unwrap();
+        let crate::query::parser::ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+        let plan = crate::query::plan_v2::create_query_plan(
+            query_data,
+            transaction.clone(),
+            &mut metadata_manager,
+        )?;
+        let mut scan = plan.open()?;
+
+        let read_all = |scan: &mut Box<dyn ScanV2>| -> Result<Vec<ConstantValue>, ValueNotFound> {
+            let mut values = vec![];
+            while scan.next()? {
+                values.push(
+                    scan.get_value(TableNameAndFieldName::new(None, "a".to_string()))
+                        .unwrap(),
+                );
+            }
+            return Ok(values);
+        };
+
+        let ascending = (1..=5).map(ConstantValue::Number).collect::<Vec<_>>();
+        assert_eq!(read_all(&mut scan)?, ascending);
+        // 読み直しても先頭の行を落とさない
+        scan.move_to_before_first()?;
+        assert_eq!(read_all(&mut scan)?, ascending);
+        scan.close();
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_sort_direction() -> Result<(), crate::error::DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table directions (a integer, b integer); \
+             insert into directions (a, b) values (1, 1); \
+             insert into directions (a, b) values (2, 1); \
+             insert into directions (a, b) values (1, 3); \
+             insert into directions (a, b) values (2, 2); \
+             insert into directions (a, b) values (1, 2)",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let sorted_rows = |sql: &str| -> Result<Vec<(i32, i32)>, crate::error::DatabaseError> {
+            let transaction = database.new_transaction(2);
+            let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+            let parsed_sql_list = parse_sql(sql.to_string()).unwrap();
+            let crate::query::parser::ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+                panic!("Expected a Query variant from parse_sql");
+            };
+            let plan = crate::query::plan_v2::create_query_plan(
+                query_data,
+                transaction.clone(),
+                &mut metadata_manager,
+            )?;
+            let mut scan = plan.open()?;
+            let mut rows = vec![];
+            while scan.next()? {
+                let a = scan.get_integer(TableNameAndFieldName::new(None, "a".to_string()));
+                let b = scan.get_integer(TableNameAndFieldName::new(None, "b".to_string()));
+                rows.push((a.unwrap(), b.unwrap()));
+            }
+            scan.close();
+            transaction.borrow_mut().commit()?;
+            return Ok(rows);
+        };
+
+        assert_eq!(
+            sorted_rows("select a, b from directions order by a asc, b desc")?,
+            vec![(1, 3), (1, 2), (1, 1), (2, 2), (2, 1)]
+        );
+        assert_eq!(
+            sorted_rows("select a, b from directions order by a DESC, b")?,
+            vec![(2, 1), (2, 2), (1, 1), (1, 2), (1, 3)]
+        );
+        assert_eq!(
+            sorted_rows("select a, b from directions order by 2 desc, 1 desc")?,
+            vec![(1, 3), (2, 2), (1, 2), (2, 1), (1, 1)]
+        );
+
+        return Ok(());
+    }
 }
@@ -0,0 +1,510 @@
+use std::collections::HashSet;
+
+use crate::{
+    error::{ColumnCountMismatch, DatabaseError, TypeMismatch, ValueNotFound},
+    query::parser::ValuesData,
+    query::plan_v2::{PlanTreeNodeForDebug, PlanV2},
+    query::predicate::{Constant, ConstantValue, TableNameAndFieldName},
+    record::record_page::{TableFieldType, TableSchema},
+    record::scan_v2::ScanV2,
+    record::table_scan_v2::RecordID,
+};
+
+// from (values ...) as t(...) のリテラルの行をそのまま返すplan
+// fileには書き込まず、行はmemory上に持つ
+pub struct ValuesPlan {
+    alias: String,
+    schema: TableSchema,
+    rows: Vec<Vec<ConstantValue>>,
+}
+
+impl ValuesPlan {
+    pub fn new(values_data: &ValuesData) -> Result<Self, ValueNotFound> {
+        let mut rows: Vec<Vec<ConstantValue>> = values_data
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|c| c.value.clone()).collect())
+            .collect();
+
+        // 列数と型はvalidate_queryがvalidate_valuesで先に確かめている
+        if validate_values(values_data).is_err() {
+            return Err(ValueNotFound::new(
+                values_data.alias.clone(),
+                Some(values_data.alias.clone()),
+            ));
+        }
+
+        let mut schema = TableSchema::new();
+        for (index, column_name) in values_data.column_names.iter().enumerate() {
+            match column_type(&rows, index) {
+                Some(TableFieldType::VARCHAR) => {
+                    let max_length = rows
+                        .iter()
+                        .map(|row| match &row[index] {
+                            ConstantValue::String(s) => s.len() as i32,
+                            _ => 0,
+                        })
+                        .max()
+                        .unwrap_or(0);
+                    schema.add_string_field(column_name.clone(), max_length);
+                }
+                Some(TableFieldType::DECIMAL(precision, scale)) => {
+                    for row in rows.iter_mut() {
+                        row[index] = to_decimal(&row[index], scale as u32);
+                    }
+                    schema.add_decimal_field(column_name.clone(), precision, scale);
+                }
+                Some(TableFieldType::FLOAT) => {
+                    for row in rows.iter_mut() {
+                        row[index] = to_float(&row[index]);
+                    }
+                    schema.add_float_field(column_name.clone());
+                }
+                Some(TableFieldType::DATE) => {
+                    schema.add_date_field(column_name.clone());
+                }
+                _ => {
+                    schema.add_integer_field(column_name.clone());
+                }
+            }
+        }
+
+        return Ok(ValuesPlan {
+            alias: values_data.alias.clone(),
+            schema,
+            rows,
+        });
+    }
+}
+
+// すべての行がcolumn名と同じ数の値を持ち、各columnの値の型が混ぜられるものか確かめる
+pub fn validate_values(values_data: &ValuesData) -> Result<(), DatabaseError> {
+    let expected = values_data.column_names.len();
+    for row in values_data.rows.iter() {
+        if row.len() != expected {
+            return Err(
+                ColumnCountMismatch::new(values_data.alias.clone(), expected, row.len()).into(),
+            );
+        }
+    }
+
+    let rows: Vec<Vec<ConstantValue>> = values_data
+        .rows
+        .iter()
+        .map(|row| row.iter().map(|c| c.value.clone()).collect())
+        .collect();
+    for (index, column_name) in values_data.column_names.iter().enumerate() {
+        if column_type(&rows, index).is_none() {
+            return Err(TypeMismatch::new(column_name.clone(), values_data.alias.clone()).into());
+        }
+    }
+    return Ok(());
+}
+
+// columnの型はNULL以外のすべての行の値から決める。すべてNULLならinteger
+// integerとdecimalはdecimalに、floatが混ざればfloatにそろえる
+// 文字列や日付と数値のように混ぜられないときはNone
+fn column_type(rows: &[Vec<ConstantValue>], index: usize) -> Option<TableFieldType> {
+    let mut column_type: Option<TableFieldType> = None;
+    let mut scale = 0;
+    for row in rows.iter() {
+        let value_type = match &row[index] {
+            ConstantValue::Null => continue,
+            ConstantValue::Number(_) => TableFieldType::INTEGER,
+            ConstantValue::Decimal {
+                value: _,
+                scale: value_scale,
+            } => {
+                scale = scale.max(*value_scale);
+                TableFieldType::DECIMAL(0, 0)
+            }
+            ConstantValue::Float(_) => TableFieldType::FLOAT,
+            ConstantValue::String(_) => TableFieldType::VARCHAR,
+            ConstantValue::Date(_) => TableFieldType::DATE,
+            ConstantValue::Parameter(_) => return None,
+        };
+        column_type = match (column_type, value_type) {
+            (None, value_type) => Some(value_type),
+            (Some(column_type), value_type) if column_type == value_type => Some(column_type),
+            (Some(TableFieldType::FLOAT), TableFieldType::INTEGER)
+            | (Some(TableFieldType::FLOAT), TableFieldType::DECIMAL(_, _))
+            | (Some(TableFieldType::INTEGER), TableFieldType::FLOAT)
+            | (Some(TableFieldType::DECIMAL(_, _)), TableFieldType::FLOAT) => {
+                Some(TableFieldType::FLOAT)
+            }
+            (Some(TableFieldType::INTEGER), TableFieldType::DECIMAL(_, _))
+            | (Some(TableFieldType::DECIMAL(_, _)), TableFieldType::INTEGER) => {
+                Some(TableFieldType::DECIMAL(0, 0))
+            }
+            _ => return None,
+        };
+    }
+
+    let Some(TableFieldType::DECIMAL(_, _)) = column_type else {
+        return Some(column_type.unwrap_or(TableFieldType::INTEGER));
+    };
+    // precisionはscaleをそろえた値の桁数。i32やDECIMALの桁数に収まらなければfloatにする
+    let mut precision = scale.max(1) as i32;
+    for row in rows.iter() {
+        let value = match to_decimal(&row[index], scale) {
+            ConstantValue::Decimal { value, scale: _ } => value,
+            ConstantValue::Null => continue,
+            _ => return Some(TableFieldType::FLOAT),
+        };
+        precision = precision.max(value.unsigned_abs().to_string().len() as i32);
+    }
+    if precision > TableSchema::MAX_DECIMAL_PRECISION {
+        return Some(TableFieldType::FLOAT);
+    }
+    return Some(TableFieldType::DECIMAL(precision, scale as i32));
+}
+
+// integerとdecimalをscaleのdecimalにする。i32に収まらなければfloatにする
+fn to_decimal(value: &ConstantValue, scale: u32) -> ConstantValue {
+    let rescaled = match value {
+        ConstantValue::Number(n) => Constant::rescale_decimal(*n, 0, scale),
+        ConstantValue::Decimal {
+            value,
+            scale: from_scale,
+        } => Constant::rescale_decimal(*value, *from_scale, scale),
+        _ => return value.clone(),
+    };
+    match rescaled {
+        Some(value) => return ConstantValue::Decimal { value, scale },
+        None => return to_float(value),
+    }
+}
+
+fn to_float(value: &ConstantValue) -> ConstantValue {
+    match value {
+        ConstantValue::Number(n) => return ConstantValue::Float(*n as f64),
+        ConstantValue::Decimal { value, scale } => {
+            return ConstantValue::Float(*value as f64 / 10_f64.powi(*scale as i32));
+        }
+        _ => return value.clone(),
+    }
+}
+
+impl PlanV2 for ValuesPlan {
+    fn open(&self) -> Result<Box<dyn ScanV2>, ValueNotFound> {
+        return Ok(Box::new(ValuesScan::new(
+            self.alias.clone(),
+            self.schema.clone(),
+            self.rows.clone(),
+        )));
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    fn blocks_accessed(&self) -> u32 {
+        0
+    }
+
+    fn records_output(&self) -> u32 {
+        self.rows.len() as u32
+    }
+
+    fn get_distinct_value(&self, field_name: String) -> u32 {
+        let Some(index) = self.schema.fields.iter().position(|f| f == &field_name) else {
+            return 1;
+        };
+        let distinct_values: HashSet<String> = self
+            .rows
+            .iter()
+            .map(|row| format!("{:?}", row[index]))
+            .collect();
+        return distinct_values.len() as u32;
+    }
+
+    fn get_child_plans(&self) -> PlanTreeNodeForDebug {
+        PlanTreeNodeForDebug {
+            current_node_type: format!("ValuesPlan({})", self.alias),
+            records_output: self.records_output(),
+            blocks_accessed: self.blocks_accessed(),
+            child_nodes: vec![],
+        }
+    }
+}
+
+pub struct ValuesScan {
+    alias: String,
+    schema: TableSchema,
+    rows: Vec<Vec<ConstantValue>>,
+    // move_to_before_firstの直後はNone
+    current_row: Option<usize>,
+}
+
+impl ValuesScan {
+    pub fn new(alias: String, schema: TableSchema, rows: Vec<Vec<ConstantValue>>) -> Self {
+        ValuesScan {
+            alias,
+            schema,
+            rows,
+            current_row: None,
+        }
+    }
+
+    fn current_value(&self, field_name: &TableNameAndFieldName) -> Option<&ConstantValue> {
+        if !self.has_field(field_name.clone()) {
+            return None;
+        }
+        let index = self
+            .schema
+            .fields
+            .iter()
+            .position(|f| f == &field_name.field_name)?;
+        return self.rows.get(self.current_row?)?.get(index);
+    }
+}
+
+impl ScanV2 for ValuesScan {
+    fn move_to_before_first(&mut self) -> Result<(), ValueNotFound> {
+        self.current_row = None;
+        return Ok(());
+    }
+
+    fn next(&mut self) -> Result<bool, ValueNotFound> {
+        let next_row = match self.current_row {
+            Some(row) => row + 1,
+            None => 0,
+        };
+        if next_row >= self.rows.len() {
+            self.current_row = Some(self.rows.len());
+            return Ok(false);
+        }
+        self.current_row = Some(next_row);
+        return Ok(true);
+    }
+
+    fn get_integer(&mut self, field_name: TableNameAndFieldName) -> Option<i32> {
+        match self.current_value(&field_name) {
+            Some(ConstantValue::Number(n)) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn get_string(&mut self, field_name: TableNameAndFieldName) -> Option<String> {
+        match self.current_value(&field_name) {
+            Some(ConstantValue::String(s)) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    fn get_value(&mut self, field_name: TableNameAndFieldName) -> Option<ConstantValue> {
+        self.current_value(&field_name).cloned()
+    }
+
+    fn close(&mut self) {}
+
+    fn has_field(&self, field_name: TableNameAndFieldName) -> bool {
+        self.schema.has_field(field_name.field_name)
+            && (field_name.table_name.is_none()
+                || field_name.table_name.as_ref().unwrap() == &self.alias)
+    }
+
+    fn set_integer(&mut self, _field_name: String, _value: i32) {
+        panic!("set_integer not implemented for ValuesScan");
+    }
+
+    fn set_string(&mut self, _field_name: String, _value: String) {
+        panic!("set_string not implemented for ValuesScan");
+    }
+
+    fn set_value(&mut self, _field_name: String, _value: ConstantValue) {
+        panic!("set_value not implemented for ValuesScan");
+    }
+
+    fn insert(&mut self) {
+        panic!("insert not implemented for ValuesScan");
+    }
+
+    fn delete(&mut self) {
+        panic!("delete not implemented for ValuesScan");
+    }
+
+    fn get_record_id(&self) -> RecordID {
+        panic!("get_record_id not implemented for ValuesScan");
+    }
+
+    fn move_to_record_id(&mut self, _record_id: RecordID) {
+        panic!("move_to_record_id not implemented for ValuesScan");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        database::Database,
+        error::DatabaseError,
+        metadata::metadata_manager::MetadataManager,
+        query::cursor::Cursor,
+        query::parser::{parse_sql, ParsedSQL},
+        query::plan_v2::create_query_plan,
+    };
+
+    use super::*;
+
+    fn fetch_all(
+        database: &Database,
+        sql: &str,
+    ) -> Result<(Vec<String>, Vec<Vec<ConstantValue>>), DatabaseError> {
+        let transaction = database.new_transaction(2);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let parsed_sql_list = parse_sql(sql.to_string()).unwrap();
+        let ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+        let plan = create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+        let mut cursor = Cursor::open(plan, transaction.clone())?;
+        let fields = cursor.get_schema().fields.clone();
+        let rows = cursor.fetch(100)?;
+        cursor.close();
+        transaction.borrow_mut().commit()?;
+        return Ok((fields, rows));
+    }
+
+    #[test]
+    fn test_values_column_types() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+
+        // 最初の行だけでなく、すべての行の値から型を決める
+        let transaction = database.new_transaction(1);
+        let mut metadata_manager = MetadataManager::new(transaction.clone())?;
+        let parsed_sql_list = parse_sql(
+            "select * from (values (1, null, 1), (2.25, 'abc', 1.5e0), (10, 'x', 2)) as t(a, b, c)"
+                .to_string(),
+        )
+        .unwrap();
+        let ParsedSQL::Query(query_data) = &parsed_sql_list[0] else {
+            panic!("Expected a Query variant from parse_sql");
+        };
+        let plan = create_query_plan(query_data, transaction.clone(), &mut metadata_manager)?;
+        let schema = plan.get_schema();
+        assert_eq!(
+            schema.get_field_type("a".to_string()),
+            Some(TableFieldType::DECIMAL(4, 2))
+        );
+        assert_eq!(
+            schema.get_field_type("b".to_string()),
+            Some(TableFieldType::VARCHAR)
+        );
+        assert_eq!(schema.get_field_length("b".to_string()), Some(3));
+        assert_eq!(
+            schema.get_field_type("c".to_string()),
+            Some(TableFieldType::FLOAT)
+        );
+        transaction.borrow_mut().commit()?;
+
+        let (_, rows) = fetch_all(
+            &database,
+            "select a, c from (values (1, 1), (2.25, 1.5e0)) as t(a, c)",
+        )?;
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    ConstantValue::Decimal {
+                        value: 100,
+                        scale: 2
+                    },
+                    ConstantValue::Float(1.0)
+                ],
+                vec![
+                    ConstantValue::Decimal {
+                        value: 225,
+                        scale: 2
+                    },
+                    ConstantValue::Float(1.5)
+                ],
+            ]
+        );
+
+        // 列数が合わない行や、混ぜられない型の値はエラーにする
+        let transaction = database.new_transaction(2);
+        let result = database.execute(
+            "select * from (values (1, 'a'), (2)) as t(id, name)",
+            transaction.clone(),
+        );
+        let Err(DatabaseError::ColumnCountMismatch(error)) = result else {
+            panic!("expected ColumnCountMismatch");
+        };
+        assert_eq!(error.to_string(), "Table 't' expects 2 values but got 1");
+        let result = database.execute(
+            "select * from (values (1), ('a')) as t(id)",
+            transaction.clone(),
+        );
+        assert!(matches!(result, Err(DatabaseError::TypeMismatch(_))));
+        transaction.borrow_mut().commit()?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_join_table_with_values() -> Result<(), DatabaseError> {
+        let test_directory = crate::database::TestDirectory::new();
+        let directory_path_name = test_directory.path_name();
+        let database = Database::new(Path::new(&directory_path_name));
+        let transaction = database.new_transaction(1);
+        database.execute(
+            "create table users (id integer, name varchar(10)); \
+             insert into users (id, name) values (1, 'alice'); \
+             insert into users (id, name) values (2, 'bob'); \
+             insert into users (id, name) values (3, 'carol')",
+            transaction.clone(),
+        )?;
+        transaction.borrow_mut().commit()?;
+
+        let (fields, rows) = fetch_all(
+            &database,
+            "select * from (values (1, 'a'), (2, 'bb')) as t(id, name)",
+        )?;
+        assert_eq!(fields, vec!["id".to_string(), "name".to_string()]);
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    ConstantValue::Number(1),
+                    ConstantValue::String("a".to_string())
+                ],
+                vec![
+                    ConstantValue::Number(2),
+                    ConstantValue::String("bb".to_string())
+                ],
+            ]
+        );
+
+        let (fields, rows) = fetch_all(
+            &database,
+            "select name, label from users, (values (3, 'third'), (1, 'first'), (9, 'none')) as t(uid, label) \
+             where users.id = t.uid order by name",
+        )?;
+        // productの順番はplannerが決めるので、schemaから位置を探す
+        let name_index = fields.iter().position(|f| f == "name").unwrap();
+        let label_index = fields.iter().position(|f| f == "label").unwrap();
+        let names: Vec<(ConstantValue, ConstantValue)> = rows
+            .iter()
+            .map(|row| (row[name_index].clone(), row[label_index].clone()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (
+                    ConstantValue::String("alice".to_string()),
+                    ConstantValue::String("first".to_string())
+                ),
+                (
+                    ConstantValue::String("carol".to_string()),
+                    ConstantValue::String("third".to_string())
+                ),
+            ]
+        );
+
+        return Ok(());
+    }
+}
@@ -1,9 +1,24 @@
 use std::collections::HashMap;
 
+use crate::{query::predicate::Constant, storage::page::Page};
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TableFieldInfo {
     pub field_type: TableFieldType,
     pub field_length: i32,
+    // not nullが付いたfieldはfalse
+    pub nullable: bool,
+    // insertでfieldが省略されたときに入れる値
+    pub default: Option<Constant>,
+    // unique / primary key。同じ値の行は1つしか入れられない
+    pub key: Option<KeyConstraint>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyConstraint {
+    Unique,
+    // uniqueでnot null
+    PrimaryKey,
 }
 
 impl TableFieldInfo {
@@ -11,6 +26,9 @@ impl TableFieldInfo {
         TableFieldInfo {
             field_type,
             field_length,
+            nullable: true,
+            default: None,
+            key: None,
         }
     }
 }
@@ -19,6 +37,12 @@ impl TableFieldInfo {
 pub enum TableFieldType {
     INTEGER,
     VARCHAR,
+    // DECIMAL(precision, scale)。scaleをかけたi32として保存する
+    DECIMAL(i32, i32),
+    // 8byteのf64
+    FLOAT,
+    // 1970-01-01からの日数を8byteのi64で持つ
+    DATE,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -32,6 +56,9 @@ impl Into<i32> for TableFieldType {
         match self {
             TableFieldType::INTEGER => 0,
             TableFieldType::VARCHAR => 1,
+            TableFieldType::DECIMAL(_, _) => 2,
+            TableFieldType::FLOAT => 3,
+            TableFieldType::DATE => 4,
         }
     }
 }
@@ -41,12 +68,19 @@ impl From<i32> for TableFieldType {
         match value {
             0 => TableFieldType::INTEGER,
             1 => TableFieldType::VARCHAR,
+            // precisionとscaleはcatalogの別のfieldから埋める
+            2 => TableFieldType::DECIMAL(0, 0),
+            3 => TableFieldType::FLOAT,
+            4 => TableFieldType::DATE,
             _ => panic!("Invalid field type"),
         }
     }
 }
 
 impl TableSchema {
+    // DECIMALはscaleをかけたi32で持つので、どの値もi32に収まる9桁までにする
+    pub const MAX_DECIMAL_PRECISION: i32 = 9;
+
     pub fn new() -> TableSchema {
         TableSchema {
             fields: Vec::new(),
@@ -56,13 +90,21 @@ impl TableSchema {
 
     pub fn add_field(&mut self, field_name: String, field_type: TableFieldType, field_length: i32) {
         self.fields.push(field_name.clone());
-        self.field_infos.insert(
-            field_name,
-            TableFieldInfo {
-                field_type,
-                field_length,
-            },
-        );
+        self.field_infos
+            .insert(field_name, TableFieldInfo::new(field_type, field_length));
+    }
+
+    // create tableのnot nullとdefault。fieldを足したあとに設定する
+    pub fn set_field_constraints(
+        &mut self,
+        field_name: &str,
+        nullable: bool,
+        default: Option<Constant>,
+    ) {
+        if let Some(field_info) = self.field_infos.get_mut(field_name) {
+            field_info.nullable = nullable;
+            field_info.default = default;
+        }
     }
 
     pub fn add_integer_field(&mut self, field_name: String) {
@@ -73,6 +115,22 @@ impl TableSchema {
         self.add_field(field_name, TableFieldType::VARCHAR, field_length);
     }
 
+    pub fn add_decimal_field(&mut self, field_name: String, precision: i32, scale: i32) {
+        self.add_field(
+            field_name,
+            TableFieldType::DECIMAL(precision, scale),
+            precision,
+        );
+    }
+
+    pub fn add_float_field(&mut self, field_name: String) {
+        self.add_field(field_name, TableFieldType::FLOAT, 0);
+    }
+
+    pub fn add_date_field(&mut self, field_name: String) {
+        self.add_field(field_name, TableFieldType::DATE, 0);
+    }
+
     pub fn add(&mut self, field_name: String, schema: TableSchema) {
         let field_type = schema.get_field_type(field_name.clone());
         let field_length = schema.get_field_length(field_name.clone());
@@ -134,21 +192,51 @@ impl TableSchema {
     pub fn has_field(&self, field_name: String) -> bool {
         self.field_infos.contains_key(&field_name)
     }
+
+    pub fn set_key_constraint(&mut self, field_name: &str, key: Option<KeyConstraint>) {
+        if let Some(field_info) = self.field_infos.get_mut(field_name) {
+            field_info.key = key;
+        }
+    }
+
+    pub fn get_key_constraint(&self, field_name: &str) -> Option<KeyConstraint> {
+        return self.field_infos.get(field_name)?.key;
+    }
+
+    // schemaに無いfieldはnullableとして扱う
+    pub fn is_nullable(&self, field_name: &str) -> bool {
+        match self.field_infos.get(field_name) {
+            Some(field_info) => return field_info.nullable,
+            None => return true,
+        }
+    }
+
+    pub fn get_default(&self, field_name: &str) -> Option<Constant> {
+        return self.field_infos.get(field_name)?.default.clone();
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Layout {
     pub schema: TableSchema,
     offsets: HashMap<String, i32>,
     slot_size: i32,
+    // add columnで足したfieldが入っている最初のblock。無いfieldは0
+    // それより前のblockは書き直さないので、slotの最後にそのfieldの場所が無い
+    first_blocks: HashMap<String, u64>,
 }
 
 impl Layout {
+    // slotの先頭のRecordTypeの後に、fieldごとのnull bitを持つ4byteを置く
+    pub const NULL_BITMAP_OFFSET: i32 = 4;
+    // null bitmapは4byteなので、1つのtableが持てるfieldは32まで
+    pub const MAX_FIELD_COUNT: usize = 32;
+
     pub fn new(schema: TableSchema) -> Layout {
         let mut offsets = HashMap::new();
 
-        // RecordTypeの分
-        let mut slot_size = 4;
+        // RecordTypeとnull bitmapの分
+        let mut slot_size = Layout::NULL_BITMAP_OFFSET + 4;
 
         for field in &schema.fields {
             offsets.insert(field.clone(), slot_size);
@@ -159,6 +247,7 @@ impl Layout {
             schema,
             offsets,
             slot_size,
+            first_blocks: HashMap::new(),
         }
     }
 
@@ -171,14 +260,70 @@ impl Layout {
             schema,
             offsets,
             slot_size: slot_size,
+            first_blocks: HashMap::new(),
+        }
+    }
+
+    pub fn set_first_block(&mut self, field_name: &str, block_number: u64) {
+        if block_number == 0 {
+            self.first_blocks.remove(field_name);
+        } else {
+            self.first_blocks
+                .insert(field_name.to_string(), block_number);
+        }
+    }
+
+    pub fn get_first_block(&self, field_name: &str) -> u64 {
+        return self.first_blocks.get(field_name).copied().unwrap_or(0);
+    }
+
+    // 全部のblockが今のslot_sizeで書かれているとして扱うlayout。vacuumで書き直すときに使う
+    pub fn without_first_blocks(&self) -> Layout {
+        let mut layout = self.clone();
+        layout.first_blocks.clear();
+        return layout;
+    }
+
+    // add columnより前からあるblockを読むためのlayout。今のlayoutで読めるblockならNone
+    // fieldはslotの最後に足すので、無いfieldのうち一番前のoffsetがそのblockのslot_size
+    pub fn layout_of_block(&self, block_number: u64) -> Option<Layout> {
+        let missing_fields = self
+            .first_blocks
+            .iter()
+            .filter(|(_, first_block)| block_number < **first_block)
+            .map(|(field_name, _)| field_name)
+            .collect::<Vec<&String>>();
+        let slot_size = missing_fields
+            .iter()
+            .map(|field_name| self.offsets[*field_name])
+            .min()?;
+
+        let mut schema = TableSchema::new();
+        let mut offsets = HashMap::new();
+        for field_name in self.schema.fields.iter() {
+            if missing_fields.contains(&field_name) {
+                continue;
+            }
+            schema.fields.push(field_name.clone());
+            schema.field_infos.insert(
+                field_name.clone(),
+                self.schema.field_infos[field_name].clone(),
+            );
+            offsets.insert(field_name.clone(), self.offsets[field_name]);
         }
+        return Some(Layout::new_with_offset_and_size(schema, offsets, slot_size));
     }
 
-    fn get_length_in_bytes(schema: &TableSchema, field_name: String) -> i32 {
+    pub fn get_length_in_bytes(schema: &TableSchema, field_name: String) -> i32 {
         let field_type = schema.get_field_type(field_name.clone()).unwrap();
         match field_type {
             TableFieldType::INTEGER => 4,
-            TableFieldType::VARCHAR => schema.get_field_length(field_name).unwrap() * 4 + 4,
+            TableFieldType::DECIMAL(_, _) => 4,
+            TableFieldType::FLOAT => Page::get_float_byte_size() as i32,
+            TableFieldType::DATE => Page::get_long_byte_size() as i32,
+            TableFieldType::VARCHAR => {
+                Page::get_max_length(schema.get_field_length(field_name).unwrap() as u32) as i32
+            }
         }
     }
 
@@ -186,6 +331,20 @@ impl Layout {
         self.slot_size
     }
 
+    // fieldのnull bitの位置。schemaのfieldの順番で決める
+    pub fn get_null_bit(&self, field_name: &str) -> Option<u32> {
+        let position = self.schema.fields.iter().position(|f| f == field_name)?;
+        if position >= Layout::MAX_FIELD_COUNT {
+            panic!("null bitmap supports at most 32 fields");
+        }
+        return Some(position as u32);
+    }
+
+    // 1つのblockに入るrecordの数
+    pub fn records_per_block(&self, block_size: usize) -> usize {
+        block_size / self.slot_size as usize
+    }
+
     pub fn get_offset(&self, field_name: &str) -> Option<i32> {
         let offset = self.offsets.get(field_name);
         // offsetが存在しない場合はNoneを返す
@@ -206,8 +365,49 @@ impl Layout {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum RecordType {
     EMPTY = 0,
     USED = 1,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_format_schema_and_layout() {
+        let mut schema = TableSchema::new();
+        schema.add_integer_field("A".to_string());
+        schema.add_string_field("B".to_string(), 10);
+
+        let schema_debug = format!("{:?}", schema);
+        assert!(schema_debug.contains("INTEGER"));
+        assert!(schema_debug.contains("VARCHAR"));
+
+        let layout = Layout::new(schema.clone());
+        assert!(format!("{:?}", layout).contains("slot_size"));
+        assert_eq!(format!("{:?}", RecordType::USED), "USED");
+
+        // catalogから復元したlayoutと比較できる
+        let mut offsets = HashMap::new();
+        offsets.insert("A".to_string(), layout.get_offset("A").unwrap());
+        offsets.insert("B".to_string(), layout.get_offset("B").unwrap());
+        let restored = Layout::new_with_offset_and_size(schema, offsets, layout.get_slot_size());
+        assert_eq!(layout, restored);
+    }
+
+    #[test]
+    fn test_records_per_block() {
+        let mut schema = TableSchema::new();
+        schema.add_integer_field("A".to_string());
+        schema.add_integer_field("B".to_string());
+
+        // RecordTypeとnull bitmapの8byteとinteger 2つで16byte
+        let layout = Layout::new(schema);
+        assert_eq!(layout.get_slot_size(), 16);
+        assert_eq!(layout.records_per_block(400), 25);
+        assert_eq!(layout.records_per_block(16), 1);
+        assert_eq!(layout.records_per_block(15), 0);
+    }
+}
@@ -2,6 +2,7 @@ use std::{cell::RefCell, rc::Rc};
 
 use crate::{
     storage::block::BlockId,
+    storage::page::Page,
     record::record_page::{Layout, RecordType, TableFieldType},
     tx::transaction_v2::TransactionV2,
 };
@@ -10,6 +11,8 @@ pub struct RecordPage {
     layout: Layout,
     block_id: BlockId,
     transaction: Rc<RefCell<TransactionV2>>,
+    // add columnより前からあるblock。足したfieldを書く場所が無いので、新しい行は入れない
+    is_old_block: bool,
 }
 
 impl RecordPage {
@@ -19,13 +22,24 @@ impl RecordPage {
         block_id: BlockId,
     ) -> RecordPage {
         transaction.borrow_mut().pin(block_id.clone());
+        // 古いblockは、そのblockを書いたときのslot_sizeとfieldで読み書きする
+        let (layout, is_old_block) = match layout.layout_of_block(block_id.get_block_number()) {
+            Some(old_layout) => (old_layout, true),
+            None => (layout, false),
+        };
         RecordPage {
             transaction,
             layout,
             block_id,
+            is_old_block,
         }
     }
 
+    // このblockの行がfieldを持っているか。add columnより前からあるblockには足したfieldが無い
+    pub fn has_field(&self, field_name: &str) -> bool {
+        return self.layout.has_field(field_name.to_string());
+    }
+
     pub fn get_integer(&mut self, field_name: String, slot_id: i32) -> Option<i32> {
         if !self.layout.has_field(field_name.clone()) {
             return None;
@@ -35,7 +49,10 @@ impl RecordPage {
         let record_offset = self.get_offset_of_record(slot_id);
         let field_type = self.layout.get_field_type(field_name.clone()).unwrap();
 
-        if field_type != TableFieldType::INTEGER {
+        if !matches!(
+            field_type,
+            TableFieldType::INTEGER | TableFieldType::DECIMAL(_, _)
+        ) {
             return None;
         }
 
@@ -47,6 +64,62 @@ impl RecordPage {
         Some(result)
     }
 
+    pub fn get_float(&mut self, field_name: String, slot_id: i32) -> Option<f64> {
+        if self.layout.get_field_type(field_name.clone()) != Some(TableFieldType::FLOAT) {
+            return None;
+        }
+        let offset = self.layout.get_offset(&field_name).unwrap();
+        let record_offset = self.get_offset_of_record(slot_id);
+        let result = self
+            .transaction
+            .borrow_mut()
+            .get_float(self.block_id.clone(), (record_offset + offset) as usize);
+        Some(result)
+    }
+
+    pub fn set_float(&mut self, field_name: String, slot_id: i32, value: f64) {
+        if self.layout.get_field_type(field_name.clone()) != Some(TableFieldType::FLOAT) {
+            return;
+        }
+        let offset = self.layout.get_offset(&field_name).unwrap();
+        let record_offset = self.get_offset_of_record(slot_id);
+        self.transaction.borrow_mut().set_float(
+            self.block_id.clone(),
+            (record_offset + offset) as usize,
+            value,
+            true,
+        );
+        self.set_null_bit(&field_name, slot_id, false);
+    }
+
+    pub fn get_date(&mut self, field_name: String, slot_id: i32) -> Option<i64> {
+        if self.layout.get_field_type(field_name.clone()) != Some(TableFieldType::DATE) {
+            return None;
+        }
+        let offset = self.layout.get_offset(&field_name).unwrap();
+        let record_offset = self.get_offset_of_record(slot_id);
+        let result = self
+            .transaction
+            .borrow_mut()
+            .get_long(self.block_id.clone(), (record_offset + offset) as usize);
+        Some(result)
+    }
+
+    pub fn set_date(&mut self, field_name: String, slot_id: i32, value: i64) {
+        if self.layout.get_field_type(field_name.clone()) != Some(TableFieldType::DATE) {
+            return;
+        }
+        let offset = self.layout.get_offset(&field_name).unwrap();
+        let record_offset = self.get_offset_of_record(slot_id);
+        self.transaction.borrow_mut().set_long(
+            self.block_id.clone(),
+            (record_offset + offset) as usize,
+            value,
+            true,
+        );
+        self.set_null_bit(&field_name, slot_id, false);
+    }
+
     pub fn get_block_id(&self) -> BlockId {
         self.block_id.clone()
     }
@@ -60,7 +133,11 @@ impl RecordPage {
         let record_offset = self.get_offset_of_record(slot_id);
         let field_type = self.layout.get_field_type(field_name.clone()).unwrap();
 
-        if field_type != TableFieldType::INTEGER {
+        // DECIMALもscaleをかけた整数として保存する
+        if !matches!(
+            field_type,
+            TableFieldType::INTEGER | TableFieldType::DECIMAL(_, _)
+        ) {
             return;
         }
 
@@ -70,6 +147,7 @@ impl RecordPage {
             value,
             true,
         );
+        self.set_null_bit(&field_name, slot_id, false);
     }
 
     pub fn delete(&mut self, slot_id: i32) {
@@ -95,12 +173,72 @@ impl RecordPage {
             return;
         }
 
+        // SQLからの書き込みはvalidate_value_typeでValueTooLongにしている
+        // ここまで来た長すぎる文字列は、後ろのフィールドを壊さないように切り詰める
+        let field_length = self.layout.schema.get_field_length(field_name.clone()).unwrap() as u32;
+        let max_bytes = Page::get_max_length(field_length) - Page::get_integer_byte_size();
+        let mut value = value;
+        if value.len() > max_bytes {
+            let mut end = max_bytes;
+            while !value.is_char_boundary(end) {
+                end -= 1;
+            }
+            value.truncate(end);
+        }
+
         self.transaction.borrow_mut().set_string(
             self.block_id.clone(),
             (record_offset + offset) as usize,
             value.as_str(),
             true,
         );
+        self.set_null_bit(&field_name, slot_id, false);
+    }
+
+    // NULLのfieldは値のbyteには書き込まず、null bitだけを立てる
+    pub fn set_null(&mut self, field_name: String, slot_id: i32) {
+        self.set_null_bit(&field_name, slot_id, true);
+    }
+
+    pub fn is_null(&mut self, field_name: String, slot_id: i32) -> bool {
+        let Some(bit) = self.layout.get_null_bit(&field_name) else {
+            return false;
+        };
+        return self.get_null_bitmap(slot_id) & (1 << bit) != 0;
+    }
+
+    fn set_null_bit(&mut self, field_name: &str, slot_id: i32, is_null: bool) {
+        let Some(bit) = self.layout.get_null_bit(field_name) else {
+            return;
+        };
+        let bitmap = self.get_null_bitmap(slot_id);
+        let new_bitmap = if is_null {
+            bitmap | (1 << bit)
+        } else {
+            bitmap & !(1 << bit)
+        };
+        // 変わらないときはlogを書かない
+        if new_bitmap != bitmap {
+            self.set_null_bitmap(slot_id, new_bitmap);
+        }
+    }
+
+    fn get_null_bitmap(&mut self, slot_id: i32) -> i32 {
+        let record_offset = self.get_offset_of_record(slot_id);
+        return self.transaction.borrow_mut().get_integer(
+            self.block_id.clone(),
+            (record_offset + Layout::NULL_BITMAP_OFFSET) as usize,
+        );
+    }
+
+    fn set_null_bitmap(&mut self, slot_id: i32, bitmap: i32) {
+        let record_offset = self.get_offset_of_record(slot_id);
+        self.transaction.borrow_mut().set_integer(
+            self.block_id.clone(),
+            (record_offset + Layout::NULL_BITMAP_OFFSET) as usize,
+            bitmap,
+            true,
+        );
     }
 
     fn search_after(&mut self, slot_id: i32, target_record_type: RecordType) -> Option<i32> {
@@ -155,7 +293,10 @@ impl RecordPage {
 
     pub fn is_valid_slot_id(&self, slot_id: i32) -> bool {
         return self.get_offset_of_record(slot_id + 1)
-            <= self.transaction.borrow_mut().get_block_size() as i32;
+            <= self
+                .transaction
+                .borrow()
+                .get_file_block_size(self.block_id.get_file_name()) as i32;
     }
 
     pub fn get_offset_of_record(&self, slot_id: i32) -> i32 {
@@ -167,16 +308,42 @@ impl RecordPage {
     }
 
     pub fn insert_after_slot_id(&mut self, slot_id: i32) -> Option<i32> {
+        if self.is_old_block {
+            return None;
+        }
         let next_slot_id = self.search_after(slot_id, RecordType::EMPTY);
 
         if let Some(next_slot_id) = next_slot_id {
             self.set_flag(next_slot_id, RecordType::USED);
+            // 書き込むまではすべてのfieldをNULLにしておく。''は書き込まれた値として区別する
+            let field_count = self.layout.schema.fields.len().min(Layout::MAX_FIELD_COUNT);
+            let all_null_bitmap = ((1u64 << field_count) - 1) as u32 as i32;
+            if self.get_null_bitmap(next_slot_id) != all_null_bitmap {
+                self.set_null_bitmap(next_slot_id, all_null_bitmap);
+            }
             return Some(next_slot_id);
         }
 
         return None;
     }
 
+    // blockの全byteを0にする。0はEMPTYのslot、0、""、null bitなしとして読める
+    // 別のlayoutで書かれたblockはstringの長さが壊れて見えるので、formatではなくこちらを使う
+    pub fn clear(&mut self) {
+        let block_size = self
+            .transaction
+            .borrow()
+            .get_file_block_size(self.block_id.get_file_name());
+        let integer_size = Page::get_integer_byte_size();
+        let mut offset = 0;
+        while offset + integer_size <= block_size {
+            self.transaction
+                .borrow_mut()
+                .set_integer(self.block_id.clone(), offset, 0, true);
+            offset += integer_size;
+        }
+    }
+
     pub fn format(&mut self) {
         let mut slot_id = 0;
         while self.is_valid_slot_id(slot_id) {
@@ -188,7 +355,7 @@ impl RecordPage {
                 let offset = self.layout.get_offset(&field).unwrap();
 
                 match field_type {
-                    TableFieldType::INTEGER => {
+                    TableFieldType::INTEGER | TableFieldType::DECIMAL(_, _) => {
                         self.transaction.borrow_mut().set_integer(
                             self.block_id.clone(),
                             offset as usize,
@@ -204,6 +371,22 @@ impl RecordPage {
                             true,
                         );
                     }
+                    TableFieldType::FLOAT => {
+                        self.transaction.borrow_mut().set_float(
+                            self.block_id.clone(),
+                            offset as usize,
+                            0.0,
+                            true,
+                        );
+                    }
+                    TableFieldType::DATE => {
+                        self.transaction.borrow_mut().set_long(
+                            self.block_id.clone(),
+                            offset as usize,
+                            0,
+                            true,
+                        );
+                    }
                 }
             }
 
@@ -285,19 +468,19 @@ mod tests {
                 let offset = layout.get_offset(field);
                 println!("Field: {}, Offset: {}", field, offset.unwrap());
                 if index == 0 {
-                    assert_eq!(offset.unwrap(), 4);
+                    assert_eq!(offset.unwrap(), 8);
                     assert_eq!(field, &integer_field_name);
                 }
 
                 if index == 1 {
-                    assert_eq!(offset.unwrap(), 8);
+                    assert_eq!(offset.unwrap(), 12);
                     assert_eq!(field, &string_field_name);
                 }
             });
 
         assert_eq!(
             layout.get_slot_size(),
-            Page::get_max_length(string_field_length as u32) as i32 + 8
+            Page::get_max_length(string_field_length as u32) as i32 + 12
         );
 
         let test_file_name = format!("test_file_{}.txt", uuid::Uuid::new_v4());
@@ -362,7 +545,102 @@ mod tests {
         }
 
         transaction.borrow_mut().unpin(block.clone());
-        transaction.borrow_mut().commit();
+        transaction.borrow_mut().commit().unwrap();
+
+        remove_file(test_dir.join(test_file_name)).unwrap();
+        remove_file(test_dir.join(log_file_name)).unwrap();
+    }
+
+    #[test]
+    fn test_record_page_v2_string_boundary() {
+        let test_dir = Path::new("test_data");
+
+        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
+        let block_size = 400;
+
+        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
+        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
+            file_manager.clone(),
+            log_file_name.clone(),
+        )));
+
+        let buffer_manager = Arc::new(Mutex::new(BufferManagerV2::new(
+            3,
+            file_manager.clone(),
+            log_manager.clone(),
+        )));
+
+        let lock_table = Arc::new(Mutex::new(LockTable::new()));
+
+        let transaction = Rc::new(RefCell::new(TransactionV2::new(
+            1,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        )));
+
+        let mut schema = TableSchema::new();
+        schema.add_string_field("empty_field".to_string(), 0);
+        schema.add_string_field("name".to_string(), 5);
+        schema.add_integer_field("id".to_string());
+        let layout = Layout::new(schema);
+
+        // 長さ0のVARCHARでもlengthの4byte分は確保される
+        assert_eq!(layout.get_offset("name").unwrap(), 8 + 4);
+        assert_eq!(
+            layout.get_offset("id").unwrap(),
+            8 + 4 + Page::get_max_length(5) as i32
+        );
+
+        let test_file_name = format!("test_file_{}.txt", uuid::Uuid::new_v4());
+        let block = transaction.borrow_mut().append(&test_file_name);
+        transaction.borrow_mut().pin(block.clone());
+
+        let mut record_page = RecordPage::new(transaction.clone(), layout, block.clone());
+        record_page.format();
+
+        let test_values = [
+            ("", "", 1),
+            ("x", "あいうえお", 2),
+            ("", "😀😀😀😀😀", 3),
+            ("", "abcdefghijklmnopqrstuvwxyz", 4),
+            ("", "😀😀😀😀😀😀", 5),
+        ];
+
+        let mut slot = -1;
+        for (empty_value, name, id) in test_values.iter() {
+            slot = record_page.insert_after_slot_id(slot).unwrap();
+            record_page.set_string("empty_field".to_string(), slot, empty_value.to_string());
+            record_page.set_string("name".to_string(), slot, name.to_string());
+            record_page.set_integer("id".to_string(), slot, *id);
+        }
+
+        let expected_values = [
+            ("", "", 1),
+            ("", "あいうえお", 2),
+            ("", "😀😀😀😀😀", 3),
+            // RecordPageに直接書くと、確保したbyte数(5 * 4byte)を超える分は切り詰められる
+            ("", "abcdefghijklmnopqrst", 4),
+            ("", "😀😀😀😀😀", 5),
+        ];
+
+        let mut slot = -1;
+        for (empty_value, name, id) in expected_values.iter() {
+            slot = record_page.find_next_after_slot_id(slot).unwrap();
+            assert_eq!(
+                record_page.get_string("empty_field".to_string(), slot),
+                Some(empty_value.to_string())
+            );
+            assert_eq!(
+                record_page.get_string("name".to_string(), slot),
+                Some(name.to_string())
+            );
+            assert_eq!(record_page.get_integer("id".to_string(), slot), Some(*id));
+        }
+
+        transaction.borrow_mut().unpin(block.clone());
+        transaction.borrow_mut().commit().unwrap();
 
         remove_file(test_dir.join(test_file_name)).unwrap();
         remove_file(test_dir.join(log_file_name)).unwrap();
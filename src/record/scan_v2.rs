@@ -2,6 +2,7 @@ use crate::{
     error::ValueNotFound,
     query::predicate::{ConstantValue, TableNameAndFieldName},
     query::predicate_v3::PredicateV2,
+    record::record_page::TableSchema,
     record::table_scan_v2::RecordID,
 };
 
@@ -37,7 +38,7 @@ impl SelectScanV2 {
 
 impl ScanV2 for SelectScanV2 {
     fn move_to_before_first(&mut self) -> Result<(), ValueNotFound> {
-        self.scan.move_to_before_first();
+        self.scan.move_to_before_first()?;
         return Ok(());
     }
 
@@ -207,6 +208,12 @@ impl ProductScanV2 {
             first_left_scan_next: true,
         }
     }
+
+    // 右のscanを閉じて、左のscanだけを返す
+    pub fn into_left_scan(mut self) -> Box<dyn ScanV2> {
+        self.right_scan.close();
+        return self.left_scan;
+    }
 }
 
 impl ScanV2 for ProductScanV2 {
@@ -223,18 +230,17 @@ impl ScanV2 for ProductScanV2 {
             return Ok(false);
         }
 
-        let right_scan_next = self.right_scan.next()?;
-
-        if right_scan_next {
+        // 今のleftの行にrightの次の行を組み合わせる
+        if self.right_scan.next()? {
             return Ok(true);
         }
 
-        self.right_scan.move_to_before_first();
-
-        let left_scan_next = self.left_scan.next()?;
-        let right_scan_next = self.right_scan.next()?;
-
-        return Ok(right_scan_next && left_scan_next);
+        // rightを読み終えたら、leftを1行進めてrightの最初の行から組み合わせ直す
+        if !self.left_scan.next()? {
+            return Ok(false);
+        }
+        self.right_scan.move_to_before_first()?;
+        return self.right_scan.next();
     }
 
     fn get_integer(&mut self, field_name: TableNameAndFieldName) -> Option<i32> {
@@ -297,6 +303,171 @@ impl ScanV2 for ProductScanV2 {
     }
 }
 
+// 常に0件を返すscan。where 1 = 0 のように結果が空と分かっている場合に使う
+pub struct EmptyScanV2 {
+    schema: TableSchema,
+}
+
+impl EmptyScanV2 {
+    pub fn new(schema: TableSchema) -> Self {
+        EmptyScanV2 { schema }
+    }
+}
+
+impl ScanV2 for EmptyScanV2 {
+    fn move_to_before_first(&mut self) -> Result<(), ValueNotFound> {
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<bool, ValueNotFound> {
+        Ok(false)
+    }
+
+    fn get_integer(&mut self, _field_name: TableNameAndFieldName) -> Option<i32> {
+        None
+    }
+
+    fn get_string(&mut self, _field_name: TableNameAndFieldName) -> Option<String> {
+        None
+    }
+
+    fn get_value(&mut self, _field_name: TableNameAndFieldName) -> Option<ConstantValue> {
+        None
+    }
+
+    fn close(&mut self) {}
+
+    fn has_field(&self, field_name: TableNameAndFieldName) -> bool {
+        self.schema.has_field(field_name.field_name)
+    }
+
+    fn set_integer(&mut self, _field_name: String, _value: i32) {
+        panic!("set_integer not implemented for EmptyScan");
+    }
+
+    fn set_string(&mut self, _field_name: String, _value: String) {
+        panic!("set_string not implemented for EmptyScan");
+    }
+
+    fn set_value(&mut self, _field_name: String, _value: ConstantValue) {
+        panic!("set_value not implemented for EmptyScan");
+    }
+
+    fn insert(&mut self) {
+        panic!("insert not implemented for EmptyScan");
+    }
+
+    fn delete(&mut self) {
+        panic!("delete not implemented for EmptyScan");
+    }
+
+    fn get_record_id(&self) -> RecordID {
+        panic!("get_record_id not implemented for EmptyScan");
+    }
+
+    fn move_to_record_id(&mut self, _record_id: RecordID) {
+        panic!("move_to_record_id not implemented for EmptyScan");
+    }
+}
+
+// limit/offset。最初のnextでoffset件読み飛ばし、limit件返したらそれ以上は読まない
+pub struct LimitScanV2 {
+    scan: Box<dyn ScanV2>,
+    limit: Option<usize>,
+    offset: usize,
+    returned: usize,
+    skipped: bool,
+}
+
+impl LimitScanV2 {
+    pub fn new(scan: Box<dyn ScanV2>, limit: Option<usize>, offset: usize) -> Self {
+        LimitScanV2 {
+            scan,
+            limit,
+            offset,
+            returned: 0,
+            skipped: false,
+        }
+    }
+}
+
+impl ScanV2 for LimitScanV2 {
+    fn move_to_before_first(&mut self) -> Result<(), ValueNotFound> {
+        self.returned = 0;
+        self.skipped = false;
+        self.scan.move_to_before_first()
+    }
+
+    fn next(&mut self) -> Result<bool, ValueNotFound> {
+        if let Some(limit) = self.limit {
+            if self.returned >= limit {
+                return Ok(false);
+            }
+        }
+        if !self.skipped {
+            self.skipped = true;
+            for _ in 0..self.offset {
+                if !self.scan.next()? {
+                    return Ok(false);
+                }
+            }
+        }
+        if !self.scan.next()? {
+            return Ok(false);
+        }
+        self.returned += 1;
+        return Ok(true);
+    }
+
+    fn get_integer(&mut self, field_name: TableNameAndFieldName) -> Option<i32> {
+        self.scan.get_integer(field_name)
+    }
+
+    fn get_string(&mut self, field_name: TableNameAndFieldName) -> Option<String> {
+        self.scan.get_string(field_name)
+    }
+
+    fn get_value(&mut self, field_name: TableNameAndFieldName) -> Option<ConstantValue> {
+        self.scan.get_value(field_name)
+    }
+
+    fn close(&mut self) {
+        self.scan.close();
+    }
+
+    fn has_field(&self, field_name: TableNameAndFieldName) -> bool {
+        self.scan.has_field(field_name)
+    }
+
+    fn set_integer(&mut self, _field_name: String, _value: i32) {
+        panic!("set_integer not implemented for LimitScan");
+    }
+
+    fn set_string(&mut self, _field_name: String, _value: String) {
+        panic!("set_string not implemented for LimitScan");
+    }
+
+    fn set_value(&mut self, _field_name: String, _value: ConstantValue) {
+        panic!("set_value not implemented for LimitScan");
+    }
+
+    fn insert(&mut self) {
+        panic!("insert not implemented for LimitScan");
+    }
+
+    fn delete(&mut self) {
+        panic!("delete not implemented for LimitScan");
+    }
+
+    fn get_record_id(&self) -> RecordID {
+        self.scan.get_record_id()
+    }
+
+    fn move_to_record_id(&mut self, record_id: RecordID) {
+        self.scan.move_to_record_id(record_id);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -314,16 +485,18 @@ mod tests {
         query::predicate::{Constant, ConstantValue, ExpressionValue, TableNameAndFieldName},
         query::predicate_v3::{ExpressionV2, PredicateV2, TermV2},
         record::record_page::{Layout, TableSchema},
-        record::scan_v2::{ScanV2, SelectScanV2},
+        record::scan_v2::{ProductScanV2, ScanV2, SelectScanV2},
         metadata::table_manager_v2::TableManagerV2,
         record::table_scan_v2::TableScan,
         tx::transaction_v2::TransactionV2,
         metadata::view_manager::ViewManager,
+        query::values_plan::ValuesScan,
     };
 
     #[test]
     fn test_view_mgr() {
-        let test_dir_name = format!("test_data_{}", uuid::Uuid::new_v4());
+        let test_directory = crate::database::TestDirectory::new();
+        let test_dir_name = test_directory.path_name();
         let test_dir = Path::new(&test_dir_name);
         let block_size = 400;
 
@@ -361,11 +534,14 @@ mod tests {
 
         let student_table_layout = Layout::new(student_table_schema.clone());
 
-        table_manager.borrow_mut().create_table(
-            "student".to_string(),
-            &student_table_schema,
-            transaction.clone(),
-        );
+        table_manager
+            .borrow_mut()
+            .create_table(
+                "student".to_string(),
+                &student_table_schema,
+                transaction.clone(),
+            )
+            .unwrap();
 
         let mut student_table_scan = TableScan::new(
             "student".to_string(),
@@ -385,7 +561,7 @@ mod tests {
         student_table_scan.set_string("name".to_string(), "John".to_string());
         student_table_scan.set_integer("age".to_string(), 20);
 
-        student_table_scan.move_to_before_first();
+        student_table_scan.move_to_before_first().unwrap();
 
         let lhs_expression = ExpressionV2::new(ExpressionValue::TableNameAndFieldName(
             TableNameAndFieldName::new(None, "age".to_string()),
@@ -423,4 +599,41 @@ mod tests {
 
         println!("name: {}, age: {}", name, age);
     }
+
+    #[test]
+    fn test_product_scan_returns_every_combination() {
+        let values_scan = |alias: &str, field_name: &str, values: Vec<i32>| {
+            let mut schema = TableSchema::new();
+            schema.add_integer_field(field_name.to_string());
+            let rows = values
+                .into_iter()
+                .map(|value| vec![ConstantValue::Number(value)])
+                .collect();
+            return Box::new(ValuesScan::new(alias.to_string(), schema, rows));
+        };
+
+        let mut scan = ProductScanV2::new(
+            values_scan("l", "a", vec![1, 2]),
+            values_scan("r", "b", vec![10, 20, 30]),
+        );
+        scan.move_to_before_first().unwrap();
+
+        let mut combinations = vec![];
+        while scan.next().unwrap() {
+            combinations.push((
+                scan.get_integer(TableNameAndFieldName::new(None, "a".to_string()))
+                    .unwrap(),
+                scan.get_integer(TableNameAndFieldName::new(None, "b".to_string()))
+                    .unwrap(),
+            ));
+        }
+        // 最後まで読んだ後もfalseを返し続ける
+        assert!(!scan.next().unwrap());
+        scan.close();
+
+        assert_eq!(
+            combinations,
+            vec![(1, 10), (1, 20), (1, 30), (2, 10), (2, 20), (2, 30)]
+        );
+    }
 }
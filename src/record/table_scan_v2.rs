@@ -3,7 +3,7 @@ use std::{cell::RefCell, rc::Rc};
 use crate::{
     storage::block::BlockId,
     error::ValueNotFound,
-    query::predicate::TableNameAndFieldName,
+    query::predicate::{Constant, TableNameAndFieldName},
     record::record_page::{Layout, TableFieldType},
     record::record_page_v2::RecordPage,
     record::scan_v2::ScanV2,
@@ -99,6 +99,15 @@ impl TableScan {
         self.current_slot = -1;
     }
 
+    // INTEGERやDECIMALのfieldに入れるfloatを、scaleをかけた整数に四捨五入する
+    fn round_float(value: f64, scale: u32, field_name: &str) -> i32 {
+        let rounded = (value * 10_f64.powi(scale as i32)).round();
+        if rounded.is_nan() || rounded < i32::MIN as f64 || rounded > i32::MAX as f64 {
+            panic!("Float value overflows field '{}'", field_name);
+        }
+        return rounded as i32;
+    }
+
     fn at_last_block(&self) -> bool {
         let file_size = self.transaction.borrow().get_size(self.file_name.clone());
         let current_block = self.record_page.get_block_id().get_block_number();
@@ -135,16 +144,74 @@ impl ScanV2 for TableScan {
             panic!("No current record to set value");
         }
 
+        if value == crate::query::predicate::ConstantValue::Null {
+            if self.layout.has_field(field_name.clone()) {
+                self.record_page.set_null(field_name, self.current_slot);
+            }
+            return;
+        }
+
+        // FLOATのfieldには数値をf64に広げて保存する
+        if let Some(TableFieldType::FLOAT) = self.layout.schema.get_field_type(field_name.clone()) {
+            let Some(float) = Constant::float_value(&value) else {
+                panic!("Expected a number for FLOAT field");
+            };
+            self.record_page
+                .set_float(field_name, self.current_slot, float);
+            return;
+        }
+
+        if let Some(TableFieldType::DATE) = self.layout.schema.get_field_type(field_name.clone()) {
+            let crate::query::predicate::ConstantValue::Date(days) = value else {
+                panic!("Expected a date for DATE field");
+            };
+            self.record_page
+                .set_date(field_name, self.current_slot, days);
+            return;
+        }
+
+        // DECIMALのfieldにはそのfieldのscaleに揃えてから保存する
+        if let Some(TableFieldType::DECIMAL(_, scale)) =
+            self.layout.schema.get_field_type(field_name.clone())
+        {
+            let (value, from_scale) = match value {
+                crate::query::predicate::ConstantValue::Decimal { value, scale } => (value, scale),
+                crate::query::predicate::ConstantValue::Number(num) => (num, 0),
+                crate::query::predicate::ConstantValue::Float(float) => {
+                    (Self::round_float(float, scale as u32, &field_name), scale as u32)
+                }
+                _ => panic!("Expected a number for DECIMAL field"),
+            };
+            let scaled = Constant::rescale_decimal(value, from_scale, scale as u32)
+                .unwrap_or_else(|| panic!("Decimal value overflows field '{}'", field_name));
+            self.set_integer(field_name, scaled);
+            return;
+        }
+
         match value {
             crate::query::predicate::ConstantValue::Number(num) => {
                 self.set_integer(field_name, num);
             }
+            crate::query::predicate::ConstantValue::Decimal { value, scale } => {
+                // INTEGERのfieldには小数点以下を丸めて保存する
+                let rounded = Constant::rescale_decimal(value, scale, 0)
+                    .unwrap_or_else(|| panic!("Decimal value overflows field '{}'", field_name));
+                self.set_integer(field_name, rounded);
+            }
+            crate::query::predicate::ConstantValue::Float(float) => {
+                let rounded = Self::round_float(float, 0, &field_name);
+                self.set_integer(field_name, rounded);
+            }
             crate::query::predicate::ConstantValue::String(string) => {
                 self.set_string(field_name, string);
             }
-            crate::query::predicate::ConstantValue::Null => {
-                panic!("Null value cannot be set");
+            crate::query::predicate::ConstantValue::Date(_) => {
+                panic!("Date value can only be stored in a DATE field");
             }
+            crate::query::predicate::ConstantValue::Parameter(index) => {
+                panic!("Parameter {} is not bound", index + 1);
+            }
+            crate::query::predicate::ConstantValue::Null => {}
         }
     }
 
@@ -251,6 +318,24 @@ impl ScanV2 for TableScan {
             .schema
             .get_field_type(field_name.field_name.clone());
 
+        // add columnより前からあるblockの行は、足したfieldをdefaultかNULLとして読む
+        if field_type.is_some() && !self.record_page.has_field(&field_name.field_name) {
+            let default = self.layout.schema.get_default(&field_name.field_name);
+            return Some(match default {
+                Some(default) => default.value,
+                None => crate::query::predicate::ConstantValue::Null,
+            });
+        }
+
+        if field_type.is_some()
+            && self.current_slot != -1
+            && self
+                .record_page
+                .is_null(field_name.field_name.clone(), self.current_slot)
+        {
+            return Some(crate::query::predicate::ConstantValue::Null);
+        }
+
         match field_type {
             None => return None,
             Some(table_field_type) => match table_field_type {
@@ -262,6 +347,17 @@ impl ScanV2 for TableScan {
                         return Some(crate::query::predicate::ConstantValue::Null);
                     }
                 }
+                TableFieldType::DECIMAL(_, scale) => {
+                    let integer_value = self.get_integer(field_name);
+                    if let Some(value) = integer_value {
+                        return Some(crate::query::predicate::ConstantValue::Decimal {
+                            value,
+                            scale: scale as u32,
+                        });
+                    } else {
+                        return Some(crate::query::predicate::ConstantValue::Null);
+                    }
+                }
                 TableFieldType::VARCHAR => {
                     let string_value = self.get_string(field_name);
                     if let Some(value) = string_value {
@@ -270,6 +366,38 @@ impl ScanV2 for TableScan {
                         return Some(crate::query::predicate::ConstantValue::Null);
                     }
                 }
+                TableFieldType::FLOAT => {
+                    if self.current_slot == -1 {
+                        panic!(
+                            "No current record to get value: {},{}",
+                            self.table_name, field_name.field_name
+                        );
+                    }
+                    let float_value = self
+                        .record_page
+                        .get_float(field_name.field_name, self.current_slot);
+                    if let Some(value) = float_value {
+                        return Some(crate::query::predicate::ConstantValue::Float(value));
+                    } else {
+                        return Some(crate::query::predicate::ConstantValue::Null);
+                    }
+                }
+                TableFieldType::DATE => {
+                    if self.current_slot == -1 {
+                        panic!(
+                            "No current record to get value: {},{}",
+                            self.table_name, field_name.field_name
+                        );
+                    }
+                    let date_value = self
+                        .record_page
+                        .get_date(field_name.field_name, self.current_slot);
+                    if let Some(value) = date_value {
+                        return Some(crate::query::predicate::ConstantValue::Date(value));
+                    } else {
+                        return Some(crate::query::predicate::ConstantValue::Null);
+                    }
+                }
             },
         };
     }
@@ -325,6 +453,8 @@ mod tests {
         storage::file_manager::FileManager, storage::log_manager_v2::LogManagerV2, record::record_page::TableSchema,
     };
 
+    use crate::query::predicate::ConstantValue;
+
     use super::*;
 
     #[test]
@@ -403,7 +533,193 @@ mod tests {
         assert_eq!(count, 1000);
 
         table_scan.close();
-        transaction.borrow_mut().commit();
+        transaction.borrow_mut().commit().unwrap();
+
+        _ = remove_file(test_dir.join(log_file_name));
+        _ = remove_file(test_dir.join(format!("{}.tbl", test_file_name)));
+
+        return Ok(());
+    }
+
+    // v1のRecordPage/TableScanはすでに削除されているので、deleteの経路はv2のみ
+    #[test]
+    fn test_table_scan_v2_delete() -> Result<(), Box<dyn std::error::Error>> {
+        let test_dir = Path::new("test_data");
+        let block_size = 400;
+        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
+
+        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
+        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
+            file_manager.clone(),
+            log_file_name.clone(),
+        )));
+
+        let buffer_manager = Arc::new(Mutex::new(BufferManagerV2::new(
+            3,
+            file_manager.clone(),
+            log_manager.clone(),
+        )));
+
+        let lock_table = Arc::new(Mutex::new(LockTable::new()));
+
+        let transaction = Rc::new(RefCell::new(TransactionV2::new(
+            1,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        )));
+
+        let integer_field_name = "Field1".to_string();
+
+        let mut schema = TableSchema::new();
+        schema.add_integer_field(integer_field_name.clone());
+        let layout = Layout::new(schema);
+
+        let test_file_name = format!("test_table_{}", uuid::Uuid::new_v4());
+
+        let mut table_scan =
+            TableScan::new(test_file_name.clone(), transaction.clone(), layout.clone());
+
+        _ = table_scan.move_to_before_first();
+
+        // 複数blockにまたがるように挿入する
+        for value in 0..200 {
+            table_scan.insert();
+            table_scan.set_integer(integer_field_name.clone(), value);
+        }
+
+        _ = table_scan.move_to_before_first();
+
+        while table_scan.next()? {
+            let value = table_scan
+                .get_integer(TableNameAndFieldName::new(None, integer_field_name.clone()))
+                .unwrap();
+            if value % 2 == 0 {
+                table_scan.delete();
+            }
+        }
+
+        _ = table_scan.move_to_before_first();
+
+        let mut values = vec![];
+
+        while table_scan.next()? {
+            let value = table_scan
+                .get_integer(TableNameAndFieldName::new(None, integer_field_name.clone()))
+                .unwrap();
+            values.push(value);
+        }
+
+        assert_eq!(values, (0..200).filter(|value| value % 2 == 1).collect::<Vec<i32>>());
+
+        table_scan.close();
+        transaction.borrow_mut().commit().unwrap();
+
+        _ = remove_file(test_dir.join(log_file_name));
+        _ = remove_file(test_dir.join(format!("{}.tbl", test_file_name)));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_table_scan_v2_null() -> Result<(), Box<dyn std::error::Error>> {
+        let test_dir = Path::new("test_data");
+        let block_size = 400;
+        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
+
+        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
+        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
+            file_manager.clone(),
+            log_file_name.clone(),
+        )));
+
+        let buffer_manager = Arc::new(Mutex::new(BufferManagerV2::new(
+            3,
+            file_manager.clone(),
+            log_manager.clone(),
+        )));
+
+        let lock_table = Arc::new(Mutex::new(LockTable::new()));
+
+        let transaction = Rc::new(RefCell::new(TransactionV2::new(
+            1,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        )));
+
+        let mut schema = TableSchema::new();
+        schema.add_integer_field("id".to_string());
+        schema.add_string_field("name".to_string(), 9);
+        let layout = Layout::new(schema);
+
+        let test_file_name = format!("test_table_{}", uuid::Uuid::new_v4());
+
+        let mut table_scan =
+            TableScan::new(test_file_name.clone(), transaction.clone(), layout.clone());
+
+        _ = table_scan.move_to_before_first();
+
+        table_scan.insert();
+        table_scan.set_value("id".to_string(), ConstantValue::Number(1));
+        table_scan.set_value("name".to_string(), ConstantValue::Null);
+        table_scan.insert();
+        table_scan.set_value("id".to_string(), ConstantValue::Null);
+        table_scan.set_value("name".to_string(), ConstantValue::String("bob".to_string()));
+
+        let read_rows = |table_scan: &mut TableScan| -> Vec<(ConstantValue, ConstantValue)> {
+            _ = table_scan.move_to_before_first();
+            let mut rows = vec![];
+            while table_scan.next().unwrap() {
+                rows.push((
+                    table_scan
+                        .get_value(TableNameAndFieldName::new(None, "id".to_string()))
+                        .unwrap(),
+                    table_scan
+                        .get_value(TableNameAndFieldName::new(None, "name".to_string()))
+                        .unwrap(),
+                ));
+            }
+            return rows;
+        };
+
+        assert_eq!(
+            read_rows(&mut table_scan),
+            vec![
+                (ConstantValue::Number(1), ConstantValue::Null),
+                (ConstantValue::Null, ConstantValue::String("bob".to_string())),
+            ]
+        );
+
+        // 値を書き込むとnull bitは消える
+        _ = table_scan.move_to_before_first();
+        table_scan.next()?;
+        table_scan.set_value("name".to_string(), ConstantValue::String("alice".to_string()));
+        // deleteしたslotに挿入したrecordには前のnull bitが残らない
+        table_scan.next()?;
+        table_scan.delete();
+        table_scan.insert();
+        table_scan.set_value("id".to_string(), ConstantValue::Number(2));
+        table_scan.set_value("name".to_string(), ConstantValue::String("carol".to_string()));
+
+        assert_eq!(
+            read_rows(&mut table_scan),
+            vec![
+                (
+                    ConstantValue::Number(1),
+                    ConstantValue::String("alice".to_string())
+                ),
+                (
+                    ConstantValue::Number(2),
+                    ConstantValue::String("carol".to_string())
+                ),
+            ]
+        );
+
+        table_scan.close();
+        transaction.borrow_mut().commit().unwrap();
 
         _ = remove_file(test_dir.join(log_file_name));
         _ = remove_file(test_dir.join(format!("{}.tbl", test_file_name)));
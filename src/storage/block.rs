@@ -1,15 +1,15 @@
-#[derive(Eq, PartialEq, Clone, Debug)]
+use std::fmt;
+
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
 pub struct BlockId {
     file_name: String,
     block_number: u64,
 }
 
-use std::hash::{Hash, Hasher};
-
-impl Hash for BlockId {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.file_name.hash(state);
-        self.block_number.hash(state);
+// filename#blocknumの形で表示する
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{}", self.file_name, self.block_number)
     }
 }
 
@@ -29,14 +29,6 @@ impl BlockId {
         self.block_number
     }
 
-    pub fn equals(&self, other: &BlockId) -> bool {
-        self.file_name == other.file_name && self.block_number == other.block_number
-    }
-
-    pub fn to_string(&self) -> String {
-        format!("{}-{}", self.file_name, self.block_number)
-    }
-
     fn file_name_hash_code(&self) -> i32 {
         let mut h: i32 = 0;
         for char in self.file_name.chars() {
@@ -52,3 +44,28 @@ impl BlockId {
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_block_id_as_map_key_and_display() {
+        let mut pins: HashMap<BlockId, i32> = HashMap::new();
+        pins.insert(BlockId::new("t.tbl".to_string(), 0), 1);
+        pins.insert(BlockId::new("t.tbl".to_string(), 1), 2);
+        // 別に作っても同じfileとblock番号なら同じkey
+        *pins.get_mut(&BlockId::new("t.tbl".to_string(), 0)).unwrap() += 1;
+
+        assert_eq!(pins.len(), 2);
+        assert_eq!(pins[&BlockId::new("t.tbl".to_string(), 0)], 2);
+        assert_eq!(pins.get(&BlockId::new("u.tbl".to_string(), 0)), None);
+        assert!(BlockId::new("t.tbl".to_string(), 1) != BlockId::new("t.tbl".to_string(), 0));
+
+        let block_id = BlockId::new("t.tbl".to_string(), 3);
+        assert_eq!(format!("{}", block_id), "t.tbl#3");
+        assert_eq!(block_id.to_string(), "t.tbl#3");
+    }
+}
@@ -12,6 +12,8 @@ use crate::Page;
 pub struct FileManager {
     pub block_size: usize,
     open_files: Mutex<HashMap<String, Arc<Mutex<File>>>>,
+    // fileごとのblock size。登録されていないfileはdatabaseのblock_sizeを使う
+    file_block_sizes: Mutex<HashMap<String, usize>>,
     directory_path: PathBuf,
 }
 
@@ -24,6 +26,7 @@ impl FileManager {
             directory_path: directory_path.to_path_buf(),
             block_size,
             open_files: Mutex::new(open_files),
+            file_block_sizes: Mutex::new(HashMap::new()),
         }
     }
 
@@ -31,6 +34,23 @@ impl FileManager {
         self.block_size
     }
 
+    pub fn set_file_block_size(&self, file_name: &str, block_size: usize) {
+        self.file_block_sizes
+            .lock()
+            .unwrap()
+            .insert(file_name.to_string(), block_size);
+    }
+
+    pub fn get_file_block_size(&self, file_name: &str) -> usize {
+        return self
+            .file_block_sizes
+            .lock()
+            .unwrap()
+            .get(file_name)
+            .cloned()
+            .unwrap_or(self.block_size);
+    }
+
     pub fn get_file(&self, file_name: &str) -> Arc<Mutex<File>> {
         let file_path = self.directory_path.join(file_name);
 
@@ -51,15 +71,38 @@ impl FileManager {
         result.clone()
     }
 
+    pub fn exists(&self, file_name: &str) -> bool {
+        self.directory_path.join(file_name).exists()
+    }
+
+    // temp tableなど、使い終わったfileを消す
+    pub fn delete_file(&self, file_name: &str) -> std::io::Result<()> {
+        self.open_files.lock().unwrap().remove(file_name);
+        self.file_block_sizes.lock().unwrap().remove(file_name);
+        let file_path = self.directory_path.join(file_name);
+        if file_path.exists() {
+            fs::remove_file(file_path)?;
+        }
+        return Ok(());
+    }
+
+    // vacuumで空になった末尾のblockを切り詰める
+    pub fn truncate(&self, file_name: &str, block_count: usize) {
+        let block_size = self.get_file_block_size(file_name);
+        let file = self.get_file(file_name);
+        let file = file.lock().unwrap();
+        file.set_len((block_count * block_size) as u64).unwrap();
+    }
+
     pub fn length(&self, file_name: &str) -> usize {
         let file = self.get_file(file_name);
         let file = file.lock().unwrap();
         let file_length = file.metadata().unwrap().len() as usize;
-        file_length / self.block_size
+        file_length / self.get_file_block_size(file_name)
     }
 
     pub fn read(&self, block_id: &BlockId, page: &mut Page) {
-        let block_size = self.block_size;
+        let block_size = self.get_file_block_size(block_id.get_file_name());
         let file = self.get_file(block_id.get_file_name());
         let file = file.lock().unwrap();
         let offset = block_id.get_block_number() as usize * block_size;
@@ -68,7 +111,7 @@ impl FileManager {
     }
 
     pub fn write(&self, block_id: &BlockId, page: &mut Page) {
-        let block_size = self.block_size;
+        let block_size = self.get_file_block_size(block_id.get_file_name());
         let file = self.get_file(block_id.get_file_name());
         let file = file.lock().unwrap();
         let offset = block_id.get_block_number() as usize * block_size;
@@ -76,7 +119,7 @@ impl FileManager {
             .unwrap();
     }
     pub fn append(&self, file_name: &str) -> BlockId {
-        let block_size = self.block_size;
+        let block_size = self.get_file_block_size(file_name);
         let file = self.get_file(file_name);
         let file = file.lock().unwrap();
         let offset = file.metadata().unwrap().len() as usize;
@@ -96,7 +139,8 @@ mod tests {
     // FileManagerのテスト
     #[test]
     fn test_file_manager_read_write() {
-        let test_dir_name = format!("test_data_{}", uuid::Uuid::new_v4());
+        let test_directory = crate::database::TestDirectory::new();
+        let test_dir_name = test_directory.path_name();
         let test_dir = Path::new(&test_dir_name);
         let block_size = 400;
         let mut file_manager = FileManager::new(test_dir, block_size);
@@ -136,7 +180,8 @@ mod tests {
 
     #[test]
     fn file_lock_blocks_concurrent_access() {
-        let test_dir_name = format!("test_data_{}", uuid::Uuid::new_v4());
+        let test_directory = crate::database::TestDirectory::new();
+        let test_dir_name = test_directory.path_name();
         let test_dir = Path::new(&test_dir_name);
         let fm = Arc::new(FileManager::new(test_dir, 400));
         let file_arc = fm.get_file("locktest");
@@ -165,4 +210,52 @@ mod tests {
             "lock should be released after holder finishes"
         );
     }
+
+    #[test]
+    fn test_file_manager_file_block_size() {
+        let test_directory = crate::database::TestDirectory::new();
+        let test_dir_name = test_directory.path_name();
+        let test_dir = Path::new(&test_dir_name);
+        let file_manager = FileManager::new(test_dir, 400);
+
+        let test_file_name = format!("test_file_{}.tbl", uuid::Uuid::new_v4());
+        file_manager.set_file_block_size(&test_file_name, 1024);
+
+        assert_eq!(file_manager.get_file_block_size(&test_file_name), 1024);
+        assert_eq!(file_manager.get_file_block_size("other_file.tbl"), 400);
+
+        file_manager.append(&test_file_name);
+        let block_id = file_manager.append(&test_file_name);
+        assert_eq!(block_id.get_block_number(), 1);
+        assert_eq!(file_manager.length(&test_file_name), 2);
+
+        let mut page = Page::new(1024);
+        page.set_integer(1000, 42);
+        file_manager.write(&block_id, &mut page);
+
+        let mut page2 = Page::new(1024);
+        file_manager.read(&block_id, &mut page2);
+        assert_eq!(page2.get_integer(1000), 42);
+
+        remove_file(test_dir.join(&test_file_name)).unwrap_or_default();
+    }
+
+    #[test]
+    fn test_delete_file_returns_error() {
+        let test_directory = crate::database::TestDirectory::new();
+        let test_dir_name = test_directory.path_name();
+        let test_dir = Path::new(&test_dir_name);
+        let file_manager = FileManager::new(test_dir, 400);
+
+        let test_file_name = format!("test_file_{}.tbl", uuid::Uuid::new_v4());
+        file_manager.append(&test_file_name);
+        assert!(file_manager.delete_file(&test_file_name).is_ok());
+        assert!(!test_dir.join(&test_file_name).exists());
+        // 無いfileは消さなくてよい
+        assert!(file_manager.delete_file(&test_file_name).is_ok());
+
+        // 同じ名前のdirectoryは消せないが、panicせずにerrorを返す
+        fs::create_dir(test_dir.join(&test_file_name)).unwrap();
+        assert!(file_manager.delete_file(&test_file_name).is_err());
+    }
 }
@@ -17,7 +17,7 @@ impl LogManagerV2 {
         let file_manager_mut_ref = file_manager.lock().unwrap();
         let log_size = file_manager_mut_ref.length(&log_file_name);
 
-        let mut log_page = Page::new(400);
+        let mut log_page = Page::new(file_manager_mut_ref.block_size);
 
         let block_id;
 
@@ -204,4 +204,44 @@ mod tests {
 
         return page.get_data().clone();
     }
+
+    #[test]
+    fn test_log_manager_record_boundary() {
+        let test_dir = std::path::Path::new("test_data");
+        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
+
+        let block_size = 400;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
+        let mut log_manager = LogManagerV2::new(file_manager.clone(), log_file_name.clone());
+
+        // 空文字列のrecord
+        let empty_record = create_log_record("", 1);
+        log_manager.append_record(&empty_record);
+
+        // blockの残りにちょうど収まるrecord (先頭のboundary分の4byteだけが残る)
+        let remaining = block_size - INTEGER_BYTE_SIZE - (INTEGER_BYTE_SIZE + empty_record.len());
+        let exact_record = vec![7; remaining - INTEGER_BYTE_SIZE];
+        log_manager.append_record(&exact_record);
+        assert_eq!(log_manager.current_block_id.get_block_number(), 0);
+        assert_eq!(
+            log_manager.log_page.get_integer(0) as usize,
+            INTEGER_BYTE_SIZE
+        );
+
+        // 次のrecordは新しいblockに書かれる
+        let next_record = create_log_record("next", 2);
+        log_manager.append_record(&next_record);
+        assert_eq!(log_manager.current_block_id.get_block_number(), 1);
+
+        let mut log_iterator = log_manager.iterator();
+        assert_eq!(log_iterator.next(), next_record);
+        assert_eq!(log_iterator.next(), exact_record);
+
+        let record_page = Page::from(log_iterator.next());
+        assert_eq!(record_page.get_string(0), "");
+        assert_eq!(record_page.get_integer(Page::get_max_length(0)), 1);
+        assert!(!log_iterator.has_next());
+
+        remove_file(test_dir.join(log_file_name)).unwrap();
+    }
 }
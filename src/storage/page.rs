@@ -1,4 +1,8 @@
-use crate::constant::{INTEGER_BYTE_SIZE, MAX_BYTE_SIZE_PER_CHAR};
+use crate::{
+    constant::{FLOAT_BYTE_SIZE, INTEGER_BYTE_SIZE, LONG_BYTE_SIZE, MAX_BYTE_SIZE_PER_CHAR},
+    query::predicate::ConstantValue,
+    record::record_page::TableFieldType,
+};
 
 pub struct Page {
     data: Vec<u8>,
@@ -16,18 +20,66 @@ impl Page {
         Page { data }
     }
 
+    // slotの計算を間違えたときに隣のdataを壊さないよう、範囲外のアクセスはその場で止める
+    fn check_bounds(&self, offset: usize, length: usize) {
+        let in_bounds = match offset.checked_add(length) {
+            Some(end) => end <= self.data.len(),
+            None => false,
+        };
+        if !in_bounds {
+            panic!(
+                "Page access out of bounds: offset {} length {} page size {}",
+                offset,
+                length,
+                self.data.len()
+            );
+        }
+    }
+
     pub fn set_integer(&mut self, offset: usize, value: i32) {
+        self.check_bounds(offset, INTEGER_BYTE_SIZE);
         let integer_bytes = value.to_be_bytes();
         self.data[offset..offset + INTEGER_BYTE_SIZE].copy_from_slice(&integer_bytes);
     }
 
     pub fn get_integer(&self, offset: usize) -> i32 {
+        self.check_bounds(offset, INTEGER_BYTE_SIZE);
         let mut bytes = [0; INTEGER_BYTE_SIZE];
         bytes.copy_from_slice(&self.data[offset..offset + INTEGER_BYTE_SIZE]);
         i32::from_be_bytes(bytes)
     }
 
+    // f64のbit列をbig endianの8byteで持つ。前半と後半の4byteはそれぞれi32としても読める
+    pub fn set_float(&mut self, offset: usize, value: f64) {
+        self.check_bounds(offset, FLOAT_BYTE_SIZE);
+        let float_bytes = value.to_be_bytes();
+        self.data[offset..offset + FLOAT_BYTE_SIZE].copy_from_slice(&float_bytes);
+    }
+
+    pub fn get_float(&self, offset: usize) -> f64 {
+        self.check_bounds(offset, FLOAT_BYTE_SIZE);
+        let mut bytes = [0; FLOAT_BYTE_SIZE];
+        bytes.copy_from_slice(&self.data[offset..offset + FLOAT_BYTE_SIZE]);
+        f64::from_be_bytes(bytes)
+    }
+
+    // floatと同じくbig endianの8byte。DATEの日数に使う
+    pub fn set_long(&mut self, offset: usize, value: i64) {
+        self.check_bounds(offset, LONG_BYTE_SIZE);
+        let long_bytes = value.to_be_bytes();
+        self.data[offset..offset + LONG_BYTE_SIZE].copy_from_slice(&long_bytes);
+    }
+
+    pub fn get_long(&self, offset: usize) -> i64 {
+        self.check_bounds(offset, LONG_BYTE_SIZE);
+        let mut bytes = [0; LONG_BYTE_SIZE];
+        bytes.copy_from_slice(&self.data[offset..offset + LONG_BYTE_SIZE]);
+        i64::from_be_bytes(bytes)
+    }
+
     pub fn set_bytes(&mut self, offset: usize, value: &[u8]) {
+        // 長さだけ書いてから失敗しないよう、先に全体を確認する
+        self.check_bounds(offset, INTEGER_BYTE_SIZE + value.len());
         self.set_integer(offset, value.len() as i32);
         let offset = offset + INTEGER_BYTE_SIZE;
         self.data[offset..offset + value.len()].copy_from_slice(value);
@@ -36,6 +88,7 @@ impl Page {
     pub fn get_bytes(&self, offset: usize) -> Vec<u8> {
         let length = self.get_integer(offset) as usize;
         let offset = offset + INTEGER_BYTE_SIZE;
+        self.check_bounds(offset, length);
         self.data[offset..offset + length].to_vec()
     }
 
@@ -47,11 +100,42 @@ impl Page {
         String::from_utf8(self.get_bytes(offset)).unwrap()
     }
 
+    // 型ごとの書き方をここにまとめる。DECIMALはscaleをかけたi32のまま書く
+    // NULLはslotのnull bitmapで表すので、pageには書けない
+    pub fn set_value(&mut self, offset: usize, value: &ConstantValue) {
+        match value {
+            ConstantValue::Number(n) => self.set_integer(offset, *n),
+            ConstantValue::Decimal { value, scale: _ } => self.set_integer(offset, *value),
+            ConstantValue::Float(f) => self.set_float(offset, *f),
+            ConstantValue::String(s) => self.set_string(offset, s),
+            ConstantValue::Date(days) => self.set_long(offset, *days),
+            ConstantValue::Null => panic!("NULL cannot be written to a page at offset {}", offset),
+            ConstantValue::Parameter(index) => panic!("Parameter {} is not bound", index + 1),
+        }
+    }
+
+    pub fn get_value(&self, offset: usize, field_type: TableFieldType) -> ConstantValue {
+        match field_type {
+            TableFieldType::INTEGER => return ConstantValue::Number(self.get_integer(offset)),
+            TableFieldType::DECIMAL(_, scale) => {
+                return ConstantValue::Decimal {
+                    value: self.get_integer(offset),
+                    scale: scale as u32,
+                };
+            }
+            TableFieldType::FLOAT => return ConstantValue::Float(self.get_float(offset)),
+            TableFieldType::VARCHAR => return ConstantValue::String(self.get_string(offset)),
+            TableFieldType::DATE => return ConstantValue::Date(self.get_long(offset)),
+        }
+    }
+
+    // string_lengthは文字数。VARCHAR(n)のフィールド幅の計算に使う
     pub fn get_max_length(string_length: u32) -> usize {
         // string型のlengthをi32 = 4byteで表現 + 文字列の長さ * utf-8の最大バイト数(4byte)
         return INTEGER_BYTE_SIZE + string_length as usize * MAX_BYTE_SIZE_PER_CHAR;
     }
 
+    // 書き込む文字列が決まっている場合(log recordなど)はこちらで正確なbyte数を使う
     pub fn get_string_data_length(string: &str) -> usize {
         // stringのbyte数
         string.len() + INTEGER_BYTE_SIZE
@@ -64,4 +148,97 @@ impl Page {
     pub fn get_integer_byte_size() -> usize {
         INTEGER_BYTE_SIZE
     }
+
+    pub fn get_float_byte_size() -> usize {
+        FLOAT_BYTE_SIZE
+    }
+
+    pub fn get_long_byte_size() -> usize {
+        LONG_BYTE_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access_inside_page() {
+        let mut page = Page::new(16);
+        page.set_integer(12, -7);
+        assert_eq!(page.get_integer(12), -7);
+        page.set_string(0, "abcdefgh");
+        assert_eq!(page.get_string(0), "abcdefgh");
+    }
+
+    #[test]
+    fn test_value_round_trip() {
+        let cases = vec![
+            (ConstantValue::Number(0), TableFieldType::INTEGER),
+            (ConstantValue::Number(i32::MIN), TableFieldType::INTEGER),
+            (ConstantValue::Number(i32::MAX), TableFieldType::INTEGER),
+            (
+                ConstantValue::Decimal {
+                    value: -1234,
+                    scale: 2,
+                },
+                TableFieldType::DECIMAL(9, 2),
+            ),
+            (ConstantValue::Float(-0.5), TableFieldType::FLOAT),
+            (ConstantValue::Float(f64::MAX), TableFieldType::FLOAT),
+            (
+                ConstantValue::Float(f64::MIN_POSITIVE),
+                TableFieldType::FLOAT,
+            ),
+            (
+                ConstantValue::Float(f64::NEG_INFINITY),
+                TableFieldType::FLOAT,
+            ),
+            (
+                ConstantValue::String("".to_string()),
+                TableFieldType::VARCHAR,
+            ),
+            (
+                ConstantValue::String("こんにちは".to_string()),
+                TableFieldType::VARCHAR,
+            ),
+            (ConstantValue::Date(-719468), TableFieldType::DATE),
+            (ConstantValue::Date(19723), TableFieldType::DATE),
+        ];
+        for (value, field_type) in cases {
+            let mut page = Page::new(64);
+            page.set_value(4, &value);
+            assert_eq!(page.get_value(4, field_type), value);
+        }
+
+        // -0.0も符号ごと戻る
+        let mut page = Page::new(16);
+        page.set_value(0, &ConstantValue::Float(-0.0));
+        let ConstantValue::Float(f) = page.get_value(0, TableFieldType::FLOAT) else {
+            panic!("Expected a Float value");
+        };
+        assert!(f == 0.0 && f.is_sign_negative());
+    }
+
+    #[test]
+    #[should_panic(expected = "Page access out of bounds: offset 13 length 4 page size 16")]
+    fn test_set_integer_past_end_of_page() {
+        let mut page = Page::new(16);
+        page.set_integer(13, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Page access out of bounds: offset 8 length 12 page size 16")]
+    fn test_set_string_past_end_of_page() {
+        let mut page = Page::new(16);
+        page.set_string(8, "abcdefgh");
+    }
+
+    #[test]
+    #[should_panic(expected = "Page access out of bounds: offset 4 length 100 page size 16")]
+    fn test_get_bytes_with_broken_length() {
+        let mut page = Page::new(16);
+        page.set_integer(0, 100);
+        page.get_bytes(0);
+    }
 }
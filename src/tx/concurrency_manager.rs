@@ -1,32 +1,52 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
+use crate::error::LockAbortError;
 use crate::storage::block::BlockId;
 
 pub struct LockTable {
     locks: HashMap<BlockId, i32>,
+    // transactionごとに持っているlockとそのmode ("S" / "X")
+    holders: HashMap<i32, HashMap<BlockId, String>>,
+    wait_timeout: std::time::Duration,
 }
 
 impl LockTable {
     pub fn new() -> LockTable {
         let locks: HashMap<BlockId, i32> = HashMap::new();
-        LockTable { locks }
+        LockTable {
+            locks,
+            holders: HashMap::new(),
+            wait_timeout: std::time::Duration::from_secs(5),
+        }
+    }
+
+    pub fn set_wait_timeout(&mut self, wait_timeout: std::time::Duration) {
+        self.wait_timeout = wait_timeout;
     }
 
-    pub fn s_lock(&mut self, block_id: BlockId) {
+    pub fn s_lock(&mut self, tx_num: i32, block_id: BlockId) {
         if self.has_xlock(&block_id) {
             panic!("lock conflict");
         }
 
         let lock = self.get_lock_value(&block_id);
-        self.locks.insert(block_id, lock + 1);
+        self.locks.insert(block_id.clone(), lock + 1);
+        self.holders
+            .entry(tx_num)
+            .or_default()
+            .insert(block_id, "S".to_string());
     }
 
-    pub fn x_lock(&mut self, block_id: BlockId) {
+    pub fn x_lock(&mut self, tx_num: i32, block_id: BlockId) {
         if self.has_other_slock(&block_id) || self.has_xlock(&block_id) {
             panic!("lock conflict");
         }
-        self.locks.insert(block_id, -1);
+        self.locks.insert(block_id.clone(), -1);
+        self.holders
+            .entry(tx_num)
+            .or_default()
+            .insert(block_id, "X".to_string());
     }
 
     fn has_xlock(&self, block_id: &BlockId) -> bool {
@@ -37,7 +57,7 @@ impl LockTable {
         return self.get_lock_value(block_id) > 1;
     }
 
-    pub fn unlock(&mut self, block_id: &BlockId) {
+    pub fn unlock(&mut self, tx_num: i32, block_id: &BlockId) {
         let val = self.get_lock_value(block_id);
 
         if val > 1 {
@@ -45,6 +65,32 @@ impl LockTable {
         } else {
             self.locks.remove(block_id);
         }
+
+        if let Some(blocks) = self.holders.get_mut(&tx_num) {
+            blocks.remove(block_id);
+            if blocks.is_empty() {
+                self.holders.remove(&tx_num);
+            }
+        }
+    }
+
+    // lockを持っているtransactionごとに、そのblockとmodeを返す
+    // transaction番号、file名、block番号の順に並べる
+    pub fn held_locks(&self) -> Vec<(i32, Vec<(BlockId, String)>)> {
+        let mut held_locks: Vec<(i32, Vec<(BlockId, String)>)> = self
+            .holders
+            .iter()
+            .map(|(tx_num, blocks)| {
+                let mut blocks: Vec<(BlockId, String)> = blocks
+                    .iter()
+                    .map(|(block_id, mode)| (block_id.clone(), mode.clone()))
+                    .collect();
+                blocks.sort_by(|a, b| compare_block_id(&a.0, &b.0));
+                (*tx_num, blocks)
+            })
+            .collect();
+        held_locks.sort_by_key(|(tx_num, _)| *tx_num);
+        return held_locks;
     }
 
     fn get_lock_value(&self, block_id: &BlockId) -> i32 {
@@ -59,22 +105,87 @@ impl LockTable {
 
     fn wait_too_long(&self, start_time: std::time::Instant) -> bool {
         let elapsed = start_time.elapsed();
-        elapsed.as_secs() > 5
+        elapsed > self.wait_timeout
     }
 }
 
+fn compare_block_id(a: &BlockId, b: &BlockId) -> std::cmp::Ordering {
+    return a
+        .get_file_name()
+        .cmp(b.get_file_name())
+        .then(a.get_block_number().cmp(&b.get_block_number()));
+}
+
+// 読み込みのときのslockの取り方。xlockはどのlevelでもcommitまで持つ
+// phantomを防ぐlockは無いので、RepeatableReadとSerializableは同じ動きになる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    // slockを取らずに読む
+    ReadUncommitted,
+    // 読み終わったらすぐslockを外す
+    ReadCommitted,
+    // slockをcommitまで持つ
+    RepeatableRead,
+    Serializable,
+}
+
 pub struct ConcurrencyManagerV2 {
+    tx_num: i32,
     locks: HashMap<BlockId, String>,
     lock_table: Arc<Mutex<LockTable>>,
+    isolation_level: IsolationLevel,
 }
 
 impl ConcurrencyManagerV2 {
-    pub fn new(lock_table: Arc<Mutex<LockTable>>) -> ConcurrencyManagerV2 {
+    pub fn new(tx_num: i32, lock_table: Arc<Mutex<LockTable>>) -> ConcurrencyManagerV2 {
         let locks: HashMap<BlockId, String> = HashMap::new();
-        ConcurrencyManagerV2 { locks, lock_table }
+        ConcurrencyManagerV2 {
+            tx_num,
+            locks,
+            lock_table,
+            isolation_level: IsolationLevel::Serializable,
+        }
+    }
+
+    pub fn set_isolation_level(&mut self, isolation_level: IsolationLevel) {
+        self.isolation_level = isolation_level;
     }
 
-    pub fn s_lock(&mut self, block_id: BlockId) {
+    pub fn get_isolation_level(&self) -> IsolationLevel {
+        return self.isolation_level;
+    }
+
+    // このtransactionが今持っているlockのblock
+    pub fn held_locks(&self) -> Vec<BlockId> {
+        let mut blocks: Vec<BlockId> = self.locks.keys().cloned().collect();
+        blocks.sort_by(compare_block_id);
+        return blocks;
+    }
+
+    // 読み込みの前に呼ぶ。isolation levelによってはslockを取らない
+    pub fn s_lock(&mut self, block_id: BlockId) -> Result<(), LockAbortError> {
+        if self.isolation_level == IsolationLevel::ReadUncommitted {
+            return Ok(());
+        }
+        return self.acquire_s_lock(block_id);
+    }
+
+    // 読み込みの後に呼ぶ。ReadCommittedのときはslockをすぐ外す
+    // xlockを持っているblockはcommitまでそのまま
+    pub fn release_s_lock(&mut self, block_id: &BlockId) {
+        if self.isolation_level != IsolationLevel::ReadCommitted {
+            return;
+        }
+        if self.locks.get(block_id).map(|mode| mode == "S") == Some(true) {
+            self.lock_table
+                .lock()
+                .unwrap()
+                .unlock(self.tx_num, block_id);
+            self.locks.remove(block_id);
+        }
+    }
+
+    fn acquire_s_lock(&mut self, block_id: BlockId) -> Result<(), LockAbortError> {
         let lock_value = self.locks.get(&block_id);
         let current_time = std::time::Instant::now();
         if lock_value.is_none() {
@@ -82,39 +193,48 @@ impl ConcurrencyManagerV2 {
                 {
                     let mut lock_table = self.lock_table.lock().unwrap();
                     if !lock_table.has_xlock(&block_id) {
-                        lock_table.s_lock(block_id.clone());
+                        lock_table.s_lock(self.tx_num, block_id.clone());
                         self.locks.insert(block_id, "S".to_string());
-                        return;
+                        return Ok(());
                     }
                 }
                 std::thread::sleep(std::time::Duration::from_millis(100));
                 if self.lock_table.lock().unwrap().wait_too_long(current_time) {
-                    panic!("lock wait timeout");
+                    return Err(Self::abort_error(&block_id));
                 }
             }
         }
+        return Ok(());
     }
 
-    pub fn x_lock(&mut self, block_id: BlockId) {
+    pub fn x_lock(&mut self, block_id: BlockId) -> Result<(), LockAbortError> {
         if !self.has_xlock(&block_id) {
-            self.s_lock(block_id.clone());
+            self.acquire_s_lock(block_id.clone())?;
             let current_time = std::time::Instant::now();
 
             loop {
                 {
                     let mut lock_table = self.lock_table.lock().unwrap();
                     if !lock_table.has_other_slock(&block_id) {
-                        lock_table.x_lock(block_id.clone());
+                        lock_table.x_lock(self.tx_num, block_id.clone());
                         self.locks.insert(block_id, "X".to_string());
-                        return;
+                        return Ok(());
                     }
                 }
                 std::thread::sleep(std::time::Duration::from_millis(100));
                 if self.lock_table.lock().unwrap().wait_too_long(current_time) {
-                    panic!("lock wait timeout");
+                    return Err(Self::abort_error(&block_id));
                 }
             }
         }
+        return Ok(());
+    }
+
+    fn abort_error(block_id: &BlockId) -> LockAbortError {
+        return LockAbortError::new(
+            block_id.get_file_name().clone(),
+            block_id.get_block_number(),
+        );
     }
 
     fn has_xlock(&self, block_id: &BlockId) -> bool {
@@ -131,7 +251,7 @@ impl ConcurrencyManagerV2 {
 
     pub fn release(&mut self) {
         for (key, value) in self.locks.iter() {
-            self.lock_table.lock().unwrap().unlock(key);
+            self.lock_table.lock().unwrap().unlock(self.tx_num, key);
         }
         self.locks.clear();
     }
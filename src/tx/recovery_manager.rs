@@ -2,7 +2,7 @@ use std::sync::{Arc, Mutex};
 
 use crate::{
     storage::block::BlockId,
-    buffer::buffer_manager_v2::{BufferManagerV2, BufferV2},
+    buffer::buffer_manager_v2::{BufferManagerV2, BufferV2, FlushPolicy},
     storage::log_manager_v2::LogManagerV2,
     storage::page::Page,
     tx::transaction_v2::InnerTransactionV2,
@@ -14,8 +14,12 @@ enum LogRecordType {
     START = 1,
     COMMIT = 2,
     ROLLBACK = 3,
+    // 変更前の値だけを持つ古い形式。読めるようにだけ残しておく
     SETINT = 4,
     SETSTRING = 5,
+    // 変更後の値も持ち、redoできる形式
+    SETINTV2 = 6,
+    SETSTRINGV2 = 7,
 }
 
 impl LogRecordType {
@@ -27,6 +31,8 @@ impl LogRecordType {
             3 => LogRecordType::ROLLBACK,
             4 => LogRecordType::SETINT,
             5 => LogRecordType::SETSTRING,
+            6 => LogRecordType::SETINTV2,
+            7 => LogRecordType::SETSTRINGV2,
             _ => panic!("Invalid log record type"),
         }
     }
@@ -36,6 +42,9 @@ pub trait LogRecord {
     fn operator_code(&self) -> LogRecordType;
     fn transaction_id(&self) -> i32;
     fn undo(&self, transaction: &mut InnerTransactionV2, recovery_manager: &mut RecoveryManager);
+    // 変更後の値を書き直す。値を持たないrecordでは何もしない
+    fn redo(&self, _transaction: &mut InnerTransactionV2, _recovery_manager: &mut RecoveryManager) {
+    }
     fn stringify(&self) -> String {
         format!(
             "LogRecord(type: {:?}, tx_id: {})",
@@ -63,11 +72,11 @@ fn create_log_record(bytes: Vec<u8>) -> Box<dyn LogRecord> {
         LogRecordType::ROLLBACK => {
             return Box::new(RollbackRecord::new(page));
         }
-        LogRecordType::SETINT => {
-            return Box::new(SetIntegerRecord::new(page));
+        LogRecordType::SETINT | LogRecordType::SETINTV2 => {
+            return Box::new(SetIntegerRecord::new(page, record_type));
         }
-        LogRecordType::SETSTRING => {
-            return Box::new(SetStringRecord::new(page));
+        LogRecordType::SETSTRING | LogRecordType::SETSTRINGV2 => {
+            return Box::new(SetStringRecord::new(page, record_type));
         }
     }
 }
@@ -77,10 +86,12 @@ struct SetStringRecord {
     block_id: BlockId,
     offset: usize,
     value: String,
+    // 古い形式のrecordには無い
+    new_value: Option<String>,
 }
 
 impl SetStringRecord {
-    pub fn new(page: Page) -> Self {
+    pub fn new(page: Page, record_type: LogRecordType) -> Self {
         let transaction_id_offset = Page::get_integer_byte_size();
         let transaction_id = page.get_integer(transaction_id_offset);
         let file_name_offset = transaction_id_offset + Page::get_integer_byte_size();
@@ -88,7 +99,7 @@ impl SetStringRecord {
 
         print!("filename in set string record: {}", filename);
 
-        let block_number_offset = file_name_offset + Page::get_max_length(filename.len() as u32);
+        let block_number_offset = file_name_offset + Page::get_string_data_length(&filename);
 
         let block_number = page.get_integer(block_number_offset);
 
@@ -100,11 +111,18 @@ impl SetStringRecord {
         let value_offset = offset_offset + Page::get_integer_byte_size();
         let value = page.get_string(value_offset);
 
+        let new_value_offset = value_offset + Page::get_string_data_length(&value);
+        let new_value = match record_type {
+            LogRecordType::SETSTRINGV2 => Some(page.get_string(new_value_offset)),
+            _ => None,
+        };
+
         SetStringRecord {
             transaction_id,
             block_id,
             offset: offset_value,
             value,
+            new_value,
         }
     }
 
@@ -114,22 +132,25 @@ impl SetStringRecord {
         block_id: &BlockId,
         offset: usize,
         value: &str,
+        new_value: &str,
     ) -> i32 {
         let transaction_id_offset: usize = Page::get_integer_byte_size();
         let filename_offset = transaction_id_offset + Page::get_integer_byte_size();
         let block_number_offset =
-            filename_offset + Page::get_max_length(block_id.get_file_name().len() as u32);
+            filename_offset + Page::get_string_data_length(block_id.get_file_name());
         let offset_value_offset = block_number_offset + Page::get_integer_byte_size();
         let value_offset = offset_value_offset + Page::get_integer_byte_size();
-        let record_length = value_offset + Page::get_max_length(value.len() as u32);
+        let new_value_offset = value_offset + Page::get_string_data_length(value);
+        let record_length = new_value_offset + Page::get_string_data_length(new_value);
 
         let mut page = Page::new(record_length);
-        page.set_integer(0, LogRecordType::SETSTRING as i32);
+        page.set_integer(0, LogRecordType::SETSTRINGV2 as i32);
         page.set_integer(transaction_id_offset, transaction_id);
         page.set_string(filename_offset, block_id.get_file_name());
         page.set_integer(block_number_offset, block_id.get_block_number() as i32);
         page.set_integer(offset_value_offset, offset as i32);
         page.set_string(value_offset, value);
+        page.set_string(new_value_offset, new_value);
         let lsn = log_manager.append_record(page.get_data());
 
         return lsn;
@@ -138,7 +159,10 @@ impl SetStringRecord {
 
 impl LogRecord for SetStringRecord {
     fn operator_code(&self) -> LogRecordType {
-        LogRecordType::SETSTRING
+        match self.new_value {
+            Some(_) => LogRecordType::SETSTRINGV2,
+            None => LogRecordType::SETSTRING,
+        }
     }
 
     fn transaction_id(&self) -> i32 {
@@ -156,6 +180,24 @@ impl LogRecord for SetStringRecord {
         );
         transaction.unpin(self.block_id.clone());
     }
+
+    fn redo(&self, transaction: &mut InnerTransactionV2, recovery_manager: &mut RecoveryManager) {
+        let Some(new_value) = &self.new_value else {
+            return;
+        };
+        if !transaction.file_exists(self.block_id.get_file_name()) {
+            return;
+        }
+        transaction.pin(self.block_id.clone());
+        transaction.set_string(
+            self.block_id.clone(),
+            self.offset,
+            new_value,
+            false,
+            recovery_manager,
+        );
+        transaction.unpin(self.block_id.clone());
+    }
 }
 
 struct SetIntegerRecord {
@@ -163,15 +205,17 @@ struct SetIntegerRecord {
     block_id: BlockId,
     offset: usize,
     value: i32,
+    // 古い形式のrecordには無い
+    new_value: Option<i32>,
 }
 
 impl SetIntegerRecord {
-    pub fn new(page: Page) -> Self {
+    pub fn new(page: Page, record_type: LogRecordType) -> Self {
         let transaction_id_offset = Page::get_integer_byte_size();
         let transaction_id = page.get_integer(transaction_id_offset);
         let file_name_offset = transaction_id_offset + Page::get_integer_byte_size();
         let filename = page.get_string(file_name_offset);
-        let block_number_offset = file_name_offset + Page::get_max_length(filename.len() as u32);
+        let block_number_offset = file_name_offset + Page::get_string_data_length(&filename);
 
         let block_number = page.get_integer(block_number_offset);
 
@@ -183,11 +227,18 @@ impl SetIntegerRecord {
         let value_offset = offset_offset + Page::get_integer_byte_size();
         let value = page.get_integer(value_offset);
 
+        let new_value_offset = value_offset + Page::get_integer_byte_size();
+        let new_value = match record_type {
+            LogRecordType::SETINTV2 => Some(page.get_integer(new_value_offset)),
+            _ => None,
+        };
+
         SetIntegerRecord {
             transaction_id,
             block_id,
             offset: offset_value,
             value,
+            new_value,
         }
     }
 
@@ -197,22 +248,25 @@ impl SetIntegerRecord {
         block_id: &BlockId,
         offset: usize,
         value: i32,
+        new_value: i32,
     ) -> i32 {
         let transaction_id_offset = Page::get_integer_byte_size();
         let filename_offset = transaction_id_offset + Page::get_integer_byte_size();
         let block_number_offset =
-            filename_offset + Page::get_max_length(block_id.get_file_name().len() as u32);
+            filename_offset + Page::get_string_data_length(block_id.get_file_name());
         let offset_value_offset = block_number_offset + Page::get_integer_byte_size();
         let value_offset = offset_value_offset + Page::get_integer_byte_size();
-        let record_length = value_offset + Page::get_integer_byte_size();
+        let new_value_offset = value_offset + Page::get_integer_byte_size();
+        let record_length = new_value_offset + Page::get_integer_byte_size();
 
         let mut page = Page::new(record_length);
-        page.set_integer(0, LogRecordType::SETINT as i32);
+        page.set_integer(0, LogRecordType::SETINTV2 as i32);
         page.set_integer(transaction_id_offset, transaction_id);
         page.set_string(filename_offset, block_id.get_file_name());
         page.set_integer(block_number_offset, block_id.get_block_number() as i32);
         page.set_integer(offset_value_offset, offset as i32);
         page.set_integer(value_offset, value);
+        page.set_integer(new_value_offset, new_value);
         let lsn = log_manager.append_record(page.get_data());
 
         return lsn;
@@ -221,7 +275,10 @@ impl SetIntegerRecord {
 
 impl LogRecord for SetIntegerRecord {
     fn operator_code(&self) -> LogRecordType {
-        LogRecordType::SETINT
+        match self.new_value {
+            Some(_) => LogRecordType::SETINTV2,
+            None => LogRecordType::SETINT,
+        }
     }
 
     fn transaction_id(&self) -> i32 {
@@ -239,6 +296,24 @@ impl LogRecord for SetIntegerRecord {
         );
         transaction.unpin(self.block_id.clone());
     }
+
+    fn redo(&self, transaction: &mut InnerTransactionV2, recovery_manager: &mut RecoveryManager) {
+        let Some(new_value) = self.new_value else {
+            return;
+        };
+        if !transaction.file_exists(self.block_id.get_file_name()) {
+            return;
+        }
+        transaction.pin(self.block_id.clone());
+        transaction.set_integer(
+            self.block_id.clone(),
+            self.offset,
+            new_value,
+            false,
+            recovery_manager,
+        );
+        transaction.unpin(self.block_id.clone());
+    }
 }
 
 struct CheckpointRecord {}
@@ -391,10 +466,12 @@ impl RecoveryManager {
     }
 
     pub fn commit(&self) {
-        self.buffer_manager
-            .lock()
-            .unwrap()
-            .flush_all(self.transaction_number);
+        // NoForceのときはbufferを書き出さず、logのredoに任せる
+        let mut buffer_manager = self.buffer_manager.lock().unwrap();
+        if buffer_manager.get_flush_policy() == FlushPolicy::Force {
+            buffer_manager.flush_all(self.transaction_number);
+        }
+        drop(buffer_manager);
         let lsn = CommitRecord::write_to_log(
             &mut self.log_manager.lock().unwrap(),
             self.transaction_number,
@@ -430,38 +507,49 @@ impl RecoveryManager {
         }
     }
 
+    // 後ろから読んで終わっていないtransactionの変更をundoし、
+    // その後に前から読んでcommit済みのtransactionの変更をredoする
     fn do_recover(&mut self, transaction: &mut InnerTransactionV2) {
+        let mut committed_transactions = vec![];
         let mut finished_transactions = vec![];
+        let mut log_records = vec![];
 
         let mut iterator = self.log_manager.lock().unwrap().iterator();
         while iterator.has_next() {
             let bytes = iterator.next();
             let log_record = create_log_record(bytes);
             if log_record.operator_code() == LogRecordType::CHECKPOINT {
-                return;
+                break;
             }
 
-            if log_record.operator_code() == LogRecordType::COMMIT
-                || log_record.operator_code() == LogRecordType::ROLLBACK
-            {
+            if log_record.operator_code() == LogRecordType::COMMIT {
+                committed_transactions.push(log_record.transaction_id());
+                finished_transactions.push(log_record.transaction_id());
+            } else if log_record.operator_code() == LogRecordType::ROLLBACK {
                 finished_transactions.push(log_record.transaction_id());
             } else if !finished_transactions.contains(&log_record.transaction_id()) {
                 log_record.undo(transaction, self);
             }
+            log_records.push(log_record);
+        }
+
+        for log_record in log_records.iter().rev() {
+            if committed_transactions.contains(&log_record.transaction_id()) {
+                log_record.redo(transaction, self);
+            }
         }
     }
 
-    fn recover(&mut self, transaction: &mut InnerTransactionV2) {
+    // checkpointより前のlogはもう読まないので、NoForceでcommitした他のtransactionの
+    // bufferも含めて、全部diskに書き出してからcheckpointを書く
+    pub fn recover(&mut self, transaction: &mut InnerTransactionV2) {
         self.do_recover(transaction);
-        self.buffer_manager
-            .lock()
-            .unwrap()
-            .flush_all(self.transaction_number);
+        self.buffer_manager.lock().unwrap().flush_all_modified();
         let lsn = CheckpointRecord::write_to_log(&mut self.log_manager.lock().unwrap());
         self.log_manager.lock().unwrap().flush_with_lsn(lsn);
     }
 
-    pub fn set_integer(&self, offset: usize, buffer: &mut BufferV2) -> i32 {
+    pub fn set_integer(&self, offset: usize, buffer: &mut BufferV2, new_value: i32) -> i32 {
         let old_value = buffer.content().get_integer(offset);
         let block = buffer.block_id().as_ref().unwrap().clone();
         let lsn = SetIntegerRecord::write_to_log(
@@ -470,11 +558,12 @@ impl RecoveryManager {
             &block,
             offset,
             old_value,
+            new_value,
         );
         return lsn;
     }
 
-    pub fn set_string(&self, offset: usize, buffer: &mut BufferV2) -> i32 {
+    pub fn set_string(&self, offset: usize, buffer: &mut BufferV2, new_value: &str) -> i32 {
         let old_value = buffer.content().get_string(offset);
         let block = buffer.block_id().as_ref().unwrap().clone();
 
@@ -484,6 +573,7 @@ impl RecoveryManager {
             &block,
             offset,
             &old_value,
+            new_value,
         );
 
         return lsn;
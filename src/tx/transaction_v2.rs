@@ -1,11 +1,15 @@
 use std::sync::{Arc, Mutex};
+use std::{cell::RefCell, rc::Rc};
+
+use crate::error::{DatabaseError, FileAccessError, LockAbortError, TransactionAborted};
 
 use crate::storage::block::BlockId;
+use crate::storage::page::Page;
 use crate::storage::log_manager_v2::LogManagerV2;
 use crate::tx::recovery_manager::RecoveryManager;
 use crate::{
     buffer::buffer_manager_v2::{BufferListV2, BufferManagerV2},
-    tx::concurrency_manager::LockTable,
+    tx::concurrency_manager::{IsolationLevel, LockTable},
 };
 use crate::{tx::concurrency_manager::ConcurrencyManagerV2, storage::file_manager::FileManager};
 
@@ -16,11 +20,21 @@ pub struct InnerTransactionV2 {
     concurrency_manager: ConcurrencyManagerV2,
     buffer_list: BufferListV2,
     file_manager: Arc<Mutex<FileManager>>,
+    // このtransactionの中で作ったtemp tableのfile。commit/rollbackで消す
+    temp_files: Vec<String>,
+    // drop tableしたtableのfile。commitのときだけ消し、rollbackでは残す
+    dropped_files: Vec<String>,
+    // vacuumしたtableのfileと残すblockの数。commitのときだけ切り詰める
+    truncated_files: Vec<(String, usize)>,
+    // 待ちきれなかったlock。これがある間はblockを読み書きせず、rollbackを待つ
+    lock_failure: Option<LockAbortError>,
 }
 
 pub struct TransactionV2 {
     inner: InnerTransactionV2,
     recovery_manager: RecoveryManager,
+    // new_transactionで始めるtransactionも同じlogに書く
+    log_manager: Arc<Mutex<LogManagerV2>>,
 }
 
 impl InnerTransactionV2 {
@@ -30,7 +44,7 @@ impl InnerTransactionV2 {
         buffer_manager: Arc<Mutex<BufferManagerV2>>,
         lock_table: Arc<Mutex<LockTable>>,
     ) -> InnerTransactionV2 {
-        let concurrency_manager = ConcurrencyManagerV2::new(lock_table.clone());
+        let concurrency_manager = ConcurrencyManagerV2::new(tx_num, lock_table.clone());
         let buffer_list = BufferListV2::new(buffer_manager.clone());
 
         InnerTransactionV2 {
@@ -40,6 +54,10 @@ impl InnerTransactionV2 {
             lock_table,
             concurrency_manager,
             buffer_list,
+            temp_files: Vec::new(),
+            dropped_files: Vec::new(),
+            truncated_files: Vec::new(),
+            lock_failure: None,
         }
     }
 
@@ -47,6 +65,17 @@ impl InnerTransactionV2 {
         self.file_manager.lock().unwrap().get_block_size()
     }
 
+    fn get_file_block_size(&self, file_name: &str) -> usize {
+        self.file_manager.lock().unwrap().get_file_block_size(file_name)
+    }
+
+    fn set_file_block_size(&self, file_name: &str, block_size: usize) {
+        self.file_manager
+            .lock()
+            .unwrap()
+            .set_file_block_size(file_name, block_size);
+    }
+
     pub fn pin(&mut self, block_id: BlockId) {
         self.buffer_list.pin(block_id);
     }
@@ -55,16 +84,89 @@ impl InnerTransactionV2 {
         self.buffer_list.unpin(block_id);
     }
 
-    fn commit(&mut self, recovery_manager: &mut RecoveryManager) {
+    // 変更はcommitしてから、消せなかったtemp fileのerrorを返す
+    fn commit(&mut self, recovery_manager: &mut RecoveryManager) -> Result<(), FileAccessError> {
         recovery_manager.commit();
-        self.concurrency_manager.release();
+        // 切り詰め終わるまで、他のtransactionにはblockを読ませない
         self.buffer_list.unpin_all();
+        self.truncate_files();
+        self.concurrency_manager.release();
+        let dropped_files = std::mem::take(&mut self.dropped_files);
+        self.temp_files.extend(dropped_files);
+        return self.delete_temp_files();
     }
 
     fn rollback(&mut self, recovery_manager: &mut RecoveryManager) {
         recovery_manager.rollback(self);
         self.concurrency_manager.release();
         self.buffer_list.unpin_all();
+        self.dropped_files.clear();
+        self.truncated_files.clear();
+        // rollbackはerrorを返せないので、消せなかったfileは次のcommitで消し直す
+        let _ = self.delete_temp_files();
+    }
+
+    fn truncate_files(&mut self) {
+        let mut buffer_manager = self.buffer_manager.lock().unwrap();
+        let file_manager = self.file_manager.lock().unwrap();
+        for (file_name, block_count) in self.truncated_files.drain(..) {
+            buffer_manager.discard_blocks(&file_name, block_count as u64);
+            file_manager.truncate(&file_name, block_count);
+        }
+    }
+
+    // NoForceのときはtemp tableのbufferが変更されたまま残っているので、
+    // 後から書き出されないように捨ててからfileを消す
+    // 消せなかったfileはtemp_filesに残して続け、最初のerrorを返す
+    fn delete_temp_files(&mut self) -> Result<(), FileAccessError> {
+        let mut buffer_manager = self.buffer_manager.lock().unwrap();
+        let file_manager = self.file_manager.lock().unwrap();
+        let mut first_error = None;
+        let mut remaining_files = Vec::new();
+        for file_name in self.temp_files.drain(..) {
+            buffer_manager.discard_file(&file_name);
+            if let Err(error) = file_manager.delete_file(&file_name) {
+                if first_error.is_none() {
+                    first_error = Some(FileAccessError::new(file_name.clone(), error.to_string()));
+                }
+                remaining_files.push(file_name);
+            }
+        }
+        self.temp_files = remaining_files;
+
+        return match first_error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        };
+    }
+
+    // lockを取れなかったら記録して、それ以降のlockもすぐに失敗させる
+    // 読み込みは0や空の値を返し、書き込みは何もしないので、呼び出し側は
+    // TransactionV2::abort_on_lock_failureかcommitでrollbackしてTransactionAbortedを返す
+    fn s_lock(&mut self, block_id: &BlockId) -> Result<(), LockAbortError> {
+        if let Some(lock_failure) = &self.lock_failure {
+            return Err(lock_failure.clone());
+        }
+        let result = self.concurrency_manager.s_lock(block_id.clone());
+        if let Err(lock_failure) = &result {
+            self.lock_failure = Some(lock_failure.clone());
+        }
+        return result;
+    }
+
+    fn x_lock(&mut self, block_id: &BlockId) -> Result<(), LockAbortError> {
+        if let Some(lock_failure) = &self.lock_failure {
+            return Err(lock_failure.clone());
+        }
+        let result = self.concurrency_manager.x_lock(block_id.clone());
+        if let Err(lock_failure) = &result {
+            self.lock_failure = Some(lock_failure.clone());
+        }
+        return result;
+    }
+
+    pub fn file_exists(&self, file_name: &str) -> bool {
+        self.file_manager.lock().unwrap().exists(file_name)
     }
 
     pub fn set_integer(
@@ -75,7 +177,9 @@ impl InnerTransactionV2 {
         set_to_log: bool,
         recovery_manager: &mut RecoveryManager,
     ) {
-        self.concurrency_manager.x_lock(block_id.clone());
+        if self.x_lock(&block_id).is_err() {
+            return;
+        }
 
         let buffer = self.buffer_list.get_buffer(block_id).unwrap();
         let mut buffer = buffer.lock().unwrap();
@@ -83,7 +187,7 @@ impl InnerTransactionV2 {
         let mut lsn = -1;
 
         if set_to_log {
-            lsn = recovery_manager.set_integer(offset, &mut buffer);
+            lsn = recovery_manager.set_integer(offset, &mut buffer, value);
         }
 
         let page = buffer.content();
@@ -99,26 +203,62 @@ impl InnerTransactionV2 {
         set_to_log: bool,
         recovery_manager: &mut RecoveryManager,
     ) {
-        self.concurrency_manager.x_lock(block_id.clone());
+        if self.x_lock(&block_id).is_err() {
+            return;
+        }
 
         let buffer = self.buffer_list.get_buffer(block_id).unwrap();
         let mut buffer = buffer.lock().unwrap();
 
+        let mut lsn = -1;
+
         if set_to_log {
-            recovery_manager.set_string(offset, &mut buffer);
+            lsn = recovery_manager.set_string(offset, &mut buffer, value);
         }
 
         let page = buffer.content();
         page.set_string(offset, value);
-        buffer.set_modified(self.tx_num, -1);
+        // bufferをflushする前に、このlsnまでのlogが書き出されるようにする
+        buffer.set_modified(self.tx_num, lsn);
     }
 
     fn get_integer(&mut self, block_id: BlockId, offset: usize) -> i32 {
-        self.concurrency_manager.s_lock(block_id.clone());
-        let buffer = self.buffer_list.get_buffer(block_id).unwrap();
-        let mut buffer = buffer.lock().unwrap();
-        let page = buffer.content();
-        page.get_integer(offset)
+        if self.s_lock(&block_id).is_err() {
+            return 0;
+        }
+        let value = {
+            let buffer = self.buffer_list.get_buffer(block_id.clone()).unwrap();
+            let mut buffer = buffer.lock().unwrap();
+            buffer.content().get_integer(offset)
+        };
+        self.concurrency_manager.release_s_lock(&block_id);
+        return value;
+    }
+
+    fn get_long(&mut self, block_id: BlockId, offset: usize) -> i64 {
+        if self.s_lock(&block_id).is_err() {
+            return 0;
+        }
+        let value = {
+            let buffer = self.buffer_list.get_buffer(block_id.clone()).unwrap();
+            let mut buffer = buffer.lock().unwrap();
+            buffer.content().get_long(offset)
+        };
+        self.concurrency_manager.release_s_lock(&block_id);
+        return value;
+    }
+
+    fn get_float(&mut self, block_id: BlockId, offset: usize) -> f64 {
+        if self.s_lock(&block_id).is_err() {
+            return 0.0;
+        }
+        let value = {
+            let buffer = self.buffer_list.get_buffer(block_id.clone()).unwrap();
+            let mut buffer = buffer.lock().unwrap();
+            buffer.content().get_float(offset)
+        };
+        self.concurrency_manager.release_s_lock(&block_id);
+        return value;
     }
 
     fn get_size(&self, file_name: String) -> usize {
@@ -126,11 +266,16 @@ impl InnerTransactionV2 {
     }
 
     fn get_string(&mut self, block_id: BlockId, offset: usize) -> String {
-        self.concurrency_manager.s_lock(block_id.clone());
-        let buffer = self.buffer_list.get_buffer(block_id).unwrap();
-        let mut buffer = buffer.lock().unwrap();
-        let page = buffer.content();
-        page.get_string(offset)
+        if self.s_lock(&block_id).is_err() {
+            return String::new();
+        }
+        let value = {
+            let buffer = self.buffer_list.get_buffer(block_id.clone()).unwrap();
+            let mut buffer = buffer.lock().unwrap();
+            buffer.content().get_string(offset)
+        };
+        self.concurrency_manager.release_s_lock(&block_id);
+        return value;
     }
 
     fn append(&mut self, file_name: &str) -> BlockId {
@@ -156,13 +301,56 @@ impl TransactionV2 {
         TransactionV2 {
             inner: InnerTransactionV2::new(tx_num, file_manager, buffer_manager, lock_table),
             recovery_manager,
+            log_manager,
         }
     }
 
+    // 同じfileとbufferを使う別のtransactionを始める
+    // 呼び出し側のtransactionとは別にcommitしたい変更に使う
+    pub fn new_transaction(&self, tx_num: i32) -> Rc<RefCell<TransactionV2>> {
+        Rc::new(RefCell::new(TransactionV2::new(
+            tx_num,
+            self.inner.file_manager.clone(),
+            self.inner.buffer_manager.clone(),
+            self.inner.lock_table.clone(),
+            self.log_manager.clone(),
+        )))
+    }
+
+    pub fn get_transaction_id(&self) -> i32 {
+        self.inner.tx_num
+    }
+
+    // operationの途中でlockを待ちきれなかったら、operationの結果は捨てて
+    // transactionをrollbackし、lockとbufferを解放してTransactionAbortedを返す
+    pub fn abort_on_lock_failure<T>(
+        transaction: Rc<RefCell<TransactionV2>>,
+        operation: impl FnOnce() -> Result<T, DatabaseError>,
+    ) -> Result<T, DatabaseError> {
+        let result = operation();
+        let Some(lock_failure) = transaction.borrow().inner.lock_failure.clone() else {
+            return result;
+        };
+        let transaction_id = transaction.borrow().get_transaction_id();
+        transaction.borrow_mut().rollback();
+        return Err(DatabaseError::TransactionAborted(TransactionAborted::new(
+            transaction_id,
+            lock_failure.to_string(),
+        )));
+    }
+
     pub fn get_block_size(&self) -> usize {
         self.inner.get_block_size()
     }
 
+    pub fn get_file_block_size(&self, file_name: &str) -> usize {
+        self.inner.get_file_block_size(file_name)
+    }
+
+    pub fn set_file_block_size(&self, file_name: &str, block_size: usize) {
+        self.inner.set_file_block_size(file_name, block_size);
+    }
+
     pub fn pin(&mut self, block_id: BlockId) {
         self.inner.pin(block_id);
     }
@@ -171,14 +359,34 @@ impl TransactionV2 {
         self.inner.unpin(block_id);
     }
 
-    pub fn commit(&mut self) {
-        self.inner.commit(&mut self.recovery_manager);
+    // lockを取れずに途中で止まった変更はcommitせずに戻し、TransactionAbortedを返す
+    // commitした後でtemp fileを消せなかったときは、FileAccessを返す
+    pub fn commit(&mut self) -> Result<(), DatabaseError> {
+        if let Some(lock_failure) = self.inner.lock_failure.clone() {
+            let transaction_id = self.get_transaction_id();
+            self.rollback();
+            return Err(DatabaseError::TransactionAborted(TransactionAborted::new(
+                transaction_id,
+                lock_failure.to_string(),
+            )));
+        }
+        self.inner.commit(&mut self.recovery_manager)?;
+        return Ok(());
     }
 
+    // undoで書き戻せるように、lockの失敗は忘れてからrollbackする
     pub fn rollback(&mut self) {
+        self.inner.lock_failure = None;
         self.inner.rollback(&mut self.recovery_manager);
     }
 
+    // crashの後に呼ぶ。終わっていないtransactionをundoし、commit済みのものをredoする
+    pub fn recover(&mut self) {
+        self.recovery_manager.recover(&mut self.inner);
+        self.inner.concurrency_manager.release();
+        self.inner.buffer_list.unpin_all();
+    }
+
     pub fn set_integer(&mut self, block_id: BlockId, offset: usize, value: i32, set_to_log: bool) {
         self.inner.set_integer(
             block_id,
@@ -203,6 +411,31 @@ impl TransactionV2 {
         self.inner.get_integer(block_id, offset)
     }
 
+    // f64の前半と後半の4byteを2つのintegerとして書く。logもSetIntの2つになる
+    pub fn set_float(&mut self, block_id: BlockId, offset: usize, value: f64, set_to_log: bool) {
+        let bits = value.to_bits();
+        let high = (bits >> 32) as u32 as i32;
+        let low = bits as u32 as i32;
+        self.set_integer(block_id.clone(), offset, high, set_to_log);
+        self.set_integer(block_id, offset + Page::get_integer_byte_size(), low, set_to_log);
+    }
+
+    pub fn get_float(&mut self, block_id: BlockId, offset: usize) -> f64 {
+        self.inner.get_float(block_id, offset)
+    }
+
+    // floatと同じく、前半と後半の4byteを2つのintegerとして書く
+    pub fn set_long(&mut self, block_id: BlockId, offset: usize, value: i64, set_to_log: bool) {
+        let high = (value >> 32) as i32;
+        let low = value as u32 as i32;
+        self.set_integer(block_id.clone(), offset, high, set_to_log);
+        self.set_integer(block_id, offset + Page::get_integer_byte_size(), low, set_to_log);
+    }
+
+    pub fn get_long(&mut self, block_id: BlockId, offset: usize) -> i64 {
+        self.inner.get_long(block_id, offset)
+    }
+
     pub fn get_size(&self, file_name: String) -> usize {
         self.inner.get_size(file_name)
     }
@@ -218,13 +451,43 @@ impl TransactionV2 {
     pub fn get_available_buffer_size(&self) -> i32 {
         self.inner.get_available_buffer_size()
     }
+
+    pub fn held_locks(&self) -> Vec<BlockId> {
+        self.inner.concurrency_manager.held_locks()
+    }
+
+    // 読み込みのslockの取り方を変える。何も指定しなければSerializable
+    pub fn set_isolation_level(&mut self, isolation_level: IsolationLevel) {
+        self.inner
+            .concurrency_manager
+            .set_isolation_level(isolation_level);
+    }
+
+    pub fn get_isolation_level(&self) -> IsolationLevel {
+        self.inner.concurrency_manager.get_isolation_level()
+    }
+
+    pub fn register_temp_file(&mut self, file_name: String) {
+        self.inner.temp_files.push(file_name);
+    }
+
+    pub fn register_dropped_file(&mut self, file_name: String) {
+        self.inner.dropped_files.push(file_name);
+    }
+
+    pub fn register_truncated_file(&mut self, file_name: String, block_count: usize) {
+        self.inner.truncated_files.push((file_name, block_count));
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::{fs::remove_file, path::Path};
 
-    use crate::storage::log_manager_v2::LogManagerV2;
+    use crate::{
+        buffer::buffer_manager_v2::FlushPolicy,
+        storage::{log_manager_v2::LogManagerV2, page::Page},
+    };
 
     use super::*;
 
@@ -269,7 +532,7 @@ mod tests {
 
         transaction.set_string(block_id_1.clone(), 40, string_value, true);
 
-        transaction.commit();
+        transaction.commit().unwrap();
 
         let mut transaction2 = TransactionV2::new(
             2,
@@ -289,7 +552,7 @@ mod tests {
         transaction2.set_integer(block_id_1.clone(), 80, integer_value_2 + 100, true);
         transaction2.set_string(block_id_1.clone(), 40, &(string_value_2 + "!!!"), true);
 
-        transaction2.commit();
+        transaction2.commit().unwrap();
 
         let mut transaction3 = TransactionV2::new(
             3,
@@ -325,4 +588,748 @@ mod tests {
         remove_file(test_dir.join(test_file_name)).unwrap();
         remove_file(test_dir.join(log_file_name)).unwrap();
     }
+
+    #[test]
+    fn test_transaction_v2_string_boundary() {
+        let test_dir = Path::new("test_data");
+
+        let test_file_name = format!("test_file_{}.txt", uuid::Uuid::new_v4());
+        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
+
+        let block_size = 400;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
+        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
+            file_manager.clone(),
+            log_file_name.clone(),
+        )));
+        let buffer_manager = Arc::new(Mutex::new(BufferManagerV2::new(
+            10,
+            file_manager.clone(),
+            log_manager.clone(),
+        )));
+        let lock_table = Arc::new(Mutex::new(LockTable::new()));
+
+        let block_id_1 = BlockId::new(test_file_name.clone(), 1);
+
+        // VARCHAR(20)に4byte文字を詰めた最大長の文字列
+        let max_length_value = "😀".repeat(20);
+
+        let mut transaction = TransactionV2::new(
+            1,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        );
+        transaction.pin(block_id_1.clone());
+        transaction.set_string(block_id_1.clone(), 40, "", true);
+        transaction.set_integer(block_id_1.clone(), 40 + Page::get_max_length(20), 7, true);
+        transaction.commit().unwrap();
+
+        let mut transaction2 = TransactionV2::new(
+            2,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        );
+        transaction2.pin(block_id_1.clone());
+        assert_eq!(transaction2.get_string(block_id_1.clone(), 40), "");
+        transaction2.set_string(block_id_1.clone(), 40, &max_length_value, true);
+        transaction2.commit().unwrap();
+
+        let mut transaction3 = TransactionV2::new(
+            3,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        );
+        transaction3.pin(block_id_1.clone());
+        assert_eq!(transaction3.get_string(block_id_1.clone(), 40), max_length_value);
+        transaction3.set_string(block_id_1.clone(), 40, "", true);
+        transaction3.rollback();
+
+        let mut transaction4 = TransactionV2::new(
+            4,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        );
+        transaction4.pin(block_id_1.clone());
+        assert_eq!(transaction4.get_string(block_id_1.clone(), 40), max_length_value);
+        // 隣のフィールドは壊れていない
+        assert_eq!(
+            transaction4.get_integer(block_id_1.clone(), 40 + Page::get_max_length(20)),
+            7
+        );
+        transaction4.commit().unwrap();
+
+        remove_file(test_dir.join(test_file_name)).unwrap();
+        remove_file(test_dir.join(log_file_name)).unwrap();
+    }
+
+    #[test]
+    fn test_transaction_v2_rollback() {
+        let test_dir = Path::new("test_data");
+
+        let test_file_name = format!("test_file_{}.txt", uuid::Uuid::new_v4());
+        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
+
+        let block_size = 400;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
+        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
+            file_manager.clone(),
+            log_file_name.clone(),
+        )));
+        let buffer_manager = Arc::new(Mutex::new(BufferManagerV2::new(
+            10,
+            file_manager.clone(),
+            log_manager.clone(),
+        )));
+        let lock_table = Arc::new(Mutex::new(LockTable::new()));
+
+        let block_id = BlockId::new(test_file_name.clone(), 0);
+
+        let mut transaction1 = TransactionV2::new(
+            1,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        );
+        transaction1.pin(block_id.clone());
+        transaction1.set_integer(block_id.clone(), 0, 100, true);
+        transaction1.set_string(block_id.clone(), 20, "original", true);
+        transaction1.commit().unwrap();
+
+        let mut transaction2 = TransactionV2::new(
+            2,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        );
+        transaction2.pin(block_id.clone());
+        transaction2.set_integer(block_id.clone(), 0, 200, true);
+        transaction2.set_string(block_id.clone(), 20, "changed", true);
+        transaction2.set_integer(block_id.clone(), 0, 300, true);
+        assert_eq!(transaction2.get_integer(block_id.clone(), 0), 300);
+        transaction2.rollback();
+
+        // rollbackでlockが解放されているので、別のtransactionから読める
+        let mut transaction3 = TransactionV2::new(
+            3,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        );
+        transaction3.pin(block_id.clone());
+        assert_eq!(transaction3.get_integer(block_id.clone(), 0), 100);
+        assert_eq!(transaction3.get_string(block_id.clone(), 20), "original");
+        transaction3.commit().unwrap();
+
+        // 元に戻した値はdiskにも書き出されている
+        let mut page = Page::new(block_size);
+        file_manager.lock().unwrap().read(&block_id, &mut page);
+        assert_eq!(page.get_integer(0), 100);
+        assert_eq!(page.get_string(20), "original");
+
+        remove_file(test_dir.join(test_file_name)).unwrap();
+        remove_file(test_dir.join(log_file_name)).unwrap();
+    }
+
+    #[test]
+    fn test_held_locks() {
+        let test_dir = Path::new("test_data");
+
+        let test_file_name = format!("test_file_{}.txt", uuid::Uuid::new_v4());
+        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
+
+        let block_size = 400;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
+        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
+            file_manager.clone(),
+            log_file_name.clone(),
+        )));
+        let buffer_manager = Arc::new(Mutex::new(BufferManagerV2::new(
+            10,
+            file_manager.clone(),
+            log_manager.clone(),
+        )));
+        let lock_table = Arc::new(Mutex::new(LockTable::new()));
+
+        let block_id_0 = BlockId::new(test_file_name.clone(), 0);
+        let block_id_1 = BlockId::new(test_file_name.clone(), 1);
+
+        let mut transaction1 = TransactionV2::new(
+            1,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        );
+        let mut transaction2 = TransactionV2::new(
+            2,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        );
+        transaction1.pin(block_id_0.clone());
+        transaction1.pin(block_id_1.clone());
+        transaction2.pin(block_id_0.clone());
+        assert_eq!(transaction1.held_locks(), vec![]);
+
+        transaction1.set_integer(block_id_1.clone(), 0, 100, true);
+        transaction1.get_integer(block_id_0.clone(), 0);
+        transaction2.get_integer(block_id_0.clone(), 0);
+
+        assert_eq!(
+            transaction1.held_locks(),
+            vec![block_id_0.clone(), block_id_1.clone()]
+        );
+        assert_eq!(transaction2.held_locks(), vec![block_id_0.clone()]);
+        assert_eq!(
+            lock_table.lock().unwrap().held_locks(),
+            vec![
+                (
+                    1,
+                    vec![
+                        (block_id_0.clone(), "S".to_string()),
+                        (block_id_1.clone(), "X".to_string())
+                    ]
+                ),
+                (2, vec![(block_id_0.clone(), "S".to_string())]),
+            ]
+        );
+
+        // commitしたtransactionのlockは一覧から消える
+        transaction1.commit().unwrap();
+        assert_eq!(transaction1.held_locks(), vec![]);
+        assert_eq!(
+            lock_table.lock().unwrap().held_locks(),
+            vec![(2, vec![(block_id_0.clone(), "S".to_string())])]
+        );
+        transaction2.commit().unwrap();
+        assert_eq!(lock_table.lock().unwrap().held_locks(), vec![]);
+
+        remove_file(test_dir.join(test_file_name)).unwrap();
+        remove_file(test_dir.join(log_file_name)).unwrap();
+    }
+
+    #[test]
+    fn test_lock_wait_timeout_aborts_transaction() {
+        let test_dir = Path::new("test_data");
+
+        let test_file_name = format!("test_file_{}.txt", uuid::Uuid::new_v4());
+        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
+
+        let block_size = 400;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
+        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
+            file_manager.clone(),
+            log_file_name.clone(),
+        )));
+        let buffer_manager = Arc::new(Mutex::new(BufferManagerV2::new(
+            10,
+            file_manager.clone(),
+            log_manager.clone(),
+        )));
+        let lock_table = Arc::new(Mutex::new(LockTable::new()));
+        lock_table
+            .lock()
+            .unwrap()
+            .set_wait_timeout(std::time::Duration::from_millis(300));
+
+        let block_id = BlockId::new(test_file_name.clone(), 0);
+
+        let mut transaction1 = TransactionV2::new(
+            1,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        );
+        let transaction2 = Rc::new(RefCell::new(TransactionV2::new(
+            2,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        )));
+        transaction1.pin(block_id.clone());
+        transaction1.set_integer(block_id.clone(), 0, 100, true);
+        transaction2.borrow_mut().pin(block_id.clone());
+
+        // transaction1がxlockを持っているので、transaction2は待ちきれずにabortされる
+        let result = TransactionV2::abort_on_lock_failure(transaction2.clone(), || {
+            transaction2
+                .borrow_mut()
+                .set_integer(block_id.clone(), 0, 200, true);
+            return Ok(());
+        });
+        match result {
+            Err(DatabaseError::TransactionAborted(_)) => {}
+            _ => panic!("expected TransactionAborted"),
+        }
+        assert_eq!(
+            lock_table.lock().unwrap().held_locks(),
+            vec![(1, vec![(block_id.clone(), "X".to_string())])]
+        );
+
+        transaction1.commit().unwrap();
+        assert_eq!(lock_table.lock().unwrap().held_locks(), vec![]);
+
+        remove_file(test_dir.join(test_file_name)).unwrap();
+        remove_file(test_dir.join(log_file_name)).unwrap();
+    }
+
+    #[test]
+    fn test_no_force_commit_is_redone_after_crash() {
+        let test_dir = Path::new("test_data");
+
+        let test_file_name = format!("test_file_{}.txt", uuid::Uuid::new_v4());
+        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
+
+        let block_size = 400;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
+        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
+            file_manager.clone(),
+            log_file_name.clone(),
+        )));
+        let buffer_manager = Arc::new(Mutex::new(BufferManagerV2::new(
+            10,
+            file_manager.clone(),
+            log_manager.clone(),
+        )));
+        buffer_manager
+            .lock()
+            .unwrap()
+            .set_flush_policy(FlushPolicy::NoForce);
+        let lock_table = Arc::new(Mutex::new(LockTable::new()));
+
+        let block_id = BlockId::new(test_file_name.clone(), 0);
+
+        let mut transaction1 = TransactionV2::new(
+            1,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        );
+        transaction1.pin(block_id.clone());
+        transaction1.set_integer(block_id.clone(), 80, 123, true);
+        transaction1.set_string(block_id.clone(), 100, "committed", true);
+        transaction1.commit().unwrap();
+
+        // commitしてもblockはdiskに書き出されていない
+        let mut page = Page::new(block_size);
+        file_manager.lock().unwrap().read(&block_id, &mut page);
+        assert_eq!(page.get_integer(80), 0);
+
+        // commitしていない変更はdiskに書き出されてしまった
+        let mut transaction2 = TransactionV2::new(
+            2,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        );
+        transaction2.pin(block_id.clone());
+        transaction2.set_integer(block_id.clone(), 84, 555, true);
+        buffer_manager.lock().unwrap().flush_all(2);
+
+        // crashしたことにして、buffer poolとlock tableを作り直す
+        let buffer_manager = Arc::new(Mutex::new(BufferManagerV2::new(
+            10,
+            file_manager.clone(),
+            log_manager.clone(),
+        )));
+        let lock_table = Arc::new(Mutex::new(LockTable::new()));
+        let mut transaction3 = TransactionV2::new(
+            3,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        );
+        transaction3.recover();
+
+        let mut page = Page::new(block_size);
+        file_manager.lock().unwrap().read(&block_id, &mut page);
+        assert_eq!(page.get_integer(80), 123);
+        assert_eq!(page.get_string(100), "committed");
+        assert_eq!(page.get_integer(84), 0);
+
+        remove_file(test_dir.join(test_file_name)).unwrap();
+        remove_file(test_dir.join(log_file_name)).unwrap();
+    }
+
+    #[test]
+    fn test_recover_stops_at_checkpoint() {
+        let test_dir = Path::new("test_data");
+
+        let test_file_name = format!("test_file_{}.txt", uuid::Uuid::new_v4());
+        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
+
+        let block_size = 400;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
+        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
+            file_manager.clone(),
+            log_file_name.clone(),
+        )));
+        let new_buffer_manager = || {
+            Arc::new(Mutex::new(BufferManagerV2::new(
+                10,
+                file_manager.clone(),
+                log_manager.clone(),
+            )))
+        };
+        let block_id = BlockId::new(test_file_name.clone(), 0);
+
+        // commitしていない変更をdiskに書き出してからcrashする
+        let buffer_manager = new_buffer_manager();
+        let mut transaction1 = TransactionV2::new(
+            1,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            Arc::new(Mutex::new(LockTable::new())),
+            log_manager.clone(),
+        );
+        transaction1.pin(block_id.clone());
+        transaction1.set_integer(block_id.clone(), 80, 111, true);
+        buffer_manager.lock().unwrap().flush_all(1);
+
+        // recoverでundoされ、最後にcheckpointが書かれる
+        let mut transaction2 = TransactionV2::new(
+            2,
+            file_manager.clone(),
+            new_buffer_manager(),
+            Arc::new(Mutex::new(LockTable::new())),
+            log_manager.clone(),
+        );
+        transaction2.recover();
+        let mut page = Page::new(block_size);
+        file_manager.lock().unwrap().read(&block_id, &mut page);
+        assert_eq!(page.get_integer(80), 0);
+
+        // checkpointより前のrecordはもう読まないので、transaction1のundoはやり直さない
+        page.set_integer(80, 999);
+        file_manager.lock().unwrap().write(&block_id, &mut page);
+        let mut transaction3 = TransactionV2::new(
+            3,
+            file_manager.clone(),
+            new_buffer_manager(),
+            Arc::new(Mutex::new(LockTable::new())),
+            log_manager.clone(),
+        );
+        transaction3.recover();
+        let mut page = Page::new(block_size);
+        file_manager.lock().unwrap().read(&block_id, &mut page);
+        assert_eq!(page.get_integer(80), 999);
+
+        remove_file(test_dir.join(test_file_name)).unwrap();
+        remove_file(test_dir.join(log_file_name)).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_flushes_other_committed_transactions() {
+        let test_dir = Path::new("test_data");
+
+        let test_file_name = format!("test_file_{}.txt", uuid::Uuid::new_v4());
+        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
+
+        let block_size = 400;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
+        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
+            file_manager.clone(),
+            log_file_name.clone(),
+        )));
+        let buffer_manager = Arc::new(Mutex::new(BufferManagerV2::new(
+            10,
+            file_manager.clone(),
+            log_manager.clone(),
+        )));
+        buffer_manager
+            .lock()
+            .unwrap()
+            .set_flush_policy(FlushPolicy::NoForce);
+        let block_id = BlockId::new(test_file_name.clone(), 0);
+
+        // NoForceでcommitした変更はbufferにだけある
+        // blockのformatのようにlogを書かない変更は、recoverでもredoされない
+        let mut transaction1 = TransactionV2::new(
+            1,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            Arc::new(Mutex::new(LockTable::new())),
+            log_manager.clone(),
+        );
+        transaction1.pin(block_id.clone());
+        transaction1.set_integer(block_id.clone(), 80, 123, false);
+        transaction1.commit().unwrap();
+
+        // 同じbuffer poolでrecoverしてcheckpointを書く
+        let mut transaction2 = TransactionV2::new(
+            2,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            Arc::new(Mutex::new(LockTable::new())),
+            log_manager.clone(),
+        );
+        transaction2.recover();
+
+        // checkpointより前はredoされないので、checkpointの前に書き出されていないといけない
+        let mut page = Page::new(block_size);
+        file_manager.lock().unwrap().read(&block_id, &mut page);
+        assert_eq!(page.get_integer(80), 123);
+
+        remove_file(test_dir.join(test_file_name)).unwrap();
+        remove_file(test_dir.join(log_file_name)).unwrap();
+    }
+
+    #[test]
+    fn test_recover_reads_records_without_new_value() {
+        let test_dir = Path::new("test_data");
+
+        let test_file_name = format!("test_file_{}.txt", uuid::Uuid::new_v4());
+        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
+
+        let block_size = 400;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
+        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
+            file_manager.clone(),
+            log_file_name.clone(),
+        )));
+        let block_id = BlockId::new(test_file_name.clone(), 0);
+
+        // 変更前の値だけを持つ古い形式のSETINTのrecordを、終わっていないtransactionとして書く
+        let mut page = Page::new(block_size);
+        page.set_integer(80, 555);
+        file_manager.lock().unwrap().write(&block_id, &mut page);
+        let mut record = Page::new(Page::get_integer_byte_size() * 2);
+        record.set_integer(0, 1);
+        record.set_integer(Page::get_integer_byte_size(), 1);
+        log_manager.lock().unwrap().append_record(record.get_data());
+        let file_name_offset = Page::get_integer_byte_size() * 2;
+        let block_number_offset = file_name_offset + Page::get_string_data_length(&test_file_name);
+        let mut record = Page::new(block_number_offset + Page::get_integer_byte_size() * 3);
+        record.set_integer(0, 4);
+        record.set_integer(Page::get_integer_byte_size(), 1);
+        record.set_string(file_name_offset, &test_file_name);
+        record.set_integer(block_number_offset, 0);
+        record.set_integer(block_number_offset + Page::get_integer_byte_size(), 80);
+        record.set_integer(block_number_offset + Page::get_integer_byte_size() * 2, 7);
+        log_manager.lock().unwrap().append_record(record.get_data());
+
+        let mut transaction2 = TransactionV2::new(
+            2,
+            file_manager.clone(),
+            Arc::new(Mutex::new(BufferManagerV2::new(
+                10,
+                file_manager.clone(),
+                log_manager.clone(),
+            ))),
+            Arc::new(Mutex::new(LockTable::new())),
+            log_manager.clone(),
+        );
+        transaction2.recover();
+
+        let mut page = Page::new(block_size);
+        file_manager.lock().unwrap().read(&block_id, &mut page);
+        assert_eq!(page.get_integer(80), 7);
+
+        remove_file(test_dir.join(test_file_name)).unwrap();
+        remove_file(test_dir.join(log_file_name)).unwrap();
+    }
+
+    #[test]
+    fn test_read_committed_releases_slock_early() {
+        let test_dir = Path::new("test_data");
+
+        let test_file_name = format!("test_file_{}.txt", uuid::Uuid::new_v4());
+        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
+
+        let block_size = 400;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
+        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
+            file_manager.clone(),
+            log_file_name.clone(),
+        )));
+        let buffer_manager = Arc::new(Mutex::new(BufferManagerV2::new(
+            10,
+            file_manager.clone(),
+            log_manager.clone(),
+        )));
+        let lock_table = Arc::new(Mutex::new(LockTable::new()));
+        lock_table
+            .lock()
+            .unwrap()
+            .set_wait_timeout(std::time::Duration::from_millis(300));
+        let new_transaction = |tx_num: i32| {
+            TransactionV2::new(
+                tx_num,
+                file_manager.clone(),
+                buffer_manager.clone(),
+                lock_table.clone(),
+                log_manager.clone(),
+            )
+        };
+
+        let block_id = BlockId::new(test_file_name.clone(), 0);
+
+        let mut transaction1 = new_transaction(1);
+        transaction1.pin(block_id.clone());
+        transaction1.set_integer(block_id.clone(), 0, 100, true);
+        transaction1.commit().unwrap();
+
+        // ReadCommittedでは読み終わったらslockを外すので、commit前でも書き込める
+        let mut transaction2 = new_transaction(2);
+        assert_eq!(transaction2.get_isolation_level(), IsolationLevel::Serializable);
+        transaction2.set_isolation_level(IsolationLevel::ReadCommitted);
+        transaction2.pin(block_id.clone());
+        assert_eq!(transaction2.get_integer(block_id.clone(), 0), 100);
+        assert_eq!(transaction2.held_locks(), vec![]);
+
+        let mut transaction3 = new_transaction(3);
+        transaction3.pin(block_id.clone());
+        transaction3.set_integer(block_id.clone(), 0, 200, true);
+        transaction3.commit().unwrap();
+
+        // commitされた新しい値が見える
+        assert_eq!(transaction2.get_integer(block_id.clone(), 0), 200);
+        transaction2.commit().unwrap();
+
+        // Serializableではslockをcommitまで持つので、書き込みは待ちきれずにabortされる
+        let mut transaction4 = new_transaction(4);
+        transaction4.pin(block_id.clone());
+        assert_eq!(transaction4.get_integer(block_id.clone(), 0), 200);
+        assert_eq!(transaction4.held_locks(), vec![block_id.clone()]);
+
+        let transaction5 = Rc::new(RefCell::new(new_transaction(5)));
+        transaction5.borrow_mut().pin(block_id.clone());
+        let result = TransactionV2::abort_on_lock_failure(transaction5.clone(), || {
+            transaction5
+                .borrow_mut()
+                .set_integer(block_id.clone(), 0, 300, true);
+            return Ok(());
+        });
+        match result {
+            Err(DatabaseError::TransactionAborted(_)) => {}
+            _ => panic!("expected TransactionAborted"),
+        }
+
+        // abort_on_lock_failureを通さなくても、commitがrollbackしてTransactionAbortedを返す
+        let mut transaction6 = new_transaction(6);
+        transaction6.pin(block_id.clone());
+        transaction6.set_integer(block_id.clone(), 0, 400, true);
+        match transaction6.commit() {
+            Err(DatabaseError::TransactionAborted(_)) => {}
+            _ => panic!("expected TransactionAborted"),
+        }
+        assert_eq!(transaction6.held_locks(), vec![]);
+        transaction4.commit().unwrap();
+
+        remove_file(test_dir.join(test_file_name)).unwrap();
+        remove_file(test_dir.join(log_file_name)).unwrap();
+    }
+
+    #[test]
+    fn test_read_uncommitted_does_not_wait_for_xlock() {
+        let test_dir = Path::new("test_data");
+
+        let test_file_name = format!("test_file_{}.txt", uuid::Uuid::new_v4());
+        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
+
+        let block_size = 400;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
+        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
+            file_manager.clone(),
+            log_file_name.clone(),
+        )));
+        let buffer_manager = Arc::new(Mutex::new(BufferManagerV2::new(
+            10,
+            file_manager.clone(),
+            log_manager.clone(),
+        )));
+        let lock_table = Arc::new(Mutex::new(LockTable::new()));
+        lock_table
+            .lock()
+            .unwrap()
+            .set_wait_timeout(std::time::Duration::from_millis(300));
+        let new_transaction = |tx_num: i32| {
+            TransactionV2::new(
+                tx_num,
+                file_manager.clone(),
+                buffer_manager.clone(),
+                lock_table.clone(),
+                log_manager.clone(),
+            )
+        };
+
+        let block_id = BlockId::new(test_file_name.clone(), 0);
+
+        let mut transaction1 = new_transaction(1);
+        transaction1.pin(block_id.clone());
+        transaction1.set_integer(block_id.clone(), 0, 100, true);
+
+        // transaction1がxlockを持っていても、slockを取らずにcommit前の値を読む
+        let mut transaction2 = new_transaction(2);
+        transaction2.set_isolation_level(IsolationLevel::ReadUncommitted);
+        transaction2.pin(block_id.clone());
+        assert_eq!(transaction2.get_integer(block_id.clone(), 0), 100);
+        assert_eq!(transaction2.held_locks(), vec![]);
+        transaction2.commit().unwrap();
+
+        transaction1.rollback();
+
+        remove_file(test_dir.join(test_file_name)).unwrap();
+        remove_file(test_dir.join(log_file_name)).unwrap();
+    }
+
+    #[test]
+    fn test_commit_returns_error_when_temp_file_is_not_deleted() {
+        let test_dir = Path::new("test_data");
+
+        let temp_file_name = format!("temp_file_{}.tbl", uuid::Uuid::new_v4());
+        let log_file_name = format!("log_file_{}.txt", uuid::Uuid::new_v4());
+
+        let block_size = 400;
+        let file_manager = Arc::new(Mutex::new(FileManager::new(test_dir, block_size)));
+        let log_manager = Arc::new(Mutex::new(LogManagerV2::new(
+            file_manager.clone(),
+            log_file_name.clone(),
+        )));
+        let buffer_manager = Arc::new(Mutex::new(BufferManagerV2::new(
+            10,
+            file_manager.clone(),
+            log_manager.clone(),
+        )));
+        let lock_table = Arc::new(Mutex::new(LockTable::new()));
+
+        // directoryはremove_fileで消せないので、temp fileの削除が失敗する
+        std::fs::create_dir(test_dir.join(&temp_file_name)).unwrap();
+        let mut transaction = TransactionV2::new(
+            1,
+            file_manager.clone(),
+            buffer_manager.clone(),
+            lock_table.clone(),
+            log_manager.clone(),
+        );
+        transaction.register_temp_file(temp_file_name.clone());
+        let result = transaction.commit();
+        assert!(matches!(
+            result,
+            Err(DatabaseError::FileAccess(ref error)) if error.to_string().contains(&temp_file_name)
+        ));
+
+        std::fs::remove_dir(test_dir.join(&temp_file_name)).unwrap();
+        // 消せなかったfileは残っているので、次のcommitで消し直す
+        transaction.commit().unwrap();
+
+        remove_file(test_dir.join(log_file_name)).unwrap();
+    }
 }